@@ -0,0 +1,93 @@
+//! Throughput of whole simulation runs on a few canonical encounters, in combats per second.
+//! Every run is seeded and single-threaded, so a change in the numbers comes from the
+//! simulator rather than the dice or the machine's core count.
+//!
+//! `cargo bench` runs them all; `cargo bench -- horde` picks one. Save a baseline before a
+//! performance change with `cargo bench -- --save-baseline before`, then compare with
+//! `cargo bench -- --baseline before`.
+
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use tunnel_fight::combat::EventDetail;
+use tunnel_fight::simulation::{run_simulation, SimulationOptions};
+use tunnel_fight::types::Encounter;
+
+/// Combats per measured run
+const ITERATIONS: u32 = 200;
+
+/// A party of four against a handful of goblins: the typical request
+const SKIRMISH: &str = r#"
+side1:
+  - { name: Fighter, hp: 2d8, ac: 17, attack_bonus: 2, damage: 1d8+1, start_zone: melee }
+  - { name: Cleric, hp: 2d6, ac: 15, attack_bonus: 1, damage: 1d6+1, start_zone: melee }
+  - { name: Thief, hp: 2d4, ac: 13, attack_bonus: 1, damage: 1d6, start_zone: reach }
+  - { name: Magic-User, hp: 1d4, ac: 10, attack_bonus: 0, damage: 1d4, range: ranged, start_zone: ranged }
+side2:
+  - { name: Goblin, count: 6, hp: 1d6, ac: 13, attack_bonus: 0, damage: 1d6, start_zone: melee }
+"#;
+
+/// Twenty a side through narrow zones: most of the time goes to movement and capacity checks
+const HORDE: &str = r#"
+zone_capacity: { ranged: ~, reach: 12, melee: 12 }
+side1:
+  - { name: Spearman, count: 12, hp: 1d8, ac: 14, attack_bonus: 1, damage: 1d6, range: reach, start_zone: reach }
+  - { name: Archer, count: 8, hp: 1d6, ac: 12, attack_bonus: 1, damage: 1d6, range: ranged, start_zone: ranged }
+side2:
+  - { name: Orc, count: 20, hp: 1d8, ac: 13, attack_bonus: 1, damage: 1d8, start_zone: melee }
+"#;
+
+/// Phase initiative with weapon speed: every actor is ordered once per phase
+const PHASES: &str = r#"
+initiative: { type: individual_phases, dice: 1d10, weapon_speed: true }
+side1:
+  - { name: Knife Fighter, count: 3, initiative_modifier: 1, weapon_speed: 2, hp: 6, ac: 12, attack_bonus: 1, damage: 1d4 }
+  - { name: Halberdier, count: 3, weapon_speed: 9, range: reach, start_zone: reach, hp: 8, ac: 14, attack_bonus: 1, damage: 1d10 }
+side2:
+  - { name: Hobgoblin, count: 6, weapon_speed: 5, hp: 1d8+1, ac: 15, attack_bonus: 1, damage: 1d8 }
+"#;
+
+/// Archers trading shots from cover until the round limit: long combats with long event logs
+const STANDOFF: &str = r#"
+max_rounds: 100
+zones:
+  - { name: wall, terrain: { cover: 4 } }
+  - { name: field }
+  - { name: ridge, terrain: { cover: 4 } }
+side1:
+  - { name: Crossbowman, count: 4, hp: 12, ac: 16, attack_bonus: 0, damage: 1d4, range: ranged, start_zone: wall,
+      apl: [ { action: attack, if: enemy.in_range } ] }
+side2:
+  - { name: Bandit Archer, count: 4, hp: 12, ac: 16, attack_bonus: 0, damage: 1d4, range: ranged, start_zone: ridge,
+      apl: [ { action: attack, if: enemy.in_range } ] }
+"#;
+
+const ENCOUNTERS: [(&str, &str); 4] = [("skirmish", SKIRMISH), ("horde", HORDE), ("phases", PHASES), ("standoff", STANDOFF)];
+
+fn options(encounter: &Encounter, event_detail: EventDetail) -> SimulationOptions {
+    SimulationOptions {
+        iterations: ITERATIONS,
+        sample_count: 0,
+        seed: Some(1),
+        threads: 1,
+        event_detail,
+        ..SimulationOptions::for_encounter(encounter)
+    }
+}
+
+fn simulate(c: &mut Criterion) {
+    let mut group = c.benchmark_group("simulate");
+    group.throughput(Throughput::Elements(ITERATIONS as u64));
+    for (name, yaml) in ENCOUNTERS {
+        let encounter: Encounter = serde_yaml::from_str(yaml).expect("benchmark encounters are well-formed");
+        for (detail_name, detail) in [("full", EventDetail::Full), ("none", EventDetail::None)] {
+            let options = options(&encounter, detail);
+            group.bench_with_input(BenchmarkId::new(name, detail_name), &options, |b, options| {
+                b.iter(|| run_simulation(&encounter, options))
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, simulate);
+criterion_main!(benches);
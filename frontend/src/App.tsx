@@ -149,6 +149,7 @@ function Docs({ onClose }: { onClose: () => void }) {
               <tr><td><code>start_zone</code></td><td>ranged (default), reach, melee</td></tr>
               <tr><td><code>frontage</code></td><td>Space occupied in zones (default: 3)</td></tr>
               <tr><td><code>initiative_modifier</code></td><td>Bonus to initiative roll (default: 0)</td></tr>
+              <tr><td><code>weapon_speed</code></td><td>Weapon speed factor, used with <code>initiative.weapon_speed</code> (default: 0)</td></tr>
             </tbody>
           </table>
         </section>
@@ -163,6 +164,7 @@ function Docs({ onClose }: { onClose: () => void }) {
               <tr><td><code>individual</code></td><td>Each actor rolls initiative dice + modifier, acts in order</td></tr>
               <tr><td><code>side_phases</code></td><td>Phased combat by side: phases execute in order for each side</td></tr>
               <tr><td><code>individual_phases</code></td><td>Phased combat: phases execute in order, actors act by initiative within each phase</td></tr>
+              <tr><td><code>declared</code></td><td>Everyone declares from the start-of-round state, then actions resolve simultaneously</td></tr>
             </tbody>
           </table>
           <h4>Options</h4>
@@ -170,7 +172,9 @@ function Docs({ onClose }: { onClose: () => void }) {
             <tbody>
               <tr><td><code>type</code></td><td>Initiative type (see above)</td></tr>
               <tr><td><code>dice</code></td><td>Dice formula for rolls (default: 1d20)</td></tr>
+              <tr><td><code>roll</code></td><td><code>each_round</code> (default) or <code>once</code> to keep the first order all fight</td></tr>
               <tr><td><code>phases</code></td><td>Phase order for phase-based systems</td></tr>
+              <tr><td><code>weapon_speed</code></td><td>Subtract actors' weapon speed from initiative for attacks (default: false)</td></tr>
             </tbody>
           </table>
           <h4>Example</h4>
@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tunnel_fight::types::parse_damage_dice;
+
+fuzz_target!(|input: &str| {
+    let _ = parse_damage_dice(input);
+});
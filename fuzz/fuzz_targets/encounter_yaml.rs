@@ -0,0 +1,10 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tunnel_fight::loader::parse_encounter;
+use tunnel_fight::storage::Storage;
+
+fuzz_target!(|input: &str| {
+    let storage = Storage::open_in_memory().expect("in-memory library");
+    let _ = parse_encounter(input, &storage);
+});
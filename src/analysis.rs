@@ -0,0 +1,115 @@
+//! Exact odds for every attacker/target pair, worked out from the statblocks instead of
+//! simulated: a cross-check on the Monte Carlo numbers, and something a UI can show at once.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::combat::d20_chance;
+use crate::types::{ActorTemplate, DiceRules, Encounter, Rules, Side, WeaponRange, ZoneLayout};
+
+/// One template's attacks against one enemy template
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Matchup {
+    pub attacker: String,
+    pub attacker_side: Side,
+    pub target: String,
+    /// Percent chance that one attack hits: d20 + attack bonus - cover against the target's AC
+    pub hit_chance: f64,
+    /// Expected damage of one attack, misses included, after the target's armor (with
+    /// `rules.armor_dr`) and damage threshold, with the encounter's dice house rules
+    pub expected_damage_per_attack: f64,
+    /// One attack a turn plus one per legendary action
+    pub attacks_per_round: u32,
+    /// Expected damage each round the attacker spends in range of the target
+    pub expected_damage_per_round: f64,
+    /// Zones between the two at the start of the fight
+    pub start_distance: u32,
+    /// Whether the attacker can hit the target from where both start
+    pub in_range_at_start: bool,
+    /// Penalty from the target's starting zone (ranged attacks only)
+    pub cover: i32,
+}
+
+/// Every side 1 template against every template on a side it fights, then the same for side 2
+/// and any other factions
+pub fn matchups(encounter: &Encounter) -> Vec<Matchup> {
+    let zones = encounter.zone_layout();
+    let mut matchups = Vec::new();
+    for side in encounter.sides() {
+        let hostile = encounter.hostile_to(side);
+        for attacker in encounter.templates(side) {
+            for target_side in encounter.sides().filter(|&other| hostile.contains(other)) {
+                for target in encounter.templates(target_side) {
+                    matchups.push(matchup(attacker, (side, target_side), target, &zones, encounter.rules));
+                }
+            }
+        }
+    }
+    matchups
+}
+
+fn matchup(
+    attacker: &ActorTemplate,
+    (side, target_side): (Side, Side),
+    target: &ActorTemplate,
+    zones: &ZoneLayout,
+    rules: Rules,
+) -> Matchup {
+    // Unknown zone names fail validation; the simulator falls back to the rear the same way
+    let from = zones.start_zone(side, &attacker.start_zone).unwrap_or_else(|| zones.rear(side));
+    let to = zones
+        .start_zone(target_side, &target.start_zone)
+        .unwrap_or_else(|| zones.rear(target_side));
+    let distance = zones.distance(from, to);
+    let reach = attacker.size.extra_reach();
+    let in_range = distance != u32::MAX
+        && (0..=reach).any(|extra| distance > extra && attacker.range.can_hit_at_distance(distance - extra));
+    let cover = if attacker.range == WeaponRange::Ranged { zones.terrain(to).cover } else { 0 };
+
+    let hit_chance = d20_chance(target.ac - attacker.attack_bonus + cover, false, false);
+    let dr = if rules.armor_dr { target.damage_reduction } else { 0 };
+    let per_attack = hit_chance * damage_per_hit(attacker, target, dr, rules.dice());
+    let attacks_per_round = attacker.legendary_actions.saturating_add(1);
+    Matchup {
+        attacker: attacker.name.clone(),
+        attacker_side: side,
+        target: target.name.clone(),
+        hit_chance: hit_chance * 100.0,
+        expected_damage_per_attack: per_attack,
+        attacks_per_round,
+        expected_damage_per_round: per_attack * attacks_per_round as f64,
+        start_distance: distance,
+        in_range_at_start: in_range,
+        cover,
+    }
+}
+
+/// Expected damage of a hit: minions deal their average, drain-only hits deal none, armor
+/// takes `dr` off each hit, and what's left under the target's threshold counts for nothing.
+/// Exact when the damage dice have a distribution; otherwise the average stands in for every
+/// roll.
+fn damage_per_hit(attacker: &ActorTemplate, target: &ActorTemplate, dr: i32, dice: DiceRules) -> f64 {
+    let threshold = target.damage_threshold;
+    let gets_through = |damage: f64| {
+        let damage = (damage - dr as f64).max(0.0);
+        if damage < threshold as f64 {
+            0.0
+        } else {
+            damage
+        }
+    };
+    if attacker.drain_only {
+        return 0.0;
+    }
+    if attacker.minion {
+        return gets_through((attacker.damage.expected_value_with(dice) as i32).max(1) as f64);
+    }
+    match attacker.damage.distribution_with(dice) {
+        Some((lowest, chances)) => chances
+            .iter()
+            .enumerate()
+            .map(|(i, chance)| chance * gets_through((lowest + i as i64) as f64))
+            .sum(),
+        None => gets_through(attacker.damage.expected_value_with(dice).max(if dice.min_one { 1.0 } else { 0.0 })),
+    }
+}
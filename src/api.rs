@@ -1,94 +1,1900 @@
+use std::convert::Infallible;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
 use axum::{
-    extract::Json,
-    http::StatusCode,
-    response::IntoResponse,
-    routing::{get, post},
+    body::Body,
+    extract::{
+        ws::{Message, WebSocket, WebSocketUpgrade},
+        DefaultBodyLimit, Json, Path, Query, State,
+    },
+    http::{header, HeaderName, HeaderValue, StatusCode},
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse, Response,
+    },
+    routing::{get, post, put},
     Router,
 };
-use rand::SeedableRng;
-use rand_chacha::ChaCha8Rng;
 use serde::{Deserialize, Serialize};
-use tower_http::cors::{Any, CorsLayer};
+use tokio::sync::{mpsc, OwnedSemaphorePermit, Semaphore};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tower_http::cors::{AllowHeaders, AllowOrigin, Any, CorsLayer};
+use tower_http::timeout::TimeoutLayer;
+use tracing::{Instrument, Span};
+use utoipa::openapi::security::{Http, HttpAuthScheme, SecurityRequirement, SecurityScheme};
+use utoipa::{IntoParams, Modify, OpenApi, ToSchema};
+
+use tunnel_fight::apl::{self, ErrorCode, ValidationError};
+use tunnel_fight::builtins::{self, Builtin};
+use tunnel_fight::campaign::{self, Campaign, CampaignResult};
+use tunnel_fight::combat::{ActorView, ChoiceTrace, Controller, Decision, EventDetail};
+use tunnel_fight::decide::{self, DecisionCase, DecisionReport};
+use tunnel_fight::export::{self, LogFormat};
+use tunnel_fight::import::{import_5e, import_ose, Imported};
+use tunnel_fight::lint::{self, LintWarning};
+use tunnel_fight::loader::{
+    build_campaign, build_encounter, encounter_schema, parse_campaign_as, parse_document, parse_encounter, parse_encounter_as,
+    SourceFormat,
+};
+use tunnel_fight::optimize::{optimize as run_optimize, Candidate, OptimizeResult, ParamGrid};
+use tunnel_fight::report::{self, ReportFormat};
+use tunnel_fight::rating::{self, Rating, RATING_ITERATIONS};
+use tunnel_fight::sensitivity::{self, SensitivityResult};
+use tunnel_fight::sequence::{self, BatchResult};
+use tunnel_fight::simulation::{
+    average_combat, debug_combat, for_each_combat, play_combat, replay_combat, run_simulation, run_simulation_with_progress, Progress, SimulationOptions,
+};
+use tunnel_fight::stats::{format_combat_log, format_events, CombatLog, CombatLogEntry, CombatSummary, SimulationResult, CSV_HEADER};
+use tunnel_fight::storage::{Storage, StorageError, StoredEncounter, StoredResult};
+use tunnel_fight::types::{ActorTemplate, AplEntry, Encounter, Phase, Side};
 
-use crate::combat::CombatSimulator;
-use crate::stats::{SimulationResult, StatsCollector};
-use crate::types::Encounter;
+use crate::auth::{self, Access};
+use crate::cache::{CacheKey, ResultCache};
+use crate::limits::Limits;
+use crate::logging;
 
-#[derive(Debug, Deserialize)]
-pub struct SimulateRequest {
-    pub encounter_yaml: String,
+/// Shared state for all handlers
+pub struct AppState {
+    pub storage: Storage,
+    pub limits: Limits,
+    /// One permit per simulation allowed to run at once
+    simulations: Arc<Semaphore>,
+    /// One permit per `/interactive` session allowed open at once. Sessions spend most of
+    /// their time waiting on the client, so they don't take simulation slots.
+    sessions: Arc<Semaphore>,
+    /// Recent `/simulate` results
+    cache: ResultCache,
+}
+
+impl AppState {
+    pub fn new(storage: Storage, limits: Limits, cache: ResultCache) -> Self {
+        AppState {
+            storage,
+            simulations: Arc::new(Semaphore::new(limits.max_concurrent_simulations)),
+            sessions: Arc::new(Semaphore::new(limits.max_interactive_sessions)),
+            limits,
+            cache,
+        }
+    }
+}
+
+type SharedState = Arc<AppState>;
+
+/// Per-run knobs shared by every endpoint that runs a simulation
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SimulationParams {
     #[serde(default = "default_sample_count")]
     pub sample_count: usize,
     pub seed: Option<u64>,
+    /// Overrides the encounter's `max_rounds`
+    pub max_rounds: Option<u32>,
+    /// Stop early once win rates are known to within ± this many percentage points
+    pub target_precision: Option<f64>,
+    /// Events recorded for combats that aren't samples: `none` and `deaths` run faster but
+    /// leave the stats built from events at zero
+    #[serde(default)]
+    pub event_detail: EventDetail,
+}
+
+/// An encounter, either as text (YAML, JSON, or TOML) or as a JSON object. Exactly one must
+/// be given.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct EncounterInput {
+    pub encounter_yaml: Option<String>,
+    /// Format of `encounter_yaml` (detected from the text if omitted)
+    pub encounter_format: Option<SourceFormat>,
+    #[schema(value_type = Option<Encounter>)]
+    pub encounter: Option<serde_json::Value>,
+}
+
+impl EncounterInput {
+    /// Parse, resolve library refs, and validate the encounter
+    fn parse(&self, storage: &Storage) -> Result<Encounter, Vec<ValidationError>> {
+        match (&self.encounter_yaml, &self.encounter) {
+            (Some(text), None) => parse_encounter_as(text, self.encounter_format, storage),
+            (None, Some(json)) => {
+                let doc = serde_yaml::to_value(json).map_err(|e| request_error(e.to_string()))?;
+                build_encounter(doc, None, storage)
+            }
+            (Some(_), Some(_)) => Err(request_error(
+                "Provide either encounter_yaml or encounter, not both".to_string(),
+            )),
+            (None, None) => Err(request_error("Missing encounter_yaml or encounter".to_string())),
+        }
+    }
+
+    /// The encounter's text, if it was given as YAML, for locating paths in it
+    fn yaml_source(&self) -> Option<&str> {
+        let text = self.encounter_yaml.as_deref()?;
+        (self.encounter_format.unwrap_or_else(|| SourceFormat::detect(text)) == SourceFormat::Yaml).then_some(text)
+    }
+
+    /// The encounter as YAML text for storage (JSON objects and JSON or TOML text are converted)
+    fn to_yaml(&self) -> String {
+        match (&self.encounter_yaml, &self.encounter) {
+            (Some(text), _) => match parse_document(text, self.encounter_format) {
+                Ok((doc, format)) if format != SourceFormat::Yaml => serde_yaml::to_string(&doc).unwrap_or_default(),
+                _ => text.clone(),
+            },
+            (None, Some(json)) => serde_yaml::to_string(json).unwrap_or_default(),
+            (None, None) => String::new(),
+        }
+    }
+}
+
+fn request_error(message: String) -> Vec<ValidationError> {
+    vec![ValidationError::new("", ErrorCode::InvalidRequest, message)]
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SimulateRequest {
+    #[serde(flatten)]
+    pub encounter: EncounterInput,
+    #[serde(flatten)]
+    pub params: SimulationParams,
+    /// `csv_raw` streams one CSV row per iteration instead of aggregate stats
+    #[serde(default)]
+    pub output: OutputMode,
+    /// Iterations between progress events on `/simulate/stream`
+    #[serde(default = "default_progress_interval")]
+    pub progress_interval: u32,
+    /// `expected_value` plays one deterministic average fight instead of many random ones
+    #[serde(default)]
+    pub analysis_mode: AnalysisMode,
+    /// `false` always runs the simulation instead of answering from recent results
+    #[serde(default = "default_cache")]
+    pub cache: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AnalysisMode {
+    #[default]
+    MonteCarlo,
+    ExpectedValue,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputMode {
+    #[default]
+    Stats,
+    CsvRaw,
 }
 
 fn default_sample_count() -> usize {
     5
 }
 
-#[derive(Debug, Serialize)]
+fn default_progress_interval() -> u32 {
+    1000
+}
+
+fn default_cache() -> bool {
+    true
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ReportQuery {
+    /// Return a rendered battle report instead of JSON
+    pub format: Option<ReportFormat>,
+}
+
+/// Re-run one combat from a previous simulation
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ReplayRequest {
+    #[serde(flatten)]
+    pub encounter: EncounterInput,
+    /// The run's master seed (`seed` in the simulation result)
+    pub seed: u64,
+    /// 0-based iteration to replay (`iteration` on a sample combat)
+    pub iteration: u32,
+    /// Must match the original run's `max_rounds` override, if any
+    pub max_rounds: Option<u32>,
+    /// Export the log for a virtual tabletop instead of returning it as JSON
+    pub format: Option<LogFormat>,
+}
+
+/// Run one combat, tracing how every actor picks its actions
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DebugRequest {
+    #[serde(flatten)]
+    pub encounter: EncounterInput,
+    /// Master seed; a random one is chosen (and reported) when unset. With `iteration`, picks
+    /// out the same combat as `/replay`.
+    pub seed: Option<u64>,
+    /// 0-based iteration of the run to trace
+    #[serde(default)]
+    pub iteration: u32,
+    pub max_rounds: Option<u32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct DebugResponse {
+    pub seed: u64,
+    pub log: CombatLog,
+    /// Every pass an actor made over its APL, in the order they happened
+    pub choices: Vec<ChoiceTrace>,
+}
+
+/// Check what actors would pick in posed combat states
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct DecideRequest {
+    #[serde(flatten)]
+    pub encounter: EncounterInput,
+    pub cases: Vec<DecisionCase>,
+    /// Seed for rolled HP and random targets (default 0, so a table answers the same every time)
+    #[serde(default)]
+    pub seed: u64,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ValidateRequest {
+    #[serde(flatten)]
+    pub encounter: EncounterInput,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ValidateResponse {
+    pub valid: bool,
+    pub errors: Vec<ValidationError>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LintResponse {
+    pub valid: bool,
+    pub errors: Vec<ValidationError>,
+    /// Likely authoring mistakes in an encounter that validates (empty if it doesn't)
+    pub warnings: Vec<LintWarning>,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SaveEncounterRequest {
+    /// Defaults to the encounter's own `name`
+    pub name: Option<String>,
+    #[serde(flatten)]
+    pub encounter: EncounterInput,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
 pub struct ErrorResponse {
     pub error: String,
+    /// Set for invalid input (the problem's code, or `validation_failed` with one per detail)
+    /// and for requests turned away by the server's limits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub code: Option<ErrorCode>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub details: Vec<ValidationError>,
 }
 
-pub fn create_router() -> Router {
+#[derive(OpenApi)]
+#[openapi(
+    info(title = "Tunnel Fight", description = "OSR combat simulator"),
+    modifiers(&ApiKeyAuth),
+    paths(
+        health_check,
+        schema,
+        simulate,
+        simulate_stream,
+        simulate_batch,
+        run_campaign,
+        replay,
+        debug,
+        decide,
+        optimize,
+        sensitivity,
+        rate,
+        validate,
+        lint,
+        list_monsters,
+        list_builtins,
+        get_monster,
+        create_monster,
+        put_monster,
+        delete_monster,
+        import_5e_statblock,
+        import_ose_statblock,
+        list_encounters,
+        create_encounter,
+        get_encounter,
+        update_encounter,
+        delete_encounter,
+        simulate_saved_encounter,
+        get_encounter_results,
+    ),
+)]
+pub struct ApiDoc;
+
+/// Declares the API key a server may require (see `auth.rs`); without one, no key is needed
+struct ApiKeyAuth;
+
+impl Modify for ApiKeyAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Default::default);
+        components.add_security_scheme("api_key", SecurityScheme::Http(Http::new(HttpAuthScheme::Bearer)));
+        openapi.security = Some(vec![
+            SecurityRequirement::default(),
+            SecurityRequirement::new("api_key", Vec::<String>::new()),
+        ]);
+    }
+}
+
+/// The API's routes and middleware. Browsers may call it from `cors_origins`, or from anywhere
+/// when that's None; `access` decides who may call it at all, and how often.
+pub fn create_router(state: SharedState, cors_origins: Option<Vec<HeaderValue>>, access: Access) -> Router {
+    let access = Arc::new(access);
+    let allowed = match cors_origins {
+        Some(origins) => AllowOrigin::list(origins),
+        None => AllowOrigin::any(),
+    };
     let cors = CorsLayer::new()
-        .allow_origin(Any)
+        .allow_origin(allowed)
         .allow_methods(Any)
-        .allow_headers(Any);
+        // Mirrored rather than `*`, which doesn't cover `Authorization`
+        .allow_headers(AllowHeaders::mirror_request());
+    let body_limit = DefaultBodyLimit::max(state.limits.max_body_bytes);
+    let timeout = TimeoutLayer::new(state.limits.request_timeout);
 
-    Router::new()
-        .route("/health", get(health_check))
+    // Anything that runs a combat draws from the caller's rate limit
+    let simulations = Router::new()
         .route("/simulate", post(simulate))
-        .layer(cors)
+        .route("/simulate/stream", post(simulate_stream))
+        .route("/simulate/batch", post(simulate_batch))
+        .route("/campaign", post(run_campaign))
+        .route("/optimize", post(optimize))
+        .route("/sensitivity", post(sensitivity))
+        .route("/rate", post(rate))
+        .route("/encounters/:id/simulate", post(simulate_saved_encounter))
+        .route("/replay", post(replay))
+        .route("/debug", post(debug))
+        .route("/decide", post(decide))
+        .route("/interactive", get(interactive))
+        .route_layer(axum::middleware::from_fn_with_state(Arc::clone(&access), auth::rate_limit));
+
+    let api = Router::new()
+        .route("/validate", post(validate))
+        .route("/lint", post(lint))
+        .route("/monsters", get(list_monsters).post(create_monster))
+        .route("/builtins", get(list_builtins))
+        .route(
+            "/monsters/:name",
+            put(put_monster).get(get_monster).delete(delete_monster),
+        )
+        .route("/import/5e", post(import_5e_statblock))
+        .route("/import/ose", post(import_ose_statblock))
+        .route("/encounters", get(list_encounters).post(create_encounter))
+        .route(
+            "/encounters/:id",
+            get(get_encounter).put(update_encounter).delete(delete_encounter),
+        )
+        .route("/encounters/:id/results", get(get_encounter_results))
+        .merge(simulations)
+        .route_layer(axum::middleware::from_fn_with_state(access, auth::require_key));
+
+    let router = Router::new()
+        .route("/health", get(health_check))
+        .route("/openapi.json", get(openapi_json))
+        .route("/schema", get(schema))
+        .merge(api)
+        .layer(body_limit)
+        .layer(timeout)
+        .layer(cors);
+    logging::trace_requests(router).with_state(state)
 }
 
+#[utoipa::path(get, path = "/health", tag = "meta", responses((status = 200, description = "Server is up")))]
 async fn health_check() -> impl IntoResponse {
     Json(serde_json::json!({ "status": "ok" }))
 }
 
-async fn simulate(Json(request): Json<SimulateRequest>) -> impl IntoResponse {
-    // Parse the encounter YAML
-    let encounter: Encounter = match serde_yaml::from_str(&request.encounter_yaml) {
+async fn openapi_json() -> impl IntoResponse {
+    Json(ApiDoc::openapi())
+}
+
+#[utoipa::path(
+    get,
+    path = "/schema",
+    tag = "meta",
+    responses((status = 200, description = "JSON Schema for encounter files, for editor validation and autocomplete", body = Object))
+)]
+async fn schema() -> impl IntoResponse {
+    Json(encounter_schema())
+}
+
+#[utoipa::path(
+    post,
+    path = "/validate",
+    tag = "simulation",
+    request_body = ValidateRequest,
+    responses((status = 200, description = "Validation report", body = ValidateResponse))
+)]
+async fn validate(
+    State(state): State<SharedState>,
+    Json(request): Json<ValidateRequest>,
+) -> impl IntoResponse {
+    let errors = request.encounter.parse(&state.storage).err().unwrap_or_default();
+    Json(ValidateResponse {
+        valid: errors.is_empty(),
+        errors,
+    })
+}
+
+#[utoipa::path(
+    post,
+    path = "/lint",
+    tag = "simulation",
+    request_body = ValidateRequest,
+    responses((status = 200, description = "Validation errors and tactical warnings", body = LintResponse))
+)]
+async fn lint(State(state): State<SharedState>, Json(request): Json<ValidateRequest>) -> impl IntoResponse {
+    let (errors, mut warnings) = match request.encounter.parse(&state.storage) {
+        Ok(encounter) => (Vec::new(), lint::lint(&encounter)),
+        Err(errors) => (errors, Vec::new()),
+    };
+    if let Some(yaml) = request.encounter.yaml_source() {
+        for warning in &mut warnings {
+            warning.line = apl::locate_line(yaml, &warning.path);
+        }
+    }
+    Json(LintResponse {
+        valid: errors.is_empty(),
+        errors,
+        warnings,
+    })
+}
+
+/// 400 response for an encounter that failed to parse or validate
+fn invalid_encounter(errors: Vec<ValidationError>) -> Response {
+    let (error, code) = if errors.len() == 1 && errors[0].path.is_empty() {
+        (errors[0].message.clone(), errors[0].code)
+    } else {
+        ("Invalid encounter".to_string(), ErrorCode::ValidationFailed)
+    };
+    let details = errors.into_iter().filter(|e| !e.path.is_empty()).collect();
+    let response = ErrorResponse {
+        error,
+        code: Some(code),
+        details,
+    };
+    (StatusCode::BAD_REQUEST, Json(response)).into_response()
+}
+
+/// Options for a run, which must finish within the server's time budget from now. The seed is
+/// settled here so it can be logged before the run starts.
+fn simulation_options(limits: &Limits, params: &SimulationParams, encounter: &Encounter) -> SimulationOptions {
+    SimulationOptions {
+        max_rounds: params.max_rounds.unwrap_or(encounter.max_rounds),
+        sample_count: params.sample_count.min(limits.max_sample_count),
+        seed: Some(params.seed.unwrap_or_else(rand::random)),
+        target_precision: params.target_precision,
+        deadline: Some(Instant::now() + limits.time_budget),
+        event_detail: params.event_detail,
+        threads: limits.threads,
+        ..SimulationOptions::for_encounter(encounter)
+    }
+}
+
+/// A response for a request turned away by the server's limits
+type Rejection = (StatusCode, Json<ErrorResponse>);
+
+/// Check a run, and the request's `max_rounds` override if it has one, against the server's
+/// limits and take a simulation slot for it: 413 if it's too big, 429 if the server is already running as many simulations as it allows. The slot is
+/// freed when the permit is dropped.
+fn admit(
+    state: &AppState,
+    encounter: &Encounter,
+    iterations: u32,
+    max_rounds: Option<u32>,
+) -> Result<OwnedSemaphorePermit, Rejection> {
+    if let Err(e) = state.limits.check(encounter, iterations, max_rounds) {
+        tracing::warn!(reason = e.message.as_str(), "run refused");
+        return Err(rejection(StatusCode::PAYLOAD_TOO_LARGE, e.code, e.message));
+    }
+    Arc::clone(&state.simulations).try_acquire_owned().map_err(|_| {
+        tracing::warn!("run refused: server busy");
+        rejection(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::ServerBusy,
+            "Too many simulations running; try again shortly".to_string(),
+        )
+    })
+}
+
+/// Whether a run that completed `completed` iterations was cut short by its deadline
+fn cut_off(options: &SimulationOptions, completed: u32) -> bool {
+    completed < options.iterations && options.deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// 413 for a run that didn't finish within the server's time budget
+fn over_budget(limits: &Limits) -> Rejection {
+    tracing::warn!(budget_secs = limits.time_budget.as_secs(), "run cut off by the time budget");
+    rejection(
+        StatusCode::PAYLOAD_TOO_LARGE,
+        ErrorCode::TimeBudgetExceeded,
+        format!(
+            "Simulation didn't finish within this server's {}s budget; ask for fewer iterations",
+            limits.time_budget.as_secs()
+        ),
+    )
+}
+
+/// Span for one run, carrying what's needed to reproduce a reported result
+fn simulation_span(encounter: &Encounter, options: &SimulationOptions) -> Span {
+    tracing::info_span!(
+        "simulation",
+        encounter = encounter.name.as_deref().unwrap_or("unnamed"),
+        iterations = options.iterations,
+        seed = options.seed.unwrap_or_default()
+    )
+}
+
+/// Run `simulate` inside `span`, logging how long it took
+fn run_in_span<T>(span: Span, simulate: impl FnOnce() -> T) -> T {
+    let _entered = span.enter();
+    let started = Instant::now();
+    let result = simulate();
+    tracing::info!(duration_ms = started.elapsed().as_millis() as u64, "simulation finished");
+    result
+}
+
+pub(crate) fn rejection(status: StatusCode, code: ErrorCode, error: String) -> Rejection {
+    let response = ErrorResponse {
+        error,
+        code: Some(code),
+        details: Vec::new(),
+    };
+    (status, Json(response))
+}
+
+#[utoipa::path(
+    post,
+    path = "/simulate",
+    tag = "simulation",
+    params(ReportQuery),
+    request_body = SimulateRequest,
+    responses(
+        (status = 200, description = "Aggregate stats and sample combats, a rendered battle report with `format`, \
+            one row per iteration with `output: csv_raw`, or the log of the average fight with \
+            `analysis_mode: expected_value`",
+            content(
+                (SimulationResult = "application/json"),
+                (CombatLog = "application/json"),
+                (String = "text/markdown"),
+                (String = "text/html"),
+                (String = "text/csv")
+            )),
+        (status = 400, description = "Encounter failed to parse or validate", body = ErrorResponse),
+        (status = 413, description = "Request exceeds the server's size or time limits", body = ErrorResponse),
+        (status = 429, description = "Server is already running as many simulations as it allows", body = ErrorResponse)
+    )
+)]
+async fn simulate(
+    State(state): State<SharedState>,
+    Query(query): Query<ReportQuery>,
+    Json(request): Json<SimulateRequest>,
+) -> Response {
+    let encounter = match request.encounter.parse(&state.storage) {
         Ok(e) => e,
-        Err(e) => {
-            return (
-                StatusCode::BAD_REQUEST,
-                Json(serde_json::json!({ "error": format!("Invalid YAML: {}", e) })),
+        Err(errors) => return invalid_encounter(errors),
+    };
+    if let Err(errors) = rounds_override(request.params.max_rounds) {
+        return invalid_encounter(errors);
+    }
+
+    if request.analysis_mode == AnalysisMode::ExpectedValue {
+        let permit = match admit(&state, &encounter, 1, request.params.max_rounds) {
+            Ok(permit) => permit,
+            Err(rejection) => return rejection.into_response(),
+        };
+        let max_rounds = request.params.max_rounds.unwrap_or(encounter.max_rounds);
+        return match tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            average_combat(&encounter, max_rounds)
+        })
+        .await
+        {
+            Ok(result) => Json(format_combat_log(&result, 0)).into_response(),
+            Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), Vec::new()),
+        };
+    }
+
+    let options = simulation_options(&state.limits, &request.params, &encounter);
+    let key = (request.output == OutputMode::Stats && request.cache)
+        .then(|| CacheKey::new(&encounter, request.params.seed, &options))
+        .flatten();
+    let name = encounter.name.clone().unwrap_or_else(|| "Encounter".to_string());
+    let (result, cache_status) = match key.as_ref().and_then(|key| state.cache.get(key)) {
+        Some(result) => (result, "hit"),
+        None => {
+            let permit = match admit(&state, &encounter, options.iterations, request.params.max_rounds) {
+                Ok(permit) => permit,
+                Err(rejection) => return rejection.into_response(),
+            };
+            if request.output == OutputMode::CsvRaw {
+                return stream_csv(encounter, options, permit);
+            }
+            let span = simulation_span(&encounter, &options);
+            let result = match tokio::task::spawn_blocking(move || {
+                let _permit = permit;
+                let result = run_in_span(span, || run_simulation(&encounter, &options));
+                (!cut_off(&options, result.stats.iterations)).then_some(result)
+            })
+            .await
+            {
+                Ok(Some(result)) => Arc::new(result),
+                Ok(None) => return over_budget(&state.limits).into_response(),
+                Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), Vec::new()),
+            };
+            if let Some(key) = key {
+                state.cache.put(key, Arc::clone(&result));
+            }
+            (result, if request.cache { "miss" } else { "bypass" })
+        }
+    };
+    let cache_header = (HeaderName::from_static("x-simulation-cache"), cache_status);
+
+    match query.format {
+        Some(format) => {
+            let content_type = match format {
+                ReportFormat::Markdown => "text/markdown; charset=utf-8",
+                ReportFormat::Html => "text/html; charset=utf-8",
+            };
+            (
+                [(header::CONTENT_TYPE, content_type), cache_header],
+                report::render(format, &name, &result),
             )
-                .into_response();
+                .into_response()
+        }
+        None => (StatusCode::OK, [cache_header], Json(result.as_ref())).into_response(),
+    }
+}
+
+/// Most encounters one batch may hold
+const MAX_BATCH_ENCOUNTERS: usize = 20;
+
+/// Several encounters, simulated separately and then back to back
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct BatchRequest {
+    /// In the order they'd be fought; side 1 of each is the same party
+    pub encounters: Vec<EncounterInput>,
+    /// Iterations for each encounter and for the sequence (default: the first encounter's)
+    pub iterations: Option<u32>,
+    #[serde(flatten)]
+    pub params: SimulationParams,
+}
+
+#[utoipa::path(
+    post,
+    path = "/simulate/batch",
+    tag = "simulation",
+    request_body = BatchRequest,
+    responses(
+        (status = 200, description = "Stats for each encounter, plus the party's attrition fighting them in order", body = BatchResult),
+        (status = 400, description = "An encounter failed to parse or validate", body = ErrorResponse),
+        (status = 413, description = "Request exceeds the server's size or time limits", body = ErrorResponse),
+        (status = 429, description = "Server is already running as many simulations as it allows", body = ErrorResponse)
+    )
+)]
+async fn simulate_batch(State(state): State<SharedState>, Json(request): Json<BatchRequest>) -> Response {
+    if request.encounters.is_empty() || request.encounters.len() > MAX_BATCH_ENCOUNTERS {
+        return invalid_encounter(request_error(format!(
+            "a batch takes 1 to {} encounters, not {}",
+            MAX_BATCH_ENCOUNTERS,
+            request.encounters.len()
+        )));
+    }
+    if let Err(errors) = rounds_override(request.params.max_rounds) {
+        return invalid_encounter(errors);
+    }
+    let mut encounters = Vec::with_capacity(request.encounters.len());
+    let mut errors = Vec::new();
+    for (i, input) in request.encounters.iter().enumerate() {
+        match input.parse(&state.storage) {
+            Ok(mut encounter) => {
+                encounter.max_rounds = request.params.max_rounds.unwrap_or(encounter.max_rounds);
+                encounters.push(encounter);
+            }
+            Err(e) => errors.extend(e.into_iter().map(|e| ValidationError {
+                path: format!("encounters[{}]{}{}", i, if e.path.is_empty() { "" } else { "." }, e.path),
+                ..e
+            })),
         }
+    }
+    if !errors.is_empty() {
+        return invalid_encounter(errors);
+    }
+
+    let iterations = match iterations_override(request.iterations, &encounters[0]) {
+        Ok(n) => n,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    let options = SimulationOptions {
+        iterations,
+        ..simulation_options(&state.limits, &request.params, &encounters[0])
     };
+    if let Some(e) = encounters.iter().find_map(|encounter| state.limits.check(encounter, iterations, None).err()) {
+        return rejection(StatusCode::PAYLOAD_TOO_LARGE, e.code, e.message).into_response();
+    }
+    let permit = match admit(&state, &encounters[0], iterations, None) {
+        Ok(permit) => permit,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let span = simulation_span(&encounters[0], &options);
+    let result = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        (run_in_span(span, || sequence::run_batch(&encounters, &options)), options)
+    })
+    .await;
+    match result {
+        Ok((result, options))
+            if cut_off(&options, result.iterations)
+                || result.encounters.iter().any(|r| cut_off(&options, r.stats.iterations)) =>
+        {
+            over_budget(&state.limits).into_response()
+        }
+        Ok((result, _)) => Json(result).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), Vec::new()),
+    }
+}
+
+/// A campaign, either as text (YAML, JSON, or TOML) or as a JSON object. Exactly one must be
+/// given.
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct CampaignRequest {
+    pub campaign_yaml: Option<String>,
+    /// Format of `campaign_yaml` (detected from the text if omitted)
+    pub campaign_format: Option<SourceFormat>,
+    #[schema(value_type = Option<Object>)]
+    pub campaign: Option<serde_json::Value>,
+    /// Days to simulate (default: the campaign's, or its first encounter's)
+    pub iterations: Option<u32>,
+    #[serde(flatten)]
+    pub params: SimulationParams,
+}
+
+impl CampaignRequest {
+    fn parse(&self, storage: &Storage) -> Result<Campaign, Vec<ValidationError>> {
+        match (&self.campaign_yaml, &self.campaign) {
+            (Some(text), None) => parse_campaign_as(text, self.campaign_format, storage),
+            (None, Some(json)) => {
+                let doc = serde_yaml::to_value(json).map_err(|e| request_error(e.to_string()))?;
+                build_campaign(doc, None, storage)
+            }
+            (Some(_), Some(_)) => Err(request_error(
+                "Provide either campaign_yaml or campaign, not both".to_string(),
+            )),
+            (None, None) => Err(request_error("Missing campaign_yaml or campaign".to_string())),
+        }
+    }
+}
 
-    // Create RNG
-    let mut rng = match request.seed {
-        Some(seed) => ChaCha8Rng::seed_from_u64(seed),
-        None => ChaCha8Rng::from_entropy(),
+#[utoipa::path(
+    post,
+    path = "/campaign",
+    tag = "simulation",
+    request_body = CampaignRequest,
+    responses(
+        (status = 200, description = "Whole-day survival and the party's state after each fight", body = CampaignResult),
+        (status = 400, description = "The campaign or one of its encounters failed to parse or validate", body = ErrorResponse),
+        (status = 413, description = "Request exceeds the server's size or time limits", body = ErrorResponse),
+        (status = 429, description = "Server is already running as many simulations as it allows", body = ErrorResponse)
+    )
+)]
+async fn run_campaign(State(state): State<SharedState>, Json(request): Json<CampaignRequest>) -> Response {
+    let mut campaign = match request.parse(&state.storage) {
+        Ok(campaign) => campaign,
+        Err(errors) => return invalid_encounter(errors),
     };
+    if let Err(errors) = rounds_override(request.params.max_rounds) {
+        return invalid_encounter(errors);
+    }
+    let fights = campaign.encounters().count();
+    if fights > MAX_BATCH_ENCOUNTERS {
+        return invalid_encounter(request_error(format!(
+            "a campaign takes at most {} encounters, not {}",
+            MAX_BATCH_ENCOUNTERS, fights
+        )));
+    }
+    for step in &mut campaign.steps {
+        if let campaign::CampaignStep::Encounter(encounter) = step {
+            encounter.max_rounds = request.params.max_rounds.unwrap_or(encounter.max_rounds);
+        }
+    }
+
+    let first = campaign.encounters().next().cloned().expect("build_campaign requires an encounter");
+    let iterations = match iterations_override(request.iterations.or(campaign.iterations), &first) {
+        Ok(n) => n,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    let options = SimulationOptions {
+        iterations,
+        ..simulation_options(&state.limits, &request.params, &first)
+    };
+    if let Some(e) = campaign.encounters().find_map(|encounter| state.limits.check(encounter, iterations, None).err()) {
+        return rejection(StatusCode::PAYLOAD_TOO_LARGE, e.code, e.message).into_response();
+    }
+    let permit = match admit(&state, &first, iterations, None) {
+        Ok(permit) => permit,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let span = simulation_span(&first, &options);
+    let result = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        (run_in_span(span, || campaign::run_campaign(&campaign, &options)), options)
+    })
+    .await;
+    match result {
+        Ok((result, options)) if cut_off(&options, result.iterations) => over_budget(&state.limits).into_response(),
+        Ok((result, _)) => Json(result).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), Vec::new()),
+    }
+}
 
-    // Calculate totals for stats (using expected values for dice-based HP)
-    let side1_count = encounter.side1.len();
-    let side2_count = encounter.side2.len();
-    let side1_total_hp: i32 = encounter.side1.iter().map(|a| a.hp.expected_value() as i32).sum();
-    let side2_total_hp: i32 = encounter.side2.iter().map(|a| a.hp.expected_value() as i32).sum();
+/// Rows per chunk when streaming raw CSV
+const CSV_CHUNK_ROWS: u32 = 1000;
 
-    let mut collector = StatsCollector::new(side1_count, side2_count, side1_total_hp, side2_total_hp);
+/// Stream one CSV row per iteration, generated on a blocking thread as the client reads. Rows
+/// stop early if the run hits its deadline.
+fn stream_csv(encounter: Encounter, mut options: SimulationOptions, permit: OwnedSemaphorePermit) -> Response {
+    // Pick the seed up front so it can go in a header before any rows are sent
+    let seed = *options.seed.get_or_insert_with(rand::random);
+    // Rows only need each combat's outcome
+    options.event_detail = EventDetail::None;
+    let (tx, rx) = mpsc::channel::<String>(4);
+    let span = simulation_span(&encounter, &options);
 
-    // Run simulations
-    let iterations = encounter.iterations;
-    for _ in 0..iterations {
-        let mut sim = CombatSimulator::new(&encounter, 100, &mut rng);
-        let result = sim.run(&mut rng);
-        collector.add_result(result);
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let mut chunk = String::from(CSV_HEADER);
+        run_in_span(span, || {
+            for_each_combat(&encounter, &options, |i, result| {
+                chunk.push_str(&CombatSummary::from_result(result).csv_row(i));
+                if (i + 1) % CSV_CHUNK_ROWS != 0 {
+                    return true;
+                }
+                // Stop simulating if the client has gone away
+                tx.blocking_send(std::mem::take(&mut chunk)).is_ok()
+            })
+        });
+        if !chunk.is_empty() {
+            let _ = tx.blocking_send(chunk);
+        }
+    });
+
+    let stream = ReceiverStream::new(rx).map(Ok::<_, Infallible>);
+    (
+        [
+            (header::CONTENT_TYPE, "text/csv; charset=utf-8".to_string()),
+            (HeaderName::from_static("x-simulation-seed"), seed.to_string()),
+        ],
+        Body::from_stream(stream),
+    )
+        .into_response()
+}
+
+/// Server-sent events variant of `/simulate`: emits `progress` events every
+/// `progress_interval` iterations and a final `result` event with the full `SimulationResult`.
+#[utoipa::path(
+    post,
+    path = "/simulate/stream",
+    tag = "simulation",
+    request_body = SimulateRequest,
+    responses(
+        (status = 200, description = "`progress` events carrying a `Progress`, then one `result` event carrying a `SimulationResult`", content_type = "text/event-stream", body = Progress),
+        (status = 400, description = "Encounter failed to parse or validate", body = ErrorResponse),
+        (status = 413, description = "Request exceeds the server's size or time limits", body = ErrorResponse),
+        (status = 429, description = "Server is already running as many simulations as it allows", body = ErrorResponse)
+    )
+)]
+async fn simulate_stream(State(state): State<SharedState>, Json(request): Json<SimulateRequest>) -> Response {
+    let encounter = match request.encounter.parse(&state.storage) {
+        Ok(e) => e,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    if let Err(errors) = rounds_override(request.params.max_rounds) {
+        return invalid_encounter(errors);
     }
+    if request.analysis_mode == AnalysisMode::ExpectedValue {
+        // The average fight is a single pass with no progress to report; use /simulate
+        return invalid_encounter(request_error("analysis_mode expected_value is not streamed".to_string()));
+    }
+
+    let options = simulation_options(&state.limits, &request.params, &encounter);
+    let permit = match admit(&state, &encounter, options.iterations, request.params.max_rounds) {
+        Ok(permit) => permit,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let progress_interval = request.progress_interval.max(1);
+    let (tx, rx) = mpsc::channel::<Event>(16);
+    let span = simulation_span(&encounter, &options);
 
-    let stats = collector.compute_stats();
-    let sample_combats = collector.get_sample_combats(request.sample_count);
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let result = run_in_span(span, || {
+            run_simulation_with_progress(&encounter, &options, progress_interval, |progress| {
+                // Stop simulating if the client has gone away
+                tx.blocking_send(sse_event("progress", &progress)).is_ok()
+            })
+        });
+        let event = if cut_off(&options, result.stats.iterations) {
+            let (_, Json(error)) = over_budget(&state.limits);
+            sse_event("error", &error)
+        } else {
+            sse_event("result", &result)
+        };
+        let _ = tx.blocking_send(event);
+    });
+
+    let stream = ReceiverStream::new(rx).map(Ok::<_, Infallible>);
+    Sse::new(stream).keep_alive(KeepAlive::default()).into_response()
+}
 
-    let result = SimulationResult {
-        stats,
-        sample_combats,
+fn sse_event(name: &str, data: &impl Serialize) -> Event {
+    Event::default()
+        .event(name)
+        .json_data(data)
+        .unwrap_or_else(|e| Event::default().event("error").data(e.to_string()))
+}
+
+#[utoipa::path(
+    post,
+    path = "/replay",
+    tag = "simulation",
+    request_body = ReplayRequest,
+    responses(
+        (status = 200, description = "Full log of the requested combat, or its Foundry VTT chat messages with `format: foundry`", body = CombatLog),
+        (status = 400, description = "Encounter failed to parse or validate", body = ErrorResponse)
+    )
+)]
+async fn replay(State(state): State<SharedState>, Json(request): Json<ReplayRequest>) -> Response {
+    let encounter = match request.encounter.parse(&state.storage) {
+        Ok(e) => e,
+        Err(errors) => return invalid_encounter(errors),
     };
+    if let Err(errors) = rounds_override(request.max_rounds) {
+        return invalid_encounter(errors);
+    }
 
-    (StatusCode::OK, Json(result)).into_response()
+    let permit = match admit(&state, &encounter, 1, request.max_rounds) {
+        Ok(permit) => permit,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let max_rounds = request.max_rounds.unwrap_or(encounter.max_rounds);
+    let (seed, iteration) = (request.seed, request.iteration);
+    let result = match tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        replay_combat(&encounter, max_rounds, seed, iteration)
+    })
+    .await
+    {
+        Ok(result) => result,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), Vec::new()),
+    };
+
+    let log = format_combat_log(&result, request.iteration);
+    match request.format {
+        None => Json(log).into_response(),
+        Some(LogFormat::Foundry) => Json(export::to_foundry(&log)).into_response(),
+        Some(LogFormat::Roll20) => (
+            [(header::CONTENT_TYPE, "text/plain; charset=utf-8")],
+            export::to_roll20(&log),
+        )
+            .into_response(),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/debug",
+    tag = "simulation",
+    request_body = DebugRequest,
+    responses(
+        (status = 200, description = "Full log of one combat, with every APL entry each actor weighed and why it was chosen or passed over", body = DebugResponse),
+        (status = 400, description = "Encounter failed to parse or validate", body = ErrorResponse)
+    )
+)]
+async fn debug(State(state): State<SharedState>, Json(request): Json<DebugRequest>) -> Response {
+    let encounter = match request.encounter.parse(&state.storage) {
+        Ok(e) => e,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    if let Err(errors) = rounds_override(request.max_rounds) {
+        return invalid_encounter(errors);
+    }
+
+    let permit = match admit(&state, &encounter, 1, request.max_rounds) {
+        Ok(permit) => permit,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let max_rounds = request.max_rounds.unwrap_or(encounter.max_rounds);
+    let seed = request.seed.unwrap_or_else(rand::random);
+    let iteration = request.iteration;
+    let (result, choices) = match tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        debug_combat(&encounter, max_rounds, seed, iteration)
+    })
+    .await
+    {
+        Ok(debugged) => debugged,
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), Vec::new()),
+    };
+
+    let log = format_combat_log(&result, request.iteration);
+    Json(DebugResponse { seed, log, choices }).into_response()
+}
+
+#[utoipa::path(
+    post,
+    path = "/decide",
+    tag = "simulation",
+    request_body = DecideRequest,
+    responses(
+        (status = 200, description = "What each case's actor picked, whether it matched the case's expectations, and how each APL entry fared", body = DecisionReport),
+        (status = 400, description = "Encounter or cases failed to parse or validate", body = ErrorResponse)
+    )
+)]
+async fn decide(State(state): State<SharedState>, Json(request): Json<DecideRequest>) -> Response {
+    let encounter = match request.encounter.parse(&state.storage) {
+        Ok(e) => e,
+        Err(errors) => return invalid_encounter(errors),
+    };
+
+    let permit = match admit(&state, &encounter, 1, None) {
+        Ok(permit) => permit,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let result = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        decide::run_cases(&encounter, &request.cases, request.seed)
+    })
+    .await;
+    match result {
+        Ok(Ok(report)) => Json(report).into_response(),
+        Ok(Err(errors)) => invalid_encounter(errors),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), Vec::new()),
+    }
+}
+
+/// Search for the best APL for one actor
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct OptimizeRequest {
+    #[serde(flatten)]
+    pub encounter: EncounterInput,
+    /// Name of the actor whose APL is varied (every actor built from that entry)
+    pub actor: String,
+    #[serde(default)]
+    pub candidates: Vec<Candidate>,
+    /// Candidates generated from an APL template and parameter values
+    pub grid: Option<ParamGrid>,
+    /// Iterations per candidate; overrides the encounter's `iterations`
+    pub iterations: Option<u32>,
+    #[serde(flatten)]
+    pub params: SimulationParams,
+}
+
+#[utoipa::path(
+    post,
+    path = "/optimize",
+    tag = "simulation",
+    request_body = OptimizeRequest,
+    responses(
+        (status = 200, description = "Candidates ranked by the actor's side's win rate", body = OptimizeResult),
+        (status = 400, description = "Encounter, actor, or candidates failed to validate", body = ErrorResponse),
+        (status = 413, description = "Request exceeds the server's size or time limits", body = ErrorResponse),
+        (status = 429, description = "Server is already running as many simulations as it allows", body = ErrorResponse)
+    )
+)]
+async fn optimize(State(state): State<SharedState>, Json(request): Json<OptimizeRequest>) -> Response {
+    let encounter = match request.encounter.parse(&state.storage) {
+        Ok(e) => e,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    if let Err(errors) = rounds_override(request.params.max_rounds) {
+        return invalid_encounter(errors);
+    }
+
+    let mut candidates = request.candidates;
+    if let Some(grid) = &request.grid {
+        match grid.expand() {
+            Ok(expanded) => candidates.extend(expanded),
+            Err(errors) => return invalid_search(errors),
+        }
+    }
+    let iterations = match iterations_override(request.iterations, &encounter) {
+        Ok(n) => n,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    let options = SimulationOptions {
+        iterations,
+        ..simulation_options(&state.limits, &request.params, &encounter)
+    };
+    let permit = match admit(&state, &encounter, iterations, request.params.max_rounds) {
+        Ok(permit) => permit,
+        Err(rejection) => return rejection.into_response(),
+    };
+
+    let actor = request.actor;
+    let span = simulation_span(&encounter, &options);
+    let result = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let result = run_in_span(span, || run_optimize(&encounter, &actor, candidates, &options));
+        (result, options)
+    })
+    .await;
+    match result {
+        Ok((Ok(result), options)) if result.candidates.iter().any(|c| cut_off(&options, c.stats.iterations)) => {
+            over_budget(&state.limits).into_response()
+        }
+        Ok((Ok(result), _)) => Json(result).into_response(),
+        Ok((Err(errors), _)) => invalid_search(errors),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), Vec::new()),
+    }
+}
+
+fn invalid_search(errors: Vec<ValidationError>) -> Response {
+    error_response(StatusCode::BAD_REQUEST, "Invalid optimization request".to_string(), errors)
+}
+
+/// Re-run an encounter with each actor's stats nudged
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SensitivityRequest {
+    #[serde(flatten)]
+    pub encounter: EncounterInput,
+    /// Iterations per run; overrides the encounter's `iterations`
+    pub iterations: Option<u32>,
+    #[serde(flatten)]
+    pub params: SimulationParams,
+}
+
+#[utoipa::path(
+    post,
+    path = "/sensitivity",
+    tag = "simulation",
+    request_body = SensitivityRequest,
+    responses(
+        (status = 200, description = "Win-rate swing from each actor's AC, attack bonus, and HP", body = SensitivityResult),
+        (status = 400, description = "Encounter failed to parse or validate", body = ErrorResponse),
+        (status = 413, description = "Request exceeds the server's size or time limits", body = ErrorResponse),
+        (status = 429, description = "Server is already running as many simulations as it allows", body = ErrorResponse)
+    )
+)]
+async fn sensitivity(State(state): State<SharedState>, Json(request): Json<SensitivityRequest>) -> Response {
+    let encounter = match request.encounter.parse(&state.storage) {
+        Ok(e) => e,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    if let Err(errors) = rounds_override(request.params.max_rounds) {
+        return invalid_encounter(errors);
+    }
+
+    let iterations = match iterations_override(request.iterations, &encounter) {
+        Ok(n) => n,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    let options = SimulationOptions {
+        iterations,
+        ..simulation_options(&state.limits, &request.params, &encounter)
+    };
+    let permit = match admit(&state, &encounter, iterations, request.params.max_rounds) {
+        Ok(permit) => permit,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let span = simulation_span(&encounter, &options);
+    let result = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        (run_in_span(span, || sensitivity::analyze(&encounter, &options)), options)
+    })
+    .await;
+    match result {
+        Ok((result, options)) if cut_off(&options, result.iterations) => over_budget(&state.limits).into_response(),
+        Ok((result, _)) => Json(result).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), Vec::new()),
+    }
+}
+
+/// Rate an encounter's difficulty for side 1
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RateRequest {
+    #[serde(flatten)]
+    pub encounter: EncounterInput,
+    /// Iterations behind the rating (default 2000, whatever the encounter's `iterations`)
+    pub iterations: Option<u32>,
+    #[serde(flatten)]
+    pub params: SimulationParams,
+}
+
+#[utoipa::path(
+    post,
+    path = "/rate",
+    tag = "simulation",
+    request_body = RateRequest,
+    responses(
+        (status = 200, description = "Difficulty for side 1 (trivial to deadly) with a short justification", body = Rating),
+        (status = 400, description = "Encounter failed to parse or validate", body = ErrorResponse),
+        (status = 413, description = "Request exceeds the server's size or time limits", body = ErrorResponse),
+        (status = 429, description = "Server is already running as many simulations as it allows", body = ErrorResponse)
+    )
+)]
+async fn rate(State(state): State<SharedState>, Json(request): Json<RateRequest>) -> Response {
+    let encounter = match request.encounter.parse(&state.storage) {
+        Ok(e) => e,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    if let Err(errors) = rounds_override(request.params.max_rounds) {
+        return invalid_encounter(errors);
+    }
+
+    let iterations = match iterations_override(Some(request.iterations.unwrap_or(RATING_ITERATIONS)), &encounter) {
+        Ok(n) => n,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    let options = SimulationOptions {
+        iterations,
+        sample_count: 0,
+        target_precision: None,
+        ..simulation_options(&state.limits, &request.params, &encounter)
+    };
+    let permit = match admit(&state, &encounter, iterations, request.params.max_rounds) {
+        Ok(permit) => permit,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let span = simulation_span(&encounter, &options);
+    let result = tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        (run_in_span(span, || rating::rate(&encounter, &options)), options)
+    })
+    .await;
+    match result {
+        Ok((rating, options)) if cut_off(&options, rating.iterations) => over_budget(&state.limits).into_response(),
+        Ok((rating, _)) => Json(rating).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), Vec::new()),
+    }
+}
+
+/// How long `/interactive` waits for the client's turn before hanging up
+const INTERACTIVE_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+/// Largest message accepted from an `/interactive` client
+const MAX_INTERACTIVE_MESSAGE: usize = 1 << 20;
+
+/// Opening message on `/interactive`
+#[derive(Debug, Deserialize)]
+struct InteractiveStart {
+    #[serde(flatten)]
+    encounter: EncounterInput,
+    /// The side the client plays; the other side runs on its APLs
+    side: Side,
+    seed: Option<u64>,
+    /// Overrides the encounter's `max_rounds`
+    max_rounds: Option<u32>,
+}
+
+/// Messages sent to the client on `/interactive`
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum InteractiveMessage {
+    /// One of the client's actors is up: answer with an APL entry or a list of them
+    Decision {
+        round: u32,
+        actor_id: usize,
+        actor: String,
+        phase: Option<Phase>,
+        /// What happened since the previous decision
+        events: Vec<CombatLogEntry>,
+        actors: Vec<ActorView>,
+    },
+    /// The opening message or a turn was rejected; a rejected turn is asked for again
+    Error(ErrorResponse),
+    /// The fight is over
+    Result(CombatLog),
+}
+
+impl InteractiveMessage {
+    fn decision(decision: &Decision) -> Self {
+        let name = |id: usize| decision.actors.iter().find(|a| a.id == id).map(|a| a.name.as_str());
+        InteractiveMessage::Decision {
+            round: decision.round,
+            actor_id: decision.actor_id,
+            actor: name(decision.actor_id).unwrap_or_default().to_string(),
+            phase: decision.phase,
+            events: format_events(&decision.events, name),
+            actors: decision.actors.clone(),
+        }
+    }
+
+    fn error(error: &str, details: Vec<ValidationError>) -> Self {
+        InteractiveMessage::Error(ErrorResponse {
+            error: error.to_string(),
+            code: Some(ErrorCode::ValidationFailed),
+            details,
+        })
+    }
+}
+
+/// Play one side of a combat over a WebSocket. The client opens with the encounter and its
+/// side, then picks each of its actors' turns as APL entries.
+async fn interactive(State(state): State<SharedState>, upgrade: WebSocketUpgrade) -> Response {
+    let Ok(permit) = Arc::clone(&state.sessions).try_acquire_owned() else {
+        tracing::warn!("session refused: server busy");
+        return rejection(
+            StatusCode::TOO_MANY_REQUESTS,
+            ErrorCode::ServerBusy,
+            "Too many interactive sessions open; try again shortly".to_string(),
+        )
+        .into_response();
+    };
+    // The session outlives the upgrade request, but its logs stay under the request's id
+    let span = Span::current();
+    upgrade
+        .max_message_size(MAX_INTERACTIVE_MESSAGE)
+        .on_upgrade(move |socket| interactive_session(socket, state, permit).instrument(span))
+}
+
+/// `permit` holds the session's slot until the combat's thread finishes, which may be after
+/// the client has gone
+async fn interactive_session(mut socket: WebSocket, state: SharedState, permit: OwnedSemaphorePermit) {
+    let start = match recv_text(&mut socket).await.map(|text| serde_json::from_str::<InteractiveStart>(&text)) {
+        Some(Ok(start)) => start,
+        Some(Err(e)) => return reject(socket, request_error(e.to_string())).await,
+        None => return,
+    };
+    let encounter = match start.encounter.parse(&state.storage) {
+        Ok(e) => e,
+        Err(errors) => return reject(socket, errors).await,
+    };
+    if let Err(errors) = rounds_override(start.max_rounds) {
+        return reject(socket, errors).await;
+    }
+    // The encounter must still fit the server's limits
+    if let Err(e) = state.limits.check(&encounter, 1, start.max_rounds) {
+        return reject(socket, vec![e]).await;
+    }
+
+    let (to_client, mut from_sim) = mpsc::channel::<InteractiveMessage>(1);
+    let (to_sim, mut turns) = mpsc::channel::<Vec<AplEntry>>(1);
+    let decisions = to_client.clone();
+    let controller: Controller = Box::new(move |decision| {
+        // Once the client has gone, the rest of the fight runs on the APLs
+        if decisions.blocking_send(InteractiveMessage::decision(decision)).is_err() {
+            return Vec::new();
+        }
+        turns.blocking_recv().unwrap_or_default()
+    });
+    let seed = start.seed.unwrap_or_else(rand::random);
+    let max_rounds = start.max_rounds.unwrap_or(encounter.max_rounds);
+    tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let result = play_combat(&encounter, max_rounds, seed, start.side, controller);
+        let _ = to_client.blocking_send(InteractiveMessage::Result(format_combat_log(&result, 0)));
+    });
+
+    while let Some(message) = from_sim.recv().await {
+        let awaits_turn = matches!(message, InteractiveMessage::Decision { .. });
+        if send_message(&mut socket, &message).await.is_err() {
+            return;
+        }
+        if !awaits_turn {
+            break;
+        }
+        let apl = loop {
+            let text = match tokio::time::timeout(INTERACTIVE_IDLE_TIMEOUT, recv_text(&mut socket)).await {
+                Ok(Some(text)) => text,
+                _ => return,
+            };
+            match parse_turn(&text) {
+                Ok(apl) => break apl,
+                Err(errors) => {
+                    let message = InteractiveMessage::error("Invalid turn", errors);
+                    if send_message(&mut socket, &message).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        };
+        if to_sim.send(apl).await.is_err() {
+            return;
+        }
+    }
+    let _ = socket.close().await;
+}
+
+/// A turn from the client: one APL entry or a list of them (empty for the default APL)
+fn parse_turn(text: &str) -> Result<Vec<AplEntry>, Vec<ValidationError>> {
+    let value: serde_json::Value = serde_json::from_str(text).map_err(|e| request_error(e.to_string()))?;
+    let apl = if value.is_array() {
+        serde_json::from_value(value)
+    } else {
+        serde_json::from_value(value).map(|entry| vec![entry])
+    }
+    .map_err(|e| request_error(e.to_string()))?;
+
+    let errors = apl::validate_apl(&apl);
+    if errors.is_empty() {
+        Ok(apl)
+    } else {
+        Err(errors)
+    }
+}
+
+/// The client's next text message, or None once it closes the connection or sends binary
+async fn recv_text(socket: &mut WebSocket) -> Option<String> {
+    loop {
+        match socket.recv().await? {
+            Ok(Message::Text(text)) => return Some(text),
+            // axum answers pings itself
+            Ok(Message::Ping(_) | Message::Pong(_)) => continue,
+            Ok(Message::Binary(_) | Message::Close(_)) | Err(_) => return None,
+        }
+    }
+}
+
+async fn send_message(socket: &mut WebSocket, message: &InteractiveMessage) -> Result<(), axum::Error> {
+    let text = serde_json::to_string(message).map_err(axum::Error::new)?;
+    socket.send(Message::Text(text)).await
+}
+
+async fn reject(mut socket: WebSocket, errors: Vec<ValidationError>) {
+    let _ = send_message(&mut socket, &InteractiveMessage::error("Invalid encounter", errors)).await;
+    let _ = socket.close().await;
+}
+
+fn error_response(status: StatusCode, error: String, details: Vec<ValidationError>) -> Response {
+    let code = (!details.is_empty()).then_some(ErrorCode::ValidationFailed);
+    (status, Json(ErrorResponse { error, code, details })).into_response()
+}
+
+/// An `iterations` override, held to the same bounds as an encounter's own
+fn iterations_override(iterations: Option<u32>, encounter: &Encounter) -> Result<u32, Vec<ValidationError>> {
+    match iterations {
+        Some(0) => Err(vec![ValidationError::new("iterations", ErrorCode::OutOfRange, "iterations must be at least 1")]),
+        Some(n) if n > apl::MAX_ITERATIONS => Err(vec![ValidationError::new(
+            "iterations",
+            ErrorCode::TooManyIterations,
+            format!("iterations must be at most {}", apl::MAX_ITERATIONS),
+        )]),
+        Some(n) => Ok(n),
+        None => Ok(encounter.iterations),
+    }
+}
+
+/// A `max_rounds` override, held to the same bounds as an encounter's own
+fn rounds_override(max_rounds: Option<u32>) -> Result<(), Vec<ValidationError>> {
+    match max_rounds.and_then(apl::validate_max_rounds) {
+        Some(error) => Err(vec![error]),
+        None => Ok(()),
+    }
+}
+
+fn storage_error(e: StorageError) -> Response {
+    let status = match e {
+        StorageError::AlreadyExists(_) => StatusCode::CONFLICT,
+        StorageError::NotFound(_) => StatusCode::NOT_FOUND,
+        StorageError::Database(_) | StorageError::Serialization(_) => StatusCode::INTERNAL_SERVER_ERROR,
+    };
+    error_response(status, e.to_string(), Vec::new())
+}
+
+/// 400 response for a library monster whose APL doesn't validate, if any
+fn invalid_monster(template: &ActorTemplate) -> Option<Response> {
+    let errors = apl::validate_template(template);
+    (!errors.is_empty()).then(|| {
+        error_response(
+            StatusCode::BAD_REQUEST,
+            format!("Invalid monster '{}'", template.name),
+            errors,
+        )
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/monsters",
+    tag = "monsters",
+    responses((status = 200, description = "All saved monsters", body = Vec<ActorTemplate>))
+)]
+async fn list_monsters(State(state): State<SharedState>) -> Response {
+    match state.storage.list_monsters() {
+        Ok(monsters) => Json(monsters).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/builtins",
+    tag = "monsters",
+    responses((status = 200, description = "Built-in characters for `ref: builtin/...`", body = Vec<Builtin>))
+)]
+async fn list_builtins() -> impl IntoResponse {
+    Json(builtins::catalog())
+}
+
+#[utoipa::path(
+    get,
+    path = "/monsters/{name}",
+    tag = "monsters",
+    params(("name" = String, Path, description = "Monster name (case-insensitive)")),
+    responses(
+        (status = 200, body = ActorTemplate),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+async fn get_monster(State(state): State<SharedState>, Path(name): Path<String>) -> Response {
+    match state.storage.get_monster(&name) {
+        Ok(Some(monster)) => Json(monster).into_response(),
+        Ok(None) => storage_error(StorageError::NotFound(name)),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/monsters",
+    tag = "monsters",
+    request_body = ActorTemplate,
+    responses(
+        (status = 201, body = ActorTemplate),
+        (status = 400, description = "Invalid APL", body = ErrorResponse),
+        (status = 409, description = "Name already taken", body = ErrorResponse)
+    )
+)]
+async fn create_monster(State(state): State<SharedState>, Json(template): Json<ActorTemplate>) -> Response {
+    if let Some(response) = invalid_monster(&template) {
+        return response;
+    }
+    match state.storage.create_monster(&template) {
+        Ok(()) => (StatusCode::CREATED, Json(template)).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Create or replace a monster; the name in the path wins over the one in the body
+#[utoipa::path(
+    put,
+    path = "/monsters/{name}",
+    tag = "monsters",
+    params(("name" = String, Path, description = "Monster name (case-insensitive)")),
+    request_body = ActorTemplate,
+    responses(
+        (status = 200, description = "Replaced", body = ActorTemplate),
+        (status = 201, description = "Created", body = ActorTemplate),
+        (status = 400, description = "Invalid APL", body = ErrorResponse)
+    )
+)]
+async fn put_monster(
+    State(state): State<SharedState>,
+    Path(name): Path<String>,
+    Json(mut template): Json<ActorTemplate>,
+) -> Response {
+    template.name = name;
+    if let Some(response) = invalid_monster(&template) {
+        return response;
+    }
+    match state.storage.put_monster(&template) {
+        Ok(true) => Json(template).into_response(),
+        Ok(false) => (StatusCode::CREATED, Json(template)).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/monsters/{name}",
+    tag = "monsters",
+    params(("name" = String, Path, description = "Monster name (case-insensitive)")),
+    responses(
+        (status = 204, description = "Deleted"),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+async fn delete_monster(State(state): State<SharedState>, Path(name): Path<String>) -> Response {
+    match state.storage.delete_monster(&name) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct ImportRequest {
+    /// A monster in Open5e or 5e.tools JSON
+    #[schema(value_type = Object)]
+    pub statblock: serde_json::Value,
+}
+
+/// Convert a 5e statblock into an actor template (not saved; `PUT /monsters/{name}` it to keep it)
+#[utoipa::path(
+    post,
+    path = "/import/5e",
+    tag = "monsters",
+    request_body = ImportRequest,
+    responses(
+        (status = 200, description = "The converted template, with notes on what was approximated", body = Imported),
+        (status = 400, description = "The statblock couldn't be converted", body = ErrorResponse)
+    )
+)]
+async fn import_5e_statblock(Json(request): Json<ImportRequest>) -> Response {
+    imported_response(import_5e(&request.statblock))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct OseImportRequest {
+    /// A one-line statblock, e.g. `Wolf: AC 7 [12], HD 2+2, Att 1 × bite (1d6), THAC0 17 [+2], MV 180' (60')`
+    pub statblock: String,
+    /// The monster's name, if the statblock doesn't start with it
+    pub name: Option<String>,
+}
+
+/// Convert an OSE or B/X statblock into an actor template (not saved)
+#[utoipa::path(
+    post,
+    path = "/import/ose",
+    tag = "monsters",
+    request_body = OseImportRequest,
+    responses(
+        (status = 200, description = "The converted template, with notes on what was approximated", body = Imported),
+        (status = 400, description = "The statblock couldn't be converted", body = ErrorResponse)
+    )
+)]
+async fn import_ose_statblock(Json(request): Json<OseImportRequest>) -> Response {
+    imported_response(import_ose(&request.statblock, request.name.as_deref()))
+}
+
+fn imported_response(imported: Result<Imported, String>) -> Response {
+    match imported {
+        Ok(imported) => invalid_monster(&imported.template).unwrap_or_else(|| Json(imported).into_response()),
+        Err(message) => invalid_encounter(vec![ValidationError::new("", ErrorCode::ParseError, message)]),
+    }
+}
+
+/// Validate an encounter before saving it; returns its name (or the requested override)
+fn saved_encounter_name(
+    request: &SaveEncounterRequest,
+    storage: &Storage,
+) -> Result<String, Vec<ValidationError>> {
+    let encounter = request.encounter.parse(storage)?;
+    Ok(request
+        .name
+        .clone()
+        .or(encounter.name)
+        .unwrap_or_else(|| "Untitled encounter".to_string()))
+}
+
+fn load_encounter(storage: &Storage, id: i64) -> Result<StoredEncounter, StorageError> {
+    storage
+        .get_encounter(id)?
+        .ok_or_else(|| StorageError::NotFound(format!("encounter {}", id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/encounters",
+    tag = "encounters",
+    responses((status = 200, description = "All saved encounters", body = Vec<StoredEncounter>))
+)]
+async fn list_encounters(State(state): State<SharedState>) -> Response {
+    match state.storage.list_encounters() {
+        Ok(encounters) => Json(encounters).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[utoipa::path(
+    post,
+    path = "/encounters",
+    tag = "encounters",
+    request_body = SaveEncounterRequest,
+    responses(
+        (status = 201, body = StoredEncounter),
+        (status = 400, description = "Encounter failed to parse or validate", body = ErrorResponse)
+    )
+)]
+async fn create_encounter(
+    State(state): State<SharedState>,
+    Json(request): Json<SaveEncounterRequest>,
+) -> Response {
+    let name = match saved_encounter_name(&request, &state.storage) {
+        Ok(name) => name,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    match state.storage.create_encounter(&name, &request.encounter.to_yaml()) {
+        Ok(saved) => (StatusCode::CREATED, Json(saved)).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/encounters/{id}",
+    tag = "encounters",
+    params(("id" = i64, Path, description = "Saved encounter id")),
+    responses(
+        (status = 200, body = StoredEncounter),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+async fn get_encounter(State(state): State<SharedState>, Path(id): Path<i64>) -> Response {
+    match load_encounter(&state.storage, id) {
+        Ok(encounter) => Json(encounter).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[utoipa::path(
+    put,
+    path = "/encounters/{id}",
+    tag = "encounters",
+    params(("id" = i64, Path, description = "Saved encounter id")),
+    request_body = SaveEncounterRequest,
+    responses(
+        (status = 200, body = StoredEncounter),
+        (status = 400, description = "Encounter failed to parse or validate", body = ErrorResponse),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+async fn update_encounter(
+    State(state): State<SharedState>,
+    Path(id): Path<i64>,
+    Json(request): Json<SaveEncounterRequest>,
+) -> Response {
+    let name = match saved_encounter_name(&request, &state.storage) {
+        Ok(name) => name,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    match state.storage.update_encounter(id, &name, &request.encounter.to_yaml()) {
+        Ok(saved) => Json(saved).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[utoipa::path(
+    delete,
+    path = "/encounters/{id}",
+    tag = "encounters",
+    params(("id" = i64, Path, description = "Saved encounter id")),
+    responses(
+        (status = 204, description = "Deleted along with its results"),
+        (status = 404, body = ErrorResponse)
+    )
+)]
+async fn delete_encounter(State(state): State<SharedState>, Path(id): Path<i64>) -> Response {
+    match state.storage.delete_encounter(id) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Run a saved encounter (as it currently stands) and store the result as its latest
+#[utoipa::path(
+    post,
+    path = "/encounters/{id}/simulate",
+    tag = "encounters",
+    params(("id" = i64, Path, description = "Saved encounter id")),
+    request_body = SimulationParams,
+    responses(
+        (status = 200, body = StoredResult),
+        (status = 400, description = "Saved encounter no longer validates", body = ErrorResponse),
+        (status = 404, body = ErrorResponse),
+        (status = 413, description = "Request exceeds the server's size or time limits", body = ErrorResponse),
+        (status = 429, description = "Server is already running as many simulations as it allows", body = ErrorResponse)
+    )
+)]
+async fn simulate_saved_encounter(
+    State(state): State<SharedState>,
+    Path(id): Path<i64>,
+    Json(params): Json<SimulationParams>,
+) -> Response {
+    let saved = match load_encounter(&state.storage, id) {
+        Ok(saved) => saved,
+        Err(e) => return storage_error(e),
+    };
+    let encounter = match parse_encounter(&saved.encounter_yaml, &state.storage) {
+        Ok(e) => e,
+        Err(errors) => return invalid_encounter(errors),
+    };
+    if let Err(errors) = rounds_override(params.max_rounds) {
+        return invalid_encounter(errors);
+    }
+
+    let options = simulation_options(&state.limits, &params, &encounter);
+    let permit = match admit(&state, &encounter, options.iterations, params.max_rounds) {
+        Ok(permit) => permit,
+        Err(rejection) => return rejection.into_response(),
+    };
+    let span = simulation_span(&encounter, &options);
+    let result = match tokio::task::spawn_blocking(move || {
+        let _permit = permit;
+        let result = run_in_span(span, || run_simulation(&encounter, &options));
+        (!cut_off(&options, result.stats.iterations)).then_some(result)
+    })
+    .await
+    {
+        Ok(Some(result)) => result,
+        Ok(None) => return over_budget(&state.limits).into_response(),
+        Err(e) => return error_response(StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), Vec::new()),
+    };
+
+    match state.storage.save_result(id, &result) {
+        Ok(stored) => Json(stored).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/encounters/{id}/results",
+    tag = "encounters",
+    params(("id" = i64, Path, description = "Saved encounter id")),
+    responses(
+        (status = 200, body = StoredResult),
+        (status = 404, description = "Unknown encounter, or it has never been simulated", body = ErrorResponse)
+    )
+)]
+async fn get_encounter_results(State(state): State<SharedState>, Path(id): Path<i64>) -> Response {
+    if let Err(e) = load_encounter(&state.storage, id) {
+        return storage_error(e);
+    }
+    match state.storage.latest_result(id) {
+        Ok(Some(stored)) => Json(stored).into_response(),
+        Ok(None) => storage_error(StorageError::NotFound(format!("results for encounter {}", id))),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body};
+    use axum::http::{header, Request};
+    use tower::ServiceExt;
+
+    /// POST `body` to `/rate` on a fresh server, returning the status and the JSON it answered
+    async fn rate_with(body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+        let state = AppState::new(Storage::open_in_memory().unwrap(), Limits::default(), ResultCache::new(1));
+        let router = create_router(Arc::new(state), None, Access::new(Vec::new(), 0, 1));
+        let request = Request::post("/rate")
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn iterations_overrides_are_held_to_the_encounters_bounds() {
+        let encounter = "
+side1: [ { name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6 } ]
+side2: [ { name: B, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6 } ]
+";
+        for (iterations, code) in [(0, "out_of_range"), (apl::MAX_ITERATIONS + 1, "too_many_iterations")] {
+            let (status, body) = rate_with(serde_json::json!({ "encounter_yaml": encounter, "iterations": iterations })).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", iterations);
+            assert_eq!(body["details"][0]["path"], "iterations", "{}", iterations);
+            assert_eq!(body["details"][0]["code"], code, "{}", iterations);
+        }
+        let (status, _) = rate_with(serde_json::json!({ "encounter_yaml": encounter, "iterations": 1 })).await;
+        assert_eq!(status, StatusCode::OK);
+    }
 }
@@ -1,73 +1,178 @@
-use crate::types::{Actor, AplEntry, Side, Zone};
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::dice::MAX_DAMAGE_CONSTANT;
+use crate::effects::EffectKind;
+use crate::script;
+use crate::traits;
+use crate::types::{parse_damage_dice, Actor, ActorTemplate, AplEntry, ConditionKind, Encounter, HitLocation, Objective, HpValue, Light, OnHit, Phase, Side, Slot, StartingZone, MAX_FACTIONS, Trigger, Zone, ZoneLayout};
 
 #[derive(Debug, Clone)]
 pub enum MoveAction {
     Move { direction: MoveDirection },
+    /// Deliberately stay put (claimed by `hold` and `defend`)
+    Hold,
+    /// Run for the side's rear, leaving the fight from there if it's an exit
+    Flee,
+    /// Back toward the side's rear at half speed without provoking opportunity attacks
+    Withdraw,
     None,
 }
 
 #[derive(Debug, Clone)]
 pub enum AttackAction {
-    Attack { target_id: usize },
+    /// Attack with these riders, aimed at a hit location if `called_shot` is set
+    Attack { target_id: usize, on_hit: Vec<OnHit>, called_shot: Option<HitLocation> },
     Guard,
+    /// Full defensive posture: bigger AC bonus, no movement
+    Defend,
+    /// Attacks against the actor have disadvantage until its next turn
+    Dodge,
+    /// Deliberately do nothing
+    Hold,
+    Buff { target_id: usize, amount: HpValue, duration: Option<u32> },
+    /// Contested roll to lock an adjacent enemy (and the grappler) in place
+    Grapple { target_id: usize },
+    /// Contested roll to break free of a grapple
+    Escape,
+    /// Spend the slot on another `speed` worth of movement
+    Dash { direction: MoveDirection },
+    /// Call up creatures from the actor's `index`th `summons` entry: `amount` of them (one
+    /// if None), as many as its limit still allows
+    Summon { index: usize, amount: Option<HpValue> },
     None,
 }
 
 #[derive(Debug, Clone)]
 pub struct TurnActions {
     pub move_action: MoveAction,
+    /// The action, or attack slot
     pub attack_action: AttackAction,
+    pub bonus_action: AttackAction,
+    pub free_interaction: AttackAction,
+}
+
+impl TurnActions {
+    /// The action picked for `slot`
+    pub fn slot_mut(&mut self, slot: Slot) -> &mut AttackAction {
+        match slot {
+            Slot::Action => &mut self.attack_action,
+            Slot::BonusAction => &mut self.bonus_action,
+            Slot::FreeInteraction => &mut self.free_interaction,
+        }
+    }
+
+    /// Take the action picked for `slot`, leaving None in its place
+    pub fn take(&mut self, slot: Slot) -> AttackAction {
+        std::mem::replace(self.slot_mut(slot), AttackAction::None)
+    }
+}
+
+/// What an actor still has to spend this turn. APL entries only fill open slots, and moves
+/// flagged `after_action` are only picked once the action is spent.
+#[derive(Debug, Clone, Copy)]
+pub struct TurnBudget {
+    /// Movement points left (the actor's speed at the start of the turn)
+    pub movement: u32,
+    pub action: bool,
+    pub bonus_action: bool,
+    pub free_interaction: bool,
+}
+
+impl TurnBudget {
+    /// Everything the actor gets at the start of its turn
+    pub fn full(actor: &Actor) -> Self {
+        TurnBudget { movement: actor.speed, action: true, bonus_action: true, free_interaction: true }
+    }
+
+    pub fn has(&self, slot: Slot) -> bool {
+        match slot {
+            Slot::Action => self.action,
+            Slot::BonusAction => self.bonus_action,
+            Slot::FreeInteraction => self.free_interaction,
+        }
+    }
+
+    pub fn spend(&mut self, slot: Slot) {
+        match slot {
+            Slot::Action => self.action = false,
+            Slot::BonusAction => self.bonus_action = false,
+            Slot::FreeInteraction => self.free_interaction = false,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum MoveDirection {
     Toward(usize),
-    ToZone(Zone),
     Forward,
     Backward,
+    /// Toward the enemy's rear, detouring around blocked zones (e.g. through a side lane)
+    Flank,
 }
 
 pub struct AplContext<'a> {
     pub actor: &'a Actor,
     pub actors: &'a [Actor],
+    pub zones: &'a ZoneLayout,
+    pub round: u32,
 }
 
 impl<'a> AplContext<'a> {
+    /// Living actors on the sides this one fights
     pub fn enemies(&self) -> impl Iterator<Item = &Actor> {
         self.actors
             .iter()
-            .filter(|a| a.side != self.actor.side && a.is_alive())
+            .filter(|a| self.actor.is_hostile_to(a) && a.is_alive() && !a.noncombatant)
     }
 
+    /// Living noncombatants on the sides it fights, which only `target: noncombatant` picks out
+    pub fn noncombatants(&self) -> impl Iterator<Item = &Actor> {
+        self.actors
+            .iter()
+            .filter(|a| self.actor.is_hostile_to(a) && a.is_alive() && a.noncombatant)
+    }
+
+    /// Who a target selector chooses among: noncombatants for `noncombatant`, enemies otherwise
+    fn candidates(&self, target_str: &str) -> Vec<&Actor> {
+        if target_str.trim().eq_ignore_ascii_case("noncombatant") {
+            self.noncombatants().collect()
+        } else {
+            self.enemies().collect()
+        }
+    }
+
+    /// Other living actors on its side, or on a side that neither fights nor is fought by it
     pub fn allies(&self) -> impl Iterator<Item = &Actor> {
         self.actors
             .iter()
-            .filter(|a| a.side == self.actor.side && a.is_alive() && a.id != self.actor.id)
+            .filter(|a| self.actor.is_allied_with(a) && a.is_alive() && a.id != self.actor.id)
     }
 
-    pub fn nearest_enemy(&self) -> Option<&Actor> {
-        self.enemies()
-            .min_by_key(|e| self.actor.zone.distance_to(&e.zone))
+    /// The nearest of the actor's own living VIPs, other than itself
+    pub fn ally_vip(&self) -> Option<&Actor> {
+        self.allies()
+            .filter(|a| a.vip)
+            .min_by_key(|a| self.zones.distance(self.actor.zone, a.zone))
     }
 
-    pub fn lowest_hp_enemy(&self) -> Option<&Actor> {
-        self.enemies().min_by_key(|e| e.current_hp)
+    pub fn lowest_hp_ally(&self) -> Option<&Actor> {
+        self.allies().min_by_key(|a| a.current_hp)
     }
 
-    pub fn random_enemy(&self, rng: &mut impl rand::Rng) -> Option<&Actor> {
-        let enemies: Vec<_> = self.enemies().collect();
-        if enemies.is_empty() {
+    pub fn random_ally(&self, rng: &mut impl rand::Rng) -> Option<&Actor> {
+        let allies: Vec<_> = self.allies().collect();
+        if allies.is_empty() {
             None
         } else {
-            Some(enemies[rng.gen_range(0..enemies.len())])
+            Some(allies[rng.gen_range(0..allies.len())])
         }
     }
 
     pub fn enemies_in_range(&self) -> impl Iterator<Item = &Actor> {
-        let actor_zone = self.actor.zone;
-        let actor_range = self.actor.range;
-        self.enemies()
-            .filter(move |e| actor_range.can_hit_at_distance(actor_zone.distance_to(&e.zone)))
+        self.enemies().filter(move |e| self.actor.can_attack(e, self.zones, self.actors))
     }
 
     pub fn has_enemy_in_range(&self) -> bool {
@@ -75,64 +180,369 @@ impl<'a> AplContext<'a> {
     }
 }
 
-pub fn evaluate_condition(condition: &str, ctx: &AplContext) -> bool {
+/// A parsed APL condition, e.g. `nearest_enemy.distance <= 1 and self.hp_percent < 25`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Condition {
+    Literal(bool),
+    /// Named boolean variable such as `enemy.in_range`
+    Flag(String),
+    Not(Box<Condition>),
+    And(Vec<Condition>),
+    Or(Vec<Condition>),
+    Compare {
+        lhs: Operand,
+        op: CompareOp,
+        rhs: Operand,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Operand {
+    Number(f64),
+    Variable(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl CompareOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            CompareOp::Lt => "<",
+            CompareOp::Le => "<=",
+            CompareOp::Gt => ">",
+            CompareOp::Ge => ">=",
+            CompareOp::Eq => "==",
+            CompareOp::Ne => "!=",
+        }
+    }
+
+    fn apply(&self, lhs: f64, rhs: f64) -> bool {
+        match self {
+            CompareOp::Lt => lhs < rhs,
+            CompareOp::Le => lhs <= rhs,
+            CompareOp::Gt => lhs > rhs,
+            CompareOp::Ge => lhs >= rhs,
+            CompareOp::Eq => lhs == rhs,
+            CompareOp::Ne => lhs != rhs,
+        }
+    }
+}
+
+/// Parse a condition string. `or` binds looser than `and`; `not`/`!` negates a single term.
+pub fn parse_condition(condition: &str) -> Result<Condition, String> {
     let condition = condition.trim().to_lowercase();
+    parse_or(&condition)
+}
 
-    match condition.as_str() {
-        "true" | "" => true,
-        "false" => false,
-        "enemy.in_range" | "enemy_in_range" => ctx.has_enemy_in_range(),
-        "!enemy.in_range" | "!enemy_in_range" | "not enemy.in_range" => !ctx.has_enemy_in_range(),
-        _ => {
-            // Handle comparisons like target.health_percent < 20
-            if condition.contains('<') {
-                let parts: Vec<&str> = condition.split('<').collect();
-                if parts.len() == 2 {
-                    let lhs = parts[0].trim();
-                    let rhs = parts[1].trim().parse::<f64>().unwrap_or(0.0);
-                    if let Some(lhs_val) = evaluate_numeric(lhs, ctx) {
-                        return lhs_val < rhs;
-                    }
-                }
-            } else if condition.contains('>') {
-                let parts: Vec<&str> = condition.split('>').collect();
-                if parts.len() == 2 {
-                    let lhs = parts[0].trim();
-                    let rhs = parts[1].trim().parse::<f64>().unwrap_or(0.0);
-                    if let Some(lhs_val) = evaluate_numeric(lhs, ctx) {
-                        return lhs_val > rhs;
-                    }
-                }
+fn split_keyword<'s>(expr: &'s str, keywords: &[&str]) -> Vec<&'s str> {
+    let mut parts = vec![expr];
+    for keyword in keywords {
+        parts = parts.into_iter().flat_map(|p| p.split(keyword)).collect();
+    }
+    parts
+}
+
+fn parse_or(expr: &str) -> Result<Condition, String> {
+    let parts = split_keyword(expr, &[" or ", "||"]);
+    if parts.len() == 1 {
+        return parse_and(parts[0]);
+    }
+    Ok(Condition::Or(parts.into_iter().map(parse_and).collect::<Result<_, _>>()?))
+}
+
+fn parse_and(expr: &str) -> Result<Condition, String> {
+    let parts = split_keyword(expr, &[" and ", "&&"]);
+    if parts.len() == 1 {
+        return parse_term(parts[0]);
+    }
+    Ok(Condition::And(parts.into_iter().map(parse_term).collect::<Result<_, _>>()?))
+}
+
+fn parse_term(expr: &str) -> Result<Condition, String> {
+    let expr = expr.trim();
+    if expr.is_empty() || expr == "true" {
+        return Ok(Condition::Literal(true));
+    }
+    if expr == "false" {
+        return Ok(Condition::Literal(false));
+    }
+    if let Some(rest) = expr.strip_prefix("not ") {
+        return Ok(Condition::Not(Box::new(parse_term(rest)?)));
+    }
+    if let Some(rest) = expr.strip_prefix('!').filter(|r| !r.starts_with('=')) {
+        return Ok(Condition::Not(Box::new(parse_term(rest)?)));
+    }
+
+    // Two-character operators first so `<=` isn't read as `<`
+    const OPERATORS: [(&str, CompareOp); 6] = [
+        ("<=", CompareOp::Le),
+        (">=", CompareOp::Ge),
+        ("==", CompareOp::Eq),
+        ("!=", CompareOp::Ne),
+        ("<", CompareOp::Lt),
+        (">", CompareOp::Gt),
+    ];
+    for (symbol, op) in OPERATORS {
+        if let Some(idx) = expr.find(symbol) {
+            let lhs = parse_operand(&expr[..idx])?;
+            let rhs = parse_operand(&expr[idx + symbol.len()..])?;
+            return Ok(Condition::Compare { lhs, op, rhs });
+        }
+    }
+
+    if BOOLEAN_VARIABLES.contains(&expr) {
+        return Ok(Condition::Flag(expr.to_string()));
+    }
+    Err(format!("unknown condition '{}'", expr))
+}
+
+fn parse_operand(expr: &str) -> Result<Operand, String> {
+    let expr = expr.trim();
+    if let Ok(n) = expr.parse::<f64>() {
+        return Ok(Operand::Number(n));
+    }
+    if NUMERIC_VARIABLES.contains(&expr) {
+        return Ok(Operand::Variable(expr.to_string()));
+    }
+    if expr.is_empty() {
+        return Err("comparison is missing a value".to_string());
+    }
+    Err(format!(
+        "unknown variable '{}' (expected a number or one of: {})",
+        expr,
+        NUMERIC_VARIABLES.join(", ")
+    ))
+}
+
+/// Evaluate a parsed condition. Variables that have no value (e.g. distance with no
+/// enemies left) make their comparison false.
+pub fn evaluate(condition: &Condition, ctx: &AplContext) -> bool {
+    match condition {
+        Condition::Literal(b) => *b,
+        Condition::Flag(name) => evaluate_flag(name, ctx).unwrap_or(false),
+        Condition::Not(inner) => !evaluate(inner, ctx),
+        Condition::And(terms) => terms.iter().all(|t| evaluate(t, ctx)),
+        Condition::Or(terms) => terms.iter().any(|t| evaluate(t, ctx)),
+        Condition::Compare { lhs, op, rhs } => {
+            match (evaluate_operand(lhs, ctx), evaluate_operand(rhs, ctx)) {
+                (Some(l), Some(r)) => op.apply(l, r),
+                _ => false,
             }
-            true // Default to true for unknown conditions
         }
     }
 }
 
+pub fn evaluate_condition(condition: &str, ctx: &AplContext) -> bool {
+    match parse_condition(condition) {
+        Ok(parsed) => evaluate(&parsed, ctx),
+        Err(_) => true, // Default to true for unknown conditions (validation reports them)
+    }
+}
+
+fn evaluate_operand(operand: &Operand, ctx: &AplContext) -> Option<f64> {
+    match operand {
+        Operand::Number(n) => Some(*n),
+        Operand::Variable(name) => evaluate_numeric(name, ctx),
+    }
+}
+
+fn evaluate_flag(name: &str, ctx: &AplContext) -> Option<bool> {
+    match name {
+        "enemy.in_range" | "enemy_in_range" => Some(ctx.has_enemy_in_range()),
+        "self.in_front" => Some(ctx.zones.is_melee(ctx.actor.zone) && !ctx.actor.in_reserve),
+        "self.grappled" => Some(ctx.actor.is_grappled(ctx.actors)),
+        "self.grappling" => Some(ctx.actor.is_grappling(ctx.actors)),
+        "self.poisoned" => Some(ctx.actor.has_condition(ConditionKind::Poisoned)),
+        "self.prone" => Some(ctx.actor.has_condition(ConditionKind::Prone)),
+        "self.bleeding" => Some(ctx.actor.has_condition(ConditionKind::Bleeding)),
+        "self.disarmed" => Some(ctx.actor.has_condition(ConditionKind::Disarmed)),
+        "self.slowed" => Some(ctx.actor.has_condition(ConditionKind::Slowed)),
+        "adjacent_to_vip" => Some(
+            ctx.ally_vip()
+                .is_some_and(|vip| ctx.zones.distance(ctx.actor.zone, vip.zone) <= 1),
+        ),
+        _ => None,
+    }
+}
+
 fn evaluate_numeric(expr: &str, ctx: &AplContext) -> Option<f64> {
+    let actor = ctx.actor;
     match expr {
         "self.health_percent" | "self.hp_percent" => {
-            Some(ctx.actor.current_hp as f64 / ctx.actor.max_hp as f64 * 100.0)
+            Some(actor.current_hp as f64 / actor.max_hp as f64 * 100.0)
         }
-        "self.hp" | "self.health" => Some(ctx.actor.current_hp as f64),
+        "self.hp" | "self.health" => Some(actor.current_hp as f64),
+        "self.zone" => Some(ctx.zones.depth_for(actor.zone, actor.side) as f64),
         "enemy.count" => Some(ctx.enemies().count() as f64),
         "ally.count" => Some(ctx.allies().count() as f64),
+        "nearest_enemy.distance" => ctx
+            .enemies()
+            .map(|e| ctx.zones.distance(actor.zone, e.zone))
+            .min()
+            .map(|d| d as f64),
+        "enemies_in_my_zone" => Some(ctx.enemies().filter(|e| e.zone == actor.zone).count() as f64),
+        "allies_in_my_zone" => Some(ctx.allies().filter(|a| a.zone == actor.zone).count() as f64),
+        "enemies_in_range" => Some(ctx.enemies_in_range().count() as f64),
+        "visible_enemies" => Some(
+            ctx.enemies()
+                .filter(|e| actor.sight_of(e, ctx.zones, ctx.actors) > Light::Dark)
+                .count() as f64,
+        ),
+        "enemies_in_melee" => Some(ctx.enemies().filter(|e| ctx.zones.is_melee(e.zone)).count() as f64),
+        "allies_in_melee" => Some(ctx.allies().filter(|a| ctx.zones.is_melee(a.zone)).count() as f64),
+        "round" => Some(ctx.round as f64),
+        "self.morale" => Some(actor.aura_effect(ctx.actors, ctx.zones).morale as f64),
         _ => None,
     }
 }
 
+/// Resolve a target selector against all living enemies (or noncombatants, for `noncombatant`)
 pub fn resolve_target(target_str: &str, ctx: &AplContext, rng: &mut impl rand::Rng) -> Option<usize> {
+    select_enemy(target_str, &ctx.candidates(target_str), ctx, rng)
+}
+
+/// Pick one of `candidates` using a target selector. Unknown selectors pick nothing.
+fn select_enemy(target_str: &str, candidates: &[&Actor], ctx: &AplContext, rng: &mut impl rand::Rng) -> Option<usize> {
+    let target_str = target_str.trim().to_lowercase();
+    let actor = ctx.actor;
+    // min_by_key keeps the first of equal candidates, so use Reverse for "highest" selectors
+    let chosen = match target_str.as_str() {
+        "nearest_enemy" | "nearest" => candidates.iter().min_by_key(|e| ctx.zones.distance(actor.zone, e.zone)),
+        "lowest_hp_enemy" | "lowest_hp" | "weakest" => candidates.iter().min_by_key(|e| e.current_hp),
+        "highest_hp_enemy" | "highest_hp" | "strongest" => {
+            candidates.iter().min_by_key(|e| std::cmp::Reverse(e.current_hp))
+        }
+        "lowest_ac_enemy" | "lowest_ac" => candidates.iter().min_by_key(|e| e.effective_ac()),
+        "highest_ac_enemy" | "highest_ac" => {
+            candidates.iter().min_by_key(|e| std::cmp::Reverse(e.effective_ac()))
+        }
+        "highest_damage_enemy" | "most_dangerous" => {
+            let my_ac = actor.effective_ac();
+            candidates.iter().min_by(|a, b| {
+                b.expected_damage_against(my_ac)
+                    .total_cmp(&a.expected_damage_against(my_ac))
+            })
+        }
+        "vip" => candidates
+            .iter()
+            .filter(|e| e.vip)
+            .min_by_key(|e| ctx.zones.distance(actor.zone, e.zone)),
+        "noncombatant" => candidates.iter().min_by_key(|e| ctx.zones.distance(actor.zone, e.zone)),
+        "random_enemy" | "random" => {
+            if candidates.is_empty() {
+                None
+            } else {
+                Some(&candidates[rng.gen_range(0..candidates.len())])
+            }
+        }
+        _ => match (target_str.strip_prefix("name:"), actor_id(&target_str)) {
+            // Focus fire a specific template, nearest one first
+            (Some(name), _) => candidates
+                .iter()
+                .filter(|e| e.template_name.eq_ignore_ascii_case(name.trim()))
+                .min_by_key(|e| ctx.zones.distance(actor.zone, e.zone)),
+            (None, Some(id)) => candidates.iter().find(|e| e.id == id),
+            (None, None) => None,
+        },
+    };
+    chosen.map(|a| a.id)
+}
+
+/// The actor an `id:N` selector names, as scripts and controllers see ids
+fn actor_id(target_str: &str) -> Option<usize> {
+    target_str.strip_prefix("id:")?.trim().parse().ok()
+}
+
+/// Resolve a target for beneficial actions (buffs) - self or an ally
+pub fn resolve_ally_target(target_str: &str, ctx: &AplContext, rng: &mut impl rand::Rng) -> Option<usize> {
     let target_str = target_str.trim().to_lowercase();
     match target_str.as_str() {
-        "nearest_enemy" | "nearest" => ctx.nearest_enemy().map(|a| a.id),
-        "lowest_hp_enemy" | "lowest_hp" | "weakest" => ctx.lowest_hp_enemy().map(|a| a.id),
-        "random_enemy" | "random" => ctx.random_enemy(rng).map(|a| a.id),
-        _ => ctx.nearest_enemy().map(|a| a.id), // Default to nearest
+        "lowest_hp_ally" | "weakest_ally" => ctx.lowest_hp_ally().map(|a| a.id),
+        "random_ally" => ctx.random_ally(rng).map(|a| a.id),
+        "vip" => ctx.ally_vip().map(|a| a.id),
+        _ => Some(ctx.actor.id), // Default to self
     }
 }
 
-pub fn execute_apl(actor: &Actor, actors: &[Actor], rng: &mut impl rand::Rng) -> TurnActions {
-    let ctx = AplContext { actor, actors };
+/// Pick the actor's move and attack for this turn. `phase` is the current initiative phase
+/// when using phase-based initiative; entries tagged with a different phase are skipped.
+pub fn execute_apl(
+    actor: &Actor,
+    actors: &[Actor],
+    zones: &ZoneLayout,
+    round: u32,
+    phase: Option<Phase>,
+    rng: &mut impl rand::Rng,
+) -> TurnActions {
+    select_actions(actor, &actor.apl, actors, zones, round, phase, &TurnBudget::full(actor), rng)
+}
+
+/// Pick a legendary action from the actor's `legendary_apl` (the default APL if it has none)
+pub fn execute_legendary_apl(
+    actor: &Actor,
+    actors: &[Actor],
+    zones: &ZoneLayout,
+    round: u32,
+    rng: &mut impl rand::Rng,
+) -> TurnActions {
+    select_actions(actor, &actor.legendary_apl, actors, zones, round, None, &TurnBudget::full(actor), rng)
+}
+
+/// Pick the actor's move and actions from `apl` rather than its own APL, filling only what
+/// `budget` still has open
+#[allow(clippy::too_many_arguments)]
+pub fn select_actions(
+    actor: &Actor,
+    apl: &[AplEntry],
+    actors: &[Actor],
+    zones: &ZoneLayout,
+    round: u32,
+    phase: Option<Phase>,
+    budget: &TurnBudget,
+    rng: &mut impl rand::Rng,
+) -> TurnActions {
+    pick_actions(actor, apl, actors, zones, round, phase, budget, rng, None)
+}
+
+/// [`select_actions`], also reporting how each entry of the APL it picked from fared. Makes
+/// exactly the same picks (and rolls) as `select_actions`.
+#[allow(clippy::too_many_arguments)]
+pub fn trace_actions(
+    actor: &Actor,
+    apl: &[AplEntry],
+    actors: &[Actor],
+    zones: &ZoneLayout,
+    round: u32,
+    phase: Option<Phase>,
+    budget: &TurnBudget,
+    rng: &mut impl rand::Rng,
+) -> (TurnActions, Vec<EntryTrace>) {
+    let mut trace = Vec::new();
+    let actions = pick_actions(actor, apl, actors, zones, round, phase, budget, rng, Some(&mut trace));
+    (actions, trace)
+}
+
+#[allow(clippy::too_many_arguments)]
+fn pick_actions(
+    actor: &Actor,
+    apl: &[AplEntry],
+    actors: &[Actor],
+    zones: &ZoneLayout,
+    round: u32,
+    phase: Option<Phase>,
+    budget: &TurnBudget,
+    rng: &mut impl rand::Rng,
+    mut trace: Option<&mut Vec<EntryTrace>>,
+) -> TurnActions {
+    let ctx = AplContext { actor, actors, zones, round };
 
     // Default APL if none specified
     let default_apl = vec![
@@ -140,21 +550,76 @@ pub fn execute_apl(actor: &Actor, actors: &[Actor], rng: &mut impl rand::Rng) ->
             action: "attack".to_string(),
             condition: Some("enemy.in_range".to_string()),
             target: Some("nearest_enemy".to_string()),
+            amount: None,
+            duration: None,
+            phase: None,
+            on_hit: Vec::new(),
+            called_shot: None,
+            ready: None,
+            trigger: None,
+            held: false,
+            uses: None,
+            after_action: false,
         },
         AplEntry {
             action: "move".to_string(),
             condition: None,
             target: Some("nearest_enemy".to_string()),
+            amount: None,
+            duration: None,
+            phase: None,
+            on_hit: Vec::new(),
+            called_shot: None,
+            ready: None,
+            trigger: None,
+            held: false,
+            uses: None,
+            after_action: false,
         },
     ];
 
-    let apl = if actor.apl.is_empty() { &default_apl } else { &actor.apl };
+    // Noncombatants with nothing better to do make for their side's rear (and out, given exits)
+    let noncombatant_apl = vec![AplEntry { action: "flee".to_string(), target: None, ..default_apl[1].clone() }];
+    let apl = match apl.is_empty() {
+        true if actor.noncombatant => &noncombatant_apl,
+        true => &default_apl,
+        false => apl,
+    };
 
     let mut move_action = MoveAction::None;
-    let mut attack_action = AttackAction::None;
+    let mut picked = [AttackAction::None, AttackAction::None, AttackAction::None];
+
+    // The stunned lose their turn, and the disarmed (and noncombatants) can't attack or grapple
+    if actor.has_condition(ConditionKind::Stunned) {
+        if let Some(trace) = trace {
+            for (index, entry) in apl.iter().enumerate().filter(|(_, e)| e.ready.is_none()) {
+                trace.push(EntryTrace::new(index, entry, EntryOutcome::Skipped, "stunned: loses its turn".to_string()));
+            }
+        }
+        let [attack_action, bonus_action, free_interaction] = picked;
+        return TurnActions { move_action, attack_action, bonus_action, free_interaction };
+    }
+    let armed = !actor.has_condition(ConditionKind::Disarmed) && !actor.noncombatant;
+
+    // The open slots the APL has entries for; the action is always looked for while it's open
+    let wanted = Slot::ALL.map(|slot| {
+        budget.has(slot) && (slot == Slot::Action || apl.iter().any(|e| e.uses == Some(slot)))
+    });
+
+    // Find the first valid move and the first valid action for each open slot
+    let mut stopped_at = None;
+    for (index, entry) in apl.iter().enumerate().filter(|(_, e)| e.ready.is_none()) {
+        // Skip entries reserved for a different phase
+        if let (Some(entry_phase), Some(current)) = (entry.phase, phase) {
+            if entry_phase != current {
+                if let Some(trace) = trace.as_deref_mut() {
+                    let reason = format!("for the {:?} phase, not {:?}", entry_phase, current).to_lowercase();
+                    trace.push(EntryTrace::new(index, entry, EntryOutcome::WrongPhase, reason));
+                }
+                continue;
+            }
+        }
 
-    // Find the first valid move action and first valid attack action
-    for entry in apl {
         // Check condition
         let condition_met = entry
             .condition
@@ -163,72 +628,1274 @@ pub fn execute_apl(actor: &Actor, actors: &[Actor], rng: &mut impl rand::Rng) ->
             .unwrap_or(true);
 
         if !condition_met {
+            if let Some(trace) = trace.as_deref_mut() {
+                let reason = "condition is false".to_string();
+                trace.push(EntryTrace::new(index, entry, EntryOutcome::ConditionFailed, reason).with_terms(&ctx));
+            }
             continue;
         }
 
+        // Moves before the action come from plain entries, moves after it from `after_action` ones
+        let slot = entry.uses.unwrap_or(Slot::Action);
+        let open = budget.has(slot) && matches!(picked[slot as usize], AttackAction::None);
+        let can_move = entry.after_action != budget.action && matches!(move_action, MoveAction::None);
+        let action = &mut picked[slot as usize];
+
         match entry.action.to_lowercase().as_str() {
-            "attack" => {
-                // Only set attack if we haven't found one yet
-                if matches!(attack_action, AttackAction::None) && ctx.has_enemy_in_range() {
-                    let target_str = entry.target.as_deref().unwrap_or("nearest_enemy");
-                    let in_range: Vec<_> = ctx.enemies_in_range().collect();
-                    let target = match target_str.to_lowercase().as_str() {
-                        "lowest_hp_enemy" | "lowest_hp" | "weakest" => {
-                            in_range.iter().min_by_key(|e| e.current_hp).map(|a| a.id)
-                        }
-                        "random_enemy" | "random" => {
-                            if in_range.is_empty() {
-                                None
-                            } else {
-                                Some(in_range[rng.gen_range(0..in_range.len())].id)
-                            }
-                        }
-                        _ => in_range.first().map(|a| a.id),
-                    };
-
-                    if let Some(target_id) = target {
-                        attack_action = AttackAction::Attack { target_id };
-                    }
+            "attack" if open && armed => {
+                let target_str = entry.target.as_deref().unwrap_or("nearest_enemy");
+                let mut in_range = ctx.candidates(target_str);
+                in_range.retain(|e| actor.can_attack(e, zones, actors));
+                let target = select_enemy(target_str, &in_range, &ctx, rng);
+
+                if let Some(target_id) = target {
+                    let on_hit = riders(entry, actor);
+                    *action = AttackAction::Attack { target_id, on_hit, called_shot: entry.called_shot };
                 }
             }
-            "move" => {
-                // Only set move if we haven't found one yet
-                if matches!(move_action, MoveAction::None) {
-                    let target_str = entry.target.as_deref().unwrap_or("nearest_enemy");
-                    match target_str.to_lowercase().as_str() {
-                        "forward" => {
-                            move_action = MoveAction::Move { direction: MoveDirection::Forward };
-                        }
-                        "backward" => {
-                            move_action = MoveAction::Move { direction: MoveDirection::Backward };
-                        }
-                        _ => {
-                            if let Some(target_id) = resolve_target(target_str, &ctx, rng) {
-                                move_action = MoveAction::Move {
-                                    direction: MoveDirection::Toward(target_id),
-                                };
-                            }
-                        }
-                    }
+            "move" if can_move => {
+                if let Some(direction) = move_direction(entry, &ctx, rng) {
+                    move_action = MoveAction::Move { direction };
+                }
+            }
+            // Withdraw takes the move - slower, but nobody gets a free swing
+            "withdraw" if can_move => {
+                move_action = MoveAction::Withdraw;
+            }
+            // Dash replaces attack - the actor moves again instead
+            "dash" if open => {
+                if let Some(direction) = move_direction(entry, &ctx, rng) {
+                    *action = AttackAction::Dash { direction };
                 }
             }
-            "guard" | "defend" => {
+            "guard" if open => {
                 // Guard action replaces attack - raises AC by 2 for the round
-                if matches!(attack_action, AttackAction::None) {
-                    attack_action = AttackAction::Guard;
+                *action = AttackAction::Guard;
+            }
+            "defend" if open => {
+                // Defend replaces attack and movement - raises AC by 4 for the round
+                *action = AttackAction::Defend;
+                if can_move {
+                    move_action = MoveAction::Hold;
+                }
+            }
+            "dodge" if open => {
+                // Dodge replaces attack - attackers roll with disadvantage until next turn
+                *action = AttackAction::Dodge;
+            }
+            "flee" if can_move && traits::will_flee(actor) => {
+                // Flee replaces attack and movement - nobody stops to fight on the way out
+                move_action = MoveAction::Flee;
+                if open {
+                    *action = AttackAction::Hold;
+                }
+            }
+            "hold" => {
+                // Hold claims whichever of move/attack is still open and does nothing with it
+                if can_move {
+                    move_action = MoveAction::Hold;
+                }
+                if open {
+                    *action = AttackAction::Hold;
+                }
+            }
+            // Grapple replaces attack - only against an adjacent enemy nobody is holding yet
+            "grapple" if open && armed && !actor.is_grappling(actors) && !actor.in_reserve => {
+                let target_str = entry.target.as_deref().unwrap_or("nearest_enemy");
+                let mut candidates = ctx.candidates(target_str);
+                candidates.retain(|e| zones.distance(actor.zone, e.zone) == 1 && !e.is_grappled(actors));
+                if let Some(target_id) = select_enemy(target_str, &candidates, &ctx, rng) {
+                    *action = AttackAction::Grapple { target_id };
+                }
+            }
+            // Escape replaces attack - struggle free of whoever is holding the actor
+            "escape" if open && actor.is_grappled(actors) => {
+                *action = AttackAction::Escape;
+            }
+            // Summon replaces attack - only while the chosen creature's limit isn't used up
+            "summon" if open && !actor.noncombatant => {
+                let index = match &entry.target {
+                    Some(name) => ActorTemplate::summon_index(&actor.summons, name),
+                    None => (!actor.summons.is_empty()).then_some(0),
+                };
+                if let Some(index) = index.filter(|&i| actor.summons_left(i) > 0) {
+                    *action = AttackAction::Summon { index, amount: entry.amount.clone() };
+                }
+            }
+            "buff" if open => {
+                // Buff replaces attack - grants temporary HP to self or an ally
+                if let Some(amount) = &entry.amount {
+                    let target_str = entry.target.as_deref().unwrap_or("self");
+                    if let Some(target_id) = resolve_ally_target(target_str, &ctx, rng) {
+                        *action = AttackAction::Buff {
+                            target_id,
+                            amount: amount.clone(),
+                            duration: entry.duration,
+                        };
+                    }
                 }
             }
             _ => {}
         }
 
-        // If we've found both actions, we can stop
-        if !matches!(move_action, MoveAction::None) && !matches!(attack_action, AttackAction::None) {
+        if let Some(trace) = trace.as_deref_mut() {
+            // Whatever the entry claimed was open before it, so anything filled now is its doing
+            let took_move = can_move && !matches!(move_action, MoveAction::None);
+            let took_slot = open && !matches!(picked[slot as usize], AttackAction::None);
+            let (outcome, reason) = if took_move || took_slot {
+                let mut took = Vec::new();
+                if took_slot {
+                    took.push(format!("{}: {}", slot_name(slot), describe_action(&picked[slot as usize], actor, actors)));
+                }
+                if took_move {
+                    took.push(format!("move: {}", describe_move(&move_action, actors)));
+                }
+                (EntryOutcome::Chosen, took.join("; "))
+            } else {
+                (EntryOutcome::Skipped, skip_reason(entry, &ctx, budget, slot, open, can_move, armed))
+            };
+            trace.push(EntryTrace::new(index, entry, outcome, reason).with_terms(&ctx));
+        }
+
+        // If we've found a move and everything wanted, we can stop
+        let filled = wanted.iter().zip(&picked).all(|(&wanted, action)| !wanted || !matches!(action, AttackAction::None));
+        if !matches!(move_action, MoveAction::None) && filled {
+            stopped_at = Some(index);
             break;
         }
     }
 
-    TurnActions {
-        move_action,
-        attack_action,
+    if let (Some(trace), Some(stopped_at)) = (trace, stopped_at) {
+        for (index, entry) in apl.iter().enumerate().skip(stopped_at + 1).filter(|(_, e)| e.ready.is_none()) {
+            let reason = "the move and every open slot were already filled".to_string();
+            trace.push(EntryTrace::new(index, entry, EntryOutcome::NotReached, reason));
+        }
+    }
+
+    let [attack_action, bonus_action, free_interaction] = picked;
+    TurnActions { move_action, attack_action, bonus_action, free_interaction }
+}
+
+/// How one APL entry fared when an actor picked its actions, for `POST /debug`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct EntryTrace {
+    /// Position of the entry in the APL the actor picked from
+    pub index: usize,
+    pub action: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub target: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub condition: Option<String>,
+    /// Each flag and comparison in the condition, and what it came to
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub terms: Vec<TermTrace>,
+    pub outcome: EntryOutcome,
+    /// What the entry claimed, or what stopped it
+    pub reason: String,
+}
+
+impl EntryTrace {
+    fn new(index: usize, entry: &AplEntry, outcome: EntryOutcome, reason: String) -> Self {
+        EntryTrace {
+            index,
+            action: entry.action.clone(),
+            target: entry.target.clone(),
+            condition: entry.condition.clone(),
+            terms: Vec::new(),
+            outcome,
+            reason,
+        }
+    }
+
+    /// Fill in how the entry's condition read in `ctx`
+    fn with_terms(mut self, ctx: &AplContext) -> Self {
+        if let Some(Ok(condition)) = self.condition.as_deref().map(parse_condition) {
+            condition_terms(&condition, ctx, &mut self.terms);
+        }
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EntryOutcome {
+    /// It claimed the move or a slot (or both)
+    Chosen,
+    ConditionFailed,
+    /// Tagged for another initiative phase
+    WrongPhase,
+    /// Its condition held (or it had none) but it couldn't claim anything
+    Skipped,
+    /// Everything was already picked before the APL got this far
+    NotReached,
+}
+
+/// One flag or comparison from an entry's condition
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TermTrace {
+    /// The term as written, e.g. `self.hp_percent < 50`
+    pub term: String,
+    /// Whether the term held, before any `not` around it
+    pub passed: bool,
+    /// The variables the comparison read; null where there was nothing to measure, e.g. the
+    /// distance to the nearest enemy with none left
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub values: BTreeMap<String, Option<f64>>,
+}
+
+/// The leaf terms of `condition`, in the order written
+fn condition_terms(condition: &Condition, ctx: &AplContext, terms: &mut Vec<TermTrace>) {
+    match condition {
+        Condition::Literal(_) => {}
+        Condition::Not(inner) => condition_terms(inner, ctx, terms),
+        Condition::And(inner) | Condition::Or(inner) => inner.iter().for_each(|c| condition_terms(c, ctx, terms)),
+        Condition::Flag(name) => terms.push(TermTrace {
+            term: name.clone(),
+            passed: evaluate(condition, ctx),
+            values: BTreeMap::new(),
+        }),
+        Condition::Compare { lhs, op, rhs } => {
+            let values = [lhs, rhs]
+                .into_iter()
+                .filter_map(|operand| match operand {
+                    Operand::Variable(name) => Some((name.clone(), evaluate_numeric(name, ctx))),
+                    Operand::Number(_) => None,
+                })
+                .collect();
+            terms.push(TermTrace {
+                term: format!("{} {} {}", operand_text(lhs), op.symbol(), operand_text(rhs)),
+                passed: evaluate(condition, ctx),
+                values,
+            });
+        }
+    }
+}
+
+fn operand_text(operand: &Operand) -> String {
+    match operand {
+        Operand::Number(n) => n.to_string(),
+        Operand::Variable(name) => name.clone(),
+    }
+}
+
+fn slot_name(slot: Slot) -> &'static str {
+    match slot {
+        Slot::Action => "action",
+        Slot::BonusAction => "bonus action",
+        Slot::FreeInteraction => "free interaction",
+    }
+}
+
+/// What each part of a turn went to, in words: `none` where nothing was picked, otherwise the
+/// action or direction as an APL writes it, with any target, e.g. `attack Orc (id 3)`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChosenActions {
+    #[serde(rename = "move")]
+    pub move_action: String,
+    pub action: String,
+    pub bonus_action: String,
+    pub free_interaction: String,
+}
+
+impl ChosenActions {
+    pub fn new(actions: &TurnActions, actor: &Actor, actors: &[Actor]) -> Self {
+        ChosenActions {
+            move_action: describe_move(&actions.move_action, actors),
+            action: describe_action(&actions.attack_action, actor, actors),
+            bonus_action: describe_action(&actions.bonus_action, actor, actors),
+            free_interaction: describe_action(&actions.free_interaction, actor, actors),
+        }
+    }
+}
+
+fn actor_label(actors: &[Actor], id: usize) -> String {
+    format!("{} (id {})", actors[id].name, id)
+}
+
+fn describe_direction(direction: &MoveDirection, actors: &[Actor]) -> String {
+    match direction {
+        MoveDirection::Toward(id) => format!("toward {}", actor_label(actors, *id)),
+        MoveDirection::Forward => "forward".to_string(),
+        MoveDirection::Backward => "backward".to_string(),
+        MoveDirection::Flank => "flank".to_string(),
     }
 }
+
+fn describe_move(move_action: &MoveAction, actors: &[Actor]) -> String {
+    match move_action {
+        MoveAction::Move { direction } => describe_direction(direction, actors),
+        MoveAction::Hold => "hold".to_string(),
+        MoveAction::Flee => "flee".to_string(),
+        MoveAction::Withdraw => "withdraw".to_string(),
+        MoveAction::None => "none".to_string(),
+    }
+}
+
+fn describe_action(action: &AttackAction, actor: &Actor, actors: &[Actor]) -> String {
+    match action {
+        AttackAction::Attack { target_id, .. } => format!("attack {}", actor_label(actors, *target_id)),
+        AttackAction::Guard => "guard".to_string(),
+        AttackAction::Defend => "defend".to_string(),
+        AttackAction::Dodge => "dodge".to_string(),
+        AttackAction::Hold => "hold".to_string(),
+        AttackAction::Buff { target_id, .. } => format!("buff {}", actor_label(actors, *target_id)),
+        AttackAction::Grapple { target_id } => format!("grapple {}", actor_label(actors, *target_id)),
+        AttackAction::Escape => "escape".to_string(),
+        AttackAction::Dash { direction } => format!("dash {}", describe_direction(direction, actors)),
+        AttackAction::Summon { index, .. } => format!("summon {}", actor.summons[*index].name),
+        AttackAction::None => "none".to_string(),
+    }
+}
+
+/// Why an entry whose condition held still claimed nothing
+fn skip_reason(entry: &AplEntry, ctx: &AplContext, budget: &TurnBudget, slot: Slot, open: bool, can_move: bool, armed: bool) -> String {
+    let actor = ctx.actor;
+    let move_closed = || {
+        if entry.after_action != budget.action {
+            "the move is already taken".to_string()
+        } else if entry.after_action {
+            "an after_action move waits until the action is spent".to_string()
+        } else {
+            "the action is spent, so only after_action moves are left".to_string()
+        }
+    };
+    let slot_closed = || match budget.has(slot) {
+        true => format!("the {} is already taken", slot_name(slot)),
+        false => format!("no {} left this turn", slot_name(slot)),
+    };
+    let enemy = entry.target.as_deref().unwrap_or("nearest_enemy");
+    match entry.action.to_lowercase().as_str() {
+        "move" | "withdraw" | "flee" if !can_move => move_closed(),
+        "move" => format!("nothing to move toward for `{}`", enemy),
+        "flee" => format!("{} never flees", actor.name),
+        "hold" => format!("{}, and {}", slot_closed(), move_closed()),
+        action if !ACTIONS.contains(&action) => format!("`{}` isn't an action", entry.action),
+        _ if !open => slot_closed(),
+        "attack" | "grapple" | "summon" if actor.noncombatant => "noncombatants don't fight".to_string(),
+        "attack" | "grapple" if !armed => "disarmed".to_string(),
+        "attack" => format!("no enemy in range for `{}`", enemy),
+        "dash" => format!("nothing to dash toward for `{}`", enemy),
+        "grapple" if actor.is_grappling(ctx.actors) => "already holding someone".to_string(),
+        "grapple" if actor.in_reserve => "in reserve".to_string(),
+        "grapple" => format!("no adjacent enemy free to grab for `{}`", enemy),
+        "escape" => "nobody is holding it".to_string(),
+        "summon" => "nothing left to summon".to_string(),
+        "buff" if entry.amount.is_none() => "no amount to grant".to_string(),
+        "buff" => format!("no ally for `{}`", entry.target.as_deref().unwrap_or("self")),
+        _ => "nothing to claim".to_string(),
+    }
+}
+
+/// Where a `move` or `dash` entry heads, if its target can be found
+fn move_direction(entry: &AplEntry, ctx: &AplContext, rng: &mut impl rand::Rng) -> Option<MoveDirection> {
+    let target_str = entry.target.as_deref().unwrap_or("nearest_enemy");
+    match target_str.to_lowercase().as_str() {
+        "forward" => Some(MoveDirection::Forward),
+        "backward" => Some(MoveDirection::Backward),
+        "flank" => Some(MoveDirection::Flank),
+        // Bodyguards close in on their own VIP
+        "to_vip" => ctx.ally_vip().map(|vip| MoveDirection::Toward(vip.id)),
+        _ => resolve_target(target_str, ctx, rng).map(MoveDirection::Toward),
+    }
+}
+
+/// Pick the actor's reaction to `trigger`, set off by `source`. The first armed entry for the
+/// trigger whose condition holds wins; attack reactions strike the source and need it in range.
+pub fn select_reaction(
+    actor: &Actor,
+    actors: &[Actor],
+    zones: &ZoneLayout,
+    round: u32,
+    trigger: Trigger,
+    source: &Actor,
+) -> Option<AttackAction> {
+    let ctx = AplContext { actor, actors, zones, round };
+    if actor.has_condition(ConditionKind::Stunned) {
+        return None;
+    }
+    for entry in actor.apl.iter() {
+        let Some(ready) = &entry.ready else {
+            continue;
+        };
+        if entry.trigger != Some(trigger) || (entry.held && !actor.effects.has(EffectKind::Holding)) {
+            continue;
+        }
+        let condition_met = entry
+            .condition
+            .as_ref()
+            .map(|c| evaluate_condition(c, &ctx))
+            .unwrap_or(true);
+        if !condition_met {
+            continue;
+        }
+        match ready.trim().to_lowercase().as_str() {
+            "attack"
+                if actor.is_hostile_to(source)
+                    && !actor.noncombatant
+                    && !source.noncombatant
+                    && !actor.has_condition(ConditionKind::Disarmed)
+                    && actor.can_attack(source, zones, actors) =>
+            {
+                let on_hit = riders(entry, actor);
+                return Some(AttackAction::Attack { target_id: source.id, on_hit, called_shot: entry.called_shot });
+            }
+            "guard" => return Some(AttackAction::Guard),
+            "dodge" => return Some(AttackAction::Dodge),
+            _ => {}
+        }
+    }
+    None
+}
+
+/// An entry's own riders replace the actor's
+fn riders(entry: &AplEntry, actor: &Actor) -> Vec<OnHit> {
+    if entry.on_hit.is_empty() {
+        actor.on_hit.clone()
+    } else {
+        entry.on_hit.clone()
+    }
+}
+
+/// Action names understood by `execute_apl`
+const ACTIONS: &[&str] = &[
+    "attack", "move", "guard", "defend", "dodge", "hold", "buff", "grapple", "escape", "flee", "summon", "dash", "withdraw",
+];
+
+/// Actions an entry can spend a bonus action or free interaction on
+const SLOTTED_ACTIONS: &[&str] = &["attack", "guard", "dodge", "buff", "grapple", "escape", "summon", "dash"];
+
+/// Actions a reaction can take
+const REACTIONS: &[&str] = &["attack", "guard", "dodge"];
+
+/// Enemy selectors for `attack` and `move`
+const ENEMY_TARGETS: &[&str] = &[
+    "nearest_enemy",
+    "nearest",
+    "lowest_hp_enemy",
+    "lowest_hp",
+    "weakest",
+    "highest_hp_enemy",
+    "highest_hp",
+    "strongest",
+    "lowest_ac_enemy",
+    "lowest_ac",
+    "highest_ac_enemy",
+    "highest_ac",
+    "highest_damage_enemy",
+    "most_dangerous",
+    "random_enemy",
+    "random",
+    "vip",
+    "noncombatant",
+];
+
+/// Directions accepted by `move` and `dash` in addition to enemy selectors
+const MOVE_DIRECTIONS: &[&str] = &["forward", "backward", "flank", "to_vip"];
+
+/// Self/ally selectors for `buff`
+const ALLY_TARGETS: &[&str] = &["self", "lowest_hp_ally", "weakest_ally", "random_ally", "vip"];
+
+/// Boolean variables usable on their own (optionally negated with `!` or `not`)
+const BOOLEAN_VARIABLES: &[&str] = &[
+    "enemy.in_range",
+    "enemy_in_range",
+    "self.in_front",
+    "self.grappled",
+    "self.grappling",
+    "self.poisoned",
+    "self.prone",
+    "self.bleeding",
+    "self.disarmed",
+    "self.slowed",
+    "adjacent_to_vip",
+];
+
+/// Variables usable in comparisons (`<`, `<=`, `>`, `>=`, `==`, `!=`)
+const NUMERIC_VARIABLES: &[&str] = &[
+    "self.health_percent",
+    "self.hp_percent",
+    "self.hp",
+    "self.health",
+    "self.zone",
+    "enemy.count",
+    "ally.count",
+    "nearest_enemy.distance",
+    "enemies_in_my_zone",
+    "allies_in_my_zone",
+    "enemies_in_range",
+    "visible_enemies",
+    "enemies_in_melee",
+    "allies_in_melee",
+    "round",
+    "self.morale",
+];
+
+/// Most iterations an encounter may ask for
+pub const MAX_ITERATIONS: u32 = 1_000_000;
+
+/// Longest round limit an encounter, or a request overriding it, may set
+pub const MAX_ROUNDS: u32 = 10_000;
+
+/// A problem found while validating an encounter
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ValidationError {
+    /// Location in the encounter, e.g. `side1[0].apl[1].action`
+    pub path: String,
+    pub code: ErrorCode,
+    pub message: String,
+    /// 1-based line in the source YAML, when it can be located
+    pub line: Option<usize>,
+}
+
+impl ValidationError {
+    pub fn new(path: impl Into<String>, code: ErrorCode, message: impl Into<String>) -> Self {
+        ValidationError {
+            path: path.into(),
+            code,
+            message: message.into(),
+            line: None,
+        }
+    }
+}
+
+/// Machine-readable kind of a validation error, stable for clients to match on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+    /// The text isn't valid YAML, JSON, or TOML, or doesn't fit the encounter format
+    ParseError,
+    MissingField,
+    /// A field that doesn't apply where it was given
+    UnexpectedField,
+    UnknownAction,
+    UnknownReaction,
+    UnknownTarget,
+    UnknownZone,
+    UnknownRef,
+    /// A side the encounter doesn't have, e.g. `side3` without any `factions`
+    UnknownSide,
+    InvalidCondition,
+    InvalidDice,
+    /// A number outside its allowed range, e.g. negative AC or speed 0
+    OutOfRange,
+    DuplicateName,
+    /// A side with nobody on it
+    EmptySide,
+    TooManyIterations,
+    /// A round limit longer than the server allows
+    TooManyRounds,
+    /// More actors than the server allows
+    TooManyActors,
+    /// A zone layout that can't be fought over
+    InvalidLayout,
+    /// A `script` that doesn't compile (or a build without scripting)
+    InvalidScript,
+    /// The request around the encounter is malformed
+    InvalidRequest,
+    /// Several problems at once; each detail has its own code
+    ValidationFailed,
+    /// The server is already running as many simulations as it allows
+    ServerBusy,
+    /// The run didn't finish within the server's time budget
+    TimeBudgetExceeded,
+    /// The server requires an API key and the request didn't carry a valid one
+    Unauthorized,
+    /// The client has used up its share of the server for now
+    RateLimited,
+}
+
+/// Check every APL entry in an encounter for unknown actions, malformed conditions,
+/// and unknown target selectors, plus the zone layout and starting zones. Returns an empty
+/// list if everything is valid.
+pub fn validate(encounter: &Encounter) -> Vec<ValidationError> {
+    let mut errors = validate_zones(encounter);
+    let zones = encounter.zone_layout();
+    for (i, objective) in encounter.objectives.iter().enumerate() {
+        let (field, code, message) = match objective {
+            Objective::Breakthrough { zone: Some(name), .. } if zones.find(name).is_none() => {
+                ("zone", ErrorCode::UnknownZone, format!("unknown zone '{}'", name))
+            }
+            Objective::Hold { rounds: 0, .. } => ("rounds", ErrorCode::OutOfRange, "rounds must be at least 1".to_string()),
+            _ => continue,
+        };
+        errors.push(ValidationError::new(format!("objectives[{}].{}", i, field), code, message));
+    }
+    validate_settings(encounter, &mut errors);
+
+    for side in encounter.sides() {
+        let side_name = side.path();
+        let mut enemy_names = Vec::new();
+        for template in encounter.enemy_templates(side) {
+            summon_names(template, &mut enemy_names);
+        }
+        for (t, template) in encounter.templates(side).iter().enumerate() {
+            if let StartingZone::Named(name) = &template.start_zone {
+                if zones.find(name).is_none() {
+                    let names: Vec<&str> = zones.zones().iter().map(|z| z.name.as_str()).collect();
+                    errors.push(ValidationError::new(
+                        format!("{}[{}].start_zone", side_name, t),
+                        ErrorCode::UnknownZone,
+                        format!(
+                            "unknown zone '{}' (expected ranged, reach, melee, or one of: {})",
+                            name,
+                            names.join(", ")
+                        ),
+                    ));
+                }
+            }
+            validate_stats(template, &format!("{}[{}].", side_name, t), &mut errors);
+            validate_template_riders(template, &format!("{}[{}].", side_name, t), &mut errors);
+            for (i, entry) in template.apl.iter().enumerate() {
+                let path = format!("{}[{}].apl[{}]", side_name, t, i);
+                validate_entry(entry, &path, Some(&enemy_names), &mut errors);
+            }
+            for (i, entry) in template.legendary_apl.iter().enumerate() {
+                let path = format!("{}[{}].legendary_apl[{}]", side_name, t, i);
+                validate_entry(entry, &path, Some(&enemy_names), &mut errors);
+            }
+            validate_summons(template, &format!("{}[{}].", side_name, t), Some(&enemy_names), &mut errors);
+            if !encounter.called_shots {
+                validate_called_shots(template, &format!("{}[{}].", side_name, t), &mut errors);
+            }
+            validate_legendary_slots(template, &format!("{}[{}].", side_name, t), &mut errors);
+            validate_noncombatant(template, &format!("{}[{}].", side_name, t), &mut errors);
+        }
+    }
+
+    errors
+}
+
+/// The template's name and those of everything it can summon or leave behind, which enemies
+/// can also target by `name:`
+fn summon_names<'a>(template: &'a ActorTemplate, names: &mut Vec<&'a str>) {
+    names.push(&template.name);
+    for (spawn, _) in template.spawns() {
+        summon_names(spawn, names);
+    }
+}
+
+/// `summon` entries must name one of the template's `summons`, and each of those (and its
+/// `on_death` statblock) is checked like a side entry of its own
+fn validate_summons(template: &ActorTemplate, path: &str, enemy_names: Option<&[&str]>, errors: &mut Vec<ValidationError>) {
+    let apls = [("apl", &template.apl), ("legendary_apl", &template.legendary_apl)];
+    for (field, apl) in apls {
+        for (i, entry) in apl.iter().enumerate() {
+            if entry.ready.is_some() || !entry.action.trim().eq_ignore_ascii_case("summon") {
+                continue;
+            }
+            let path = format!("{}{}[{}]", path, field, i);
+            if template.summons.is_empty() {
+                errors.push(ValidationError::new(
+                    format!("{}.action", path),
+                    ErrorCode::MissingField,
+                    "summon needs a `summons` list to call from".to_string(),
+                ));
+            } else if let Some(target) = entry
+                .target
+                .as_ref()
+                .filter(|target| ActorTemplate::summon_index(&template.summons, target).is_none())
+            {
+                let names: Vec<&str> = template.summons.iter().map(|s| s.name.as_str()).collect();
+                errors.push(ValidationError::new(
+                    format!("{}.target", path),
+                    ErrorCode::UnknownTarget,
+                    format!("nothing to summon named '{}' (expected one of: {})", target, names.join(", ")),
+                ));
+            }
+        }
+    }
+    for (path, summon) in nested_statblocks(template, path) {
+        if summon.vip {
+            errors.push(ValidationError::new(
+                format!("{}vip", path),
+                ErrorCode::UnexpectedField,
+                "only the encounter's own actors can be VIPs".to_string(),
+            ));
+        }
+        validate_stats(summon, &path, errors);
+        validate_template_riders(summon, &path, errors);
+        for (i, entry) in summon.apl.iter().enumerate() {
+            validate_entry(entry, &format!("{}apl[{}]", path, i), enemy_names, errors);
+        }
+        for (i, entry) in summon.legendary_apl.iter().enumerate() {
+            validate_entry(entry, &format!("{}legendary_apl[{}]", path, i), enemy_names, errors);
+        }
+        validate_summons(summon, &path, enemy_names, errors);
+    }
+}
+
+/// The template's `summons` and `on_death` statblocks, each with its path
+fn nested_statblocks<'a>(template: &'a ActorTemplate, path: &str) -> Vec<(String, &'a ActorTemplate)> {
+    let on_death = template.on_death.iter().flat_map(|on_death| {
+        let spawn = on_death.spawn.as_deref().map(|t| (format!("{}on_death.spawn.", path), t));
+        let transform = on_death.transform.as_deref().map(|t| (format!("{}on_death.transform.", path), t));
+        spawn.into_iter().chain(transform)
+    });
+    let summons = template.summons.iter().enumerate().map(|(s, summon)| (format!("{}summons[{}].", path, s), summon));
+    summons.chain(on_death).collect()
+}
+
+/// Without the encounter's `called_shots`, no entry may aim at a hit location
+fn validate_called_shots(template: &ActorTemplate, path: &str, errors: &mut Vec<ValidationError>) {
+    let apls = [("apl", &template.apl), ("legendary_apl", &template.legendary_apl)];
+    for (field, apl) in apls {
+        for (i, _) in apl.iter().enumerate().filter(|(_, entry)| entry.called_shot.is_some()) {
+            errors.push(ValidationError::new(
+                format!("{}{}[{}].called_shot", path, field, i),
+                ErrorCode::UnexpectedField,
+                "called shots need `called_shots: true` on the encounter".to_string(),
+            ));
+        }
+    }
+    for (path, nested) in nested_statblocks(template, path) {
+        validate_called_shots(nested, &path, errors);
+    }
+}
+
+/// A legendary action is one action taken outside the actor's turn, with no budget to split
+fn validate_legendary_slots(template: &ActorTemplate, path: &str, errors: &mut Vec<ValidationError>) {
+    for (i, entry) in template.legendary_apl.iter().enumerate() {
+        let fields = [("uses", entry.uses.is_some()), ("after_action", entry.after_action)];
+        for (field, _) in fields.into_iter().filter(|&(_, set)| set) {
+            errors.push(ValidationError::new(
+                format!("{}legendary_apl[{}].{}", path, i, field),
+                ErrorCode::UnexpectedField,
+                format!("legendary actions don't take {}", field),
+            ));
+        }
+    }
+    for (path, nested) in nested_statblocks(template, path) {
+        validate_legendary_slots(nested, &path, errors);
+    }
+}
+
+/// Noncombatants can't have fighting actions anywhere in their APLs
+fn validate_noncombatant(template: &ActorTemplate, path: &str, errors: &mut Vec<ValidationError>) {
+    if template.noncombatant {
+        let apls = [("apl", &template.apl), ("legendary_apl", &template.legendary_apl)];
+        for (field, apl) in apls {
+            for (i, entry) in apl.iter().enumerate() {
+                let (key, action) = match &entry.ready {
+                    Some(ready) => ("ready", ready),
+                    None => ("action", &entry.action),
+                };
+                let action = action.trim().to_lowercase();
+                if matches!(action.as_str(), "attack" | "grapple" | "summon") {
+                    errors.push(ValidationError::new(
+                        format!("{}{}[{}].{}", path, field, i, key),
+                        ErrorCode::UnexpectedField,
+                        format!("noncombatants don't {}", action),
+                    ));
+                }
+            }
+        }
+    }
+    for (path, nested) in nested_statblocks(template, path) {
+        validate_noncombatant(nested, &path, errors);
+    }
+}
+
+/// Every side named outside the actor lists must be one the encounter has, and factions
+/// deploy at one end of the main line or the other
+fn validate_sides(encounter: &Encounter, errors: &mut Vec<ValidationError>) {
+    let count = encounter.factions.len() + 2;
+    let mut check = |path: String, side: Side| {
+        if side.index() >= count {
+            errors.push(ValidationError::new(
+                path,
+                ErrorCode::UnknownSide,
+                format!("unknown side '{}': the encounter has side1 to side{}", side, count),
+            ));
+        }
+    };
+    check("defender".to_string(), encounter.defender);
+    for (i, objective) in encounter.objectives.iter().enumerate() {
+        check(format!("objectives[{}].side", i), objective.side());
+    }
+    for (side, hostile) in &encounter.hostility {
+        check(format!("hostility.{}", side), *side);
+        for (i, &other) in hostile.iter().enumerate() {
+            check(format!("hostility.{}[{}]", side, i), other);
+        }
+    }
+    for (side, hostile) in &encounter.hostility {
+        if let Some(i) = hostile.iter().position(|other| other == side) {
+            errors.push(ValidationError::new(
+                format!("hostility.{}[{}]", side, i),
+                ErrorCode::OutOfRange,
+                format!("{} can't fight itself", side),
+            ));
+        }
+    }
+    for (i, faction) in encounter.factions.iter().enumerate() {
+        if faction.deploys.opposite().is_none() {
+            errors.push(ValidationError::new(
+                format!("factions[{}].deploys", i),
+                ErrorCode::OutOfRange,
+                format!("factions deploy at side1's or side2's end of the line, not {}'s", faction.deploys),
+            ));
+        }
+    }
+}
+
+/// Run-wide settings: every side populated, a sane iteration count, and valid dice
+fn validate_settings(encounter: &Encounter, errors: &mut Vec<ValidationError>) {
+    if encounter.factions.len() + 2 > MAX_FACTIONS {
+        errors.push(ValidationError::new(
+            "factions",
+            ErrorCode::OutOfRange,
+            format!("at most {} factions besides side1 and side2", MAX_FACTIONS - 2),
+        ));
+        return;
+    }
+    for side in encounter.sides() {
+        // Factions can be made up of noncombatants alone, but side1 and side2 need someone to fight
+        let fielded: usize = encounter.templates(side).iter().map(|t| t.count as usize).sum();
+        if fielded == 0 {
+            errors.push(ValidationError::new(side.path(), ErrorCode::EmptySide, format!("{} has no actors", side)));
+        } else if side.opposite().is_some() && encounter.actor_count(side) == 0 {
+            errors.push(ValidationError::new(
+                side.path(),
+                ErrorCode::EmptySide,
+                format!("{} has only noncombatants", side),
+            ));
+        }
+    }
+    validate_sides(encounter, errors);
+    if encounter.iterations == 0 {
+        errors.push(ValidationError::new("iterations", ErrorCode::OutOfRange, "iterations must be at least 1"));
+    } else if encounter.iterations > MAX_ITERATIONS {
+        errors.push(ValidationError::new(
+            "iterations",
+            ErrorCode::TooManyIterations,
+            format!("iterations must be at most {}", MAX_ITERATIONS),
+        ));
+    }
+    errors.extend(validate_max_rounds(encounter.max_rounds));
+    if encounter.front_width == Some(0) {
+        errors.push(ValidationError::new("front_width", ErrorCode::OutOfRange, "front_width must be at least 1"));
+    }
+    if let Err(e) = parse_damage_dice(&encounter.initiative.dice) {
+        errors.push(ValidationError::new(
+            "initiative.dice",
+            ErrorCode::InvalidDice,
+            format!("invalid dice '{}': {}", encounter.initiative.dice, e),
+        ));
+    }
+}
+
+/// A round limit, whether an encounter's own or a request's override of it
+pub fn validate_max_rounds(max_rounds: u32) -> Option<ValidationError> {
+    (max_rounds == 0 || max_rounds > MAX_ROUNDS).then(|| {
+        let message = format!("max_rounds must be between 1 and {}", MAX_ROUNDS);
+        ValidationError::new("max_rounds", ErrorCode::OutOfRange, message)
+    })
+}
+
+/// An actor's own numbers: HP, AC, speed, frontage, and count
+fn validate_stats(template: &ActorTemplate, path: &str, errors: &mut Vec<ValidationError>) {
+    let mut error = |field: &str, code: ErrorCode, message: String| {
+        errors.push(ValidationError::new(format!("{}{}", path, field), code, message));
+    };
+    match &template.hp {
+        HpValue::Fixed(hp) if *hp < 1 => error("hp", ErrorCode::OutOfRange, "hp must be at least 1".to_string()),
+        HpValue::Dice(dice) => {
+            if let Err(e) = parse_damage_dice(dice) {
+                error("hp", ErrorCode::InvalidDice, format!("invalid dice '{}': {}", dice, e));
+            }
+        }
+        _ => {}
+    }
+    if template.damage.largest_constant() > MAX_DAMAGE_CONSTANT {
+        error("damage", ErrorCode::OutOfRange, format!("damage constants must be at most {}", MAX_DAMAGE_CONSTANT));
+    }
+    if template.ac < 0 {
+        error("ac", ErrorCode::OutOfRange, "ac must not be negative".to_string());
+    }
+    if template.damage_reduction < 0 {
+        error("damage_reduction", ErrorCode::OutOfRange, "damage_reduction must not be negative".to_string());
+    }
+    if template.speed == 0 {
+        error("speed", ErrorCode::OutOfRange, "speed must be at least 1".to_string());
+    }
+    if template.frontage == 0 {
+        error("frontage", ErrorCode::OutOfRange, "frontage must be at least 1".to_string());
+    }
+    if template.count == 0 {
+        error("count", ErrorCode::OutOfRange, "count must be at least 1".to_string());
+    }
+}
+
+/// A custom layout needs at least two zones on the main line. Every zone needs a unique name,
+/// a length of at least 1, and sensible terrain; links must name known zones, and lanes must be
+/// linked in.
+fn validate_zones(encounter: &Encounter) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    let mut error = |path: String, code: ErrorCode, message: String| {
+        errors.push(ValidationError::new(path, code, message));
+    };
+
+    if encounter.zones.len() == 1 {
+        error("zones".to_string(), ErrorCode::InvalidLayout, "a zone layout needs at least two zones".to_string());
+    }
+    for (l, lane) in encounter.lanes.iter().enumerate() {
+        if lane.zones.is_empty() {
+            error(format!("lanes[{}].zones", l), ErrorCode::InvalidLayout, "a lane needs at least one zone".to_string());
+        }
+    }
+
+    // Paths line up with the layout's zone order: main line first, then each lane
+    let layout = encounter.zone_layout();
+    let lane_zones: usize = encounter.lanes.iter().map(|lane| lane.zones.len()).sum();
+    let main_paths: Vec<String> = if encounter.zones.is_empty() {
+        // The default tunnel is always valid, but lanes may link to it
+        vec![String::new(); layout.len() - lane_zones]
+    } else {
+        (0..encounter.zones.len()).map(|i| format!("zones[{}]", i)).collect()
+    };
+    let lane_paths = encounter.lanes.iter().enumerate().flat_map(|(l, lane)| {
+        (0..lane.zones.len()).map(move |i| format!("lanes[{}].zones[{}]", l, i))
+    });
+    let paths: Vec<String> = main_paths.into_iter().chain(lane_paths).collect();
+
+    let specs = layout.zones();
+    for (i, (zone, path)) in specs.iter().zip(&paths).enumerate() {
+        if path.is_empty() {
+            continue;
+        }
+        if zone.name.trim().is_empty() {
+            error(format!("{}.name", path), ErrorCode::MissingField, "zone name must not be empty".to_string());
+        } else if specs[..i].iter().any(|z| z.name.eq_ignore_ascii_case(&zone.name)) {
+            error(format!("{}.name", path), ErrorCode::DuplicateName, "duplicate zone name".to_string());
+        }
+        if zone.length == 0 {
+            error(format!("{}.length", path), ErrorCode::OutOfRange, "zone length must be at least 1".to_string());
+        }
+        if zone.terrain.cover < 0 {
+            error(format!("{}.terrain.cover", path), ErrorCode::OutOfRange, "cover must not be negative".to_string());
+        }
+        if let Some(HpValue::Dice(dice)) = &zone.terrain.hazard {
+            if let Err(e) = parse_damage_dice(dice) {
+                error(format!("{}.terrain.hazard", path), ErrorCode::InvalidDice, format!("invalid dice '{}': {}", dice, e));
+            }
+        }
+        for link in &zone.links {
+            if layout.find(link).is_none() {
+                error(format!("{}.links", path), ErrorCode::UnknownZone, format!("unknown zone '{}'", link));
+            }
+        }
+    }
+
+    for (l, lane) in encounter.lanes.iter().enumerate() {
+        let unreachable = lane.zones.iter().any(|z| {
+            layout
+                .find(&z.name)
+                .is_some_and(|zone| layout.distance(Zone(0), zone) == u32::MAX)
+        });
+        if unreachable {
+            let name = match &lane.name {
+                Some(name) => format!("lane '{}'", name),
+                None => "lane".to_string(),
+            };
+            error(
+                format!("lanes[{}]", l),
+                ErrorCode::InvalidLayout,
+                format!("{} is not linked to the main zones (add `links` to one of its zones)", name),
+            );
+        }
+    }
+    errors
+}
+
+/// Validate a bare list of APL entries, such as a turn submitted in interactive play
+pub fn validate_apl(apl: &[AplEntry]) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    for (i, entry) in apl.iter().enumerate() {
+        validate_entry(entry, &format!("[{}]", i), None, &mut errors);
+    }
+    errors
+}
+
+/// Validate a standalone template, e.g. a monster library entry. There is no opposing side
+/// to check against, so any `name:` target is accepted.
+pub fn validate_template(template: &ActorTemplate) -> Vec<ValidationError> {
+    let mut errors = Vec::new();
+    validate_stats(template, "", &mut errors);
+    validate_template_riders(template, "", &mut errors);
+    for (i, entry) in template.apl.iter().enumerate() {
+        validate_entry(entry, &format!("apl[{}]", i), None, &mut errors);
+    }
+    for (i, entry) in template.legendary_apl.iter().enumerate() {
+        validate_entry(entry, &format!("legendary_apl[{}]", i), None, &mut errors);
+    }
+    validate_summons(template, "", None, &mut errors);
+    errors
+}
+
+fn validate_entry(entry: &AplEntry, path: &str, enemy_names: Option<&[&str]>, errors: &mut Vec<ValidationError>) {
+    let mut error = |field: &str, code: ErrorCode, message: String| {
+        errors.push(ValidationError::new(format!("{}.{}", path, field), code, message));
+    };
+
+    if let Some(ready) = &entry.ready {
+        validate_reaction(entry, ready, &mut error);
+        return;
+    }
+    if entry.trigger.is_some() {
+        error("trigger", ErrorCode::UnexpectedField, "trigger needs a `ready` action".to_string());
+    }
+    if entry.held {
+        error("held", ErrorCode::UnexpectedField, "held only applies to reactions (entries with `ready`)".to_string());
+    }
+
+    let action = entry.action.trim().to_lowercase();
+    if action.is_empty() {
+        error("action", ErrorCode::MissingField, format!("action is required (expected one of: {})", ACTIONS.join(", ")));
+    } else if !ACTIONS.contains(&action.as_str()) {
+        error(
+            "action",
+            ErrorCode::UnknownAction,
+            format!("unknown action '{}' (expected one of: {})", entry.action, ACTIONS.join(", ")),
+        );
+    }
+
+    if let Some(condition) = &entry.condition {
+        if let Err(message) = parse_condition(condition) {
+            error("if", ErrorCode::InvalidCondition, message);
+        }
+    }
+
+    // Summon targets name the actor's own `summons`, checked by `validate_summons`
+    if let Some(target) = entry.target.as_ref().filter(|_| action != "summon") {
+        let target_lower = target.trim().to_lowercase();
+        let allowed: Vec<&str> = match action.as_str() {
+            "attack" | "grapple" => ENEMY_TARGETS.to_vec(),
+            "move" | "dash" => ENEMY_TARGETS.iter().chain(MOVE_DIRECTIONS).copied().collect(),
+            "buff" => ALLY_TARGETS.to_vec(),
+            _ => Vec::new(),
+        };
+        let enemy_targeting = matches!(action.as_str(), "attack" | "move" | "dash" | "grapple");
+        let named = target_lower.strip_prefix("name:").map(str::trim);
+        let by_id = enemy_targeting && actor_id(&target_lower).is_some();
+        if allowed.is_empty() {
+            if ACTIONS.contains(&action.as_str()) {
+                error("target", ErrorCode::UnexpectedField, format!("action '{}' does not take a target", action));
+            }
+        } else if let Some(name) = named.filter(|_| enemy_targeting) {
+            if let Some(enemy_names) = enemy_names.filter(|names| !names.iter().any(|n| n.eq_ignore_ascii_case(name))) {
+                error(
+                    "target",
+                    ErrorCode::UnknownTarget,
+                    format!("no enemy named '{}' (expected one of: {})", name, enemy_names.join(", ")),
+                );
+            }
+        } else if !allowed.contains(&target_lower.as_str()) && !by_id {
+            error(
+                "target",
+                ErrorCode::UnknownTarget,
+                format!("unknown target '{}' for {} (expected one of: {})", target, action, allowed.join(", ")),
+            );
+        }
+    }
+
+    if !entry.on_hit.is_empty() && action != "attack" {
+        error("on_hit", ErrorCode::UnexpectedField, "on_hit only applies to attack".to_string());
+    }
+    if entry.called_shot.is_some() && action != "attack" {
+        error("called_shot", ErrorCode::UnexpectedField, "called_shot only applies to attack".to_string());
+    }
+    for (code, message) in entry.on_hit.iter().flat_map(rider_errors) {
+        error("on_hit", code, message);
+    }
+    if entry.uses.is_some() && !SLOTTED_ACTIONS.contains(&action.as_str()) {
+        error(
+            "uses",
+            ErrorCode::UnexpectedField,
+            format!("{} takes the whole action (uses applies to: {})", action, SLOTTED_ACTIONS.join(", ")),
+        );
+    }
+    if entry.after_action && !matches!(action.as_str(), "move" | "flee" | "withdraw") {
+        error("after_action", ErrorCode::UnexpectedField, "after_action only applies to move, flee, and withdraw".to_string());
+    }
+
+    if action == "buff" || action == "summon" {
+        match &entry.amount {
+            None if action == "buff" => error("amount", ErrorCode::MissingField, "buff requires an amount".to_string()),
+            Some(HpValue::Dice(dice)) => {
+                if let Err(e) = parse_damage_dice(dice) {
+                    error("amount", ErrorCode::InvalidDice, format!("invalid dice '{}': {}", dice, e));
+                }
+            }
+            Some(HpValue::Fixed(n)) if action == "summon" && *n < 1 => {
+                error("amount", ErrorCode::OutOfRange, "summon amount must be at least 1".to_string());
+            }
+            _ => {}
+        }
+    }
+}
+
+/// A save needs a DC, only bleeding takes (and needs) damage, and only damage has a tick
+fn rider_errors(rider: &OnHit) -> Vec<(ErrorCode, String)> {
+    let mut errors = Vec::new();
+    let OnHit::Condition(rider) = rider else {
+        return errors;
+    };
+    if rider.save.is_some() && rider.dc < 1 {
+        errors.push((ErrorCode::OutOfRange, "a save needs a dc of at least 1".to_string()));
+    }
+    if rider.duration == Some(0) {
+        errors.push((ErrorCode::OutOfRange, "duration must be at least 1 round".to_string()));
+    }
+    match (&rider.damage, rider.effect) {
+        (None, ConditionKind::Bleeding) => errors.push((ErrorCode::MissingField, "bleeding needs damage".to_string())),
+        (Some(_), kind) if kind != ConditionKind::Bleeding => {
+            errors.push((ErrorCode::UnexpectedField, format!("{} does not take damage", kind)));
+        }
+        (Some(HpValue::Dice(dice)), _) => {
+            if let Err(e) = parse_damage_dice(dice) {
+                errors.push((ErrorCode::InvalidDice, format!("invalid dice '{}': {}", dice, e)));
+            }
+        }
+        _ => {}
+    }
+    if rider.tick.is_some() && rider.damage.is_none() {
+        errors.push((ErrorCode::UnexpectedField, "tick only applies to a condition with damage".to_string()));
+    }
+    errors
+}
+
+/// Riders, drain, and script set on the template itself, outside any APL entry
+fn validate_template_riders(template: &ActorTemplate, path: &str, errors: &mut Vec<ValidationError>) {
+    let mut error = |field: &str, code: ErrorCode, message: String| {
+        errors.push(ValidationError::new(format!("{}{}", path, field), code, message));
+    };
+    for (code, message) in template.on_hit.iter().flat_map(rider_errors) {
+        error("on_hit", code, message);
+    }
+    match &template.drain {
+        Some(HpValue::Dice(dice)) => {
+            if let Err(e) = parse_damage_dice(dice) {
+                error("drain", ErrorCode::InvalidDice, format!("invalid dice '{}': {}", dice, e));
+            }
+        }
+        Some(HpValue::Fixed(amount)) if *amount < 1 => {
+            error("drain", ErrorCode::OutOfRange, "drain must be at least 1".to_string());
+        }
+        None if template.drain_only => {
+            error("drain_only", ErrorCode::MissingField, "drain_only needs a drain amount".to_string());
+        }
+        _ => {}
+    }
+    if let Some(Err(message)) = template.script.as_deref().map(script::check) {
+        error("script", ErrorCode::InvalidScript, message);
+    }
+}
+
+/// A reaction replaces `action` with `ready`, needs a trigger, and always targets whoever set
+/// it off
+fn validate_reaction(entry: &AplEntry, ready: &str, error: &mut impl FnMut(&str, ErrorCode, String)) {
+    if !entry.action.is_empty() {
+        error("action", ErrorCode::UnexpectedField, "a reaction uses `ready` instead of `action`".to_string());
+    }
+    if !REACTIONS.contains(&ready.trim().to_lowercase().as_str()) {
+        error(
+            "ready",
+            ErrorCode::UnknownReaction,
+            format!("unknown reaction '{}' (expected one of: {})", ready, REACTIONS.join(", ")),
+        );
+    }
+    if entry.trigger.is_none() {
+        error("trigger", ErrorCode::MissingField, "a reaction needs a trigger".to_string());
+    }
+    if entry.target.is_some() {
+        error("target", ErrorCode::UnexpectedField, "reactions target whoever triggered them".to_string());
+    }
+    if !entry.on_hit.is_empty() && ready.trim().to_lowercase() != "attack" {
+        error("on_hit", ErrorCode::UnexpectedField, "on_hit only applies to attack".to_string());
+    }
+    if entry.called_shot.is_some() && ready.trim().to_lowercase() != "attack" {
+        error("called_shot", ErrorCode::UnexpectedField, "called_shot only applies to attack".to_string());
+    }
+    for (code, message) in entry.on_hit.iter().flat_map(rider_errors) {
+        error("on_hit", code, message);
+    }
+    if entry.uses.is_some() {
+        error("uses", ErrorCode::UnexpectedField, "reactions spend the actor's reaction".to_string());
+    }
+    if entry.after_action {
+        error("after_action", ErrorCode::UnexpectedField, "reactions happen outside the actor's turn".to_string());
+    }
+    if let Some(condition) = &entry.condition {
+        if let Err(message) = parse_condition(condition) {
+            error("if", ErrorCode::InvalidCondition, message);
+        }
+    }
+}
+
+/// Best-effort lookup of the 1-based YAML line for a path like `side1[0].apl[1].action`.
+/// Only block-style YAML is understood; returns None if the path can't be found.
+pub fn locate_line(yaml: &str, path: &str) -> Option<usize> {
+    // (indent, content) per line, with blank lines and comments skipped later
+    let mut lines: Vec<(usize, String)> = yaml
+        .lines()
+        .map(|l| {
+            let content = l.trim_start();
+            (l.len() - content.len(), content.to_string())
+        })
+        .collect();
+
+    let is_code = |content: &str| !content.is_empty() && !content.starts_with('#');
+
+    let mut start = 0;
+    let mut end = lines.len();
+    let mut found = None;
+
+    for segment in path.split('.') {
+        let (key, index) = match segment.find('[') {
+            Some(idx) => {
+                let index = segment[idx + 1..].trim_end_matches(']').parse::<usize>().ok()?;
+                (&segment[..idx], Some(index))
+            }
+            None => (segment, None),
+        };
+
+        // Find `key:` at the shallowest indentation within the current range
+        let base_indent = (start..end).find(|&i| is_code(&lines[i].1)).map(|i| lines[i].0)?;
+        let key_prefix = format!("{}:", key);
+        let key_line = (start..end).find(|&i| {
+            lines[i].0 == base_indent && lines[i].1.starts_with(&key_prefix)
+        });
+        // A missing field (e.g. `amount`) points at the closest enclosing entry instead
+        let Some(key_line) = key_line else {
+            return found.map(|i| i + 1);
+        };
+        found = Some(key_line);
+
+        // Children are the following lines indented deeper (or list items at the same indent)
+        let key_indent = lines[key_line].0;
+        start = key_line + 1;
+        end = (start..end)
+            .find(|&i| {
+                let (indent, content) = &lines[i];
+                is_code(content) && (*indent < key_indent || (*indent == key_indent && !content.starts_with("- ")))
+            })
+            .unwrap_or(end);
+
+        if let Some(index) = index {
+            let item_indent = (start..end).find(|&i| is_code(&lines[i].1)).map(|i| lines[i].0)?;
+            let items: Vec<usize> = (start..end)
+                .filter(|&i| lines[i].0 == item_indent && lines[i].1.starts_with("- "))
+                .collect();
+            let item_line = *items.get(index)?;
+            found = Some(item_line);
+            start = item_line;
+            end = items.get(index + 1).copied().unwrap_or(end);
+            // Treat "- key: value" as "  key: value" so the item's keys line up
+            let (indent, content) = &mut lines[item_line];
+            *indent += 2;
+            *content = content[2..].trim_start().to_string();
+        }
+    }
+
+    found.map(|i| i + 1)
+}
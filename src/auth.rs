@@ -0,0 +1,232 @@
+//! Who may use the server and how often: optional API keys, checked on every request but the
+//! `meta` ones, and a token bucket per client on the endpoints that run simulations.
+
+use std::borrow::Cow;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use axum::{
+    extract::{ConnectInfo, Request, State},
+    http::{header, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use lru::LruCache;
+
+use tunnel_fight::apl::ErrorCode;
+
+use crate::api::rejection;
+
+/// Carries the key for clients that can't set `Authorization`
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Clients whose buckets are remembered; past that the one seen least recently starts over
+const MAX_CLIENTS: usize = 10_000;
+
+/// The server's keys and rate limit, shared by the middleware
+pub struct Access {
+    /// Keys a request may present; empty leaves the server open
+    keys: Vec<String>,
+    /// None when requests aren't rate limited
+    limiter: Option<RateLimiter>,
+}
+
+impl Access {
+    /// `per_minute` of 0 turns rate limiting off; `burst` is how many requests a client may
+    /// make at once before it's held to the rate
+    pub fn new(keys: Vec<String>, per_minute: u32, burst: u32) -> Self {
+        let limiter = (per_minute > 0).then(|| RateLimiter {
+            per_second: f64::from(per_minute) / 60.0,
+            burst: f64::from(burst.max(1)),
+            buckets: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_CLIENTS).expect("nonzero"))),
+        });
+        Access { keys, limiter }
+    }
+
+    fn accepts(&self, key: &str) -> bool {
+        self.keys.iter().any(|k| same_key(k, key))
+    }
+}
+
+/// A bucket per client holding up to `burst` requests, refilling at `per_second`
+struct RateLimiter {
+    per_second: f64,
+    burst: f64,
+    buckets: Mutex<LruCache<String, Bucket>>,
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl RateLimiter {
+    /// Spend one request from the client's bucket as of `now`, or say how long until it has one
+    fn take(&self, client: String, now: Instant) -> Result<(), Duration> {
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        let bucket = buckets.get_or_insert_mut(client, || Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let refilled = now.duration_since(bucket.updated).as_secs_f64() * self.per_second;
+        bucket.tokens = (bucket.tokens + refilled).min(self.burst);
+        bucket.updated = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / self.per_second))
+        }
+    }
+}
+
+/// Turn away a request without a valid key with a 401, when the server has keys
+pub async fn require_key(State(access): State<Arc<Access>>, request: Request, next: Next) -> Response {
+    if access.keys.is_empty() || presented_key(&request).is_some_and(|key| access.accepts(&key)) {
+        return next.run(request).await;
+    }
+    tracing::warn!("request refused: missing or unknown API key");
+    let (status, body) = rejection(
+        StatusCode::UNAUTHORIZED,
+        ErrorCode::Unauthorized,
+        "This server needs an API key: send `Authorization: Bearer <key>`".to_string(),
+    );
+    (status, [(header::WWW_AUTHENTICATE, "Bearer")], body).into_response()
+}
+
+/// Turn away a client that's over its rate with a 429 and a `Retry-After`. Clients are told
+/// apart by their key, or by address on a server without keys.
+pub async fn rate_limit(State(access): State<Arc<Access>>, request: Request, next: Next) -> Response {
+    let Some(limiter) = &access.limiter else {
+        return next.run(request).await;
+    };
+    let client = match presented_key(&request).filter(|key| access.accepts(key)) {
+        Some(key) => format!("key {}", key),
+        None => match request.extensions().get::<ConnectInfo<SocketAddr>>() {
+            Some(ConnectInfo(addr)) => format!("address {}", addr.ip()),
+            None => "anyone".to_string(),
+        },
+    };
+    match limiter.take(client, Instant::now()) {
+        Ok(()) => next.run(request).await,
+        Err(wait) => {
+            let retry_after = (wait.as_secs_f64().ceil() as u64).max(1);
+            tracing::warn!(retry_after, "request refused: rate limited");
+            let (status, body) = rejection(
+                StatusCode::TOO_MANY_REQUESTS,
+                ErrorCode::RateLimited,
+                format!("Too many requests; try again in {}s", retry_after),
+            );
+            (status, [(header::RETRY_AFTER, retry_after.to_string())], body).into_response()
+        }
+    }
+}
+
+/// The key from `Authorization: Bearer <key>`, `x-api-key`, or an `api_key` query parameter
+/// (for WebSocket clients, which can't set headers), percent-decoded
+fn presented_key(request: &Request) -> Option<Cow<'_, str>> {
+    let headers = request.headers();
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    let header_key = headers.get(API_KEY_HEADER).and_then(|value| value.to_str().ok());
+    let query_key = || {
+        form_urlencoded::parse(request.uri().query()?.as_bytes())
+            .find(|(name, _)| name == "api_key")
+            .map(|(_, key)| key)
+    };
+    bearer.or(header_key).map(|key| Cow::Borrowed(key.trim())).or_else(query_key)
+}
+
+/// Compare keys in time that doesn't depend on where they first differ
+fn same_key(expected: &str, given: &str) -> bool {
+    expected.len() == given.len() && expected.bytes().zip(given.bytes()).fold(0, |diff, (a, b)| diff | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, middleware::from_fn_with_state, routing::get, Router};
+    use tower::ServiceExt;
+
+    /// One route behind both middlewares, layered as `create_router` does
+    fn router(access: Access) -> Router {
+        let access = Arc::new(access);
+        Router::new()
+            .route("/", get(|| async { "ok" }))
+            .route_layer(from_fn_with_state(Arc::clone(&access), rate_limit))
+            .route_layer(from_fn_with_state(access, require_key))
+    }
+
+    async fn call(router: &Router, uri: &str, key: Option<&str>, from: [u8; 4]) -> Response {
+        let mut request = Request::builder().uri(uri);
+        if let Some(key) = key {
+            request = request.header(header::AUTHORIZATION, format!("Bearer {}", key));
+        }
+        let mut request = request.body(Body::empty()).unwrap();
+        request.extensions_mut().insert(ConnectInfo(SocketAddr::from((from, 4000))));
+        router.clone().oneshot(request).await.unwrap()
+    }
+
+    #[test]
+    fn buckets_refill_at_the_rate_up_to_the_burst() {
+        let limiter = Access::new(Vec::new(), 60, 2).limiter.unwrap();
+        let start = Instant::now();
+        let take = |at: Duration| limiter.take("client".to_string(), start + at);
+
+        assert_eq!(take(Duration::ZERO), Ok(()));
+        assert_eq!(take(Duration::ZERO), Ok(()));
+        assert_eq!(take(Duration::ZERO), Err(Duration::from_secs(1)));
+        assert_eq!(take(Duration::from_millis(500)), Err(Duration::from_millis(500)));
+        assert_eq!(take(Duration::from_secs(1)), Ok(()));
+
+        // A long wait fills the bucket only to the burst
+        assert_eq!(take(Duration::from_secs(100)), Ok(()));
+        assert_eq!(take(Duration::from_secs(100)), Ok(()));
+        assert!(take(Duration::from_secs(100)).is_err());
+    }
+
+    #[tokio::test]
+    async fn requests_without_a_key_get_a_401() {
+        let router = router(Access::new(vec!["s3cret+key".to_string()], 0, 1));
+
+        for key in [None, Some("wrong")] {
+            let response = call(&router, "/", key, [10, 0, 0, 1]).await;
+            assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+            assert_eq!(response.headers()[header::WWW_AUTHENTICATE], "Bearer");
+        }
+        assert_eq!(call(&router, "/", Some("s3cret+key"), [10, 0, 0, 1]).await.status(), StatusCode::OK);
+        let query = "/?api_key=s3cret%2Bkey";
+        assert_eq!(call(&router, query, None, [10, 0, 0, 1]).await.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn over_the_rate_gets_a_429_with_retry_after() {
+        let router = router(Access::new(Vec::new(), 1, 1));
+
+        assert_eq!(call(&router, "/", None, [10, 0, 0, 1]).await.status(), StatusCode::OK);
+        let response = call(&router, "/", None, [10, 0, 0, 1]).await;
+        assert_eq!(response.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "60");
+    }
+
+    #[tokio::test]
+    async fn clients_are_told_apart_by_key_before_address() {
+        let keyed = router(Access::new(vec!["one".to_string(), "two".to_string()], 1, 1));
+        assert_eq!(call(&keyed, "/", Some("one"), [10, 0, 0, 1]).await.status(), StatusCode::OK);
+        // The same key from elsewhere shares its bucket; another key from the same address doesn't
+        assert_eq!(
+            call(&keyed, "/", Some("one"), [10, 0, 0, 2]).await.status(),
+            StatusCode::TOO_MANY_REQUESTS
+        );
+        assert_eq!(call(&keyed, "/", Some("two"), [10, 0, 0, 1]).await.status(), StatusCode::OK);
+
+        let open = router(Access::new(Vec::new(), 1, 1));
+        assert_eq!(call(&open, "/", None, [10, 0, 0, 1]).await.status(), StatusCode::OK);
+        assert_eq!(call(&open, "/", None, [10, 0, 0, 1]).await.status(), StatusCode::TOO_MANY_REQUESTS);
+        assert_eq!(call(&open, "/", None, [10, 0, 0, 2]).await.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,139 @@
+//! Built-in player characters: the four B/X classes at levels 1-10, for a baseline party
+//! without writing statblocks. Encounters pick one up with `ref: builtin/fighter_3`.
+
+use serde::Serialize;
+use serde_json::json;
+use utoipa::ToSchema;
+
+use crate::types::ActorTemplate;
+
+/// Prefix that sends a `ref:` to this catalog instead of the monster library
+pub const BUILTIN_PREFIX: &str = "builtin/";
+
+pub const MAX_LEVEL: u32 = 10;
+
+/// One class's progression and kit
+struct Class {
+    key: &'static str,
+    name: &'static str,
+    /// Sides of the hit die rolled at each of levels 1-9
+    hit_die: u32,
+    /// Fixed HP per level past 9th
+    hp_after_ninth: u32,
+    /// (highest level, THAC0) bands from the B/X attack table
+    thac0: &'static [(u32, i32)],
+    /// Ascending AC of the class's usual armour
+    ac: i32,
+    damage: &'static str,
+    range: &'static str,
+    start_zone: &'static str,
+}
+
+const CLASSES: [Class; 4] = [
+    Class {
+        key: "fighter",
+        name: "Fighter",
+        hit_die: 8,
+        hp_after_ninth: 2,
+        thac0: &[(3, 19), (6, 17), (9, 14), (12, 12)],
+        // Plate mail and shield
+        ac: 17,
+        // Sword
+        damage: "1d8",
+        range: "melee",
+        start_zone: "melee",
+    },
+    Class {
+        key: "cleric",
+        name: "Cleric",
+        hit_die: 6,
+        hp_after_ninth: 1,
+        thac0: &[(4, 19), (8, 17), (12, 14)],
+        // Chain mail and shield
+        ac: 15,
+        // Mace
+        damage: "1d6",
+        range: "melee",
+        start_zone: "melee",
+    },
+    Class {
+        key: "thief",
+        name: "Thief",
+        hit_die: 4,
+        hp_after_ninth: 2,
+        thac0: &[(4, 19), (8, 17), (12, 14)],
+        // Leather armour
+        ac: 12,
+        // Short sword
+        damage: "1d6",
+        range: "melee",
+        start_zone: "reach",
+    },
+    Class {
+        key: "magic_user",
+        name: "Magic-User",
+        hit_die: 4,
+        hp_after_ninth: 1,
+        thac0: &[(5, 19), (10, 17)],
+        ac: 10,
+        // Thrown daggers
+        damage: "1d4",
+        range: "ranged",
+        start_zone: "ranged",
+    },
+];
+
+/// A catalog entry as listed by `GET /builtins`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Builtin {
+    /// What to write after `ref:`, e.g. `builtin/fighter_3`
+    #[serde(rename = "ref")]
+    pub reference: String,
+    pub class: String,
+    pub level: u32,
+    pub template: ActorTemplate,
+}
+
+impl Class {
+    fn template(&self, level: u32) -> ActorTemplate {
+        let dice = level.min(9);
+        let hp = match level.saturating_sub(9) * self.hp_after_ninth {
+            0 => format!("{}d{}", dice, self.hit_die),
+            bonus => format!("{}d{}+{}", dice, self.hit_die, bonus),
+        };
+        let thac0 = self.thac0.iter().find(|&&(up_to, _)| level <= up_to).map_or(12, |&(_, thac0)| thac0);
+        let template = json!({
+            "name": format!("Level {} {}", level, self.name),
+            "hp": hp,
+            "ac": self.ac,
+            "attack_bonus": 19 - thac0,
+            "damage": self.damage,
+            "range": self.range,
+            "start_zone": self.start_zone,
+        });
+        serde_json::from_value(template).expect("built-in templates are well-formed")
+    }
+}
+
+/// The template for a key like `fighter_3` (without the `builtin/` prefix)
+pub fn get(key: &str) -> Option<ActorTemplate> {
+    let (class, level) = key.rsplit_once('_')?;
+    let level: u32 = level.parse().ok().filter(|level| (1..=MAX_LEVEL).contains(level))?;
+    let class = CLASSES.iter().find(|c| c.key.eq_ignore_ascii_case(class))?;
+    Some(class.template(level))
+}
+
+/// Every class at every level, by class then level
+pub fn catalog() -> Vec<Builtin> {
+    CLASSES
+        .iter()
+        .flat_map(|class| {
+            (1..=MAX_LEVEL).map(move |level| Builtin {
+                reference: format!("{}{}_{}", BUILTIN_PREFIX, class.key, level),
+                class: class.name.to_string(),
+                level,
+                template: class.template(level),
+            })
+        })
+        .collect()
+}
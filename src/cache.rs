@@ -0,0 +1,70 @@
+//! Recently computed `/simulate` results, so a client that sends the same encounter again (a UI
+//! re-rendering, say) gets its answer without re-running every fight.
+
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+use lru::LruCache;
+
+use tunnel_fight::combat::EventDetail;
+use tunnel_fight::simulation::SimulationOptions;
+use tunnel_fight::stats::SimulationResult;
+use tunnel_fight::types::Encounter;
+
+/// Results kept when `RESULT_CACHE_SIZE` isn't set
+pub const DEFAULT_CAPACITY: usize = 64;
+
+/// Everything a result depends on. The encounter is keyed by its parsed form re-serialized, so
+/// YAML, JSON, and TOML spellings of one encounter share an entry.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CacheKey {
+    encounter: String,
+    iterations: u32,
+    /// The seed the request asked for; seedless requests share whatever seed ran first
+    seed: Option<u64>,
+    max_rounds: u32,
+    sample_count: usize,
+    target_precision: Option<u64>,
+    event_detail: EventDetail,
+}
+
+impl CacheKey {
+    /// None if the encounter can't be serialized, which leaves the run uncached
+    pub fn new(encounter: &Encounter, requested_seed: Option<u64>, options: &SimulationOptions) -> Option<Self> {
+        Some(CacheKey {
+            encounter: serde_json::to_string(encounter).ok()?,
+            iterations: options.iterations,
+            seed: requested_seed,
+            max_rounds: options.max_rounds,
+            sample_count: options.sample_count,
+            target_precision: options.target_precision.map(f64::to_bits),
+            event_detail: options.event_detail,
+        })
+    }
+}
+
+/// Least-recently-used results, shared by every request
+pub struct ResultCache {
+    /// None when caching is turned off (`RESULT_CACHE_SIZE=0`)
+    entries: Option<Mutex<LruCache<CacheKey, Arc<SimulationResult>>>>,
+}
+
+impl ResultCache {
+    /// Keeps up to `capacity` results; 0 turns caching off
+    pub fn new(capacity: usize) -> Self {
+        ResultCache {
+            entries: NonZeroUsize::new(capacity).map(|capacity| Mutex::new(LruCache::new(capacity))),
+        }
+    }
+
+    pub fn get(&self, key: &CacheKey) -> Option<Arc<SimulationResult>> {
+        let mut entries = self.entries.as_ref()?.lock().unwrap_or_else(|e| e.into_inner());
+        entries.get(key).cloned()
+    }
+
+    pub fn put(&self, key: CacheKey, result: Arc<SimulationResult>) {
+        if let Some(entries) = &self.entries {
+            entries.lock().unwrap_or_else(|e| e.into_inner()).put(key, result);
+        }
+    }
+}
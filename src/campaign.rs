@@ -0,0 +1,113 @@
+//! Adventuring days: encounters fought in order with rests between some of them. The party
+//! (side 1 of every encounter) carries its damage and its dead from fight to fight; a rest
+//! heals the survivors but never raises the fallen.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::sequence::{run_stages, AttritionStep, Stage};
+use crate::simulation::SimulationOptions;
+use crate::types::{Encounter, HpValue};
+
+/// A day's encounters and rests, in order
+#[derive(Debug, Clone)]
+pub struct Campaign {
+    pub name: Option<String>,
+    /// Days to simulate (default: the first encounter's iterations)
+    pub iterations: Option<u32>,
+    pub steps: Vec<CampaignStep>,
+}
+
+#[derive(Debug, Clone)]
+pub enum CampaignStep {
+    Encounter(Box<Encounter>),
+    Rest(Rest),
+}
+
+/// How much a rest restores. Written `rest: long` or `rest: { heal: 2d4 }`.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(untagged, expecting = "a rest of `long` or `{ heal: <hp> }`")]
+pub enum Rest {
+    /// Survivors are back to full HP
+    Long(LongRest),
+    /// Each survivor regains this much HP, up to their max
+    Heal { heal: HpValue },
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LongRest {
+    Long,
+}
+
+impl Rest {
+    /// Heal the survivors in `party` (HP by name, 0 for the dead). HP past a survivor's max is
+    /// trimmed when the next fight starts.
+    pub(crate) fn apply(&self, party: &mut BTreeMap<String, i32>, rng: &mut impl rand::Rng) {
+        for hp in party.values_mut().filter(|hp| **hp > 0) {
+            *hp = match self {
+                Rest::Long(_) => i32::MAX,
+                Rest::Heal { heal } => hp.saturating_add(heal.roll(rng)),
+            };
+        }
+    }
+}
+
+impl Campaign {
+    pub fn encounters(&self) -> impl Iterator<Item = &Encounter> {
+        self.steps.iter().filter_map(|step| match step {
+            CampaignStep::Encounter(encounter) => Some(encounter.as_ref()),
+            CampaignStep::Rest(_) => None,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CampaignResult {
+    pub name: Option<String>,
+    pub seed: u64,
+    /// Days simulated
+    pub iterations: u32,
+    /// Percent of days the party won every fight
+    pub survival_rate: f64,
+    /// The fight that ends the most days, if any do
+    pub breaking_point: Option<String>,
+    /// The party's state after each fight, in order (rests aren't listed)
+    pub steps: Vec<AttritionStep>,
+}
+
+/// Simulate the whole day `options.iterations` times
+pub fn run_campaign(campaign: &Campaign, options: &SimulationOptions) -> CampaignResult {
+    let options = SimulationOptions {
+        seed: Some(options.seed.unwrap_or_else(rand::random)),
+        ..options.clone()
+    };
+    let stages: Vec<Stage> = campaign
+        .steps
+        .iter()
+        .map(|step| match step {
+            CampaignStep::Encounter(encounter) => Stage::Fight(encounter),
+            CampaignStep::Rest(rest) => Stage::Rest(rest),
+        })
+        .collect();
+    let (iterations, steps) = run_stages(&stages, &options);
+
+    let breaking_point = steps
+        .iter()
+        .filter(|step| step.break_rate > 0.0)
+        .fold(None, |worst: Option<&AttritionStep>, step| match worst {
+            Some(worst) if worst.break_rate >= step.break_rate => Some(worst),
+            _ => Some(step),
+        })
+        .map(|step| step.encounter.clone());
+    CampaignResult {
+        name: campaign.name.clone(),
+        seed: options.seed.unwrap_or_default(),
+        iterations,
+        survival_rate: steps.last().map_or(0.0, |step| step.survival_rate),
+        breaking_point,
+        steps,
+    }
+}
@@ -0,0 +1,553 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::io::{self, BufWriter, Write as _};
+use std::path::{Path, PathBuf};
+
+use clap::{Args, Parser, Subcommand, ValueEnum};
+
+use tunnel_fight::apl::ValidationError;
+use tunnel_fight::campaign::{run_campaign, CampaignResult};
+use tunnel_fight::combat::EventDetail;
+use tunnel_fight::loader::{parse_campaign_as, parse_encounter_as, SourceFormat};
+use tunnel_fight::report::{self, ReportFormat};
+use tunnel_fight::simulation::{average_combat, for_each_combat, run_simulation, SimulationOptions};
+use tunnel_fight::stats::{format_combat_log, CombatLog, CombatSummary, ConfidenceInterval, SimulationResult, CSV_HEADER};
+use tunnel_fight::types::Encounter;
+use tunnel_fight::storage::Storage;
+
+#[derive(Debug, Parser)]
+#[command(version, about = "OSR combat simulator")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Run the HTTP API server (the default)
+    Serve,
+    /// Simulate an encounter file and print the results
+    Simulate(SimulateArgs),
+    /// Simulate a campaign file (encounters and rests fought in order) and print how far the
+    /// party gets
+    Campaign(CampaignArgs),
+}
+
+#[derive(Debug, Args)]
+pub struct SimulateArgs {
+    /// Encounter file (YAML, JSON, or TOML)
+    pub encounter: PathBuf,
+    /// Number of combats to run (defaults to the encounter's `iterations`)
+    #[arg(short, long)]
+    pub iterations: Option<u32>,
+    /// RNG seed for reproducible runs
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+    /// Override the encounter's round limit
+    #[arg(long)]
+    pub max_rounds: Option<u32>,
+    /// Stop early once win rates are within ± this many percentage points
+    #[arg(long)]
+    pub target_precision: Option<f64>,
+    /// Worker threads (defaults to one per core; results are the same for any count)
+    #[arg(long)]
+    pub threads: Option<usize>,
+    /// Number of sample combat logs to include
+    #[arg(long, default_value_t = 0)]
+    pub samples: usize,
+    /// Events recorded for combats that aren't samples: none, deaths, or full. Less is
+    /// faster, but the stats built from events read zero.
+    #[arg(long, default_value = "full")]
+    pub event_detail: EventDetail,
+    /// Output format
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+    /// Monster library used to resolve `ref:` entries (defaults to $DATABASE_PATH)
+    #[arg(long)]
+    pub library: Option<PathBuf>,
+    /// Play one deterministic average fight, every roll at its expected value, and print its
+    /// log (table or JSON) instead of running iterations
+    #[arg(long)]
+    pub expected_value: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct CampaignArgs {
+    /// Campaign file (YAML, JSON, or TOML)
+    pub campaign: PathBuf,
+    /// Number of days to run (defaults to the campaign's, or its first encounter's, `iterations`)
+    #[arg(short, long)]
+    pub iterations: Option<u32>,
+    /// RNG seed for reproducible runs
+    #[arg(short, long)]
+    pub seed: Option<u64>,
+    /// Worker threads (defaults to one per core; results are the same for any count)
+    #[arg(long)]
+    pub threads: Option<usize>,
+    /// Output format (table or json)
+    #[arg(short, long, value_enum, default_value_t = OutputFormat::Table)]
+    pub format: OutputFormat,
+    /// Monster library used to resolve `ref:` entries (defaults to $DATABASE_PATH)
+    #[arg(long)]
+    pub library: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum OutputFormat {
+    Json,
+    Table,
+    Markdown,
+    Html,
+    /// One row per iteration (winner, rounds, casualties, HP lost)
+    Csv,
+}
+
+/// Run `simulate`, printing results to stdout. Returns an error message suitable for stderr.
+pub fn simulate(args: &SimulateArgs, default_library: &Path) -> Result<(), String> {
+    let text = fs::read_to_string(&args.encounter)
+        .map_err(|e| format!("{}: {}", args.encounter.display(), e))?;
+    let storage = open_library(args.library.as_deref().unwrap_or(default_library))?;
+    let encounter = parse_encounter_as(&text, SourceFormat::from_path(&args.encounter), &storage)
+        .map_err(|errors| invalid(&args.encounter, "encounter", errors))?;
+
+    let defaults = SimulationOptions::for_encounter(&encounter);
+    if args.expected_value {
+        return write_average(&encounter, args.max_rounds.unwrap_or(defaults.max_rounds), args.format);
+    }
+    let options = SimulationOptions {
+        iterations: args.iterations.unwrap_or(defaults.iterations),
+        max_rounds: args.max_rounds.unwrap_or(defaults.max_rounds),
+        sample_count: args.samples,
+        seed: args.seed,
+        target_precision: args.target_precision,
+        threads: args.threads.unwrap_or(defaults.threads),
+        deadline: None,
+        event_detail: args.event_detail,
+    };
+    if args.format == OutputFormat::Csv {
+        return write_csv(&encounter, &options);
+    }
+    let result = run_simulation(&encounter, &options);
+
+    let name = encounter.name.as_deref().unwrap_or("Encounter");
+    let output = match args.format {
+        OutputFormat::Json => {
+            serde_json::to_string_pretty(&result).map_err(|e| e.to_string())? + "\n"
+        }
+        OutputFormat::Table => format_table(name, &result),
+        OutputFormat::Markdown => report::render(ReportFormat::Markdown, name, &result),
+        OutputFormat::Html => report::render(ReportFormat::Html, name, &result),
+        OutputFormat::Csv => unreachable!("CSV is streamed above"),
+    };
+    check_write(io::stdout().write_all(output.as_bytes()))
+}
+
+/// Run `campaign`, printing results to stdout. Returns an error message suitable for stderr.
+pub fn campaign(args: &CampaignArgs, default_library: &Path) -> Result<(), String> {
+    let text = fs::read_to_string(&args.campaign)
+        .map_err(|e| format!("{}: {}", args.campaign.display(), e))?;
+    let storage = open_library(args.library.as_deref().unwrap_or(default_library))?;
+    let campaign = parse_campaign_as(&text, SourceFormat::from_path(&args.campaign), &storage)
+        .map_err(|errors| invalid(&args.campaign, "campaign", errors))?;
+
+    let first = campaign.encounters().next().expect("parse_campaign requires an encounter");
+    let defaults = SimulationOptions::for_encounter(first);
+    let options = SimulationOptions {
+        iterations: args.iterations.or(campaign.iterations).unwrap_or(defaults.iterations),
+        seed: args.seed,
+        threads: args.threads.unwrap_or(defaults.threads),
+        ..defaults
+    };
+    let result = run_campaign(&campaign, &options);
+
+    let output = match args.format {
+        OutputFormat::Json => serde_json::to_string_pretty(&result).map_err(|e| e.to_string())? + "\n",
+        OutputFormat::Table => format_campaign_table(&result),
+        _ => return Err("campaign results print as a table or JSON; use --format table or json".to_string()),
+    };
+    check_write(io::stdout().write_all(output.as_bytes()))
+}
+
+/// Only open an existing library; a CLI run shouldn't create a database file
+fn open_library(library: &Path) -> Result<Storage, String> {
+    if library.exists() {
+        Storage::open(library)
+    } else {
+        Storage::open_in_memory()
+    }
+    .map_err(|e| format!("{}: {}", library.display(), e))
+}
+
+/// One line per validation error, located by line number where known
+fn invalid(file: &Path, what: &str, errors: Vec<ValidationError>) -> String {
+    let mut message = format!("{}: invalid {}", file.display(), what);
+    for error in errors {
+        let location = match (error.line, error.path.is_empty()) {
+            (Some(line), true) => format!("line {}", line),
+            (Some(line), false) => format!("line {}, {}", line, error.path),
+            (None, _) => error.path.clone(),
+        };
+        if location.is_empty() {
+            let _ = write!(message, "\n  {}", error.message);
+        } else {
+            let _ = write!(message, "\n  {}: {}", location, error.message);
+        }
+    }
+    message
+}
+
+/// Piping into `head` and friends closes stdout early; that's not an error
+fn check_write(result: io::Result<()>) -> Result<(), String> {
+    match result {
+        Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e.to_string()),
+        _ => Ok(()),
+    }
+}
+
+/// Print the average fight's log
+fn write_average(encounter: &Encounter, max_rounds: u32, format: OutputFormat) -> Result<(), String> {
+    let combat = format_combat_log(&average_combat(encounter, max_rounds), 0);
+    let output = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(&combat).map_err(|e| e.to_string())? + "\n",
+        OutputFormat::Table => {
+            let name = encounter.name.as_deref().unwrap_or("Encounter");
+            let winner = combat.winner.as_deref().unwrap_or("Draw");
+            let mut out = format!("{}: average fight\n{} after {} rounds\n", name, winner, combat.rounds);
+            for event in &combat.events {
+                let _ = writeln!(out, "  [R{}] {} {}", event.round, event.actor, event.description);
+            }
+            out
+        }
+        _ => return Err("--expected-value prints a single combat log; use --format table or json".to_string()),
+    };
+    check_write(io::stdout().write_all(output.as_bytes()))
+}
+
+/// Stream one CSV row per iteration to stdout
+fn write_csv(encounter: &Encounter, options: &SimulationOptions) -> Result<(), String> {
+    let mut out = BufWriter::new(io::stdout().lock());
+    let mut status = out.write_all(CSV_HEADER.as_bytes());
+    // Rows only need each combat's outcome
+    let options = &SimulationOptions { event_detail: EventDetail::None, ..options.clone() };
+    for_each_combat(encounter, options, |i, result| {
+        status = out.write_all(CombatSummary::from_result(result).csv_row(i).as_bytes());
+        status.is_ok()
+    });
+    check_write(status.and_then(|()| out.flush()))
+}
+
+fn format_ci(ci: &ConfidenceInterval) -> String {
+    format!("({:.1}-{:.1})", ci.low, ci.high)
+}
+
+/// Human-readable summary of a simulation run
+pub fn format_table(name: &str, result: &SimulationResult) -> String {
+    let stats = &result.stats;
+    let mut out = String::new();
+
+    let _ = writeln!(
+        out,
+        "{} - {} iterations (seed {})\n",
+        name, stats.iterations, result.seed
+    );
+
+    let _ = writeln!(out, "{:<14} {:>8}  95% CI", "Outcome", "Rate");
+    let _ = writeln!(
+        out,
+        "{:<14} {:>7.2}%  {}",
+        "Side 1 wins",
+        stats.side1_win_rate,
+        format_ci(&stats.side1_win_rate_ci)
+    );
+    let _ = writeln!(
+        out,
+        "{:<14} {:>7.2}%  {}",
+        "Side 2 wins",
+        stats.side2_win_rate,
+        format_ci(&stats.side2_win_rate_ci)
+    );
+    for faction in &stats.factions {
+        let _ = writeln!(
+            out,
+            "{:<14} {:>7.2}%  {}",
+            format!("Side {} wins", faction.side.index() + 1),
+            faction.win_rate,
+            format_ci(&faction.win_rate_ci)
+        );
+    }
+    let _ = writeln!(
+        out,
+        "{:<14} {:>7.2}%  {}",
+        "Draws",
+        stats.draw_rate,
+        format_ci(&stats.draw_rate_ci)
+    );
+    let _ = writeln!(out, "{:<14} {:>7.2}%", "Timeouts", stats.timeout_rate);
+    if let Some(stalemates) = &stats.stalemates {
+        let reasons = [
+            ("attrition", stalemates.attrition),
+            ("passive", stalemates.passive),
+            ("no hits", stalemates.no_hits_possible),
+            ("deadlock", stalemates.zone_deadlock),
+            ("out of reach", stalemates.out_of_reach),
+        ];
+        for (reason, rate) in reasons.into_iter().filter(|&(_, rate)| rate > 0.0) {
+            let _ = writeln!(out, "  {:<12} {:>7.2}%", reason, rate);
+        }
+    }
+    let _ = writeln!(out, "{:<14} {:>7.2}%\n", "Mutual kills", stats.mutual_destruction_rate);
+
+    let _ = writeln!(out, "{:<18} {:>10} {:>10}", "", "Side 1", "Side 2");
+    let rows = [
+        ("Casualties (avg)", stats.avg_side1_casualties, stats.avg_side2_casualties, ""),
+        ("HP lost (avg)", stats.avg_side1_hp_lost, stats.avg_side2_hp_lost, ""),
+        ("HP lost", stats.avg_side1_hp_lost_percent, stats.avg_side2_hp_lost_percent, "%"),
+        ("Flawless", stats.side1_flawless_rate, stats.side2_flawless_rate, "%"),
+        ("TPK", stats.side1_tpk_rate, stats.side2_tpk_rate, "%"),
+    ];
+    for (label, side1, side2, unit) in rows {
+        let _ = writeln!(
+            out,
+            "{:<18} {:>9.2}{:1} {:>9.2}{:1}",
+            label, side1, unit, side2, unit
+        );
+    }
+
+    for faction in &stats.factions {
+        let _ = writeln!(
+            out,
+            "Side {}: {:.2} casualties (avg), {:.2}% HP lost, {:.2}% flawless, {:.2}% TPK",
+            faction.side.index() + 1,
+            faction.avg_casualties,
+            faction.avg_hp_lost_percent,
+            faction.flawless_rate,
+            faction.tpk_rate
+        );
+    }
+    if let Some(civilians) = &stats.civilians {
+        let _ = writeln!(
+            out,
+            "Civilians: {:.2} killed (avg), {:.2}% of them, all saved in {:.2}%",
+            civilians.avg_casualties, civilians.casualty_rate, civilians.all_saved_rate
+        );
+    }
+
+    // Stats counted from events, absent when the run didn't record them
+    let nonzero = |&(side1, side2): &(f64, f64)| side1 > 0.0 || side2 > 0.0;
+    let event_rows = [
+        ("Round 1 damage", stats.avg_side1_round1_damage.zip(stats.avg_side2_round1_damage)),
+        ("Overkill/kill", stats.avg_side1_overkill.zip(stats.avg_side2_overkill)),
+        ("Wasted hits (avg)", stats.avg_side1_wasted_hits.zip(stats.avg_side2_wasted_hits).filter(nonzero)),
+        ("Lost targets (avg)", stats.avg_side1_wasted_actions.zip(stats.avg_side2_wasted_actions).filter(nonzero)),
+    ];
+    for (label, (side1, side2)) in event_rows.into_iter().filter_map(|(label, row)| Some((label, row?))) {
+        let _ = writeln!(out, "{:<18} {:>9.2}  {:>9.2}", label, side1, side2);
+    }
+    let friendly_fire = stats.avg_side1_friendly_fire_damage.zip(stats.avg_side2_friendly_fire_damage);
+    if let Some((side1, side2)) = friendly_fire.filter(nonzero) {
+        let _ = writeln!(out, "{:<18} {:>9.2}  {:>9.2}", "Friendly fire dmg", side1, side2);
+        let _ = writeln!(
+            out,
+            "{:<18} {:>9.2}  {:>9.2}",
+            "Own killed (avg)",
+            stats.avg_side1_self_inflicted_casualties.unwrap_or_default(),
+            stats.avg_side2_self_inflicted_casualties.unwrap_or_default()
+        );
+    }
+    if stats.side1_surprised_rate > 0.0 || stats.side2_surprised_rate > 0.0 {
+        let _ = writeln!(
+            out,
+            "{:<18} {:>9.2}% {:>9.2}%",
+            "Surprised", stats.side1_surprised_rate, stats.side2_surprised_rate
+        );
+        let _ = writeln!(out, "Ambushers win {:.2}% of combats with a surprise round", stats.ambusher_win_rate);
+    }
+
+    for objective in &stats.objectives {
+        let _ = writeln!(
+            out,
+            "Objective: {} {} - won {:.2}% of combats",
+            objective.side, objective.objective, objective.rate
+        );
+    }
+
+    if stats.avg_side1_fled > 0.0 || stats.avg_side2_fled > 0.0 {
+        let _ = writeln!(
+            out,
+            "{:<18} {:>9.2}  {:>9.2}",
+            "Fled (avg)", stats.avg_side1_fled, stats.avg_side2_fled
+        );
+    }
+    if stats.avg_side1_summoned > 0.0 || stats.avg_side2_summoned > 0.0 {
+        let _ = writeln!(
+            out,
+            "{:<18} {:>9.2}  {:>9.2}",
+            "Summoned (avg)", stats.avg_side1_summoned, stats.avg_side2_summoned
+        );
+    }
+    if stats.side1_vip_survival_rate.is_some() || stats.side2_vip_survival_rate.is_some() {
+        let rate = |rate: Option<f64>| rate.map_or("-".to_string(), |r| format!("{:.2}%", r));
+        let _ = writeln!(
+            out,
+            "{:<18} {:>10} {:>10}",
+            "VIP survived",
+            rate(stats.side1_vip_survival_rate),
+            rate(stats.side2_vip_survival_rate)
+        );
+    }
+    if stats.avg_side1_survivor_hp_drained > 0.0 || stats.avg_side2_survivor_hp_drained > 0.0 {
+        let _ = writeln!(
+            out,
+            "{:<18} {:>9.2}  {:>9.2}",
+            "Drained/survivor", stats.avg_side1_survivor_hp_drained, stats.avg_side2_survivor_hp_drained
+        );
+    }
+
+    if let Some(scores) = &stats.scores {
+        let margin = &scores.margin_percentiles;
+        let _ = writeln!(
+            out,
+            "Scored timeouts ({:?}): {} combats, avg {:.2} vs {:.2}, margin p10 {} / p50 {} / p90 {}",
+            scores.metric, scores.combats, scores.avg_side1, scores.avg_side2, margin.p10, margin.p50, margin.p90
+        );
+    }
+
+    match (stats.first_round_death_rate, stats.decided_by_round2_rate) {
+        (Some(deaths), Some(broken)) => {
+            let _ = writeln!(
+                out,
+                "Someone dies in round 1 in {:.2}% of combats; a side is broken by round 2 in {:.2}%",
+                deaths, broken
+            );
+        }
+        (Some(deaths), None) => {
+            let _ = writeln!(out, "Someone dies in round 1 in {:.2}% of combats", deaths);
+        }
+        _ => {}
+    }
+
+    let rounds = &stats.distributions.rounds_percentiles;
+    let _ = writeln!(
+        out,
+        "\nRounds: avg {:.2} (sd {:.2}), p10 {} / p50 {} / p90 {}",
+        stats.avg_rounds, stats.std_rounds, rounds.p10, rounds.p50, rounds.p90
+    );
+
+    if !stats.front_line.is_empty() {
+        let points: Vec<String> = stats
+            .front_line
+            .iter()
+            .take(10)
+            .map(|point| format!("R{} {:.2}", point.round, point.avg_position))
+            .collect();
+        let _ = writeln!(out, "Front line (zones from side 1's rear): {}", points.join(", "));
+    }
+    if !stats.zone_control.is_empty() {
+        let _ = writeln!(out, "\n{:<20} {:>8} {:>8} {:>10}", "Zone held at end", "Side 1", "Side 2", "Contested");
+        for zone in &stats.zone_control {
+            let _ = writeln!(
+                out,
+                "{:<20} {:>7.1}% {:>7.1}% {:>9.1}%",
+                zone.zone, zone.side1_rate, zone.side2_rate, zone.contested_rate
+            );
+        }
+    }
+
+    let kill_actors = stats.kills.as_ref().map_or(&[][..], |kills| &kills.actors);
+    if !kill_actors.is_empty() {
+        let _ = writeln!(
+            out,
+            "\n{:<24} {:<6} {:>9} {:>8} {:>8}",
+            "Actor", "Side", "Avg kills", "Kill %", "Death %"
+        );
+        for actor in kill_actors {
+            let _ = writeln!(
+                out,
+                "{:<24} {:<6} {:>9.2} {:>8.1} {:>8.1}",
+                actor.name, actor.side, actor.avg_kills, actor.kill_rate, actor.death_rate
+            );
+        }
+    }
+
+    let time_to_kill = stats.time_to_kill.as_deref().unwrap_or_default();
+    if !time_to_kill.is_empty() {
+        let _ = writeln!(
+            out,
+            "\n{:<20} {:<20} {:>8} {:>8} {:>12}",
+            "Attacker", "Defender", "Kill %", "Attacks", "First kill"
+        );
+        for matchup in time_to_kill {
+            let _ = writeln!(
+                out,
+                "{:<20} {:<20} {:>8.1} {:>8.2} {:>6.2} rnds",
+                matchup.attacker,
+                matchup.defender,
+                matchup.kill_rate,
+                matchup.avg_attacks_to_kill,
+                matchup.avg_rounds_to_first_kill
+            );
+        }
+    }
+
+    for (i, combat) in result.sample_combats.iter().enumerate() {
+        out.push('\n');
+        out.push_str(&format_sample(i + 1, combat));
+    }
+
+    out
+}
+
+fn format_sample(number: usize, combat: &CombatLog) -> String {
+    let mut out = String::new();
+    let winner = combat.winner.as_deref().unwrap_or("Draw");
+    let _ = write!(
+        out,
+        "Sample combat {} (iteration {}): {} after {} rounds",
+        number, combat.iteration, winner, combat.rounds
+    );
+    if let Some(side) = &combat.surprised {
+        let _ = write!(out, " ({} surprised)", side);
+    }
+    if let Some(stalemate) = combat.stalemate {
+        let _ = write!(out, " (stalled: {})", stalemate);
+    }
+    out.push('\n');
+    for event in &combat.events {
+        let _ = writeln!(out, "  [R{}] {} {}", event.round, event.actor, event.description);
+    }
+    out
+}
+
+/// Human-readable summary of a campaign run
+pub fn format_campaign_table(result: &CampaignResult) -> String {
+    let mut out = String::new();
+    let _ = writeln!(
+        out,
+        "{} - {} days (seed {})\n",
+        result.name.as_deref().unwrap_or("Campaign"),
+        result.iterations,
+        result.seed
+    );
+    let _ = writeln!(
+        out,
+        "{:<24} {:>8} {:>9} {:>8} {:>8} {:>10}",
+        "Encounter", "Reached", "Survived", "Broke", "HP left", "Survivors"
+    );
+    for step in &result.steps {
+        let _ = writeln!(
+            out,
+            "{:<24} {:>7.2}% {:>8.2}% {:>7.2}% {:>7.1}% {:>10.2}",
+            step.encounter,
+            step.reached_rate,
+            step.survival_rate,
+            step.break_rate,
+            step.avg_party_hp_percent,
+            step.avg_survivors
+        );
+    }
+    let _ = writeln!(out, "\nWhole day survived: {:.2}%", result.survival_rate);
+    if let Some(breaking_point) = &result.breaking_point {
+        let _ = writeln!(out, "Breaking point: {}", breaking_point);
+    }
+    out
+}
@@ -1,162 +1,991 @@
-use rand::Rng;
+use std::collections::BTreeMap;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Instant;
 
-use crate::apl::{execute_apl, AttackAction, MoveAction, MoveDirection};
-use crate::types::{Actor, DamageDice, Encounter, InitiativeType, Phase, Side, WeaponRange, Zone, ZoneCapacities, parse_damage_dice};
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::apl::{execute_legendary_apl, select_actions, select_reaction, trace_actions, AttackAction, ChosenActions, EntryTrace, MoveAction, MoveDirection, TurnActions, TurnBudget};
+use crate::decide::ActorSnapshot;
+use crate::effects::{Effect, EffectKind, Expiry, Stacking, Tick};
+use crate::modifiers::{AttackModifiers, EdgeSource, ModifierSource};
+use crate::script::Scripts;
+use crate::simulation::past;
+use crate::traits;
+use crate::types::{Actor, ActorTemplate, AplEntry, ConditionKind, ConditionRider, DamageDice, DrawPolicy, Encounter, Objective, HpValue, InitiativeRoll, InitiativeType, Light, LostTarget, TieBreak, ForcedMove, HitLocation, LineOfFire, OnHit, Phase, Rules, Side, SideSet, Slot, Surprise, Trigger, WeaponRange, Zone, ZoneLayout, parse_damage_dice};
+
+/// AC bonus from `guard` (still allows movement)
+const GUARD_AC_BONUS: i32 = 2;
+/// AC bonus from `defend` (gives up movement as well as the attack)
+const DEFEND_AC_BONUS: i32 = 4;
+/// Ranged attack penalty for shooting past allies with `rules.line_of_fire: penalty`
+const ALLIES_IN_THE_WAY_PENALTY: i32 = 2;
+/// What a d20 shows in expected-value mode: checks succeed exactly when they're at least as
+/// likely to succeed as not
+const MEDIAN_D20: i32 = 11;
 
 #[derive(Debug, Clone)]
 pub struct CombatEvent {
     pub round: u32,
     pub actor_id: usize,
-    pub actor_name: String,
+    pub actor_name: Arc<str>,
     pub event_type: EventType,
 }
 
-#[derive(Debug, Clone)]
+/// What happened in a combat event. Serialized with a `type` tag for API clients.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum EventType {
     Attack {
         target_id: usize,
-        target_name: String,
+        #[schema(value_type = String)]
+        target_name: Arc<str>,
         roll: i32,
         target_ac: i32,
         hit: bool,
         damage: i32,
+        /// Portion of the damage soaked up by the target's temp HP
+        absorbed: i32,
+        /// Penalty already applied to `roll` for a ranged attack into a zone with cover
+        cover: i32,
+        /// Damage beyond what the target had left: the excess of a killing blow, or all of it
+        /// when the target was already down (a simultaneous round where it fell first)
+        overkill: i32,
+        /// Hit location aimed at; its penalty is already applied to `roll`
+        #[serde(skip_serializing_if = "Option::is_none")]
+        called_shot: Option<HitLocation>,
+        /// Rolled damage before the target's armor soaked any of it (`rules.armor_dr`, dice
+        /// rolls only); `damage` is what got through
+        #[serde(skip_serializing_if = "Option::is_none")]
+        damage_before_dr: Option<i32>,
+        /// Everything added to the d20 and to the target's AC, and what gave advantage or
+        /// disadvantage, each with its source
+        modifiers: AttackModifiers,
     },
     Guard {
         ac_bonus: i32,
     },
+    Defend {
+        ac_bonus: i32,
+    },
+    Dodge,
+    Hold,
+    /// Spends an action on extra movement; the move follows as its own event
+    Dash,
+    /// A ranged attack that missed its target hit one of the attacker's allies instead: one in
+    /// the way (`rules.line_of_fire: hit_allies`) or one fighting the target (`friendly_fire`)
+    StrayShot {
+        target_id: usize,
+        #[schema(value_type = String)]
+        target_name: Arc<str>,
+        roll: i32,
+        target_ac: i32,
+        damage: i32,
+        absorbed: i32,
+    },
+    /// Backs away carefully, giving no opportunity attacks; the move follows as its own event
+    Withdraw,
+    Buff {
+        target_id: usize,
+        #[schema(value_type = String)]
+        target_name: Arc<str>,
+        temp_hp: i32,
+        duration: Option<u32>,
+    },
+    TempHpExpired {
+        temp_hp: i32,
+    },
+    /// The actor's hit permanently lowered the target's max HP to `max_hp`
+    Drain {
+        target_id: usize,
+        #[schema(value_type = String)]
+        target_name: Arc<str>,
+        amount: i32,
+        max_hp: i32,
+    },
+    /// Damage from hazardous terrain at the end of a round
+    Hazard {
+        zone: String,
+        damage: i32,
+        absorbed: i32,
+    },
+    /// An on-hit condition rider: `save` is the target's total against `dc` (None when the
+    /// rider has no save), and `applied` is false if the save shrugged it off
+    Condition {
+        target_id: usize,
+        #[schema(value_type = String)]
+        target_name: Arc<str>,
+        condition: ConditionKind,
+        save: Option<i32>,
+        dc: i32,
+        applied: bool,
+        duration: Option<u32>,
+    },
+    /// Damage from a condition (bleeding) at the end of a round
+    ConditionDamage {
+        condition: ConditionKind,
+        damage: i32,
+        absorbed: i32,
+    },
+    ConditionEnded {
+        condition: ConditionKind,
+    },
+    /// The actor left the fight alive through the exit in `zone`
+    Flee {
+        zone: String,
+    },
+    /// The actor completed one of its side's objectives, ending the combat
+    Objective {
+        objective: String,
+    },
+    /// Zones are given by name
     Move {
-        from: Zone,
-        to: Zone,
+        from: String,
+        to: String,
+    },
+    /// The actor shoves or drags a target it hit (zones by name)
+    ForcedMove {
+        target_id: usize,
+        #[schema(value_type = String)]
+        target_name: Arc<str>,
+        from: String,
+        to: String,
     },
     Death {
         killer_id: Option<usize>,
     },
+    /// Contested grapple: `roll` vs the target's `opposed` roll; ties go to the target
+    Grapple {
+        target_id: usize,
+        #[schema(value_type = String)]
+        target_name: Arc<str>,
+        roll: i32,
+        opposed: i32,
+        success: bool,
+    },
+    /// Contested attempt to break free of `grappler_name`
+    Escape {
+        grappler_id: usize,
+        #[schema(value_type = String)]
+        grappler_name: Arc<str>,
+        roll: i32,
+        opposed: i32,
+        success: bool,
+    },
+    /// The actor called up `summoned_name`, which joins the fight in `zone`
+    Summon {
+        summoned_id: usize,
+        #[schema(value_type = String)]
+        summoned_name: Arc<str>,
+        zone: String,
+    },
+    /// The dying actor left `spawned_name` behind in `zone` (its `on_death: spawn`)
+    Spawn {
+        spawned_id: usize,
+        #[schema(value_type = String)]
+        spawned_name: Arc<str>,
+        zone: String,
+    },
+    /// The dying actor rose again as `into_name` (its `on_death: transform`)
+    Transform {
+        into_id: usize,
+        #[schema(value_type = String)]
+        into_name: Arc<str>,
+    },
+    /// The actor's attack was wasted: its target was out of reach by the time it came to swing
+    /// (or, with `lost_target: retarget`, down with no one else to attack)
+    LostTarget {
+        target_id: usize,
+        #[schema(value_type = String)]
+        target_name: Arc<str>,
+    },
+    /// The actor's script failed this turn (a runtime error, its operation budget, or an
+    /// answer that isn't valid APL), so it fell back to its `apl`
+    ScriptError { message: String },
+    /// The actor spends a legendary action; the action's own event follows
+    Legendary {
+        remaining: u32,
+    },
+    /// The actor spends its reaction; the reaction's own event follows
+    Reaction {
+        trigger: Trigger,
+        source_id: usize,
+        #[schema(value_type = String)]
+        source_name: Arc<str>,
+    },
+}
+
+/// How much of each combat's log the simulator keeps. Recording less saves time and memory on
+/// big runs, but the stats built from events need them (see `StatsCollector::with_event_detail`).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EventDetail {
+    /// No events: only the outcome and final state
+    None,
+    /// Only `death` events
+    Deaths,
+    /// Every event
+    #[default]
+    Full,
+}
+
+impl EventDetail {
+    fn keeps(self, event: &EventType) -> bool {
+        match self {
+            EventDetail::None => false,
+            EventDetail::Deaths => matches!(event, EventType::Death { .. }),
+            EventDetail::Full => true,
+        }
+    }
+}
+
+impl FromStr for EventDetail {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "none" => Ok(EventDetail::None),
+            "deaths" => Ok(EventDetail::Deaths),
+            "full" => Ok(EventDetail::Full),
+            other => Err(format!("unknown event detail '{}' (expected none, deaths, or full)", other)),
+        }
+    }
+}
+
+/// How a finished round leaves the fight
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Outcome {
+    /// Won by this side: the other has nobody left or lost its VIP, or an objective was met
+    Winner(Side),
+    /// Nobody is left on either side, e.g. after a simultaneous exchange of killing blows
+    MutualDestruction,
+    /// Both sides are still standing
+    Undecided,
 }
 
 #[derive(Debug, Clone)]
 pub struct CombatResult {
     pub winner: Option<Side>,
     pub rounds: u32,
+    /// Combat hit `max_rounds` with both sides standing (winner decided by draw policy)
+    pub timed_out: bool,
+    /// Combat was stopped by its deadline before it finished, so its outcome means nothing
+    pub cut_off: bool,
+    /// Both sides went down together (no winner, but not a timeout)
+    pub mutual_destruction: bool,
     pub events: Vec<CombatEvent>,
     pub final_state: Vec<ActorState>,
+    /// Side that lost the first round to surprise
+    pub surprised: Option<Side>,
+    /// Index into the encounter's `objectives` of the one that decided the combat
+    pub objective: Option<usize>,
+    /// Side 1 and side 2 scores when a scored draw policy decided a timeout
+    pub score: Option<(i64, i64)>,
+    /// Where the front line stood at the end of each round (see
+    /// [`CombatSimulator::front_line`])
+    pub front_line: Vec<Option<f64>>,
+    /// Why a timed-out combat stalled
+    pub stalemate: Option<Stalemate>,
+}
+
+/// Why a combat ran out of rounds, judged from where it stood when time ran out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Stalemate {
+    /// Fighters could reach enemies and attacked them in the last round, but not hard enough to
+    /// finish the fight in time
+    Attrition,
+    /// Fighters could reach enemies, but nobody attacked in the last round: their APLs only
+    /// hold, guard, or move
+    Passive,
+    /// Every fighter that could reach an enemy needed more than a 20 to hit it
+    NoHitsPossible,
+    /// Nobody could reach an enemy, and some fighter's way toward one ran through a zone too
+    /// full to enter
+    ZoneDeadlock,
+    /// Nobody could reach an enemy, with nothing in the way: the sides kept out of each other's
+    /// range
+    OutOfReach,
+}
+
+impl Stalemate {
+    pub const ALL: [Stalemate; 5] = [
+        Stalemate::Attrition,
+        Stalemate::Passive,
+        Stalemate::NoHitsPossible,
+        Stalemate::ZoneDeadlock,
+        Stalemate::OutOfReach,
+    ];
+}
+
+impl std::fmt::Display for Stalemate {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            Stalemate::Attrition => "attrition",
+            Stalemate::Passive => "passive",
+            Stalemate::NoHitsPossible => "no_hits_possible",
+            Stalemate::ZoneDeadlock => "zone_deadlock",
+            Stalemate::OutOfReach => "out_of_reach",
+        })
+    }
+}
+
+/// One pass an actor made over its APL, recorded when tracing (see
+/// [`CombatSimulator::with_trace`]). A turn usually makes several: one for its move and action,
+/// another after moving, and one for each bonus action or free interaction it has entries for.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ChoiceTrace {
+    pub round: u32,
+    pub actor_id: usize,
+    pub actor: String,
+    /// Initiative phase being acted in, under phase-based initiative
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phase: Option<Phase>,
+    pub source: AplSource,
+    /// Movement points left when choosing
+    pub movement: u32,
+    /// Slots still open when choosing
+    pub open: Vec<Slot>,
+    pub entries: Vec<EntryTrace>,
+}
+
+/// Where the APL an actor chose from came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AplSource {
+    /// Its own `apl`
+    Apl,
+    /// The built-in one, for an actor without an `apl`
+    Default,
+    /// Handed to it for the turn: by its script, the controller of its side, or an APL test
+    /// case
+    Supplied,
 }
 
 #[derive(Debug, Clone)]
 pub struct ActorState {
     pub id: usize,
-    pub name: String,
+    pub name: Arc<str>,
+    /// Name of the encounter entry the actor was built from
+    pub template: Arc<str>,
     pub side: Side,
+    /// The actor that summoned this one, or whose death spawned it (None for the encounter's
+    /// own actors)
+    pub summoner: Option<usize>,
+    /// Max HP at the start of the combat
     pub max_hp: i32,
     pub final_hp: i32,
+    /// Max HP permanently lost to drain
+    pub drained: i32,
+    /// Left through an exit (and so still `alive`)
+    pub fled: bool,
+    pub alive: bool,
+    /// Its side loses if it dies
+    pub vip: bool,
+    /// Stayed out of the fighting, and out of its side's casualty stats
+    pub noncombatant: bool,
+    /// Name of the zone the actor ended in
+    pub zone: String,
+}
+
+/// Where and how an actor starts each combat
+#[derive(Debug, Clone)]
+struct Start {
+    hp: HpValue,
+    hp_scale: Option<f64>,
+    zone: Zone,
+    in_reserve: bool,
+}
+
+/// Picks actions for one side in place of its APLs. It is handed each decision and answers
+/// with APL entries to evaluate for it; an empty list falls back to the default APL.
+pub type Controller = Box<dyn FnMut(&Decision) -> Vec<AplEntry> + Send>;
+
+/// A turn for a controlled actor, with everything that happened since the last one
+#[derive(Debug, Clone)]
+pub struct Decision {
+    pub round: u32,
+    pub actor_id: usize,
+    /// Initiative phase being resolved, when using phase-based initiative
+    pub phase: Option<Phase>,
+    /// Events since the previous decision
+    pub events: Vec<CombatEvent>,
+    pub actors: Vec<ActorView>,
+}
+
+/// An actor as seen at a decision point
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ActorView {
+    pub id: usize,
+    pub name: String,
+    pub side: Side,
+    pub hp: i32,
+    pub max_hp: i32,
+    pub ac: i32,
+    pub zone: String,
     pub alive: bool,
-    pub zone: Zone,
 }
 
 pub struct CombatSimulator {
     actors: Vec<Actor>,
+    /// How each actor starts a combat, for `reset`
+    starts: Vec<Start>,
     events: Vec<CombatEvent>,
+    /// Which events `record` keeps
+    event_detail: EventDetail,
     round: u32,
     max_rounds: u32,
-    zone_capacity: ZoneCapacities,
+    draw_policy: DrawPolicy,
+    defender: Side,
+    zones: ZoneLayout,
+    front_width: Option<u32>,
+    surprise: Surprise,
     initiative_type: InitiativeType,
     initiative_dice: DamageDice,
     phases: Vec<Phase>,
+    weapon_speed: bool,
+    initiative_roll: InitiativeRoll,
+    tie_break: TieBreak,
+    lost_target: LostTarget,
+    /// Side and individual initiative kept from the first roll when rolling once
+    kept_side_order: Option<Vec<Side>>,
+    kept_initiative: Option<Vec<(usize, i32)>>,
+    /// Any actor has a `ready` entry; skips reaction checks entirely when not
+    has_reactions: bool,
+    /// Any actor has an aura; skips adding them up on every attack when not
+    has_auras: bool,
+    /// Death events held back until the end of a declared-initiative round
+    pending_deaths: Option<Vec<CombatEvent>>,
+    objectives: Vec<Objective>,
+    /// Sides each side fights, by side index
+    hostility: Vec<SideSet>,
+    exits: bool,
+    called_shots: bool,
+    friendly_fire: bool,
+    rules: Rules,
+    /// The objective that ended the combat, if any
+    objective_met: Option<usize>,
+    /// Side whose turns are chosen by a controller instead of its APLs
+    controller: Option<(Side, Controller)>,
+    /// Compiled `script`s, for the actors that have one
+    scripts: Scripts,
+    /// Events already handed to the controller
+    events_seen: usize,
+    /// Expected-value mode: every roll takes its mean (see `average`)
+    expected: bool,
+    /// Expected-value mode: damage below one point owed to each actor, added to its next hit
+    damage_carry: Vec<f64>,
+    /// Round of the latest attack roll, for telling a stalled fight from a slow one
+    last_attack_round: u32,
+    /// Every APL pass this combat, when tracing
+    trace: Option<Vec<ChoiceTrace>>,
+    /// Give up on a combat still going at this instant (see `with_deadline`)
+    deadline: Option<Instant>,
 }
 
 impl CombatSimulator {
+    /// A simulator ready to run one combat, with HP rolled from `rng`
     pub fn new(encounter: &Encounter, max_rounds: u32, rng: &mut impl Rng) -> Self {
+        let mut sim = CombatSimulator::prepare(encounter, max_rounds);
+        sim.reset(rng);
+        sim
+    }
+
+    /// Build the actors without rolling their HP. Call [`CombatSimulator::reset`] before each
+    /// combat; building once and resetting saves re-copying the encounter every iteration.
+    pub fn prepare(encounter: &Encounter, max_rounds: u32) -> Self {
+        let zones = encounter.zone_layout();
         let mut actors = Vec::new();
+        let mut starts = Vec::new();
         let mut id = 0;
 
-        for template in &encounter.side1 {
-            actors.push(Actor::from_template(id, template, Side::Side1, rng));
-            id += 1;
-        }
-
-        for template in &encounter.side2 {
-            actors.push(Actor::from_template(id, template, Side::Side2, rng));
-            id += 1;
+        for side in encounter.sides() {
+            let hostile_to = encounter.hostile_to(side);
+            for template in encounter.templates(side) {
+                for i in 0..template.count {
+                    // Unknown zone names are rejected by validation; fall back to the rear
+                    let zone = zones
+                        .start_zone(side, &template.start_zone)
+                        .unwrap_or_else(|| zones.rear(side));
+                    let mut actor = Actor::from_template(id, template, side, zone);
+                    actor.hostile_to = hostile_to;
+                    if template.count > 1 {
+                        actor.name = template.instance_name(i).into();
+                    }
+                    actors.push(actor);
+                    starts.push(Start {
+                        hp: template.hp.clone(),
+                        hp_scale: template.hp_scale,
+                        zone,
+                        // Everyone starts in reserve; the first front selection fills the slots
+                        in_reserve: encounter.front_width.is_some() && zones.is_melee(zone),
+                    });
+                    id += 1;
+                }
+            }
         }
 
+        let has_reactions = actors.iter().any(|a| a.apl.iter().any(|e| e.ready.is_some()));
+        let has_auras = actors.iter().any(|a| !a.auras.is_empty());
         let initiative_dice = parse_damage_dice(&encounter.initiative.dice)
-            .unwrap_or(DamageDice { count: 1, sides: 20, modifier: 0 });
+            .unwrap_or_else(|_| DamageDice::new(1, 20, 0));
 
         CombatSimulator {
             actors,
+            starts,
             events: Vec::new(),
+            event_detail: EventDetail::Full,
             round: 0,
             max_rounds,
-            zone_capacity: encounter.zone_capacity.clone(),
+            draw_policy: encounter.draw_policy,
+            defender: encounter.defender,
+            zones,
+            front_width: encounter.front_width,
+            surprise: encounter.surprise,
             initiative_type: encounter.initiative.initiative_type,
             initiative_dice,
             phases: encounter.initiative.phases.clone(),
+            weapon_speed: encounter.initiative.weapon_speed,
+            initiative_roll: encounter.initiative.roll,
+            tie_break: encounter.initiative.tie_break,
+            lost_target: encounter.initiative.lost_target,
+            kept_side_order: None,
+            kept_initiative: None,
+            has_reactions,
+            has_auras,
+            pending_deaths: None,
+            objectives: encounter.objectives.clone(),
+            hostility: encounter.sides().map(|side| encounter.hostile_to(side)).collect(),
+            exits: encounter.exits,
+            called_shots: encounter.called_shots,
+            friendly_fire: encounter.friendly_fire,
+            rules: encounter.rules,
+            objective_met: None,
+            controller: None,
+            scripts: Scripts::default(),
+            events_seen: 0,
+            expected: false,
+            damage_carry: Vec::new(),
+            last_attack_round: 0,
+            trace: None,
+            deadline: None,
+        }
+    }
+
+    /// The "average fight": HP, damage, and every other roll take their expected values, and
+    /// each attack deals its hit chance times its average damage, with fractions carried over
+    /// to the target's next hit. Checks without a fractional outcome (saves, grapples,
+    /// surprise, whether riders land) go to the more likely result, and what's left to
+    /// chance (turn order ties, random targets) comes from a fixed seed, so the run is
+    /// repeatable. Use `run` with any RNG; [`crate::simulation::average_combat`] does both.
+    pub fn average(encounter: &Encounter, max_rounds: u32) -> Self {
+        let mut sim = CombatSimulator::new(encounter, max_rounds, &mut ChaCha8Rng::seed_from_u64(0));
+        let templates = encounter
+            .sides()
+            .flat_map(|side| encounter.templates(side))
+            .flat_map(|template| (0..template.count).map(move |_| template));
+        for (actor, template) in sim.actors.iter_mut().zip(templates) {
+            let hp = (template.expected_hp().round() as i32).max(1);
+            actor.max_hp = hp;
+            actor.current_hp = hp;
+        }
+        sim.expected = true;
+        sim.damage_carry = vec![0.0; sim.actors.len()];
+        sim
+    }
+
+    /// Set up the next combat: roll every actor's HP (in actor order) and put everyone back in
+    /// their starting zone, undoing whatever the last combat did
+    pub fn reset(&mut self, rng: &mut impl Rng) {
+        // Whatever was summoned last combat goes; everyone else is at the front of the list
+        self.actors.truncate(self.starts.len());
+        self.damage_carry.truncate(self.starts.len());
+        for (actor, start) in self.actors.iter_mut().zip(&self.starts) {
+            let hp = start.hp.roll_scaled(start.hp_scale, rng);
+            actor.reset(hp, start.zone, start.in_reserve);
+        }
+        self.events.clear();
+        self.round = 0;
+        self.kept_side_order = None;
+        self.kept_initiative = None;
+        self.pending_deaths = None;
+        self.objective_met = None;
+        self.events_seen = 0;
+        self.damage_carry.iter_mut().for_each(|carry| *carry = 0.0);
+        self.last_attack_round = 0;
+        if let Some(trace) = &mut self.trace {
+            trace.clear();
+        }
+    }
+
+    /// Keep only the events `detail` calls for in the combats run from now on
+    pub fn set_event_detail(&mut self, detail: EventDetail) {
+        self.event_detail = detail;
+    }
+
+    /// Add an event to the log, if the event detail keeps it
+    fn record(&mut self, event: CombatEvent) {
+        if self.event_detail.keeps(&event.event_type) {
+            self.events.push(event);
+        }
+    }
+
+    fn d20(&self, rng: &mut impl Rng) -> i32 {
+        if self.expected {
+            MEDIAN_D20
+        } else {
+            rng.gen_range(1..=20)
+        }
+    }
+
+    fn roll_dice(&self, dice: &DamageDice, rng: &mut impl Rng) -> i32 {
+        if self.expected {
+            dice.expected_value().round() as i32
+        } else {
+            dice.roll(rng)
+        }
+    }
+
+    fn roll_value(&self, value: &HpValue, rng: &mut impl Rng) -> i32 {
+        if self.expected {
+            value.expected_value().round() as i32
+        } else {
+            value.roll(rng)
+        }
+    }
+
+    /// Start `side`'s actors where an earlier fight left them: each actor named in `hp` starts
+    /// on that much HP (at most its max), and at 0 or less it starts dead. Actors not named
+    /// start fresh.
+    pub fn with_starting_hp(mut self, side: Side, hp: &BTreeMap<String, i32>) -> Self {
+        for actor in self.actors.iter_mut().filter(|a| a.side == side) {
+            if let Some(&hp) = hp.get(&*actor.name) {
+                actor.current_hp = hp.min(actor.max_hp).max(0);
+            }
+        }
+        self
+    }
+
+    /// Let `controller` choose the moves and attacks of every actor on `side`. Reactions and
+    /// legendary actions still come from the actors' APLs.
+    pub fn with_controller(mut self, side: Side, controller: Controller) -> Self {
+        self.controller = Some((side, controller));
+        self
+    }
+
+    /// Stop any combat still going when `deadline` passes, checked each round. The result is
+    /// then marked `cut_off` and says nothing about the fight.
+    pub fn with_deadline(mut self, deadline: Option<Instant>) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Record how every actor picks its actions: each APL pass, and how each entry fared
+    pub fn with_trace(mut self) -> Self {
+        self.trace = Some(Vec::new());
+        self
+    }
+
+    /// The APL passes recorded so far (empty unless tracing)
+    pub fn take_trace(&mut self) -> Vec<ChoiceTrace> {
+        self.trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    /// The actor called `name`, as combat logs name it (e.g. `Goblin 2`)
+    pub fn actor_id(&self, name: &str) -> Option<usize> {
+        self.actors.iter().position(|a| &*a.name == name)
+    }
+
+    /// Put an actor where `snapshot` has it: on its HP (raising its max to match if need be;
+    /// dead at 0 or less), in the front rank of its zone, and under its conditions for good.
+    /// The snapshot's zone must be one of the encounter's.
+    pub fn pose(&mut self, actor_id: usize, snapshot: &ActorSnapshot) {
+        let actor = &mut self.actors[actor_id];
+        let hp = match (snapshot.hp, snapshot.hp_percent) {
+            (Some(hp), _) => Some(hp),
+            (None, Some(percent)) => Some((actor.max_hp as f64 * percent / 100.0).round() as i32),
+            (None, None) => None,
+        };
+        if let Some(hp) = hp {
+            actor.max_hp = actor.max_hp.max(hp);
+            actor.current_hp = hp.max(0);
+        }
+        if let Some(zone) = snapshot.zone.as_deref().and_then(|name| self.zones.find(name)) {
+            actor.zone = zone;
+            actor.in_reserve = false;
+        }
+        for &kind in &snapshot.conditions {
+            let condition = Effect::new(EffectKind::Condition(kind), 0, Expiry::Never, actor_id);
+            actor.effects.apply(condition, Stacking::default());
+        }
+    }
+
+    /// What an actor would pick for its whole turn in `round` as things stand, from `apl` if
+    /// given, else from its script or its own APL. Nothing is carried out.
+    pub fn decide(&mut self, actor_id: usize, apl: Option<&[AplEntry]>, round: u32, phase: Option<Phase>, rng: &mut impl Rng) -> ChosenActions {
+        self.round = round;
+        let apl = match apl {
+            Some(apl) => Some(apl.to_vec()),
+            None => self.ask_controller(actor_id, phase),
+        };
+        let budget = TurnBudget::full(&self.actors[actor_id]);
+        let actions = self.choose(actor_id, apl.as_deref(), phase, &budget, rng);
+        ChosenActions::new(&actions, &self.actors[actor_id], &self.actors)
+    }
+
+    /// For a controlled actor, ask the controller for the APL to use this turn; otherwise ask
+    /// the actor's script, if it has one
+    fn ask_controller(&mut self, actor_id: usize, phase: Option<Phase>) -> Option<Vec<AplEntry>> {
+        let side = self.actors[actor_id].side;
+        if !matches!(self.controller, Some((controlled, _)) if controlled == side) {
+            return self.ask_script(actor_id, phase);
+        }
+        let decision = Decision {
+            round: self.round,
+            actor_id,
+            phase,
+            events: self.events[self.events_seen..].to_vec(),
+            actors: self
+                .actors
+                .iter()
+                .map(|a| ActorView {
+                    id: a.id,
+                    name: a.name.to_string(),
+                    side: a.side,
+                    hp: a.current_hp,
+                    max_hp: a.max_hp,
+                    ac: a.ac,
+                    zone: self.zones.name(a.zone).to_string(),
+                    alive: a.is_alive(),
+                })
+                .collect(),
+        };
+        self.events_seen = self.events.len();
+        self.controller.as_mut().map(|(_, controller)| controller(&decision))
+    }
+
+    /// Run the actor's script for the APL to use this turn. None (the actor's own APL) when it
+    /// has no script, the script answers with nothing, or it fails.
+    fn ask_script(&mut self, actor_id: usize, phase: Option<Phase>) -> Option<Vec<AplEntry>> {
+        let actor = &self.actors[actor_id];
+        let source = actor.script.as_ref()?;
+        match self.scripts.run(source, actor, &self.actors, &self.zones, self.round, phase) {
+            Ok(apl) => Some(apl).filter(|apl| !apl.is_empty()),
+            Err(message) => {
+                self.push_event(actor_id, EventType::ScriptError { message });
+                None
+            }
         }
     }
 
-    fn zone_has_capacity_for(&self, zone: Zone, actor_id: usize, actor_frontage: u32) -> bool {
-        let capacity = self.zone_capacity.capacity_for(zone);
+    /// Pick the actor's actions from `apl` if the controller supplied one, else its own APL,
+    /// for whatever is still open in `budget`
+    fn choose(&mut self, actor_id: usize, apl: Option<&[AplEntry]>, phase: Option<Phase>, budget: &TurnBudget, rng: &mut impl Rng) -> TurnActions {
+        let actor = &self.actors[actor_id];
+        let source = match apl {
+            Some(_) => AplSource::Supplied,
+            None if actor.apl.is_empty() => AplSource::Default,
+            None => AplSource::Apl,
+        };
+        let apl = apl.unwrap_or(&actor.apl);
+        let Some(trace) = &mut self.trace else {
+            return select_actions(actor, apl, &self.actors, &self.zones, self.round, phase, budget, rng);
+        };
+        let (actions, entries) = trace_actions(actor, apl, &self.actors, &self.zones, self.round, phase, budget, rng);
+        trace.push(ChoiceTrace {
+            round: self.round,
+            actor_id,
+            actor: actor.name.to_string(),
+            phase,
+            source,
+            movement: budget.movement,
+            open: Slot::ALL.into_iter().filter(|&slot| budget.has(slot)).collect(),
+            entries,
+        });
+        actions
+    }
+
+    /// Whether any entry of the APL `choose` would pick from matches `uses`, so turn steps
+    /// nothing could fill are skipped without another pass over it
+    fn apl_has(&self, actor_id: usize, apl: Option<&[AplEntry]>, uses: impl Fn(&AplEntry) -> bool) -> bool {
+        apl.unwrap_or(&self.actors[actor_id].apl).iter().any(|e| e.ready.is_none() && uses(e))
+    }
+
+    fn zone_has_capacity_for(&self, zone: Zone, actor_id: usize, actor_space: u32) -> bool {
+        let capacity = self.zones.capacity(zone);
         match capacity {
             None => true, // Infinite capacity
             Some(cap) => {
-                let current_frontage: u32 = self
+                let current_space: u32 = self
                     .actors
                     .iter()
                     .filter(|a| a.zone == zone && a.is_alive() && a.id != actor_id)
-                    .map(|a| a.frontage)
+                    .map(|a| a.space())
                     .sum();
-                current_frontage + actor_frontage <= cap
+                current_space + actor_space <= cap
             }
         }
     }
 
-    fn zone_has_enemies(&self, zone: Zone, actor_side: Side) -> bool {
+    /// Anyone in `zone` who isn't allied with the actor, fighting it or not, holds it
+    fn zone_has_enemies(&self, zone: Zone, actor: &Actor) -> bool {
         self.actors
             .iter()
-            .any(|a| a.zone == zone && a.is_alive() && a.side != actor_side)
+            .any(|a| a.zone == zone && a.is_alive() && !a.noncombatant && !a.is_allied_with(actor))
     }
 
-    fn can_enter_zone(&self, zone: Zone, actor_id: usize, actor_side: Side, actor_frontage: u32) -> bool {
-        self.zone_has_capacity_for(zone, actor_id, actor_frontage) && !self.zone_has_enemies(zone, actor_side)
+    fn can_enter_zone(&self, zone: Zone, actor: &Actor) -> bool {
+        self.zone_has_capacity_for(zone, actor.id, actor.space()) && !self.zone_has_enemies(zone, actor)
     }
 
     pub fn run(&mut self, rng: &mut impl Rng) -> CombatResult {
+        let surprised = self.roll_surprise(rng);
+        let mut front_line = Vec::new();
+        let mut cut_off = false;
         while !self.is_combat_over() && self.round < self.max_rounds {
+            if past(self.deadline) {
+                cut_off = true;
+                break;
+            }
             self.round += 1;
-            match self.initiative_type {
-                InitiativeType::Side => self.run_round_side(rng),
-                InitiativeType::Individual => self.run_round_individual(rng),
-                InitiativeType::SidePhases => self.run_round_side_phases(rng),
-                InitiativeType::IndividualPhases => self.run_round_individual_phases(rng),
+            for actor in &mut self.actors {
+                actor.reacted = false;
+                actor.legendary_remaining = actor.legendary_actions;
+            }
+            self.expire_effects();
+            self.select_front();
+            match (surprised, self.initiative_type) {
+                (Some(side), _) if self.round == 1 => self.run_surprise_round(side, rng),
+                (_, InitiativeType::Side) => self.run_round_side(rng),
+                (_, InitiativeType::Individual) => self.run_round_individual(rng),
+                (_, InitiativeType::SidePhases) => self.run_round_side_phases(rng),
+                (_, InitiativeType::IndividualPhases) => self.run_round_individual_phases(rng),
+                (_, InitiativeType::Declared) => self.run_round_declared(rng),
             }
+            if !self.is_combat_over() {
+                self.apply_hazards(rng);
+                for actor_id in 0..self.actors.len() {
+                    self.tick_effects(actor_id, Tick::EndOfRound, rng);
+                }
+            }
+            if !self.is_combat_over() {
+                self.check_hold_objectives();
+            }
+            front_line.push(self.front_line());
         }
 
+        let timed_out = !self.is_combat_over();
+        let scores = timed_out.then(|| self.scores()).flatten();
+        let outcome = self.get_winner();
+        let winner = match outcome {
+            _ if timed_out => self.resolve_draw(scores.as_deref()),
+            Outcome::Winner(side) => Some(side),
+            Outcome::MutualDestruction | Outcome::Undecided => None,
+        };
+
         CombatResult {
-            winner: self.get_winner(),
+            winner,
             rounds: self.round,
-            events: self.events.clone(),
+            timed_out,
+            cut_off,
+            mutual_destruction: outcome == Outcome::MutualDestruction,
+            events: std::mem::take(&mut self.events),
             final_state: self
                 .actors
                 .iter()
                 .map(|a| ActorState {
                     id: a.id,
                     name: a.name.clone(),
+                    template: a.template_name.clone(),
                     side: a.side,
-                    max_hp: a.max_hp,
+                    summoner: a.summoner,
+                    max_hp: a.max_hp + a.drained,
                     final_hp: a.current_hp,
-                    alive: a.is_alive(),
-                    zone: a.zone,
+                    drained: a.drained,
+                    alive: a.current_hp > 0,
+                    fled: a.fled,
+                    vip: a.vip,
+                    noncombatant: a.noncombatant,
+                    zone: self.zones.name(a.zone).to_string(),
                 })
                 .collect(),
+            surprised,
+            objective: self.objective_met,
+            score: scores.map(|scores| (scores[0], scores[1])),
+            front_line,
+            stalemate: timed_out.then(|| self.stalemate()),
         }
     }
 
-    /// Side-based initiative: one side acts completely, then the other
-    fn run_round_side(&mut self, rng: &mut impl Rng) {
-        // Determine which side goes first (50/50)
-        let first_side = if rng.gen_bool(0.5) { Side::Side1 } else { Side::Side2 };
-        let second_side = first_side.opposite();
+    /// Which side (if any) is surprised
+    fn roll_surprise(&self, rng: &mut impl Rng) -> Option<Side> {
+        match self.surprise {
+            Surprise::None => None,
+            Surprise::Side1 => Some(Side::SIDE1),
+            Surprise::Side2 => Some(Side::SIDE2),
+            Surprise::Roll => {
+                let sneaks = |side: Side, d20: i32| {
+                    let stealth = self.side_actors(side).map(|a| a.stealth).min().unwrap_or(0);
+                    let perception = self.actors.iter().filter(|a| a.side != side).map(|a| a.perception).max().unwrap_or(0);
+                    d20 + stealth >= 10 + perception
+                };
+                let side1_roll = self.d20(rng);
+                let side2_roll = self.d20(rng);
+                match (sneaks(Side::SIDE1, side1_roll), sneaks(Side::SIDE2, side2_roll)) {
+                    (true, false) => Some(Side::SIDE2),
+                    (false, true) => Some(Side::SIDE1),
+                    _ => None,
+                }
+            }
+        }
+    }
+
+    /// Midway between side 1's and side 2's most advanced fighters on the main line, in zones
+    /// from side 1's rear. None unless both sides have someone standing on it.
+    pub fn front_line(&self) -> Option<f64> {
+        let main = self.zones.main_len();
+        let fighters = |side| {
+            self.side_actors(side)
+                .filter(move |a| a.is_alive() && !a.noncombatant && a.zone.0 < main)
+                .map(|a| a.zone.0)
+        };
+        let side1 = fighters(Side::SIDE1).max()?;
+        let side2 = fighters(Side::SIDE2).min()?;
+        Some((side1 + side2) as f64 / 2.0)
+    }
+
+    fn side_actors(&self, side: Side) -> impl Iterator<Item = &Actor> {
+        self.actors.iter().filter(move |a| a.side == side)
+    }
+
+    /// Every side in the fight, in order
+    fn sides(&self) -> impl Iterator<Item = Side> {
+        (0..self.hostility.len()).map(Side::new)
+    }
+
+    /// Surprise round: everyone but the surprised side acts, side by side, each actor taking a
+    /// full turn
+    fn run_surprise_round(&mut self, surprised: Side, rng: &mut impl Rng) {
+        // The surprised can't react either
+        for actor in self.actors.iter_mut().filter(|a| a.side == surprised) {
+            actor.reacted = true;
+        }
+        for ambushers in self.sides().filter(|&side| side != surprised).collect::<Vec<_>>() {
+            for actor_id in self.get_shuffled_side_order(ambushers, rng) {
+                self.execute_full_turn(actor_id, rng);
+                if self.is_combat_over() {
+                    return;
+                }
+            }
+        }
+    }
 
-        for side in [first_side, second_side] {
+    /// Side-based initiative: one side acts completely, then the next
+    fn run_round_side(&mut self, rng: &mut impl Rng) {
+        for side in self.roll_side_order(rng) {
             // Get actors for this side, shuffled
             let mut order: Vec<usize> = self
                 .actors
@@ -170,6 +999,7 @@ impl CombatSimulator {
                 let j = rng.gen_range(0..=i);
                 order.swap(i, j);
             }
+            self.delay_heavy_weapons(&mut order);
 
             for actor_id in order {
                 self.execute_full_turn(actor_id, rng);
@@ -182,43 +1012,155 @@ impl CombatSimulator {
 
     /// Individual initiative: each actor rolls initiative dice + modifier
     fn run_round_individual(&mut self, rng: &mut impl Rng) {
+        let initiatives = self.roll_initiative(true, rng);
+
+        for group in self.tie_groups(&initiatives) {
+            self.act_together(&group, None, rng);
+            if self.is_combat_over() {
+                return;
+            }
+        }
+    }
+
+    /// Split an initiative order into the actors who act at the same moment: runs of equal
+    /// rolls with simultaneous tie-breaking, otherwise one actor at a time
+    fn tie_groups(&self, order: &[(usize, i32)]) -> Vec<Vec<usize>> {
+        let ids = |group: &[(usize, i32)]| group.iter().map(|&(id, _)| id).collect();
+        if self.tie_break == TieBreak::Simultaneous {
+            order.chunk_by(|a, b| a.1 == b.1).map(ids).collect()
+        } else {
+            order.chunks(1).map(ids).collect()
+        }
+    }
+
+    /// Have the living members of a tie group act: alone, an ordinary turn (or just the
+    /// attack, during `phase`); together, declared and resolved at once like a declared round
+    fn act_together(&mut self, group: &[usize], phase: Option<Phase>, rng: &mut impl Rng) {
+        let living: Vec<usize> = group.iter().copied().filter(|&id| self.actors[id].is_alive()).collect();
+        match (living.as_slice(), phase) {
+            ([], _) => {}
+            (&[actor_id], None) => self.execute_full_turn(actor_id, rng),
+            (&[actor_id], Some(phase)) => self.execute_attack_only(actor_id, phase, rng),
+            (_, phase) => self.resolve_declared(living, phase, rng),
+        }
+    }
+
+    /// The order sides act in this round, or the order from the first roll when initiative is
+    /// rolled once. Two sides toss a coin for who goes first; more are shuffled.
+    fn roll_side_order(&mut self, rng: &mut impl Rng) -> Vec<Side> {
+        if let Some(order) = &self.kept_side_order {
+            return order.clone();
+        }
+        let mut order: Vec<Side> = self.sides().collect();
+        if order.len() == 2 {
+            if !rng.gen_bool(0.5) {
+                order.swap(0, 1);
+            }
+        } else {
+            for i in (1..order.len()).rev() {
+                let j = rng.gen_range(0..=i);
+                order.swap(i, j);
+            }
+        }
+        if self.initiative_roll == InitiativeRoll::Once {
+            self.kept_side_order = Some(order.clone());
+        }
+        order
+    }
+
+    /// Living actors in initiative order (highest first). `delay_weapons` subtracts weapon speed
+    /// from each roll. When initiative is rolled once, later rounds reuse the first order.
+    fn roll_initiative(&mut self, delay_weapons: bool, rng: &mut impl Rng) -> Vec<(usize, i32)> {
+        if let Some(kept) = &self.kept_initiative {
+            return kept
+                .iter()
+                .copied()
+                .filter(|&(id, _)| self.actors[id].is_alive())
+                .collect();
+        }
+
         // Roll initiative for each actor
         let mut initiatives: Vec<(usize, i32)> = self
             .actors
             .iter()
             .filter(|a| a.is_alive())
             .map(|a| {
-                let roll = self.initiative_dice.roll(rng) + a.initiative_modifier;
+                let delay = if delay_weapons { self.speed_factor(a) } else { 0 };
+                let roll = self.roll_dice(&self.initiative_dice, rng) + a.initiative_modifier - delay;
                 (a.id, roll)
             })
             .collect();
 
-        // Sort by initiative (highest first), with random tiebreaker
-        initiatives.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| {
-            if rng.gen_bool(0.5) { std::cmp::Ordering::Less } else { std::cmp::Ordering::Greater }
-        }));
+        // Highest first. Ties go to the policy, then to a random draw made before sorting so
+        // the comparison stays a total order.
+        let draws: Vec<u32> = (0..self.actors.len()).map(|_| rng.gen()).collect();
+        let policy = |id: usize| -> (i32, u32) {
+            let actor = &self.actors[id];
+            match self.tie_break {
+                TieBreak::Modifier => (actor.initiative_modifier, draws[id]),
+                TieBreak::Side1First => ((actor.side == Side::SIDE1) as i32, draws[id]),
+                TieBreak::Random | TieBreak::Reroll | TieBreak::Simultaneous => (0, draws[id]),
+            }
+        };
+        initiatives.sort_by_key(|&(id, roll)| std::cmp::Reverse((roll, policy(id))));
+        if self.tie_break == TieBreak::Reroll {
+            self.reroll_ties(&mut initiatives, rng);
+        }
 
-        for (actor_id, _) in initiatives {
-            if !self.actors[actor_id].is_alive() {
-                continue;
+        if self.initiative_roll == InitiativeRoll::Once {
+            self.kept_initiative = Some(initiatives.clone());
+        }
+        initiatives
+    }
+
+    /// Re-roll the initiative dice for each run of tied actors and reorder the run by the new
+    /// rolls, again for anyone still tied. Dice too small to separate them (or a long streak of
+    /// ties) leave the random order from the first sort.
+    fn reroll_ties(&self, order: &mut [(usize, i32)], rng: &mut impl Rng) {
+        const MAX_REROLLS: usize = 8;
+        let mut tied = Vec::new();
+        let mut start = 0;
+        for group in order.chunk_by(|a, b| a.1 == b.1) {
+            if group.len() > 1 {
+                tied.push(start..start + group.len());
             }
-            self.execute_full_turn(actor_id, rng);
-            if self.is_combat_over() {
+            start += group.len();
+        }
+        for _ in 0..MAX_REROLLS {
+            if tied.is_empty() {
                 return;
             }
+            let mut still_tied = Vec::new();
+            for run in tied {
+                let mut rerolls: Vec<(usize, i32, i32)> = order[run.clone()]
+                    .iter()
+                    .map(|&(id, roll)| (id, roll, self.roll_dice(&self.initiative_dice, rng)))
+                    .collect();
+                // Stable, so the random order from the first sort breaks any tie left over
+                rerolls.sort_by_key(|&(_, _, reroll)| std::cmp::Reverse(reroll));
+                for (slot, (id, roll, _)) in order[run.clone()].iter_mut().zip(&rerolls) {
+                    *slot = (*id, *roll);
+                }
+                let mut start = run.start;
+                for group in rerolls.chunk_by(|a, b| a.2 == b.2) {
+                    if group.len() > 1 {
+                        still_tied.push(start..start + group.len());
+                    }
+                    start += group.len();
+                }
+            }
+            tied = still_tied;
         }
     }
 
-    /// Side-based phases: each phase executes for both sides before moving to the next
+    /// Side-based phases: each phase executes for every side before moving to the next
     fn run_round_side_phases(&mut self, rng: &mut impl Rng) {
-        // Determine which side goes first (50/50)
-        let first_side = if rng.gen_bool(0.5) { Side::Side1 } else { Side::Side2 };
-        let second_side = first_side.opposite();
+        let sides = self.roll_side_order(rng);
 
         for phase in self.phases.clone() {
             match phase {
                 Phase::Movement => {
-                    for side in [first_side, second_side] {
+                    for &side in &sides {
                         let order = self.get_shuffled_side_order(side, rng);
                         for actor_id in order {
                             self.execute_movement_only(actor_id, rng);
@@ -226,33 +1168,36 @@ impl CombatSimulator {
                     }
                 }
                 Phase::Ranged => {
-                    for side in [first_side, second_side] {
-                        let order = self.get_shuffled_side_order(side, rng);
+                    for &side in &sides {
+                        let mut order = self.get_shuffled_side_order(side, rng);
+                        self.delay_heavy_weapons(&mut order);
                         for actor_id in order {
                             if self.actors[actor_id].range == WeaponRange::Ranged {
-                                self.execute_attack_only(actor_id, rng);
+                                self.execute_attack_only(actor_id, Phase::Ranged, rng);
                                 if self.is_combat_over() { return; }
                             }
                         }
                     }
                 }
                 Phase::Reach => {
-                    for side in [first_side, second_side] {
-                        let order = self.get_shuffled_side_order(side, rng);
+                    for &side in &sides {
+                        let mut order = self.get_shuffled_side_order(side, rng);
+                        self.delay_heavy_weapons(&mut order);
                         for actor_id in order {
                             if self.actors[actor_id].range == WeaponRange::Reach {
-                                self.execute_attack_only(actor_id, rng);
+                                self.execute_attack_only(actor_id, Phase::Reach, rng);
                                 if self.is_combat_over() { return; }
                             }
                         }
                     }
                 }
                 Phase::Melee => {
-                    for side in [first_side, second_side] {
-                        let order = self.get_shuffled_side_order(side, rng);
+                    for &side in &sides {
+                        let mut order = self.get_shuffled_side_order(side, rng);
+                        self.delay_heavy_weapons(&mut order);
                         for actor_id in order {
                             if self.actors[actor_id].range == WeaponRange::Melee {
-                                self.execute_attack_only(actor_id, rng);
+                                self.execute_attack_only(actor_id, Phase::Melee, rng);
                                 if self.is_combat_over() { return; }
                             }
                         }
@@ -265,62 +1210,140 @@ impl CombatSimulator {
 
     /// Individual phases: each phase executes in initiative order before moving to the next
     fn run_round_individual_phases(&mut self, rng: &mut impl Rng) {
-        // Roll initiative for each actor
-        let mut initiatives: Vec<(usize, i32)> = self
-            .actors
-            .iter()
-            .filter(|a| a.is_alive())
-            .map(|a| {
-                let roll = self.initiative_dice.roll(rng) + a.initiative_modifier;
-                (a.id, roll)
-            })
-            .collect();
-
-        // Sort by initiative (highest first)
-        initiatives.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| {
-            if rng.gen_bool(0.5) { std::cmp::Ordering::Less } else { std::cmp::Ordering::Greater }
-        }));
+        let initiatives = self.roll_initiative(false, rng);
 
-        let order: Vec<usize> = initiatives.iter().map(|(id, _)| *id).collect();
+        // Weapon speed only delays attacks; movement keeps the rolled order
+        let attack_order: Vec<(usize, i32)> = if self.weapon_speed {
+            let mut delayed: Vec<(usize, i32)> = initiatives
+                .iter()
+                .map(|&(id, roll)| (id, roll - self.actors[id].weapon_speed))
+                .collect();
+            delayed.sort_by_key(|&(_, roll)| std::cmp::Reverse(roll));
+            delayed
+        } else {
+            initiatives.clone()
+        };
 
         for phase in self.phases.clone() {
-            match phase {
+            let range = match phase {
                 Phase::Movement => {
-                    for &actor_id in &order {
+                    for &(actor_id, _) in &initiatives {
                         if self.actors[actor_id].is_alive() {
                             self.execute_movement_only(actor_id, rng);
                         }
                     }
+                    if self.is_combat_over() { return; }
+                    continue;
                 }
-                Phase::Ranged => {
-                    for &actor_id in &order {
-                        if self.actors[actor_id].is_alive() && self.actors[actor_id].range == WeaponRange::Ranged {
-                            self.execute_attack_only(actor_id, rng);
-                            if self.is_combat_over() { return; }
-                        }
-                    }
+                Phase::Ranged => WeaponRange::Ranged,
+                Phase::Reach => WeaponRange::Reach,
+                Phase::Melee => WeaponRange::Melee,
+            };
+            let acting: Vec<(usize, i32)> = attack_order
+                .iter()
+                .copied()
+                .filter(|&(id, _)| self.actors[id].range == range)
+                .collect();
+            for group in self.tie_groups(&acting) {
+                self.act_together(&group, Some(phase), rng);
+                if self.is_combat_over() { return; }
+            }
+        }
+    }
+
+    /// Declared initiative: every living actor picks its move and action from the state at the
+    /// top of the round, then declarations resolve in a random order - postures first, then
+    /// movement, then attacks. Nobody leaves the fight until the round is over, so an actor cut
+    /// down mid-round still carries out what it declared.
+    fn run_round_declared(&mut self, rng: &mut impl Rng) {
+        let living: Vec<usize> = self.actors.iter().filter(|a| a.is_alive()).map(|a| a.id).collect();
+        self.resolve_declared(living, None, rng);
+    }
+
+    /// Declare and resolve the actions of `actors` together; the fallen drop out once all of
+    /// them are done. During a `phase` only the attack slot is declared.
+    fn resolve_declared(&mut self, actors: Vec<usize>, phase: Option<Phase>, rng: &mut impl Rng) {
+        let mut declarations: Vec<(usize, TurnActions)> = Vec::new();
+        for actor_id in actors {
+            let apl = self.ask_controller(actor_id, phase);
+            let budget = TurnBudget::full(&self.actors[actor_id]);
+            let mut actions = self.choose(actor_id, apl.as_deref(), phase, &budget, rng);
+            if phase.is_some() {
+                actions.move_action = MoveAction::None;
+            }
+            declarations.push((actor_id, actions));
+        }
+        for i in (1..declarations.len()).rev() {
+            let j = rng.gen_range(0..=i);
+            declarations.swap(i, j);
+        }
+
+        self.pending_deaths = Some(Vec::new());
+        for (actor_id, actions) in &mut declarations {
+            self.start_turn(*actor_id, rng);
+            for slot in Slot::ALL {
+                if !matches!(actions.slot_mut(slot), AttackAction::Attack { .. } | AttackAction::Dash { .. }) {
+                    let action = actions.take(slot);
+                    self.execute_attack_action(*actor_id, action, rng);
                 }
-                Phase::Reach => {
-                    for &actor_id in &order {
-                        if self.actors[actor_id].is_alive() && self.actors[actor_id].range == WeaponRange::Reach {
-                            self.execute_attack_only(actor_id, rng);
-                            if self.is_combat_over() { return; }
-                        }
-                    }
+            }
+        }
+        for (actor_id, actions) in &mut declarations {
+            let speed = self.actors[*actor_id].speed;
+            let mut movement = self.execute_move_action(*actor_id, actions.move_action.clone(), speed, rng);
+            // Dashes go with the movement, on whatever it left over
+            for slot in Slot::ALL {
+                if let AttackAction::Dash { direction } = actions.slot_mut(slot) {
+                    let direction = direction.clone();
+                    movement = self.execute_dash(*actor_id, direction, movement, rng);
                 }
-                Phase::Melee => {
-                    for &actor_id in &order {
-                        if self.actors[actor_id].is_alive() && self.actors[actor_id].range == WeaponRange::Melee {
-                            self.execute_attack_only(actor_id, rng);
-                            if self.is_combat_over() { return; }
-                        }
+            }
+        }
+        for (actor_id, mut actions) in declarations {
+            for slot in Slot::ALL {
+                let attack = actions.take(slot);
+                if let AttackAction::Attack { target_id, .. } = attack {
+                    match self.retarget(actor_id, attack, slot, phase, rng) {
+                        Some(attack) => self.execute_attack_action(actor_id, attack, rng),
+                        None => self.lose_target(actor_id, target_id),
                     }
                 }
             }
-            if self.is_combat_over() { return; }
+            self.end_turn(actor_id, rng);
+            self.legendary_actions(actor_id, rng);
+        }
+        for death in self.pending_deaths.take().unwrap_or_default() {
+            self.fall(death, rng);
+        }
+    }
+
+    /// The attack to carry out for a declared `attack`. With `lost_target: retarget`, an actor
+    /// whose target is down or out of reach runs its APL again from the current state, and
+    /// gets None if there's no one else to attack.
+    fn retarget(&mut self, actor_id: usize, attack: AttackAction, slot: Slot, phase: Option<Phase>, rng: &mut impl Rng) -> Option<AttackAction> {
+        let AttackAction::Attack { target_id, .. } = attack else {
+            return Some(attack);
+        };
+        let (actor, target) = (&self.actors[actor_id], &self.actors[target_id]);
+        let lost = !target.is_alive() || !actor.can_attack(target, &self.zones, &self.actors);
+        if !lost || self.lost_target == LostTarget::Wasted {
+            return Some(attack);
+        }
+        // The fallen still finish what they declared, but aren't asked for anything new
+        let apl = if actor.is_alive() { self.ask_controller(actor_id, phase) } else { None };
+        let budget = TurnBudget::full(&self.actors[actor_id]);
+        match self.choose(actor_id, apl.as_deref(), phase, &budget, rng).take(slot) {
+            attack @ AttackAction::Attack { target_id, .. } if self.actors[target_id].is_alive() => Some(attack),
+            _ => None,
         }
     }
 
+    /// Record an attack that never happened because its target was gone
+    fn lose_target(&mut self, actor_id: usize, target_id: usize) {
+        let target_name = self.actors[target_id].name.clone();
+        self.push_event(actor_id, EventType::LostTarget { target_id, target_name });
+    }
+
     fn get_shuffled_side_order(&self, side: Side, rng: &mut impl Rng) -> Vec<usize> {
         let mut order: Vec<usize> = self
             .actors
@@ -336,41 +1359,118 @@ impl CombatSimulator {
         order
     }
 
-    /// Execute a full turn: move then attack
+    /// Initiative penalty for the actor's weapon, if weapon speed is in use
+    fn speed_factor(&self, actor: &Actor) -> i32 {
+        if self.weapon_speed {
+            actor.weapon_speed
+        } else {
+            0
+        }
+    }
+
+    /// With weapon speed in use, reorder a side so faster weapons act first. The sort is stable,
+    /// so actors with equal speed keep their shuffled order.
+    fn delay_heavy_weapons(&self, order: &mut [usize]) {
+        if self.weapon_speed {
+            order.sort_by_key(|&id| self.actors[id].weapon_speed);
+        }
+    }
+
+    /// Start-of-turn hook: end whatever lasted until the actor's next turn (guard, defend,
+    /// dodge, hold), then deal start-of-turn effect damage
+    fn start_turn(&mut self, actor_id: usize, rng: &mut impl Rng) {
+        let ended = self.actors[actor_id].effects.expire_turn();
+        self.effects_ended(actor_id, ended);
+        self.tick_effects(actor_id, Tick::StartOfTurn, rng);
+    }
+
+    /// End-of-turn hook: deal end-of-turn effect damage
+    fn end_turn(&mut self, actor_id: usize, rng: &mut impl Rng) {
+        self.tick_effects(actor_id, Tick::EndOfTurn, rng);
+    }
+
+    /// Execute a full turn, spending the actor's budget in order: move, action, bonus action,
+    /// free interaction, then any movement left over. Each step is picked afresh, since the one
+    /// before may have changed who's where.
     fn execute_full_turn(&mut self, actor_id: usize, rng: &mut impl Rng) {
         if !self.actors[actor_id].is_alive() {
             return;
         }
 
-        // Clear any temporary AC bonus from previous guard action
-        self.actors[actor_id].ac_bonus = 0;
+        self.start_turn(actor_id, rng);
+        if !self.actors[actor_id].is_alive() {
+            return;
+        }
 
         // Get initial actions based on current state
-        let turn_actions = {
-            let actor = &self.actors[actor_id];
-            execute_apl(actor, &self.actors, rng)
-        };
+        let apl = self.ask_controller(actor_id, None);
+        let mut budget = TurnBudget::full(&self.actors[actor_id]);
+        let turn_actions = self.choose(actor_id, apl.as_deref(), None, &budget, rng);
 
         // Execute move first
-        if let MoveAction::Move { direction } = turn_actions.move_action {
-            self.execute_move(actor_id, direction);
+        budget.movement = self.execute_move_action(actor_id, turn_actions.move_action, budget.movement, rng);
+        // Breaking through ends the fight on the spot, and the fled are gone
+        if self.objective_met.is_some() || self.actors[actor_id].fled {
+            return;
         }
 
         // Re-evaluate for attack after moving (position may have changed)
-        let attack_action = {
-            let actor = &self.actors[actor_id];
-            execute_apl(actor, &self.actors, rng).attack_action
-        };
+        self.spend_actions(actor_id, apl.as_deref(), None, &mut budget, rng);
 
-        // Execute attack or guard
-        match attack_action {
-            AttackAction::Attack { target_id } => {
-                self.execute_attack(actor_id, target_id, rng);
+        // Moves saved for after acting get whatever movement is left
+        if budget.movement > 0
+            && self.actors[actor_id].is_alive()
+            && self.apl_has(actor_id, apl.as_deref(), |e| e.after_action)
+        {
+            let move_action = self.choose(actor_id, apl.as_deref(), None, &budget, rng).move_action;
+            self.execute_move_action(actor_id, move_action, budget.movement, rng);
+            if self.objective_met.is_some() || self.actors[actor_id].fled {
+                return;
             }
-            AttackAction::Guard => {
-                self.execute_guard(actor_id);
+        }
+
+        self.end_turn(actor_id, rng);
+        self.legendary_actions(actor_id, rng);
+    }
+
+    /// Take the actor's action, then its bonus action and free interaction if its APL has
+    /// entries for them
+    fn spend_actions(&mut self, actor_id: usize, apl: Option<&[AplEntry]>, phase: Option<Phase>, budget: &mut TurnBudget, rng: &mut impl Rng) {
+        for slot in Slot::ALL {
+            if slot != Slot::Action {
+                if !self.apl_has(actor_id, apl, |e| e.uses == Some(slot)) {
+                    continue;
+                }
+                if !self.actors[actor_id].is_alive() || self.is_combat_over() {
+                    return;
+                }
             }
-            AttackAction::None => {}
+            match self.choose(actor_id, apl, phase, budget, rng).take(slot) {
+                AttackAction::Dash { direction } => budget.movement = self.execute_dash(actor_id, direction, budget.movement, rng),
+                action => self.execute_attack_action(actor_id, action, rng),
+            }
+            budget.spend(slot);
+        }
+    }
+
+    /// Move again on `movement` points plus another `speed` worth, returning what's left
+    fn execute_dash(&mut self, actor_id: usize, direction: MoveDirection, movement: u32, rng: &mut impl Rng) -> u32 {
+        self.push_event(actor_id, EventType::Dash);
+        let movement = movement + self.actors[actor_id].speed;
+        self.execute_move(actor_id, direction, movement, false, rng)
+    }
+
+    /// Carry out a chosen move with `movement` points, returning what's left
+    fn execute_move_action(&mut self, actor_id: usize, move_action: MoveAction, movement: u32, rng: &mut impl Rng) -> u32 {
+        match move_action {
+            MoveAction::Move { direction } => self.execute_move(actor_id, direction, movement, false, rng),
+            MoveAction::Flee => self.execute_flee(actor_id, movement, rng),
+            MoveAction::Withdraw => {
+                self.push_event(actor_id, EventType::Withdraw);
+                self.execute_move(actor_id, MoveDirection::Backward, movement, true, rng)
+            }
+            MoveAction::Hold => 0,
+            MoveAction::None => movement,
         }
     }
 
@@ -380,62 +1480,206 @@ impl CombatSimulator {
             return;
         }
 
-        let turn_actions = {
-            let actor = &self.actors[actor_id];
-            execute_apl(actor, &self.actors, rng)
-        };
-
-        if let MoveAction::Move { direction } = turn_actions.move_action {
-            self.execute_move(actor_id, direction);
-        }
+        let apl = self.ask_controller(actor_id, Some(Phase::Movement));
+        let budget = TurnBudget::full(&self.actors[actor_id]);
+        let turn_actions = self.choose(actor_id, apl.as_deref(), Some(Phase::Movement), &budget, rng);
+        self.execute_move_action(actor_id, turn_actions.move_action, budget.movement, rng);
     }
 
     /// Execute only the attack portion of a turn
-    fn execute_attack_only(&mut self, actor_id: usize, rng: &mut impl Rng) {
+    fn execute_attack_only(&mut self, actor_id: usize, phase: Phase, rng: &mut impl Rng) {
         if !self.actors[actor_id].is_alive() {
             return;
         }
 
-        // Clear any temporary AC bonus from previous guard action
-        self.actors[actor_id].ac_bonus = 0;
+        self.start_turn(actor_id, rng);
+        if !self.actors[actor_id].is_alive() {
+            return;
+        }
 
-        let attack_action = {
-            let actor = &self.actors[actor_id];
-            execute_apl(actor, &self.actors, rng).attack_action
-        };
+        let apl = self.ask_controller(actor_id, Some(phase));
+        let mut budget = TurnBudget::full(&self.actors[actor_id]);
+        self.spend_actions(actor_id, apl.as_deref(), Some(phase), &mut budget, rng);
+        self.end_turn(actor_id, rng);
+        self.legendary_actions(actor_id, rng);
+    }
 
+    /// Carry out the action chosen for the attack slot of a turn
+    fn execute_attack_action(&mut self, actor_id: usize, attack_action: AttackAction, rng: &mut impl Rng) {
         match attack_action {
-            AttackAction::Attack { target_id } => {
-                self.execute_attack(actor_id, target_id, rng);
+            AttackAction::Attack { target_id, on_hit, called_shot } => {
+                self.execute_attack(actor_id, target_id, on_hit, called_shot, rng);
             }
             AttackAction::Guard => {
                 self.execute_guard(actor_id);
             }
+            AttackAction::Defend => {
+                self.execute_defend(actor_id);
+            }
+            AttackAction::Dodge => {
+                self.hold_posture(actor_id, EffectKind::Dodging, 0);
+                self.push_event(actor_id, EventType::Dodge);
+            }
+            AttackAction::Hold => {
+                self.hold_posture(actor_id, EffectKind::Holding, 0);
+                self.push_event(actor_id, EventType::Hold);
+            }
+            AttackAction::Buff { target_id, amount, duration } => {
+                self.execute_buff(actor_id, target_id, &amount, duration, rng);
+            }
+            AttackAction::Grapple { target_id } => {
+                self.execute_grapple(actor_id, target_id, rng);
+            }
+            AttackAction::Escape => {
+                self.execute_escape(actor_id, rng);
+            }
+            AttackAction::Summon { index, amount } => {
+                self.execute_summon(actor_id, index, amount.as_ref(), rng);
+            }
+            AttackAction::Dash { direction } => {
+                self.execute_dash(actor_id, direction, 0, rng);
+            }
             AttackAction::None => {}
         }
     }
 
-    fn execute_attack(&mut self, attacker_id: usize, target_id: usize, rng: &mut impl Rng) {
+    fn push_event(&mut self, actor_id: usize, event_type: EventType) {
+        self.record(CombatEvent {
+            round: self.round,
+            actor_id,
+            actor_name: self.actors[actor_id].name.clone(),
+            event_type,
+        });
+    }
+
+    fn execute_attack(
+        &mut self,
+        attacker_id: usize,
+        target_id: usize,
+        on_hit: Vec<OnHit>,
+        called_shot: Option<HitLocation>,
+        rng: &mut impl Rng,
+    ) {
         let attacker = &self.actors[attacker_id];
         let target = &self.actors[target_id];
 
-        if !attacker.can_attack(target) {
+        if !attacker.can_attack(target, &self.zones, &self.actors) {
+            self.lose_target(attacker_id, target_id);
+            return;
+        }
+
+        if self.has_reactions {
+            let allies: Vec<usize> = self
+                .actors
+                .iter()
+                .filter(|a| a.id != target_id && a.is_allied_with(target))
+                .map(|a| a.id)
+                .collect();
+            self.react(Trigger::Attacked, attacker_id, &[target_id], rng);
+            self.react(Trigger::AllyAttacked, attacker_id, &allies, rng);
+            // A counterattack can drop the attacker before it swings (outside declared rounds,
+            // where the fallen still act)
+            if !self.actors[attacker_id].is_alive() && self.pending_deaths.is_none() {
+                return;
+            }
+        }
+
+        if !self.actors[attacker_id].area {
+            self.strike(attacker_id, target_id, on_hit, called_shot, rng);
             return;
         }
+        // An area attack goes on to everyone else caught in the target's zone
+        let zone = self.actors[target_id].zone;
+        let caught: Vec<usize> = self
+            .actors
+            .iter()
+            .filter(|a| a.is_alive() && a.zone == zone && a.id != attacker_id && a.id != target_id)
+            .filter(|a| self.friendly_fire || self.actors[attacker_id].is_hostile_to(a))
+            .map(|a| a.id)
+            .collect();
+        self.strike(attacker_id, target_id, on_hit.clone(), called_shot, rng);
+        for victim_id in caught {
+            self.strike(attacker_id, victim_id, on_hit.clone(), None, rng);
+        }
+    }
 
-        let roll = rng.gen_range(1..=20) + attacker.attack_bonus;
-        let target_ac = target.effective_ac();
-        let hit = roll >= target_ac;
-        let damage = if hit {
-            attacker.damage.roll(rng)
+    /// Roll one attack on `target_id` and apply what it does
+    fn strike(
+        &mut self,
+        attacker_id: usize,
+        target_id: usize,
+        on_hit: Vec<OnHit>,
+        called_shot: Option<HitLocation>,
+        rng: &mut impl Rng,
+    ) {
+        self.last_attack_round = self.round;
+        let attacker = &self.actors[attacker_id];
+        let target = &self.actors[target_id];
+
+        // Called shots only count in encounters that allow them
+        let called_shot = called_shot.filter(|_| self.called_shots);
+        let modifiers = self.attack_modifiers(attacker, target, called_shot);
+        let (advantage, disadvantage) = modifiers.edge();
+        let attack_bonus = modifiers.roll_bonus();
+        let target_ac = modifiers.target_ac();
+        let cover = if attacker.range == WeaponRange::Ranged {
+            self.zones.terrain(target.zone).cover
         } else {
             0
         };
 
+        // Armor soaks damage before the target's threshold looks at what's left
+        let dr = if self.rules.armor_dr { target.damage_reduction } else { 0 };
+
+        // In expected-value mode every attack lands for its expected damage, while drain and
+        // riders follow the more likely outcome
+        let (roll, hit, lands, damage, damage_before_dr) = if self.expected {
+            let needed = target_ac - attack_bonus;
+            let chance = d20_chance(needed, advantage, disadvantage);
+            let per_hit = if attacker.drain_only { 0.0 } else { (attacker.average_damage(self.rules.dice()) - dr as f64).max(0.0) };
+            let damage = carry_damage(&mut self.damage_carry[target_id], target, per_hit, chance);
+            (MEDIAN_D20 + attack_bonus, chance > 0.0, chance >= 0.5, damage, None)
+        } else {
+            let mut d20 = rng.gen_range(1..=20);
+            if disadvantage {
+                d20 = d20.min(rng.gen_range(1..=20));
+            } else if advantage {
+                d20 = d20.max(rng.gen_range(1..=20));
+            }
+            let roll = d20 + attack_bonus;
+            let hit = roll >= target_ac;
+            let rolled = if hit && !attacker.drain_only { attacker.roll_damage(self.rules.dice(), rng).max(0) } else { 0 };
+            let damage = target.after_threshold((rolled - dr).max(0));
+            (roll, hit, hit, damage, self.rules.armor_dr.then_some(rolled))
+        };
+        let attacker = &self.actors[attacker_id];
+        let target = &self.actors[target_id];
+        let drain = attacker.drain.clone().filter(|_| lands);
+
+        // A shot that may have missed carries on toward the shooter's allies in the way or
+        // fighting the target (an area attack already covers the target's zone)
+        let strays = self.rules.line_of_fire == LineOfFire::HitAllies || self.friendly_fire;
+        let stray = (strays && attacker.range == WeaponRange::Ranged && !attacker.area && (self.expected || !hit))
+            .then(|| modifiers.clone());
+
         let attacker_name = attacker.name.clone();
         let target_name = target.name.clone();
+        let was_alive = target.is_alive();
+        let hp_before = target.current_hp.max(0);
+        let absorbed = if hit {
+            self.actors[target_id].take_damage(damage)
+        } else {
+            0
+        };
+        let overkill = if !was_alive {
+            damage - absorbed
+        } else if !self.actors[target_id].is_alive() {
+            (damage - absorbed - hp_before).max(0)
+        } else {
+            0
+        };
 
-        self.events.push(CombatEvent {
+        self.record(CombatEvent {
             round: self.round,
             actor_id: attacker_id,
             actor_name: attacker_name,
@@ -446,159 +1690,933 @@ impl CombatSimulator {
                 target_ac,
                 hit,
                 damage,
+                absorbed,
+                cover,
+                overkill,
+                called_shot,
+                damage_before_dr,
+                modifiers,
             },
         });
 
-        if hit {
-            self.actors[target_id].current_hp -= damage;
+        if let Some(modifiers) = stray {
+            self.stray_shot(attacker_id, target_id, roll, &modifiers, rng);
+        }
+
+        if let Some(drain) = drain.filter(|_| self.actors[target_id].is_alive()) {
+            let amount = self.roll_value(&drain, rng);
+            let amount = self.actors[target_id].drain_max_hp(amount);
+            let target = &self.actors[target_id];
+            let event = EventType::Drain {
+                target_id,
+                target_name: target.name.clone(),
+                amount,
+                max_hp: target.max_hp,
+            };
+            self.push_event(attacker_id, event);
+        }
+
+        if lands {
+            for rider in on_hit {
+                if !self.actors[target_id].is_alive() {
+                    break;
+                }
+                match rider {
+                    OnHit::Forced(forced) => self.apply_forced_move(attacker_id, target_id, forced, rng),
+                    OnHit::Condition(rider) => self.apply_condition(attacker_id, target_id, &rider, rng),
+                }
+            }
+            if let Some(location) = called_shot.filter(|_| self.actors[target_id].is_alive()) {
+                self.apply_condition(attacker_id, target_id, &location.rider(), rng);
+            }
+        }
+
+        if was_alive && !self.actors[target_id].is_alive() {
+            self.die(target_id, Some(attacker_id), rng);
+        }
+    }
+
+    /// Build the modifier stack for `attacker`'s attack on `target`. A dodging target forces
+    /// disadvantage, as does shooting or reaching into dim light, being held in a grapple, or
+    /// being poisoned: roll twice, keep the lower. Melee against a prone target has advantage
+    /// instead, as does anything against a stunned one; the two cancel out. Traits can add
+    /// either. Ranged attacks take the target zone's cover as a penalty.
+    fn attack_modifiers(&self, attacker: &Actor, target: &Actor, called_shot: Option<HitLocation>) -> AttackModifiers {
+        let mut modifiers = AttackModifiers::new(attacker.attack_bonus, target.ac);
+        modifiers.add_ac(ModifierSource::Stance, target.effects.magnitude(EffectKind::Stance));
+        if self.has_auras {
+            modifiers.add_roll(ModifierSource::Aura, attacker.aura_effect(&self.actors, &self.zones).attack);
+            modifiers.add_ac(ModifierSource::Aura, target.aura_effect(&self.actors, &self.zones).ac);
+        }
+        if let Some(location) = called_shot {
+            modifiers.add_roll(ModifierSource::CalledShot, -location.penalty());
+        }
+        if attacker.range == WeaponRange::Ranged {
+            modifiers.add_roll(ModifierSource::Cover, -self.zones.terrain(target.zone).cover);
+            if self.rules.line_of_fire == LineOfFire::Penalty && !self.allies_in_the_way(attacker, target).is_empty() {
+                modifiers.add_roll(ModifierSource::AlliesInTheWay, -ALLIES_IN_THE_WAY_PENALTY);
+            }
+        }
+
+        if target.effects.has(EffectKind::Dodging) {
+            modifiers.add_edge(EdgeSource::Dodge, false);
+        }
+        if attacker.range != WeaponRange::Melee && attacker.sight_of(target, &self.zones, &self.actors) == Light::Dim {
+            modifiers.add_edge(EdgeSource::DimLight, false);
+        }
+        if attacker.is_grappled(&self.actors) {
+            modifiers.add_edge(EdgeSource::Grappled, false);
+        }
+        if attacker.has_condition(ConditionKind::Poisoned) {
+            modifiers.add_edge(EdgeSource::Poisoned, false);
+        }
+        if attacker.range == WeaponRange::Melee && target.has_condition(ConditionKind::Prone) {
+            modifiers.add_edge(EdgeSource::Prone, true);
+        }
+        if target.has_condition(ConditionKind::Stunned) {
+            modifiers.add_edge(EdgeSource::Stunned, true);
+        }
+        traits::attack_edges(attacker, target, &self.actors, &self.zones, &mut modifiers);
+        modifiers
+    }
+
+    /// Living allies of `shooter` in zones strictly between it and `target`, on any shortest
+    /// path from one to the other
+    fn allies_in_the_way(&self, shooter: &Actor, target: &Actor) -> Vec<usize> {
+        let (from, to) = (shooter.zone, target.zone);
+        let span = self.zones.distance(from, to);
+        self.actors
+            .iter()
+            .filter(|a| a.is_alive() && a.id != shooter.id && a.zone != from && a.zone != to && a.is_allied_with(shooter))
+            .filter(|a| self.zones.distance(from, a.zone).saturating_add(self.zones.distance(a.zone, to)) == span)
+            .map(|a| a.id)
+            .collect()
+    }
+
+    /// A ranged attack that missed `target_id` goes on to a random ally of the shooter in the
+    /// way (with `rules.line_of_fire: hit_allies`) or able to strike the target in melee (with
+    /// `friendly_fire`), hitting it if the same roll beats its AC. Expected-value mode takes the
+    /// first of them, with the chance the roll misses the target but not it.
+    fn stray_shot(&mut self, attacker_id: usize, target_id: usize, roll: i32, modifiers: &AttackModifiers, rng: &mut impl Rng) {
+        let (shooter, target) = (&self.actors[attacker_id], &self.actors[target_id]);
+        let mut in_the_way = Vec::new();
+        if self.rules.line_of_fire == LineOfFire::HitAllies {
+            in_the_way = self.allies_in_the_way(shooter, target);
+        }
+        if self.friendly_fire {
+            let engaged = self.actors.iter().filter(|a| {
+                a.is_alive()
+                    && a.id != shooter.id
+                    && a.is_allied_with(shooter)
+                    && a.range == WeaponRange::Melee
+                    && a.can_attack(target, &self.zones, &self.actors)
+            });
+            in_the_way.extend(engaged.map(|a| a.id));
+        }
+        if in_the_way.is_empty() {
+            return;
+        }
+        let ally_id = if self.expected { in_the_way[0] } else { in_the_way[rng.gen_range(0..in_the_way.len())] };
+        let (attacker, ally) = (&self.actors[attacker_id], &self.actors[ally_id]);
+        let ally_ac = ally.effective_ac();
+        let dr = if self.rules.armor_dr { ally.damage_reduction } else { 0 };
+        let (hit, damage) = if self.expected {
+            let (advantage, disadvantage) = modifiers.edge();
+            let bonus = modifiers.roll_bonus();
+            let chance = d20_chance(ally_ac - bonus, advantage, disadvantage)
+                - d20_chance(modifiers.target_ac() - bonus, advantage, disadvantage);
+            let per_hit = if attacker.drain_only { 0.0 } else { (attacker.average_damage(self.rules.dice()) - dr as f64).max(0.0) };
+            (chance > 0.0, carry_damage(&mut self.damage_carry[ally_id], ally, per_hit, chance.max(0.0)))
+        } else if roll >= ally_ac {
+            let rolled = if attacker.drain_only { 0 } else { attacker.roll_damage(self.rules.dice(), rng).max(0) };
+            (true, ally.after_threshold((rolled - dr).max(0)))
+        } else {
+            (false, 0)
+        };
+        if !hit {
+            return;
+        }
+
+        let target_name = self.actors[ally_id].name.clone();
+        let absorbed = self.actors[ally_id].take_damage(damage);
+        let event = EventType::StrayShot { target_id: ally_id, target_name, roll, target_ac: ally_ac, damage, absorbed };
+        self.push_event(attacker_id, event);
+        if !self.actors[ally_id].is_alive() {
+            self.die(ally_id, Some(attacker_id), rng);
+        }
+    }
+
+    /// Log the actor's death and carry out its `on_death`. In a declared round both wait for
+    /// the end of the round, when `resolve_declared` hands the held deaths to `fall`.
+    fn die(&mut self, actor_id: usize, killer_id: Option<usize>, rng: &mut impl Rng) {
+        let death = CombatEvent {
+            round: self.round,
+            actor_id,
+            actor_name: self.actors[actor_id].name.clone(),
+            event_type: EventType::Death { killer_id },
+        };
+        match &mut self.pending_deaths {
+            Some(pending) => pending.push(death),
+            None => self.fall(death, rng),
+        }
+    }
+
+    /// Record a death and bring in whatever the fallen actor leaves behind, in the zone where it
+    /// fell (as far as room allows)
+    fn fall(&mut self, death: CombatEvent, rng: &mut impl Rng) {
+        let actor_id = death.actor_id;
+        self.record(death);
+        let Some(on_death) = self.actors[actor_id].on_death.clone() else {
+            return;
+        };
+        if let Some(template) = &on_death.transform {
+            if let Some(into_id) = self.spawn(template, actor_id, rng) {
+                let into_name = self.actors[into_id].name.clone();
+                self.push_event(actor_id, EventType::Transform { into_id, into_name });
+            }
+        }
+        if let Some(template) = &on_death.spawn {
+            for _ in 0..template.count {
+                let Some(spawned_id) = self.spawn(template, actor_id, rng) else {
+                    break;
+                };
+                let spawned = &self.actors[spawned_id];
+                let (spawned_name, zone) = (spawned.name.clone(), self.zones.name(spawned.zone).to_string());
+                self.push_event(actor_id, EventType::Spawn { spawned_id, spawned_name, zone });
+            }
+        }
+    }
+
+    /// At the end of `turn_of`'s turn, every other living actor with legendary actions left may
+    /// spend one: its attack-slot action if the legendary APL picks one, otherwise its move.
+    /// Actions held back when there's nothing useful to do.
+    fn legendary_actions(&mut self, turn_of: usize, rng: &mut impl Rng) {
+        for actor_id in 0..self.actors.len() {
+            let actor = &self.actors[actor_id];
+            if actor_id == turn_of || actor.legendary_remaining == 0 || !actor.is_alive() || self.is_combat_over() {
+                continue;
+            }
+            let actions = execute_legendary_apl(actor, &self.actors, &self.zones, self.round, rng);
+            let acting = !matches!(actions.attack_action, AttackAction::None | AttackAction::Hold);
+            if !acting && !matches!(actions.move_action, MoveAction::Move { .. }) {
+                continue;
+            }
 
-            if !self.actors[target_id].is_alive() {
-                self.events.push(CombatEvent {
-                    round: self.round,
-                    actor_id: target_id,
-                    actor_name: target_name,
-                    event_type: EventType::Death {
-                        killer_id: Some(attacker_id),
-                    },
-                });
+            self.actors[actor_id].legendary_remaining -= 1;
+            let remaining = self.actors[actor_id].legendary_remaining;
+            self.push_event(actor_id, EventType::Legendary { remaining });
+            if acting {
+                self.execute_attack_action(actor_id, actions.attack_action, rng);
+            } else if let MoveAction::Move { direction } = actions.move_action {
+                let speed = self.actors[actor_id].speed;
+                self.execute_move(actor_id, direction, speed, false, rng);
             }
         }
     }
 
+    /// Give each of `reactors` (in order) the chance to spend its reaction on `trigger`, set off
+    /// by `source_id`. Each actor reacts at most once per round.
+    fn react(&mut self, trigger: Trigger, source_id: usize, reactors: &[usize], rng: &mut impl Rng) {
+        for &reactor_id in reactors {
+            let reactor = &self.actors[reactor_id];
+            let source = &self.actors[source_id];
+            if reactor.reacted || !reactor.is_alive() || !source.is_alive() || self.is_combat_over() {
+                continue;
+            }
+            let Some(action) = select_reaction(reactor, &self.actors, &self.zones, self.round, trigger, source)
+            else {
+                continue;
+            };
+            let source_name = source.name.clone();
+            self.actors[reactor_id].reacted = true;
+            self.push_event(reactor_id, EventType::Reaction { trigger, source_id, source_name });
+            self.execute_attack_action(reactor_id, action, rng);
+        }
+    }
+
+    /// Enemies of `actor_id` that could attack it if it stood in `zone`
+    fn threatening(&self, actor_id: usize, zone: Zone) -> Vec<usize> {
+        let mut actor = self.actors[actor_id].clone();
+        actor.zone = zone;
+        self.actors
+            .iter()
+            .filter(|a| {
+                a.is_hostile_to(&actor) && a.is_alive() && !a.noncombatant && a.can_attack(&actor, &self.zones, &self.actors)
+            })
+            .map(|a| a.id)
+            .collect()
+    }
+
+    /// Contested d20 + attack bonus: on a win both actors are locked in place until the target
+    /// escapes or one of them falls
+    fn execute_grapple(&mut self, actor_id: usize, target_id: usize, rng: &mut impl Rng) {
+        let actor = &self.actors[actor_id];
+        let target = &self.actors[target_id];
+        if !target.is_alive() || target.is_grappled(&self.actors) {
+            return;
+        }
+        let roll = self.d20(rng) + actor.attack_bonus;
+        let opposed = self.d20(rng) + target.attack_bonus;
+        let success = roll > opposed;
+        let target_name = target.name.clone();
+        if success {
+            self.actors[actor_id].grappling = Some(target_id);
+            self.actors[target_id].grappled_by = Some(actor_id);
+            self.actors[target_id].move_progress = None;
+        }
+        self.push_event(
+            actor_id,
+            EventType::Grapple { target_id, target_name, roll, opposed, success },
+        );
+    }
+
+    /// Contested d20 + attack bonus against the grappler; ties keep the hold
+    fn execute_escape(&mut self, actor_id: usize, rng: &mut impl Rng) {
+        let actor = &self.actors[actor_id];
+        let Some(grappler_id) = actor.grappled_by.filter(|_| actor.is_grappled(&self.actors)) else {
+            return;
+        };
+        let grappler = &self.actors[grappler_id];
+        let roll = self.d20(rng) + actor.attack_bonus;
+        let opposed = self.d20(rng) + grappler.attack_bonus;
+        let success = roll > opposed;
+        let grappler_name = grappler.name.clone();
+        if success {
+            self.actors[actor_id].grappled_by = None;
+            self.actors[grappler_id].grappling = None;
+        }
+        self.push_event(
+            actor_id,
+            EventType::Escape { grappler_id, grappler_name, roll, opposed, success },
+        );
+    }
+
+    /// Grappling and grappled actors stay where they are
+    fn is_locked(&self, actor_id: usize) -> bool {
+        let actor = &self.actors[actor_id];
+        actor.is_grappled(&self.actors) || actor.is_grappling(&self.actors)
+    }
+
     fn execute_guard(&mut self, actor_id: usize) {
-        let actor = &mut self.actors[actor_id];
-        actor.ac_bonus = 2;
+        self.hold_posture(actor_id, EffectKind::Stance, GUARD_AC_BONUS);
+        self.push_event(actor_id, EventType::Guard { ac_bonus: GUARD_AC_BONUS });
+    }
+
+    fn execute_defend(&mut self, actor_id: usize) {
+        self.hold_posture(actor_id, EffectKind::Stance, DEFEND_AC_BONUS);
+        self.push_event(actor_id, EventType::Defend { ac_bonus: DEFEND_AC_BONUS });
+    }
+
+    /// Take up a posture that lasts until the actor's next turn
+    fn hold_posture(&mut self, actor_id: usize, kind: EffectKind, magnitude: i32) {
+        let posture = Effect::new(kind, magnitude, Expiry::NextTurn, actor_id);
+        self.actors[actor_id].effects.apply(posture, Stacking::Replace);
+    }
+
+    /// Grant temporary HP to a target. Temp HP doesn't stack - the higher value is kept.
+    fn execute_buff(
+        &mut self,
+        actor_id: usize,
+        target_id: usize,
+        amount: &HpValue,
+        duration: Option<u32>,
+        rng: &mut impl Rng,
+    ) {
+        if !self.actors[target_id].is_alive() {
+            return;
+        }
+
+        let temp_hp = self.roll_value(amount, rng);
+        let target = &mut self.actors[target_id];
+        if temp_hp >= target.temp_hp() {
+            let temp_hp = Effect::new(EffectKind::TempHp, temp_hp, Expiry::after(duration, self.round), actor_id);
+            target.effects.apply(temp_hp, Stacking::Replace);
+        }
+        let target_name = target.name.clone();
 
-        self.events.push(CombatEvent {
+        self.record(CombatEvent {
             round: self.round,
             actor_id,
-            actor_name: actor.name.clone(),
-            event_type: EventType::Guard { ac_bonus: 2 },
+            actor_name: self.actors[actor_id].name.clone(),
+            event_type: EventType::Buff {
+                target_id,
+                target_name,
+                temp_hp,
+                duration,
+            },
         });
     }
 
-    fn execute_move(&mut self, actor_id: usize, direction: MoveDirection) {
-        let actor = &self.actors[actor_id];
-        let from_zone = actor.zone;
-        let speed = actor.speed;
-        let actor_side = actor.side;
-        let actor_frontage = actor.frontage;
-
-        let to_zone = match direction {
-            MoveDirection::Toward(target_id) => {
-                let target = &self.actors[target_id];
-                let mut current = from_zone;
-                for _ in 0..speed {
-                    if let Some(next) = current.toward(&target.zone) {
-                        if self.can_enter_zone(next, actor_id, actor_side, actor_frontage) {
-                            current = next;
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
+    /// Call up `amount` (default one) creatures from the actor's `index`th `summons` entry,
+    /// up to what its limit has left. They appear in the summoner's zone and stop coming once
+    /// it's full.
+    fn execute_summon(&mut self, actor_id: usize, index: usize, amount: Option<&HpValue>, rng: &mut impl Rng) {
+        let wanted = amount.map_or(1, |amount| self.roll_value(amount, rng).max(0) as u32);
+        let summoner = &self.actors[actor_id];
+        let template = summoner.summons[index].clone();
+        for _ in 0..wanted.min(summoner.summons_left(index)) {
+            let Some(summoned_id) = self.spawn(&template, actor_id, rng) else {
+                break;
+            };
+            self.actors[actor_id].summoned[index] += 1;
+            let summoned = &self.actors[summoned_id];
+            let (summoned_name, zone) = (summoned.name.clone(), self.zones.name(summoned.zone).to_string());
+            self.push_event(actor_id, EventType::Summon { summoned_id, summoned_name, zone });
+        }
+    }
+
+    /// Add an actor built from `template` to the fight, on `summoner`'s side and in its zone,
+    /// if there's room (the summoner may be one that just died, leaving it behind). It's numbered after the others of its name this combat, and with
+    /// initiative rolled once it acts right after the summoner; otherwise it rolls in next round.
+    fn spawn(&mut self, template: &ActorTemplate, summoner: usize, rng: &mut impl Rng) -> Option<usize> {
+        let (side, zone) = (self.actors[summoner].side, self.actors[summoner].zone);
+        let id = self.actors.len();
+        let mut actor = Actor::from_template(id, template, side, zone);
+        actor.hostile_to = self.actors[summoner].hostile_to;
+        if !self.zone_has_capacity_for(zone, id, actor.space()) {
+            return None;
+        }
+        let number = self
+            .actors
+            .iter()
+            .filter(|a| a.summoner.is_some() && *a.template_name == *actor.template_name)
+            .count()
+            + 1;
+        actor.name = format!("{} {}", template.name, number).into();
+        actor.summoner = Some(summoner);
+        let hp = if self.expected { (template.expected_hp().round() as i32).max(1) } else { template.roll_hp(rng) };
+        let in_reserve = self.zones.is_melee(zone) && self.front_width.is_some_and(|width| self.front_count(side) >= width);
+        actor.reset(hp, zone, in_reserve);
+
+        self.has_reactions |= actor.apl.iter().any(|e| e.ready.is_some());
+        self.has_auras |= !actor.auras.is_empty();
+        self.actors.push(actor);
+        if self.expected {
+            self.damage_carry.push(0.0);
+        }
+        if let Some(kept) = &mut self.kept_initiative {
+            if let Some(i) = kept.iter().position(|&(kept_id, _)| kept_id == summoner) {
+                let roll = kept[i].1;
+                kept.insert(i + 1, (id, roll));
+            }
+        }
+        Some(id)
+    }
+
+    /// Fighters on `side` currently holding a front slot
+    fn front_count(&self, side: Side) -> u32 {
+        self.actors
+            .iter()
+            .filter(|a| a.side == side && a.is_alive() && self.zones.is_melee(a.zone) && !a.in_reserve)
+            .count() as u32
+    }
+
+    /// Top of the round: reserves step up (lowest id first) into slots freed by the dead
+    fn select_front(&mut self) {
+        let Some(width) = self.front_width else {
+            return;
+        };
+        for side in [Side::SIDE1, Side::SIDE2] {
+            let mut open = width.saturating_sub(self.front_count(side));
+            for actor in &mut self.actors {
+                if open == 0 {
+                    break;
                 }
-                current
-            }
-            MoveDirection::ToZone(zone) => {
-                let mut current = from_zone;
-                for _ in 0..speed {
-                    if let Some(next) = current.toward(&zone) {
-                        if self.can_enter_zone(next, actor_id, actor_side, actor_frontage) {
-                            current = next;
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
+                if actor.side == side && actor.is_alive() && actor.in_reserve {
+                    actor.in_reserve = false;
+                    open -= 1;
                 }
-                current
             }
-            MoveDirection::Forward => {
-                let target_zone = match actor_side {
-                    Side::Side1 => Zone::Side2Ranged,
-                    Side::Side2 => Zone::Side1Ranged,
-                };
-                let mut current = from_zone;
-                for _ in 0..speed {
-                    if let Some(next) = current.toward(&target_zone) {
-                        if self.can_enter_zone(next, actor_id, actor_side, actor_frontage) {
-                            current = next;
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
-                }
-                current
+        }
+    }
+
+    /// Actors entering a melee zone take a free front slot or wait in reserve; leaving frees it
+    fn update_front(&mut self, actor_id: usize, from: Zone) {
+        let Some(width) = self.front_width else {
+            return;
+        };
+        let actor = &self.actors[actor_id];
+        if !self.zones.is_melee(actor.zone) {
+            self.actors[actor_id].in_reserve = false;
+        } else if !self.zones.is_melee(from) {
+            let side = actor.side;
+            self.actors[actor_id].in_reserve = true;
+            self.actors[actor_id].in_reserve = self.front_count(side) >= width;
+        }
+    }
+
+    /// Damage every living actor standing in hazardous terrain
+    fn apply_hazards(&mut self, rng: &mut impl Rng) {
+        for actor_id in 0..self.actors.len() {
+            let actor = &self.actors[actor_id];
+            let Some(hazard) = &self.zones.terrain(actor.zone).hazard else {
+                continue;
+            };
+            if !actor.is_alive() {
+                continue;
             }
-            MoveDirection::Backward => {
-                let target_zone = match actor_side {
-                    Side::Side1 => Zone::Side1Ranged,
-                    Side::Side2 => Zone::Side2Ranged,
-                };
-                let mut current = from_zone;
-                for _ in 0..speed {
-                    if let Some(next) = current.toward(&target_zone) {
-                        if self.can_enter_zone(next, actor_id, actor_side, actor_frontage) {
-                            current = next;
-                        } else {
-                            break;
-                        }
-                    } else {
-                        break;
-                    }
+            let damage = if self.expected {
+                carry_damage(&mut self.damage_carry[actor_id], actor, hazard.expected_value(), 1.0)
+            } else {
+                actor.after_threshold(hazard.roll(rng))
+            };
+            let zone = self.zones.name(actor.zone).to_string();
+            let absorbed = self.actors[actor_id].take_damage(damage);
+            self.push_event(actor_id, EventType::Hazard { zone, damage, absorbed });
+            if !self.actors[actor_id].is_alive() {
+                self.die(actor_id, None, rng);
+            }
+        }
+    }
+
+    /// The next zone to step into on the way to `target`, or None if the actor can't get closer.
+    /// Ordinary moves only take shortest-path steps; flanking detours around blocked zones.
+    fn next_step(&self, from: Zone, target: Zone, flanking: bool, actor_id: usize) -> Option<Zone> {
+        let actor = &self.actors[actor_id];
+        let enterable = |zone: Zone| self.can_enter_zone(zone, actor);
+        if !flanking {
+            return self.zones.steps_toward(from, target).find(|&zone| enterable(zone));
+        }
+
+        // Breadth-first over enterable zones, remembering the first step taken to reach each
+        // one. The closest reachable zone to the target wins; ties go to the shorter route.
+        let mut first_steps: Vec<Option<Zone>> = vec![None; self.zones.len()];
+        let mut visited = vec![false; self.zones.len()];
+        visited[from.index()] = true;
+        let mut queue = std::collections::VecDeque::from([from]);
+        let mut best = (self.zones.distance(from, target), None);
+        while let Some(zone) = queue.pop_front() {
+            for next in self.zones.neighbors(zone) {
+                if visited[next.index()] || !enterable(next) {
+                    continue;
+                }
+                visited[next.index()] = true;
+                let first = first_steps[zone.index()].unwrap_or(next);
+                first_steps[next.index()] = Some(first);
+                let remaining = self.zones.distance(next, target);
+                if remaining < best.0 {
+                    best = (remaining, Some(first));
                 }
-                current
+                queue.push_back(next);
             }
+        }
+        best.1
+    }
+
+    /// Move toward `direction` on up to `movement` points, returning the points left once the
+    /// actor arrives (none if it's still partway into a zone). A `careful` move (withdrawing)
+    /// pays double for every zone but gives enemies no opportunity attacks.
+    fn execute_move(&mut self, actor_id: usize, direction: MoveDirection, movement: u32, careful: bool, rng: &mut impl Rng) -> u32 {
+        if self.is_locked(actor_id) || self.actors[actor_id].has_condition(ConditionKind::Prone) {
+            return movement;
+        }
+        let actor = &self.actors[actor_id];
+        let from_zone = actor.zone;
+        let actor_side = actor.side;
+
+        let target_zone = match direction {
+            MoveDirection::Toward(target_id) => self.actors[target_id].zone,
+            MoveDirection::Forward | MoveDirection::Flank => self.zones.far_rear(actor_side),
+            MoveDirection::Backward => self.zones.rear(actor_side),
         };
+        let flanking = matches!(direction, MoveDirection::Flank);
+        let slowed = actor.has_condition(ConditionKind::Slowed);
+
+        let mut budget = movement;
+        let mut carried = actor.move_progress;
+        let mut progress = None;
+        let mut to_zone = from_zone;
+        while let Some(next) = self.next_step(to_zone, target_zone, flanking, actor_id) {
+            // Movement spent last turn only counts if the actor keeps heading into the same zone
+            if let Some((zone, spent)) = carried.take() {
+                if zone == next {
+                    budget += spent;
+                }
+            }
+            // Slowed and careful actors pay double for every zone (quadruple for both)
+            let cost = self.zones.move_cost(next) * if slowed { 2 } else { 1 } * if careful { 2 } else { 1 };
+            if budget < cost {
+                progress = Some((next, budget));
+                budget = 0;
+                break;
+            }
+            budget -= cost;
+            to_zone = next;
+        }
+        self.actors[actor_id].move_progress = progress;
 
         if to_zone != from_zone {
-            let actor_name = self.actors[actor_id].name.clone();
-            self.actors[actor_id].zone = to_zone;
-
-            self.events.push(CombatEvent {
-                round: self.round,
-                actor_id,
-                actor_name,
-                event_type: EventType::Move {
-                    from: from_zone,
-                    to: to_zone,
-                },
+            let event = EventType::Move {
+                from: self.zones.name(from_zone).to_string(),
+                to: self.zones.name(to_zone).to_string(),
+            };
+            self.relocate(actor_id, to_zone, actor_id, event, !careful, rng);
+        }
+        budget
+    }
+
+    /// Head for the side's rear; an actor already there leaves the fight if it's an exit.
+    /// Returns the movement left.
+    fn execute_flee(&mut self, actor_id: usize, movement: u32, rng: &mut impl Rng) -> u32 {
+        let actor = &self.actors[actor_id];
+        let rear = self.zones.rear(actor.side);
+        if actor.zone != rear {
+            return self.execute_move(actor_id, MoveDirection::Backward, movement, false, rng);
+        } else if self.exits && !self.is_locked(actor_id) && !actor.has_condition(ConditionKind::Prone) {
+            let zone = self.zones.name(rear).to_string();
+            let actor = &mut self.actors[actor_id];
+            actor.fled = true;
+            actor.move_progress = None;
+            self.push_event(actor_id, EventType::Flee { zone });
+            return 0;
+        }
+        movement
+    }
+
+    /// Put an actor in a new zone, logging `event` under `logged_by` once it arrives. Unless the
+    /// move doesn't `provoke`, enemies it slips out of reach of get their `enemy_leaves_zone`
+    /// reactions first (and may stop it for good); enemies it comes into reach of react after.
+    fn relocate(&mut self, actor_id: usize, to_zone: Zone, logged_by: usize, event: EventType, provoke: bool, rng: &mut impl Rng) {
+        let from_zone = self.actors[actor_id].zone;
+        let (before, after) = if self.has_reactions {
+            (self.threatening(actor_id, from_zone), self.threatening(actor_id, to_zone))
+        } else {
+            (Vec::new(), Vec::new())
+        };
+        let leaving: Vec<usize> = if provoke && traits::provokes(&self.actors[actor_id]) {
+            before.iter().copied().filter(|id| !after.contains(id)).collect()
+        } else {
+            Vec::new()
+        };
+        self.react(Trigger::EnemyLeavesZone, actor_id, &leaving, rng);
+        if !self.actors[actor_id].is_alive() {
+            return;
+        }
+
+        self.actors[actor_id].zone = to_zone;
+        self.update_front(actor_id, from_zone);
+        self.push_event(logged_by, event);
+        self.check_breakthrough(actor_id);
+
+        let entering: Vec<usize> = after.into_iter().filter(|id| !before.contains(id)).collect();
+        self.react(Trigger::EnemyEntersZone, actor_id, &entering, rng);
+    }
+
+    /// Push or pull a target up to N zones, one step at a time, stopping early if the next zone
+    /// is full or held by the target's enemies
+    fn apply_forced_move(&mut self, attacker_id: usize, target_id: usize, forced: ForcedMove, rng: &mut impl Rng) {
+        let (zones, away) = match forced {
+            ForcedMove::Push(n) => (n, true),
+            ForcedMove::Pull(n) => (n, false),
+        };
+        if self.is_locked(target_id) {
+            return;
+        }
+        let origin = self.actors[attacker_id].zone;
+        let from_zone = self.actors[target_id].zone;
+        let target = &self.actors[target_id];
+        let mut to_zone = from_zone;
+        for _ in 0..zones {
+            let distance = self.zones.distance(origin, to_zone);
+            let next = self.zones.neighbors(to_zone).find(|&zone| {
+                let d = self.zones.distance(origin, zone);
+                let closer_or_further = if away { d > distance } else { d < distance };
+                closer_or_further && self.can_enter_zone(zone, target)
             });
+            match next {
+                Some(zone) => to_zone = zone,
+                None => break,
+            }
+        }
+        if to_zone == from_zone {
+            return;
+        }
+
+        let event = EventType::ForcedMove {
+            target_id,
+            target_name: target.name.clone(),
+            from: self.zones.name(from_zone).to_string(),
+            to: self.zones.name(to_zone).to_string(),
+        };
+        self.actors[target_id].move_progress = None;
+        self.relocate(target_id, to_zone, attacker_id, event, true, rng);
+    }
+
+    /// Inflict a condition unless the target makes its save. A fresh dose replaces any
+    /// condition of the same kind.
+    fn apply_condition(&mut self, attacker_id: usize, target_id: usize, rider: &ConditionRider, rng: &mut impl Rng) {
+        let target = &self.actors[target_id];
+        let save = rider
+            .save
+            .map(|ability| self.d20(rng) + target.saves.bonus(ability));
+        let applied = save.is_none_or(|total| total < rider.dc);
+        let event = EventType::Condition {
+            target_id,
+            target_name: target.name.clone(),
+            condition: rider.effect,
+            save,
+            dc: rider.dc,
+            applied,
+            duration: rider.duration,
+        };
+        self.push_event(attacker_id, event);
+        if !applied {
+            return;
+        }
+
+        let condition = Effect {
+            damage: rider.damage.clone(),
+            tick: rider.tick.unwrap_or_default(),
+            ..Effect::new(EffectKind::Condition(rider.effect), 0, Expiry::after(rider.duration, self.round), attacker_id)
+        };
+        self.actors[target_id].effects.apply(condition, rider.stacking);
+    }
+
+    /// Deal the damage `actor_id`'s effects do at `tick` (bleeding), crediting deaths to
+    /// whoever applied the effect
+    fn tick_effects(&mut self, actor_id: usize, tick: Tick, rng: &mut impl Rng) {
+        for (kind, damage, source_id) in self.actors[actor_id].effects.ticks(tick) {
+            let actor = &self.actors[actor_id];
+            if !actor.is_alive() {
+                break;
+            }
+            let EffectKind::Condition(condition) = kind else {
+                continue;
+            };
+            let damage = if self.expected {
+                carry_damage(&mut self.damage_carry[actor_id], actor, damage.expected_value(), 1.0)
+            } else {
+                actor.after_threshold(damage.roll(rng))
+            };
+            let absorbed = self.actors[actor_id].take_damage(damage);
+            self.push_event(actor_id, EventType::ConditionDamage { condition, damage, absorbed });
+            if !self.actors[actor_id].is_alive() {
+                self.die(actor_id, Some(source_id), rng);
+            }
+        }
+    }
+
+    /// Drop effects whose duration has run out at the start of a round
+    fn expire_effects(&mut self) {
+        for actor_id in 0..self.actors.len() {
+            let ended = self.actors[actor_id].effects.expire_round(self.round);
+            self.effects_ended(actor_id, ended);
+        }
+    }
+
+    /// Log the end of a living actor's conditions and leftover temp HP
+    fn effects_ended(&mut self, actor_id: usize, ended: Vec<Effect>) {
+        if !self.actors[actor_id].is_alive() {
+            return;
+        }
+        for effect in ended {
+            match effect.kind {
+                EffectKind::Condition(condition) => self.push_event(actor_id, EventType::ConditionEnded { condition }),
+                EffectKind::TempHp if effect.magnitude > 0 => {
+                    self.push_event(actor_id, EventType::TempHpExpired { temp_hp: effect.magnitude })
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// A side wins on the spot when one of its actors reaches its breakthrough zone
+    fn check_breakthrough(&mut self, actor_id: usize) {
+        if self.objective_met.is_some() {
+            return;
+        }
+        let actor = &self.actors[actor_id];
+        let reached = self.objectives.iter().position(|objective| match objective {
+            Objective::Breakthrough { side, zone } if *side == actor.side => {
+                let target = match zone {
+                    Some(name) => self.zones.find(name),
+                    None => Some(self.zones.far_rear(*side)),
+                };
+                target == Some(actor.zone)
+            }
+            _ => false,
+        });
+        if let Some(index) = reached {
+            self.objective_met = Some(index);
+            let objective = self.objectives[index].to_string();
+            self.push_event(actor_id, EventType::Objective { objective });
+        }
+    }
+
+    /// A holding side wins at the end of its last round if anyone is left standing. The win
+    /// is logged under its first survivor.
+    fn check_hold_objectives(&mut self) {
+        let held = self.objectives.iter().enumerate().find_map(|(index, objective)| {
+            let Objective::Hold { side, rounds } = objective else {
+                return None;
+            };
+            let survivor = self.side_actors(*side).find(|a| a.is_alive())?;
+            (self.round >= *rounds).then_some((index, survivor.id))
+        });
+        if let Some((index, survivor_id)) = held {
+            self.objective_met = Some(index);
+            let objective = self.objectives[index].to_string();
+            self.push_event(survivor_id, EventType::Objective { objective });
         }
     }
 
     fn is_combat_over(&self) -> bool {
-        let side1_alive = self
-            .actors
-            .iter()
-            .any(|a| a.side == Side::Side1 && a.is_alive());
-        let side2_alive = self
-            .actors
-            .iter()
-            .any(|a| a.side == Side::Side2 && a.is_alive());
+        if self.objective_met.is_some() {
+            return true;
+        }
+        !self.contested(self.standing_sides())
+    }
 
-        !side1_alive || !side2_alive
+    /// Someone on `side` is still fighting and none of its VIPs has died
+    fn side_standing(&self, side: Side) -> bool {
+        let vip_down = self.side_actors(side).any(|a| a.vip && a.current_hp <= 0);
+        !vip_down && self.side_actors(side).any(|a| a.is_alive() && !a.noncombatant)
     }
 
-    fn get_winner(&self) -> Option<Side> {
-        let side1_alive = self
-            .actors
-            .iter()
-            .any(|a| a.side == Side::Side1 && a.is_alive());
-        let side2_alive = self
-            .actors
-            .iter()
-            .any(|a| a.side == Side::Side2 && a.is_alive());
+    fn standing_sides(&self) -> SideSet {
+        let mut standing = SideSet::default();
+        for side in self.sides().filter(|&side| self.side_standing(side)) {
+            standing.insert(side);
+        }
+        standing
+    }
+
+    /// Some side in `standing` still fights another one in it
+    fn contested(&self, standing: SideSet) -> bool {
+        self.sides()
+            .filter(|&side| standing.contains(side))
+            .any(|side| self.hostility[side.index()].intersects(standing))
+    }
+
+    /// The last side standing wins. Sides at peace with each other can be left standing
+    /// together, and then the win goes to the first of them.
+    fn get_winner(&self) -> Outcome {
+        if let Some(index) = self.objective_met {
+            return Outcome::Winner(self.objectives[index].side());
+        }
+        let standing = self.standing_sides();
+        match self.sides().find(|&side| standing.contains(side)) {
+            None => Outcome::MutualDestruction,
+            Some(_) if self.contested(standing) => Outcome::Undecided,
+            Some(side) => Outcome::Winner(side),
+        }
+    }
+
+    /// Why a combat that ran out of rounds stalled. Fighters (living combatants) that can reach an
+    /// enemy make it attrition, or passive if none of them attacked in the last round, unless
+    /// none of them could hit. With nobody in reach, a fighter whose every step toward the
+    /// nearest enemy is into a full zone makes it a deadlock.
+    fn stalemate(&self) -> Stalemate {
+        let fighters = || self.actors.iter().filter(|a| a.is_alive() && !a.noncombatant);
+        let mut in_reach = false;
+        let mut can_hit = false;
+        for attacker in fighters() {
+            for target in fighters().filter(|t| attacker.is_hostile_to(t) && attacker.can_attack(t, &self.zones, &self.actors)) {
+                in_reach = true;
+                let modifiers = self.attack_modifiers(attacker, target, None);
+                let (advantage, disadvantage) = modifiers.edge();
+                can_hit |= d20_chance(modifiers.target_ac() - modifiers.roll_bonus(), advantage, disadvantage) > 0.0;
+            }
+        }
+        if in_reach {
+            return if !can_hit {
+                Stalemate::NoHitsPossible
+            } else if self.last_attack_round < self.round {
+                Stalemate::Passive
+            } else {
+                Stalemate::Attrition
+            };
+        }
+
+        let blocked = fighters().filter(|a| !a.in_reserve).any(|actor| {
+            let nearest = fighters().filter(|e| actor.is_hostile_to(e)).map(|e| e.zone);
+            let Some(nearest) = nearest.min_by_key(|&zone| self.zones.distance(actor.zone, zone)) else {
+                return false;
+            };
+            let mut steps = self.zones.steps_toward(actor.zone, nearest).peekable();
+            steps.peek().is_some() && steps.all(|zone| !self.zone_has_capacity_for(zone, actor.id, actor.space()))
+        });
+        if blocked {
+            Stalemate::ZoneDeadlock
+        } else {
+            Stalemate::OutOfReach
+        }
+    }
 
-        match (side1_alive, side2_alive) {
-            (true, false) => Some(Side::Side1),
-            (false, true) => Some(Side::Side2),
+    /// Decide a combat that ran out of rounds according to the encounter's draw policy: the
+    /// defender, or the side with the highest score if no other side ties it
+    fn resolve_draw(&self, scores: Option<&[i64]>) -> Option<Side> {
+        match (self.draw_policy, scores) {
+            (DrawPolicy::DefenderWins, _) => Some(self.defender),
+            (_, Some(scores)) => {
+                let best = scores.iter().max()?;
+                let mut leaders = self.sides().filter(|side| scores[side.index()] == *best);
+                match (leaders.next(), leaders.next()) {
+                    (Some(side), None) => Some(side),
+                    _ => None,
+                }
+            }
             _ => None,
         }
     }
+
+    /// Every side's score under a scored draw policy, by side index
+    fn scores(&self) -> Option<Vec<i64>> {
+        if !self.draw_policy.is_scored() {
+            return None;
+        }
+        let side_score = |side: Side| -> i64 {
+            let living = self.side_actors(side).filter(|a| a.is_alive());
+            match self.draw_policy {
+                DrawPolicy::MostHp => living.map(|a| a.current_hp as i64).sum(),
+                DrawPolicy::MostHpPercent => {
+                    let left: i64 = living.map(|a| a.current_hp as i64).sum();
+                    let total: i64 = self.side_actors(side).map(|a| (a.max_hp + a.drained) as i64).sum();
+                    if total > 0 {
+                        (left as f64 / total as f64 * 100.0).round() as i64
+                    } else {
+                        0
+                    }
+                }
+                DrawPolicy::MostKills => self
+                    .actors
+                    .iter()
+                    .filter(|a| self.hostility[side.index()].contains(a.side) && a.current_hp <= 0)
+                    .count() as i64,
+                DrawPolicy::MostZones => {
+                    let mut held: Vec<Zone> = living.map(|a| a.zone).collect();
+                    held.sort_by_key(|zone| zone.index());
+                    held.dedup();
+                    held.len() as i64
+                }
+                DrawPolicy::Draw | DrawPolicy::DefenderWins => 0,
+            }
+        };
+        Some(self.sides().map(side_score).collect())
+    }
+}
+
+/// Expected-value mode: `chance` of `per_hit` damage to `target` (nothing if `per_hit` is
+/// below its damage threshold), paid out in whole points as its `carry` adds up
+fn carry_damage(carry: &mut f64, target: &Actor, per_hit: f64, chance: f64) -> i32 {
+    if per_hit < target.damage_threshold as f64 {
+        return 0;
+    }
+    *carry += per_hit * chance;
+    let whole = carry.floor();
+    *carry -= whole;
+    whole as i32
+}
+
+/// Chance that a d20 (rolled twice, keeping the higher with `advantage` or the lower with
+/// `disadvantage`) shows at least `needed`
+pub(crate) fn d20_chance(needed: i32, advantage: bool, disadvantage: bool) -> f64 {
+    let p = ((21 - needed) as f64 / 20.0).clamp(0.0, 1.0);
+    if advantage {
+        1.0 - (1.0 - p) * (1.0 - p)
+    } else if disadvantage {
+        p * p
+    } else {
+        p
+    }
 }
@@ -0,0 +1,262 @@
+//! Server settings, read once at startup: the built-in defaults, then `config.toml` (or the file
+//! `CONFIG_PATH` names), then environment variables, each overriding the one before.
+
+use std::env;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, SocketAddr};
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use axum::http::HeaderValue;
+use serde::Deserialize;
+use tracing::Level;
+
+use tunnel_fight::apl;
+
+use crate::auth::Access;
+use crate::cache;
+use crate::limits::Limits;
+
+/// Settings file read when `CONFIG_PATH` isn't set; the server runs fine without one
+const DEFAULT_PATH: &str = "config.toml";
+
+const DEFAULT_PORT: u16 = 3000;
+
+/// Monster library the server and CLI use when `DATABASE_PATH` isn't set
+pub const DEFAULT_DATABASE_PATH: &str = "tunnel-fight.db";
+
+/// Requests a client may make at once before it's held to `RATE_LIMIT_PER_MINUTE`
+const DEFAULT_RATE_LIMIT_BURST: u32 = 10;
+
+pub struct Config {
+    /// Where the server listens (`BIND_ADDRESS` and `PORT`)
+    pub addr: SocketAddr,
+    /// SQLite file holding the monster library and saved encounters (`DATABASE_PATH`)
+    pub database_path: String,
+    /// Most verbose level logged (`LOG_LEVEL`)
+    pub log_level: Level,
+    pub limits: Limits,
+    /// Recent `/simulate` results kept; 0 turns the cache off (`RESULT_CACHE_SIZE`)
+    pub result_cache_size: usize,
+    /// Origins browsers may call the API from, or None for any (`CORS_ORIGINS`)
+    pub cors_origins: Option<Vec<HeaderValue>>,
+    /// Who may call the API (`API_KEYS`) and how often (`RATE_LIMIT_PER_MINUTE`, `RATE_LIMIT_BURST`)
+    pub access: Access,
+}
+
+/// `config.toml`. Every setting is optional and named like its environment variable, in
+/// lower case.
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    bind_address: Option<IpAddr>,
+    port: Option<u16>,
+    database_path: Option<String>,
+    log_level: Option<String>,
+    max_iterations: Option<u32>,
+    max_rounds: Option<u32>,
+    max_actors: Option<usize>,
+    max_body_bytes: Option<usize>,
+    simulation_time_limit_secs: Option<u64>,
+    request_timeout_secs: Option<u64>,
+    max_concurrent_simulations: Option<usize>,
+    max_interactive_sessions: Option<usize>,
+    simulation_threads: Option<usize>,
+    max_sample_count: Option<usize>,
+    result_cache_size: Option<usize>,
+    cors_origins: Option<Vec<String>>,
+    api_keys: Option<Vec<String>>,
+    rate_limit_per_minute: Option<u32>,
+    rate_limit_burst: Option<u32>,
+}
+
+impl Config {
+    /// Everything the server needs to start. A file named by `CONFIG_PATH` must exist; the
+    /// default one is only read if it's there.
+    pub fn load() -> Result<Self, String> {
+        let file = match env::var("CONFIG_PATH") {
+            Ok(path) => read(&path)?,
+            Err(_) if Path::new(DEFAULT_PATH).exists() => read(DEFAULT_PATH)?,
+            Err(_) => ConfigFile::default(),
+        };
+        resolve(file, &|name| env::var(name).ok())
+    }
+}
+
+/// Layer the environment, as `env` looks it up, over the file and the defaults
+fn resolve(file: ConfigFile, env: &dyn Fn(&str) -> Option<String>) -> Result<Config, String> {
+    let defaults = Limits::default();
+    let limits = Limits {
+        // Encounters are validated against the library's caps, so the server can lower them
+        // but not raise them
+        max_iterations: at_most(
+            "MAX_ITERATIONS",
+            setting(env, "MAX_ITERATIONS", file.max_iterations, defaults.max_iterations)?,
+            apl::MAX_ITERATIONS,
+        )?,
+        max_rounds: at_most(
+            "MAX_ROUNDS",
+            setting(env, "MAX_ROUNDS", file.max_rounds, defaults.max_rounds)?,
+            apl::MAX_ROUNDS,
+        )?,
+        max_actors: setting(env, "MAX_ACTORS", file.max_actors, defaults.max_actors)?,
+        max_body_bytes: setting(env, "MAX_BODY_BYTES", file.max_body_bytes, defaults.max_body_bytes)?,
+        time_budget: Duration::from_secs(setting(
+            env,
+            "SIMULATION_TIME_LIMIT_SECS",
+            file.simulation_time_limit_secs,
+            defaults.time_budget.as_secs(),
+        )?),
+        request_timeout: Duration::from_secs(setting(
+            env,
+            "REQUEST_TIMEOUT_SECS",
+            file.request_timeout_secs,
+            defaults.request_timeout.as_secs(),
+        )?),
+        max_concurrent_simulations: setting(
+            env,
+            "MAX_CONCURRENT_SIMULATIONS",
+            file.max_concurrent_simulations,
+            defaults.max_concurrent_simulations,
+        )?
+        .max(1),
+        max_interactive_sessions: setting(
+            env,
+            "MAX_INTERACTIVE_SESSIONS",
+            file.max_interactive_sessions,
+            defaults.max_interactive_sessions,
+        )?,
+        threads: setting(env, "SIMULATION_THREADS", file.simulation_threads, defaults.threads)?.max(1),
+        max_sample_count: setting(env, "MAX_SAMPLE_COUNT", file.max_sample_count, defaults.max_sample_count)?,
+    };
+
+    let origins = list(env, "CORS_ORIGINS", file.cors_origins).unwrap_or_else(|| vec!["*".to_string()]);
+    let keys = list(env, "API_KEYS", file.api_keys).unwrap_or_default();
+    if keys.iter().any(|key| key.is_empty() || key.contains(|c: char| !c.is_ascii_graphic())) {
+        return Err("Invalid API_KEYS: keys must be printable ASCII, without spaces".to_string());
+    }
+    let access = Access::new(
+        keys,
+        setting(env, "RATE_LIMIT_PER_MINUTE", file.rate_limit_per_minute, 0)?,
+        setting(env, "RATE_LIMIT_BURST", file.rate_limit_burst, DEFAULT_RATE_LIMIT_BURST)?,
+    );
+
+    let log_level = setting(env, "LOG_LEVEL", file.log_level, "info".to_string())?;
+    Ok(Config {
+        addr: SocketAddr::new(
+            setting(env, "BIND_ADDRESS", file.bind_address, IpAddr::V4(Ipv4Addr::UNSPECIFIED))?,
+            setting(env, "PORT", file.port, DEFAULT_PORT)?,
+        ),
+        database_path: setting(env, "DATABASE_PATH", file.database_path, DEFAULT_DATABASE_PATH.to_string())?,
+        log_level: log_level.trim().parse().map_err(|_| {
+            format!("Invalid LOG_LEVEL: '{}' (expected error, warn, info, debug, or trace)", log_level)
+        })?,
+        limits,
+        result_cache_size: setting(env, "RESULT_CACHE_SIZE", file.result_cache_size, cache::DEFAULT_CAPACITY)?,
+        cors_origins: cors_origins(&origins)?,
+        access,
+    })
+}
+
+fn read(path: &str) -> Result<ConfigFile, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("Can't read {}: {}", path, e))?;
+    toml::from_str(&text).map_err(|e| format!("Invalid {}: {}", path, e))
+}
+
+/// The environment variable `name` if it's set, else the file's value, else the default
+fn setting<T: FromStr>(env: &dyn Fn(&str) -> Option<String>, name: &str, file: Option<T>, default: T) -> Result<T, String> {
+    match env(name) {
+        Some(value) => value.trim().parse().map_err(|_| format!("Invalid {}: '{}'", name, value)),
+        None => Ok(file.unwrap_or(default)),
+    }
+}
+
+/// A comma-separated environment variable if it's set, else the file's list
+fn list(env: &dyn Fn(&str) -> Option<String>, name: &str, file: Option<Vec<String>>) -> Option<Vec<String>> {
+    match env(name) {
+        Some(value) => Some(value.split(',').map(str::trim).filter(|v| !v.is_empty()).map(str::to_string).collect()),
+        None => file,
+    }
+}
+
+fn at_most(name: &str, value: u32, cap: u32) -> Result<u32, String> {
+    if value > cap {
+        return Err(format!("Invalid {}: {} is over the most allowed, {}", name, value, cap));
+    }
+    Ok(value)
+}
+
+/// The allowed origins as header values; a `*` among them allows any origin
+fn cors_origins(origins: &[String]) -> Result<Option<Vec<HeaderValue>>, String> {
+    if origins.iter().any(|origin| origin == "*") {
+        return Ok(None);
+    }
+    origins
+        .iter()
+        .map(|origin| HeaderValue::from_str(origin).map_err(|_| format!("Invalid CORS origin: '{}'", origin)))
+        .collect::<Result<_, _>>()
+        .map(Some)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn resolve_with(file: &str, env: &[(&str, &str)]) -> Result<Config, String> {
+        let file: ConfigFile = toml::from_str(file).map_err(|e| e.to_string())?;
+        let env: HashMap<String, String> = env.iter().map(|(k, v)| (k.to_string(), v.to_string())).collect();
+        resolve(file, &|name| env.get(name).cloned())
+    }
+
+    fn rejection(file: &str, env: &[(&str, &str)]) -> String {
+        resolve_with(file, env).err().expect("the config should be rejected")
+    }
+
+    #[test]
+    fn defaults_then_file_then_environment() {
+        let config = resolve_with("", &[]).unwrap();
+        assert_eq!(config.addr.port(), DEFAULT_PORT);
+        assert_eq!(config.limits.max_actors, Limits::default().max_actors);
+        assert_eq!(config.database_path, DEFAULT_DATABASE_PATH);
+        assert_eq!(config.log_level, Level::INFO);
+
+        let file = "port = 4000\nmax_actors = 50\ndatabase_path = \"file.db\"\nlog_level = \"debug\"";
+        let config = resolve_with(file, &[]).unwrap();
+        assert_eq!(config.addr.port(), 4000);
+        assert_eq!(config.limits.max_actors, 50);
+        assert_eq!(config.database_path, "file.db");
+        assert_eq!(config.log_level, Level::DEBUG);
+
+        let config = resolve_with(file, &[("PORT", "5000"), ("DATABASE_PATH", "env.db")]).unwrap();
+        assert_eq!(config.addr.port(), 5000);
+        assert_eq!(config.database_path, "env.db");
+        // Settings the environment doesn't name keep the file's value
+        assert_eq!(config.limits.max_actors, 50);
+    }
+
+    #[test]
+    fn environment_lists_replace_the_files() {
+        let file = r#"cors_origins = ["https://a.example", "https://b.example"]"#;
+        let config = resolve_with(file, &[]).unwrap();
+        assert_eq!(config.cors_origins.unwrap().len(), 2);
+
+        let config = resolve_with(file, &[("CORS_ORIGINS", "https://c.example")]).unwrap();
+        assert_eq!(config.cors_origins.unwrap(), vec![HeaderValue::from_static("https://c.example")]);
+        assert!(resolve_with(file, &[("CORS_ORIGINS", "*")]).unwrap().cors_origins.is_none());
+    }
+
+    #[test]
+    fn bad_settings_are_errors() {
+        assert!(rejection("", &[("PORT", "many")]).contains("PORT"));
+        assert!(rejection("log_level = \"loud\"", &[]).contains("LOG_LEVEL"));
+        assert!(rejection("prot = 4000", &[]).contains("prot"));
+        assert!(rejection("", &[("API_KEYS", "a key")]).contains("API_KEYS"));
+        // The library's own caps are the most a server may allow
+        let over = format!("max_iterations = {}", apl::MAX_ITERATIONS + 1);
+        assert!(rejection(&over, &[]).contains("MAX_ITERATIONS"));
+        let over = (apl::MAX_ROUNDS + 1).to_string();
+        assert!(rejection("", &[("MAX_ROUNDS", &over)]).contains("MAX_ROUNDS"));
+    }
+}
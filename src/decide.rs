@@ -0,0 +1,189 @@
+//! APL unit tests: pose a combat in a given state, ask one actor what it would do from there,
+//! and check the answer against what the test expects, without fighting anything out.
+
+use std::collections::BTreeMap;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::apl::{validate_apl, ChosenActions, EntryTrace, ErrorCode, ValidationError};
+use crate::combat::CombatSimulator;
+use crate::types::{AplEntry, ConditionKind, Encounter, Phase};
+
+/// Most cases one request may check
+pub const MAX_CASES: usize = 1000;
+
+/// One row of an APL test table: a frozen state, the actor asked, and what it should pick
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct DecisionCase {
+    /// Label in the results
+    pub name: Option<String>,
+    /// Who decides, named as combat logs name it (e.g. `Goblin 2`)
+    pub actor: String,
+    /// Round being played (default 1)
+    #[serde(default = "first_round")]
+    pub round: u32,
+    /// Initiative phase being acted in, for APLs with phase-tagged entries
+    pub phase: Option<Phase>,
+    /// Actors that aren't as the encounter starts them, by name
+    #[serde(default)]
+    pub state: BTreeMap<String, ActorSnapshot>,
+    /// Decide from this APL instead of the actor's own
+    pub apl: Option<Vec<AplEntry>>,
+    /// What it should pick; parts left out aren't checked
+    #[serde(default)]
+    pub expect: Expectation,
+}
+
+fn first_round() -> u32 {
+    1
+}
+
+/// How one actor stands in a posed combat. Anything left out is as the encounter starts it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct ActorSnapshot {
+    /// Current HP; 0 or less is dead
+    pub hp: Option<i32>,
+    /// Current HP as a percent of its max, instead of `hp`
+    pub hp_percent: Option<f64>,
+    pub zone: Option<String>,
+    /// Conditions it's under
+    #[serde(default)]
+    pub conditions: Vec<ConditionKind>,
+}
+
+/// The picks a case expects, written the way results report them: `none`, an action or
+/// direction (`attack`, `dodge`, `backward`, `flee`), or one with its target (`attack Orc 2`,
+/// `toward Goblin 1 (id 3)`). A pick matches when it reads the same or only adds detail, so
+/// `attack Orc` matches `attack Orc 2 (id 3)`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Expectation {
+    #[serde(rename = "move")]
+    pub move_action: Option<String>,
+    pub action: Option<String>,
+    pub bonus_action: Option<String>,
+    pub free_interaction: Option<String>,
+}
+
+/// What one case's actor picked, and whether that's what the case expected
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DecisionResult {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    pub actor: String,
+    pub chosen: ChosenActions,
+    pub passed: bool,
+    /// The expectations that didn't hold, e.g. `move: expected backward, got toward Orc (id 3)`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub failures: Vec<String>,
+    /// How each entry of the APL fared, as `POST /debug` reports them
+    pub entries: Vec<EntryTrace>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct DecisionReport {
+    pub passed: usize,
+    pub failed: usize,
+    pub results: Vec<DecisionResult>,
+}
+
+/// Check every case against the encounter. Each starts from a fresh combat with HP rolled from
+/// `seed`, posed as its `state` says; nothing is simulated past the decision.
+pub fn run_cases(encounter: &Encounter, cases: &[DecisionCase], seed: u64) -> Result<DecisionReport, Vec<ValidationError>> {
+    let errors = validate_cases(encounter, cases);
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let results: Vec<DecisionResult> = cases.iter().map(|case| run_case(encounter, case, seed)).collect();
+    let passed = results.iter().filter(|r| r.passed).count();
+    Ok(DecisionReport { passed, failed: results.len() - passed, results })
+}
+
+fn run_case(encounter: &Encounter, case: &DecisionCase, seed: u64) -> DecisionResult {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    let mut sim = CombatSimulator::new(encounter, encounter.max_rounds, &mut rng).with_trace();
+    for (name, snapshot) in &case.state {
+        let actor_id = sim.actor_id(name).expect("validated");
+        sim.pose(actor_id, snapshot);
+    }
+    let actor_id = sim.actor_id(&case.actor).expect("validated");
+    let chosen = sim.decide(actor_id, case.apl.as_deref(), case.round, case.phase, &mut rng);
+    let entries = sim.take_trace().pop().map(|choice| choice.entries).unwrap_or_default();
+
+    let expect = &case.expect;
+    let failures: Vec<String> = [
+        ("move", &expect.move_action, &chosen.move_action),
+        ("action", &expect.action, &chosen.action),
+        ("bonus_action", &expect.bonus_action, &chosen.bonus_action),
+        ("free_interaction", &expect.free_interaction, &chosen.free_interaction),
+    ]
+    .into_iter()
+    .filter_map(|(part, expected, got)| {
+        let expected = expected.as_deref()?;
+        (!picks(expected, got)).then(|| format!("{}: expected {}, got {}", part, expected, got))
+    })
+    .collect();
+    DecisionResult {
+        name: case.name.clone(),
+        actor: case.actor.clone(),
+        chosen,
+        passed: failures.is_empty(),
+        failures,
+        entries,
+    }
+}
+
+/// Whether a pick described as `got` (e.g. `attack Orc 2 (id 3)`) is the one `expected` asks
+/// for: the same words, or the same words followed by more detail
+fn picks(expected: &str, got: &str) -> bool {
+    let expected = expected.trim().to_lowercase();
+    let got = got.to_lowercase();
+    got == expected || got.strip_prefix(&expected).is_some_and(|rest| rest.starts_with(' '))
+}
+
+fn validate_cases(encounter: &Encounter, cases: &[DecisionCase]) -> Vec<ValidationError> {
+    if cases.is_empty() {
+        return vec![ValidationError::new("cases", ErrorCode::InvalidRequest, "no cases to check".to_string())];
+    }
+    if cases.len() > MAX_CASES {
+        let message = format!("{} cases exceeds the limit of {}", cases.len(), MAX_CASES);
+        return vec![ValidationError::new("cases", ErrorCode::InvalidRequest, message)];
+    }
+    let sim = CombatSimulator::prepare(encounter, encounter.max_rounds);
+    let zones = encounter.zone_layout();
+    let mut errors = Vec::new();
+    for (i, case) in cases.iter().enumerate() {
+        let path = format!("cases[{}]", i);
+        if sim.actor_id(&case.actor).is_none() {
+            let message = format!("no actor named '{}' in the encounter", case.actor);
+            errors.push(ValidationError::new(format!("{}.actor", path), ErrorCode::InvalidRequest, message));
+        }
+        for (name, snapshot) in &case.state {
+            let path = format!("{}.state.{}", path, name);
+            if sim.actor_id(name).is_none() {
+                let message = format!("no actor named '{}' in the encounter", name);
+                errors.push(ValidationError::new(&path, ErrorCode::InvalidRequest, message));
+            }
+            if snapshot.hp.is_some() && snapshot.hp_percent.is_some() {
+                let message = "give `hp` or `hp_percent`, not both".to_string();
+                errors.push(ValidationError::new(format!("{}.hp_percent", path), ErrorCode::UnexpectedField, message));
+            }
+            if let Some(zone) = snapshot.zone.as_deref().filter(|zone| zones.find(zone).is_none()) {
+                let message = format!("unknown zone '{}'", zone);
+                errors.push(ValidationError::new(format!("{}.zone", path), ErrorCode::UnknownZone, message));
+            }
+        }
+        if case.round == 0 {
+            errors.push(ValidationError::new(format!("{}.round", path), ErrorCode::OutOfRange, "rounds start at 1".to_string()));
+        }
+        if let Some(apl) = &case.apl {
+            errors.extend(validate_apl(apl).into_iter().map(|e| ValidationError {
+                path: format!("{}.apl{}", path, e.path),
+                ..e
+            }));
+        }
+    }
+    errors
+}
@@ -0,0 +1,539 @@
+//! Dice expressions: sums of dice groups and constants like `2d6+1d4+3`, where a group can keep
+//! or drop its highest or lowest dice (`4d6kh3`, `4d6dl1`), explode on its highest face
+//! (`1d6!`), or roll with advantage or disadvantage (`1d20adv`, `1d20dis`). `DiceRules` layers
+//! table house rules on top of whatever the expression says.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+/// Most dice one group may roll
+pub const MAX_DICE: u32 = 1000;
+/// Most faces a die may have
+pub const MAX_SIDES: u32 = 1000;
+/// Most dice a group that keeps or drops some may roll
+pub const MAX_KEEP_DICE: u32 = 100;
+/// Largest constant a damage expression may add or subtract
+pub const MAX_DAMAGE_CONSTANT: i32 = 10_000;
+/// Most extra dice one exploding die may add
+const MAX_EXPLOSIONS: u32 = 100;
+/// Widest range of totals `DamageDice::distribution` will tabulate
+const MAX_DISTRIBUTION_WIDTH: i64 = 2_000;
+
+/// House rules applied to every roll of an expression
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct DiceRules {
+    /// Every die explodes on its highest face, as if written with `!`; groups that keep or
+    /// drop dice and one-sided dice don't
+    pub explode: bool,
+    /// A die whose first roll is a 1 is rolled again once, and the second roll stands (the
+    /// extra dice of an explosion aren't rerolled)
+    pub reroll_ones: bool,
+    /// Totals floor at 1 instead of 0
+    pub min_one: bool,
+}
+
+impl DiceRules {
+    /// Lowest total a roll can come to
+    fn floor(self) -> i64 {
+        if self.min_one {
+            1
+        } else {
+            0
+        }
+    }
+}
+
+/// A parsed dice expression: signed terms added left to right, floored at 0 when rolled
+#[derive(Debug, Clone, PartialEq)]
+pub struct DamageDice {
+    terms: Vec<Term>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Term {
+    negative: bool,
+    value: TermValue,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum TermValue {
+    Constant(i32),
+    Dice(DiceGroup),
+}
+
+/// `NdM` with its modifiers
+#[derive(Debug, Clone, PartialEq)]
+struct DiceGroup {
+    count: u32,
+    sides: u32,
+    keep: Option<Keep>,
+    explode: bool,
+}
+
+/// Which dice of a group count toward its total
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Keep {
+    Highest(u32),
+    Lowest(u32),
+    DropHighest(u32),
+    DropLowest(u32),
+    /// `1dMadv`: roll twice, keep the higher
+    Advantage,
+    /// `1dMdis`: roll twice, keep the lower
+    Disadvantage,
+}
+
+impl DamageDice {
+    /// `NdM+K`
+    pub fn new(count: u32, sides: u32, modifier: i32) -> Self {
+        let mut terms = vec![Term {
+            negative: false,
+            value: TermValue::Dice(DiceGroup {
+                count,
+                sides,
+                keep: None,
+                explode: false,
+            }),
+        }];
+        if modifier != 0 {
+            terms.push(Term {
+                negative: modifier < 0,
+                value: TermValue::Constant(modifier.saturating_abs()),
+            });
+        }
+        DamageDice { terms }
+    }
+
+    /// Largest constant term, ignoring its sign (0 without one)
+    pub fn largest_constant(&self) -> i32 {
+        self.terms
+            .iter()
+            .filter_map(|term| match term.value {
+                TermValue::Constant(n) => Some(n.saturating_abs()),
+                TermValue::Dice(_) => None,
+            })
+            .max()
+            .unwrap_or(0)
+    }
+
+    /// Mean of the expression (ignoring the floor at 0)
+    pub fn expected_value(&self) -> f64 {
+        self.expected_value_with(DiceRules::default())
+    }
+
+    /// Mean of the expression rolled under house rules (ignoring the floor)
+    pub fn expected_value_with(&self, rules: DiceRules) -> f64 {
+        self.terms
+            .iter()
+            .map(|term| {
+                let value = match &term.value {
+                    TermValue::Constant(n) => *n as f64,
+                    TermValue::Dice(group) => group.expected_value(rules),
+                };
+                if term.negative {
+                    -value
+                } else {
+                    value
+                }
+            })
+            .sum()
+    }
+
+    /// Exact chance of each total, with the floor at 0 applied: the lowest possible total and the
+    /// chance of each total from there up. None for exploding dice and kept/dropped groups (other
+    /// than advantage and disadvantage), and for expressions spanning more than
+    /// `MAX_DISTRIBUTION_WIDTH` totals.
+    pub fn distribution(&self) -> Option<(i64, Vec<f64>)> {
+        self.distribution_with(DiceRules::default())
+    }
+
+    /// `distribution` under house rules; None whenever any die explodes
+    pub fn distribution_with(&self, rules: DiceRules) -> Option<(i64, Vec<f64>)> {
+        // (lowest total, chance of each total from there up)
+        let mut lowest = 0i64;
+        let mut chances = vec![1.0];
+        for term in &self.terms {
+            let (term_lowest, mut term_chances) = match &term.value {
+                TermValue::Constant(n) => (i64::from(*n), vec![1.0]),
+                TermValue::Dice(group) => group.distribution(rules)?,
+            };
+            let term_lowest = if term.negative {
+                term_chances.reverse();
+                -(term_lowest + term_chances.len() as i64 - 1)
+            } else {
+                term_lowest
+            };
+            if (chances.len() + term_chances.len()) as i64 > MAX_DISTRIBUTION_WIDTH {
+                return None;
+            }
+            lowest += term_lowest;
+            chances = convolve(&chances, &term_chances);
+        }
+
+        // Totals below the floor pile up on it; the constant part stays in `lowest`, so a large
+        // modifier costs nothing
+        let floor = rules.floor();
+        let below = usize::try_from(floor - lowest).unwrap_or(0);
+        let below_floor: f64 = chances.iter().take(below).sum();
+        let mut floored: Vec<f64> = chances.into_iter().skip(below).collect();
+        if below > 0 {
+            if floored.is_empty() {
+                floored.push(0.0);
+            }
+            floored[0] += below_floor;
+        }
+        Some((lowest.max(floor), floored))
+    }
+
+    pub fn roll(&self, rng: &mut impl rand::Rng) -> i32 {
+        self.roll_with(DiceRules::default(), rng)
+    }
+
+    /// Roll under house rules
+    pub fn roll_with(&self, rules: DiceRules, rng: &mut impl rand::Rng) -> i32 {
+        let total: i64 = self
+            .terms
+            .iter()
+            .map(|term| {
+                let value = match &term.value {
+                    TermValue::Constant(n) => i64::from(*n),
+                    TermValue::Dice(group) => group.roll(rules, rng),
+                };
+                if term.negative {
+                    -value
+                } else {
+                    value
+                }
+            })
+            .sum();
+        total.clamp(rules.floor(), i64::from(i32::MAX)) as i32
+    }
+}
+
+impl DiceGroup {
+    /// How many of the rolled dice are kept, and whether they're the highest ones
+    fn kept(&self) -> Option<(u32, bool)> {
+        match self.keep? {
+            Keep::Highest(n) => Some((n, true)),
+            Keep::Lowest(n) => Some((n, false)),
+            Keep::DropHighest(n) => Some((self.count - n, false)),
+            Keep::DropLowest(n) => Some((self.count - n, true)),
+            Keep::Advantage => Some((1, true)),
+            Keep::Disadvantage => Some((1, false)),
+        }
+    }
+
+    /// Dice actually rolled (advantage and disadvantage roll one die twice)
+    fn rolled(&self) -> u32 {
+        match self.keep {
+            Some(Keep::Advantage | Keep::Disadvantage) => 2,
+            _ => self.count,
+        }
+    }
+
+    /// Whether the group's dice explode, by its own `!` or the house rule
+    fn explodes(&self, rules: DiceRules) -> bool {
+        self.explode || (rules.explode && self.keep.is_none() && self.sides > 1)
+    }
+
+    /// Chance of each face of one die before any explosion, indexed from the 1
+    fn face_chances(&self, rules: DiceRules) -> Vec<f64> {
+        let faces = self.sides as f64;
+        if !rules.reroll_ones || self.sides == 1 {
+            return vec![1.0 / faces; self.sides as usize];
+        }
+        // A 1 needs two 1s in a row; every other face can come up on either roll
+        let mut chances = vec![1.0 / faces + 1.0 / (faces * faces); self.sides as usize];
+        chances[0] = 1.0 / (faces * faces);
+        chances
+    }
+
+    fn roll(&self, rules: DiceRules, rng: &mut impl rand::Rng) -> i64 {
+        let mut dice: Vec<i64> = (0..self.rolled()).map(|_| self.roll_die(rules, rng)).collect();
+        let Some((kept, highest)) = self.kept() else {
+            return dice.iter().sum();
+        };
+        dice.sort_unstable();
+        let kept = kept as usize;
+        if highest {
+            dice[dice.len() - kept..].iter().sum()
+        } else {
+            dice[..kept].iter().sum()
+        }
+    }
+
+    fn roll_die(&self, rules: DiceRules, rng: &mut impl rand::Rng) -> i64 {
+        let sides = self.sides;
+        let mut total = i64::from(rng.gen_range(1..=sides));
+        if rules.reroll_ones && total == 1 {
+            total = i64::from(rng.gen_range(1..=sides));
+        }
+        if self.explodes(rules) {
+            let mut last = total;
+            let mut explosions = 0;
+            while last == i64::from(sides) && explosions < MAX_EXPLOSIONS {
+                last = i64::from(rng.gen_range(1..=sides));
+                total += last;
+                explosions += 1;
+            }
+        }
+        total
+    }
+
+    /// Lowest total and the chance of each total from there up; see `DamageDice::distribution`
+    fn distribution(&self, rules: DiceRules) -> Option<(i64, Vec<f64>)> {
+        if self.explodes(rules) {
+            return None;
+        }
+        let die = self.face_chances(rules);
+        // Chance one die shows `v` or less, for `v` from 0 up
+        let at_most: Vec<f64> = std::iter::once(0.0)
+            .chain(die.iter().scan(0.0, |sum, chance| {
+                *sum += chance;
+                Some(*sum)
+            }))
+            .collect();
+        match self.keep {
+            None => {
+                if i64::from(self.count) * i64::from(self.sides) > MAX_DISTRIBUTION_WIDTH {
+                    return None;
+                }
+                let chances = (0..self.count).fold(vec![1.0], |chances, _| convolve(&chances, &die));
+                Some((i64::from(self.count), chances))
+            }
+            // The higher of two dice is at most `v` when both are, the lower when not both are above it
+            Some(Keep::Advantage) => Some((1, at_most.windows(2).map(|w| w[1].powi(2) - w[0].powi(2)).collect())),
+            Some(Keep::Disadvantage) => {
+                let above = |p: f64| (1.0 - p).powi(2);
+                Some((1, at_most.windows(2).map(|w| above(w[0]) - above(w[1])).collect()))
+            }
+            Some(_) => None,
+        }
+    }
+
+    fn expected_value(&self, rules: DiceRules) -> f64 {
+        let sides = self.sides as f64;
+        let chances = self.face_chances(rules);
+        let die: f64 = chances.iter().enumerate().map(|(face, chance)| (face + 1) as f64 * chance).sum();
+        let Some((kept, highest)) = self.kept() else {
+            // An exploding die adds a fresh exploding die whenever it shows its highest face
+            let die = if self.explodes(rules) {
+                die + chances[self.sides as usize - 1] * (sides + 1.0) / 2.0 * sides / (sides - 1.0)
+            } else {
+                die
+            };
+            return self.count as f64 * die;
+        };
+
+        let n = self.rolled();
+        if highest {
+            self.expected_highest(n, kept, &chances)
+        } else {
+            n as f64 * die - self.expected_highest(n, n - kept, &chances)
+        }
+    }
+
+    /// Expected sum of the highest `k` of `n` dice. Each kept die showing `v` or more adds one
+    /// for every face up to `v`, so the sum is, over faces `v`, the expected number of kept
+    /// dice showing at least `v`: `min(k, B)` where `B` is binomial in `n`. `chances` holds the
+    /// chance of each face from 1 up.
+    fn expected_highest(&self, n: u32, k: u32, chances: &[f64]) -> f64 {
+        (1..=self.sides)
+            .map(|v| {
+                let p: f64 = chances[v as usize - 1..].iter().sum();
+                let mut at_least_v = vec![1.0];
+                for _ in 0..n {
+                    let mut next = vec![0.0; at_least_v.len() + 1];
+                    for (count, chance) in at_least_v.iter().enumerate() {
+                        next[count] += chance * (1.0 - p);
+                        next[count + 1] += chance * p;
+                    }
+                    at_least_v = next;
+                }
+                at_least_v.iter().enumerate().map(|(count, chance)| chance * count.min(k as usize) as f64).sum::<f64>()
+            })
+            .sum()
+    }
+}
+
+/// Chances of the sums of two independent totals, each indexed from its lowest value
+fn convolve(a: &[f64], b: &[f64]) -> Vec<f64> {
+    let mut sums = vec![0.0; a.len() + b.len() - 1];
+    for (i, x) in a.iter().enumerate() {
+        for (j, y) in b.iter().enumerate() {
+            sums[i + j] += x * y;
+        }
+    }
+    sums
+}
+
+/// Parse a dice expression like `1d8+2`, `2d6+1d4+3`, `4d6kh3`, `1d6!`, or `1d20adv`.
+/// Whitespace and case are ignored.
+pub fn parse_damage_dice(s: &str) -> Result<DamageDice, String> {
+    let s: String = s.chars().filter(|c| !c.is_whitespace()).collect::<String>().to_lowercase();
+    if s.is_empty() {
+        return Err("empty dice expression".to_string());
+    }
+
+    let mut terms = Vec::new();
+    let mut rest = s.as_str();
+    let mut negative = false;
+    loop {
+        let end = rest.find(['+', '-']).unwrap_or(rest.len());
+        let (term, tail) = rest.split_at(end);
+        if term.is_empty() {
+            return Err(format!("invalid dice format '{}': expected a term like 2d6 or 3", s));
+        }
+        terms.push(Term {
+            negative,
+            value: parse_term(term)?,
+        });
+        let Some(op) = tail.chars().next() else { break };
+        negative = op == '-';
+        rest = &tail[1..];
+    }
+    Ok(DamageDice { terms })
+}
+
+fn parse_term(term: &str) -> Result<TermValue, String> {
+    let Some((count, rest)) = term.split_once('d') else {
+        return term.parse().map(TermValue::Constant).map_err(|e| format!("invalid number '{}': {}", term, e));
+    };
+    let count: u32 = if count.is_empty() {
+        1
+    } else {
+        count.parse().map_err(|e| format!("invalid dice count '{}': {}", count, e))?
+    };
+
+    let digits = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    let (sides, mut modifiers) = rest.split_at(digits);
+    let sides: u32 = sides.parse().map_err(|_| format!("invalid dice format '{}': expected NdM", term))?;
+    if sides == 0 {
+        return Err("dice need at least one side".to_string());
+    }
+    if sides > MAX_SIDES {
+        return Err(format!("dice can have at most {} sides", MAX_SIDES));
+    }
+    if count == 0 || count > MAX_DICE {
+        return Err(format!("dice count must be between 1 and {}", MAX_DICE));
+    }
+
+    let mut group = DiceGroup {
+        count,
+        sides,
+        keep: None,
+        explode: false,
+    };
+    while !modifiers.is_empty() {
+        if let Some(tail) = modifiers.strip_prefix('!') {
+            if group.explode || sides == 1 {
+                return Err(format!("'{}' can't explode", term));
+            }
+            group.explode = true;
+            modifiers = tail;
+            continue;
+        }
+        if group.keep.is_some() {
+            return Err(format!("'{}' has more than one keep/drop modifier", term));
+        }
+        let (keep, tail) = parse_keep(modifiers, count).ok_or_else(|| format!("unknown dice modifier in '{}'", term))?;
+        group.keep = Some(keep?);
+        modifiers = tail;
+    }
+    if group.explode && group.keep.is_some() {
+        return Err(format!("'{}' can't both explode and keep/drop dice", term));
+    }
+    if group.keep.is_some() && count > MAX_KEEP_DICE {
+        return Err(format!("keep/drop works on at most {} dice", MAX_KEEP_DICE));
+    }
+    Ok(TermValue::Dice(group))
+}
+
+/// A keep/drop modifier at the start of `s` and what follows it
+fn parse_keep(s: &str, count: u32) -> Option<(Result<Keep, String>, &str)> {
+    for (prefix, advantage) in [("adv", Keep::Advantage), ("dis", Keep::Disadvantage)] {
+        if let Some(tail) = s.strip_prefix(prefix) {
+            let keep = if count == 1 {
+                Ok(advantage)
+            } else {
+                Err(format!("{} applies to a single die", prefix))
+            };
+            return Some((keep, tail));
+        }
+    }
+
+    let (prefix, make): (&str, fn(u32) -> Keep) = [
+        ("kh", Keep::Highest as fn(u32) -> Keep),
+        ("kl", Keep::Lowest),
+        ("dh", Keep::DropHighest),
+        ("dl", Keep::DropLowest),
+        ("k", Keep::Highest),
+        ("d", Keep::DropLowest),
+    ]
+    .into_iter()
+    .find(|(prefix, _)| s.starts_with(prefix))?;
+    let s = &s[prefix.len()..];
+    let digits = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (n, tail) = s.split_at(digits);
+    let keep = match n.parse::<u32>() {
+        Ok(n) if prefix.starts_with('k') && (1..=count).contains(&n) => Ok(make(n)),
+        Ok(n) if prefix.starts_with('d') && n < count => Ok(make(n)),
+        _ => Err(format!("'{}{}' needs a number of dice between 1 and {}", prefix, n, count)),
+    };
+    Some((keep, tail))
+}
+
+impl fmt::Display for DamageDice {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, term) in self.terms.iter().enumerate() {
+            if term.negative {
+                write!(f, "-")?;
+            } else if i > 0 {
+                write!(f, "+")?;
+            }
+            match &term.value {
+                TermValue::Constant(n) => write!(f, "{}", n)?,
+                TermValue::Dice(group) => {
+                    write!(f, "{}d{}", group.count, group.sides)?;
+                    if group.explode {
+                        write!(f, "!")?;
+                    }
+                    match group.keep {
+                        Some(Keep::Highest(n)) => write!(f, "kh{}", n)?,
+                        Some(Keep::Lowest(n)) => write!(f, "kl{}", n)?,
+                        Some(Keep::DropHighest(n)) => write!(f, "dh{}", n)?,
+                        Some(Keep::DropLowest(n)) => write!(f, "dl{}", n)?,
+                        Some(Keep::Advantage) => write!(f, "adv")?,
+                        Some(Keep::Disadvantage) => write!(f, "dis")?,
+                        None => {}
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for DamageDice {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        parse_damage_dice(s)
+    }
+}
+
+/// Dice serialize as their expression text, so templates round-trip through storage
+impl Serialize for DamageDice {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for DamageDice {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        parse_damage_dice(&s).map_err(|e| serde::de::Error::custom(format!("{:?} is not a valid dice expression ({})", s, e)))
+    }
+}
@@ -0,0 +1,167 @@
+//! Timed effects on an actor: conditions, temp HP, and the postures it holds until its next
+//! turn. Each is an [`Effect`] with a duration, an optional damage tick, and a stacking rule
+//! for fresh applications; the simulator's round and turn hooks (`expire_effects`,
+//! `start_turn`, `end_turn`, `tick_effects`) do the rest, so a new timed mechanic only needs
+//! an [`EffectKind`].
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::types::{ConditionKind, HpValue};
+
+/// What an effect does while it lasts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EffectKind {
+    Condition(ConditionKind),
+    /// Absorbs damage before real HP; the magnitude is what's left
+    TempHp,
+    /// AC bonus from `guard` or `defend`
+    Stance,
+    /// Attacks against the actor have disadvantage
+    Dodging,
+    /// Arms `held` reactions
+    Holding,
+}
+
+/// When an effect ends
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    /// At the start of this round
+    Round(u32),
+    /// At the start of the actor's next turn
+    NextTurn,
+    Never,
+}
+
+impl Expiry {
+    /// `duration` rounds on from `round` (None = never)
+    pub fn after(duration: Option<u32>, round: u32) -> Expiry {
+        duration.map_or(Expiry::Never, |d| Expiry::Round(round + d))
+    }
+
+    /// Whichever of the two lasts longer
+    fn later(self, other: Expiry) -> Expiry {
+        match (self, other) {
+            (Expiry::Never, _) | (_, Expiry::Never) => Expiry::Never,
+            (Expiry::Round(a), Expiry::Round(b)) => Expiry::Round(a.max(b)),
+            (Expiry::Round(r), Expiry::NextTurn) | (Expiry::NextTurn, Expiry::Round(r)) => Expiry::Round(r),
+            (Expiry::NextTurn, Expiry::NextTurn) => Expiry::NextTurn,
+        }
+    }
+}
+
+/// When an effect with damage deals it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Tick {
+    /// As the bearer's turn begins, before it acts
+    StartOfTurn,
+    /// Once the bearer has acted
+    EndOfTurn,
+    /// After hazards at the end of every round
+    #[default]
+    EndOfRound,
+}
+
+/// What a fresh application does to an effect of the same kind the actor already has
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Stacking {
+    /// Takes its place
+    #[default]
+    Replace,
+    /// Sits alongside it; each dose ticks and ends on its own
+    Stack,
+    /// Leaves it be, but pushes its end out to whichever lasts longer
+    Extend,
+}
+
+#[derive(Debug, Clone)]
+pub struct Effect {
+    pub kind: EffectKind,
+    /// How strong it is: temp HP left, AC bonus (0 when it's all or nothing)
+    pub magnitude: i32,
+    pub expires: Expiry,
+    /// Damage dealt each `tick` (bleeding)
+    pub damage: Option<HpValue>,
+    pub tick: Tick,
+    /// Who applied it, credited with kills from its damage
+    pub source_id: usize,
+}
+
+impl Effect {
+    /// An effect with no damage
+    pub fn new(kind: EffectKind, magnitude: i32, expires: Expiry, source_id: usize) -> Self {
+        Effect { kind, magnitude, expires, damage: None, tick: Tick::default(), source_id }
+    }
+}
+
+/// Everything currently affecting one actor
+#[derive(Debug, Clone, Default)]
+pub struct Effects(Vec<Effect>);
+
+impl Effects {
+    /// Add `effect`, settling any of the same kind by `stacking`
+    pub fn apply(&mut self, effect: Effect, stacking: Stacking) {
+        match stacking {
+            Stacking::Replace => self.remove(effect.kind),
+            Stacking::Stack => {}
+            Stacking::Extend => {
+                if let Some(existing) = self.0.iter_mut().find(|e| e.kind == effect.kind) {
+                    existing.expires = existing.expires.later(effect.expires);
+                    return;
+                }
+            }
+        }
+        self.0.push(effect);
+    }
+
+    pub fn has(&self, kind: EffectKind) -> bool {
+        self.0.iter().any(|e| e.kind == kind)
+    }
+
+    /// Combined magnitude of every effect of `kind`
+    pub fn magnitude(&self, kind: EffectKind) -> i32 {
+        self.0.iter().filter(|e| e.kind == kind).map(|e| e.magnitude).sum()
+    }
+
+    pub fn get_mut(&mut self, kind: EffectKind) -> Option<&mut Effect> {
+        self.0.iter_mut().find(|e| e.kind == kind)
+    }
+
+    pub fn remove(&mut self, kind: EffectKind) {
+        self.0.retain(|e| e.kind != kind);
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    /// Take out the effects that end at the start of `round`
+    pub fn expire_round(&mut self, round: u32) -> Vec<Effect> {
+        self.take(|e| matches!(e.expires, Expiry::Round(r) if r <= round))
+    }
+
+    /// Take out the effects that end as the actor's turn begins
+    pub fn expire_turn(&mut self) -> Vec<Effect> {
+        self.take(|e| e.expires == Expiry::NextTurn)
+    }
+
+    /// Damage due at `tick`: (kind, damage, source) for each effect that deals some then
+    pub fn ticks(&self, tick: Tick) -> Vec<(EffectKind, HpValue, usize)> {
+        self.0
+            .iter()
+            .filter(|e| e.tick == tick)
+            .filter_map(|e| e.damage.clone().map(|damage| (e.kind, damage, e.source_id)))
+            .collect()
+    }
+
+    fn take(&mut self, ends: impl Fn(&Effect) -> bool) -> Vec<Effect> {
+        if !self.0.iter().any(&ends) {
+            return Vec::new();
+        }
+        let (ended, kept) = self.0.drain(..).partition(ends);
+        self.0 = kept;
+        ended
+    }
+}
@@ -0,0 +1,136 @@
+//! Virtual tabletop exports of a combat log, so a GM can replay a representative fight at the
+//! table: Foundry VTT chat messages (JSON) or Roll20 chat commands (plain text, one per line).
+
+use std::fmt::Write as _;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::stats::CombatLog;
+
+/// Tabletop formats for a replayed combat
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LogFormat {
+    /// An array of Foundry VTT `ChatMessage` data, ready for `ChatMessage.createDocuments`
+    Foundry,
+    /// Roll20 chat commands (`/desc`, `/emas`) to paste into the chat box
+    Roll20,
+}
+
+/// Data for one Foundry VTT chat message
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FoundryMessage {
+    pub speaker: FoundrySpeaker,
+    /// HTML body of the message
+    pub content: String,
+    /// Shown above the content; the round the event happened in
+    pub flavor: String,
+    pub flags: FoundryFlags,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FoundrySpeaker {
+    pub alias: String,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FoundryFlags {
+    #[serde(rename = "tunnel-fight")]
+    pub tunnel_fight: FoundryFlag,
+}
+
+/// Where the message came from in the simulated combat
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FoundryFlag {
+    pub iteration: u32,
+    pub round: u32,
+}
+
+/// Speaker for the messages no actor says: round headers and the outcome
+const NARRATOR: &str = "Tunnel Fight";
+
+/// `Side1` as `Side 1`
+fn side_label(side: &str) -> String {
+    match side.strip_prefix("Side") {
+        Some(n) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => format!("Side {}", n),
+        _ => side.to_string(),
+    }
+}
+
+/// How the fight ended, in a sentence
+fn outcome(log: &CombatLog) -> String {
+    let result = match &log.winner {
+        Some(side) => format!("{} wins after {} rounds.", side_label(side), log.rounds),
+        None => format!("Draw after {} rounds.", log.rounds),
+    };
+    let survivors: Vec<String> = log
+        .final_state
+        .iter()
+        .filter(|a| a.alive)
+        .map(|a| format!("{} ({} HP)", a.name, a.hp))
+        .collect();
+    if survivors.is_empty() {
+        format!("{} Nobody survived.", result)
+    } else {
+        format!("{} Left standing: {}.", result, survivors.join(", "))
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// One chat message per event, with a message announcing each round and a closing summary
+pub fn to_foundry(log: &CombatLog) -> Vec<FoundryMessage> {
+    let message = |alias: &str, round: u32, flavor: String, content: String| FoundryMessage {
+        speaker: FoundrySpeaker { alias: alias.to_string() },
+        content,
+        flavor,
+        flags: FoundryFlags {
+            tunnel_fight: FoundryFlag {
+                iteration: log.iteration,
+                round,
+            },
+        },
+    };
+
+    let mut messages = Vec::new();
+    if let Some(side) = &log.surprised {
+        messages.push(message(NARRATOR, 0, "Surprise".to_string(), format!("<p>{} is surprised!</p>", side_label(side))));
+    }
+    let mut round = None;
+    for event in &log.events {
+        if round != Some(event.round) {
+            round = Some(event.round);
+            messages.push(message(NARRATOR, event.round, format!("Round {}", event.round), format!("<h3>Round {}</h3>", event.round)));
+        }
+        messages.push(message(
+            &event.actor,
+            event.round,
+            format!("Round {}", event.round),
+            format!("<p>{} {}</p>", escape_html(&event.actor), escape_html(&event.description)),
+        ));
+    }
+    messages.push(message(NARRATOR, log.rounds, "Outcome".to_string(), format!("<p>{}</p>", escape_html(&outcome(log)))));
+    messages
+}
+
+/// Roll20 chat commands: `/desc` for round headers and the outcome, `/emas` for each event
+pub fn to_roll20(log: &CombatLog) -> String {
+    let mut out = String::new();
+    if let Some(side) = &log.surprised {
+        let _ = writeln!(out, "/desc {} is surprised!", side_label(side));
+    }
+    let mut round = None;
+    for event in &log.events {
+        if round != Some(event.round) {
+            round = Some(event.round);
+            let _ = writeln!(out, "/desc Round {}", event.round);
+        }
+        // A quote would end the speaker's name early
+        let _ = writeln!(out, "/emas \"{}\" {}", event.actor.replace('"', "'"), event.description);
+    }
+    let _ = writeln!(out, "/desc {}", outcome(log));
+    out
+}
@@ -0,0 +1,647 @@
+//! Statblock importers: 5e monster JSON (the Open5e API and 5e.tools formats) and classic
+//! OSR one-line statblocks (OSE, B/X) converted into `ActorTemplate`s. 5e already uses
+//! ascending AC and to-hit bonuses; OSR descending AC and THAC0 are converted. What doesn't
+//! fit the crate's one-attack model is approximated and listed in `notes`.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use utoipa::ToSchema;
+
+use crate::dice::parse_damage_dice;
+use crate::types::ActorTemplate;
+
+/// Feet of 5e speed per zone moved
+const FEET_PER_ZONE: i64 = 30;
+
+/// OSR movement rate (the exploration figure, e.g. `MV 120' (40')`) per zone moved
+const MV_PER_ZONE: i64 = 120;
+
+/// Statblock keys, longest spelling first so `Attacks` isn't read as `Att`
+const OSE_KEYS: [(&str, OseKey); 21] = [
+    ("treasure type", OseKey::Other),
+    ("no. appearing", OseKey::Other),
+    ("alignment", OseKey::Other),
+    ("attacks", OseKey::Attacks),
+    ("save as", OseKey::Other),
+    ("morale", OseKey::Other),
+    ("damage", OseKey::Damage),
+    ("thac0", OseKey::Thac0),
+    ("move", OseKey::Move),
+    ("att", OseKey::Attacks),
+    ("dmg", OseKey::Damage),
+    ("ac", OseKey::ArmorClass),
+    ("hd", OseKey::HitDice),
+    ("ab", OseKey::AttackBonus),
+    ("mv", OseKey::Move),
+    ("sv", OseKey::Other),
+    ("ml", OseKey::Other),
+    ("al", OseKey::Other),
+    ("xp", OseKey::Other),
+    ("na", OseKey::Other),
+    ("tt", OseKey::Other),
+];
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Imported {
+    pub template: ActorTemplate,
+    /// What was approximated or left out in the conversion
+    pub notes: Vec<String>,
+}
+
+/// Convert a 5e monster, in Open5e (`armor_class`, `hit_points`, `actions`) or 5e.tools
+/// (`ac`, `hp`, `action`) form. Its best attack action becomes the template's attack.
+pub fn import_5e(statblock: &Value) -> Result<Imported, String> {
+    let monster = statblock.as_object().ok_or("a statblock must be a JSON object")?;
+    let name = monster.get("name").and_then(Value::as_str).ok_or("statblock has no name")?;
+    let mut notes = Vec::new();
+
+    let ac = armor_class(monster).ok_or("statblock has no armor class")?;
+    let hp = hit_points(monster).ok_or("statblock has no hit points")?;
+
+    let raw_actions = monster.get("actions").or_else(|| monster.get("action")).and_then(Value::as_array);
+    let mut actions = Vec::new();
+    let mut attacks = Vec::new();
+    for action in raw_actions.into_iter().flatten() {
+        let Some((name, text)) = action_text(action) else {
+            continue;
+        };
+        attacks.extend(parse_attack(&name, &text).or_else(|| structured_attack(&name, action)));
+        actions.push((name, text));
+    }
+    // The hardest-hitting attack, the first listed on a tie
+    let mut best = attacks
+        .iter()
+        .rev()
+        .max_by(|a, b| a.damage.mean().total_cmp(&b.damage.mean()))
+        .cloned()
+        .ok_or_else(|| format!("{} has no attack action to convert", name))?;
+    let others: Vec<&str> = actions
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .filter(|&action| action != best.name && !action.eq_ignore_ascii_case("multiattack"))
+        .collect();
+    if let Some(times) = actions
+        .iter()
+        .find(|(name, _)| name.eq_ignore_ascii_case("multiattack"))
+        .and_then(|(_, text)| multiattack(text))
+    {
+        best.damage = best.damage.times(times);
+        notes.push(format!("Multiattack: {} attacks folded into one roll dealing {} damage", times, best.damage));
+    }
+    if !others.is_empty() {
+        notes.push(format!("attacks with {} ({}); ignored: {}", best.name, best.range, others.join(", ")));
+    }
+
+    let ability = |short: &str, long: &str| {
+        monster.get(short).or_else(|| monster.get(long)).and_then(Value::as_i64).map_or(0, |score| score.saturating_sub(10).div_euclid(2))
+    };
+    let mut saves = Map::new();
+    for (short, long) in [("str", "strength"), ("dex", "dexterity"), ("con", "constitution"), ("int", "intelligence"), ("wis", "wisdom"), ("cha", "charisma")] {
+        let proficient = monster
+            .get("save")
+            .and_then(|save| save.get(short))
+            .or_else(|| monster.get(format!("{}_save", long).as_str()))
+            .and_then(bonus);
+        saves.insert(short.to_string(), json!(proficient.unwrap_or_else(|| ability(short, long))));
+    }
+    let skill = |skill: &str| {
+        monster
+            .get("skill")
+            .or_else(|| monster.get("skills"))
+            .and_then(|skills| skills.get(skill))
+            .and_then(bonus)
+    };
+
+    let traits = traits(monster);
+    if !traits.is_empty() {
+        notes.push(format!("traits: {}", traits.join(", ")));
+    }
+
+    let walk = speed(monster);
+    let zones = (walk / FEET_PER_ZONE).max(1);
+    notes.push(format!("speed {} ft. is {} zone{} per move", walk, zones, if zones == 1 { "" } else { "s" }));
+
+    let template = json!({
+        "name": name,
+        "hp": hp,
+        "ac": ac,
+        "attack_bonus": best.bonus,
+        "damage": best.damage.to_string(),
+        "speed": zones,
+        "range": best.range,
+        "start_zone": if best.range == "ranged" { "ranged" } else { "melee" },
+        "initiative_modifier": ability("dex", "dexterity"),
+        "saves": saves,
+        "size": size(monster),
+        "stealth": skill("stealth").unwrap_or_else(|| ability("dex", "dexterity")),
+        "perception": skill("perception").unwrap_or_else(|| ability("wis", "wisdom")),
+        "darkvision": senses(monster).contains("darkvision"),
+        "traits": traits,
+    });
+    let template = serde_json::from_value(template).map_err(|e| format!("{}: {}", name, e))?;
+    Ok(Imported { template, notes })
+}
+
+/// Open5e `armor_class: 15`, or 5e.tools `ac: [15]` / `ac: [{ ac: 15, from: [...] }]`
+fn armor_class(monster: &Map<String, Value>) -> Option<i64> {
+    match monster.get("armor_class").or_else(|| monster.get("ac"))? {
+        Value::Array(list) => list.iter().find_map(|ac| ac.as_i64().or_else(|| ac.get("ac")?.as_i64())),
+        other => other.as_i64(),
+    }
+}
+
+/// The HP formula (`2d6`, `7d10+21`) when there is one, corrected to the listed average (which
+/// 5e rounds down) for sources that leave the Constitution bonus out of it; otherwise the average
+fn hit_points(monster: &Map<String, Value>) -> Option<Value> {
+    let (average, formula) = match monster.get("hp") {
+        Some(hp) => (hp.get("average").and_then(Value::as_i64), hp.get("formula").and_then(Value::as_str)),
+        None => (
+            monster.get("hit_points").and_then(Value::as_i64),
+            monster.get("hit_dice").and_then(Value::as_str),
+        ),
+    };
+    let formula: String = formula.unwrap_or_default().chars().filter(|c| !c.is_whitespace()).collect();
+    match (parse_damage_dice(&formula), average) {
+        (Ok(dice), Some(average)) => {
+            let missing = average.saturating_sub(dice.expected_value().floor() as i64);
+            Some(json!(if missing == 0 { formula } else { format!("{}{:+}", formula, missing) }))
+        }
+        (Ok(_), None) => Some(json!(formula)),
+        (Err(_), average) => average.filter(|&hp| hp > 0).map(Value::from),
+    }
+}
+
+/// Walking speed in feet (flying if it can't walk)
+fn speed(monster: &Map<String, Value>) -> i64 {
+    let feet = |mode: &str| {
+        let speed = monster.get("speed")?;
+        let value = speed.get(mode).or_else(|| (mode == "walk").then_some(speed))?;
+        value.as_i64().or_else(|| value.get("number")?.as_i64())
+    };
+    feet("walk").filter(|&ft| ft > 0).or_else(|| feet("fly")).unwrap_or(FEET_PER_ZONE)
+}
+
+fn size(monster: &Map<String, Value>) -> &'static str {
+    let size = match monster.get("size") {
+        Some(Value::Array(sizes)) => sizes.first().and_then(Value::as_str),
+        Some(size) => size.as_str(),
+        None => None,
+    };
+    match size.and_then(|s| s.chars().next()).map(|c| c.to_ascii_uppercase()) {
+        Some('T' | 'S') => "small",
+        Some('L') => "large",
+        Some('H' | 'G') => "huge",
+        _ => "medium",
+    }
+}
+
+fn senses(monster: &Map<String, Value>) -> String {
+    match monster.get("senses") {
+        Some(Value::Array(senses)) => senses.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(", "),
+        Some(Value::String(senses)) => senses.clone(),
+        _ => String::new(),
+    }
+    .to_lowercase()
+}
+
+/// Special traits (Open5e `special_abilities`, 5e.tools `trait`) that match a passive trait
+/// the simulator knows, by name
+fn traits(monster: &Map<String, Value>) -> Vec<&'static str> {
+    const KNOWN: [(&str, &str); 3] = [
+        ("pack tactics", "pack_tactics"),
+        ("sunlight sensitivity", "sunlight_sensitivity"),
+        ("nimble escape", "nimble_escape"),
+    ];
+    let names: Vec<String> = monster
+        .get("special_abilities")
+        .or_else(|| monster.get("trait"))
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|ability| Some(strip_tags(ability.get("name")?.as_str()?).to_lowercase()))
+        .collect();
+    KNOWN
+        .iter()
+        .filter(|(name, _)| names.iter().any(|n| n == name))
+        .map(|&(_, key)| key)
+        .collect()
+}
+
+/// A bonus written as a number or a string like `+4`
+fn bonus(value: &Value) -> Option<i64> {
+    value.as_i64().or_else(|| value.as_str()?.trim().trim_start_matches('+').parse().ok())
+}
+
+/// An action's name and its text with 5e.tools markup removed
+fn action_text(action: &Value) -> Option<(String, String)> {
+    let name = strip_tags(action.get("name")?.as_str()?);
+    let text = match action.get("desc").or_else(|| action.get("entries"))? {
+        Value::String(text) => text.clone(),
+        Value::Array(entries) => entries.iter().filter_map(Value::as_str).collect::<Vec<_>>().join(" "),
+        _ => return None,
+    };
+    Some((name, strip_tags(&text)))
+}
+
+/// Rewrite 5e.tools tags into plain statblock text: `{@hit 4}` → `+4`, `{@h}` → `Hit: `,
+/// `{@atk mw}` → `Melee Weapon Attack:`, and `{@anything text|source}` → `text`
+fn strip_tags(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find("{@") {
+        out.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+        let tag = &rest[start + 2..start + len];
+        let (kind, body) = tag.split_once(' ').unwrap_or((tag, ""));
+        let body = body.split('|').next().unwrap_or_default();
+        match kind {
+            "hit" if body.starts_with('-') => out.push_str(body),
+            "hit" => out.push_str(&format!("+{}", body.trim_start_matches('+'))),
+            "h" => out.push_str("Hit: "),
+            "recharge" if body.is_empty() || body == "6" => out.push_str("(Recharge 6)"),
+            "recharge" => out.push_str(&format!("(Recharge {}-6)", body)),
+            "atk" if body.contains('m') && body.contains('r') => out.push_str("Melee or Ranged Weapon Attack:"),
+            "atk" if body.contains('r') => out.push_str("Ranged Weapon Attack:"),
+            "atk" => out.push_str("Melee Weapon Attack:"),
+            _ => out.push_str(body),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    out.push_str(rest);
+    out
+}
+
+#[derive(Debug, Clone)]
+struct Attack {
+    name: String,
+    bonus: i64,
+    range: &'static str,
+    damage: DiceSum,
+}
+
+/// Read `Melee Weapon Attack: +4 to hit, reach 5 ft., one target. Hit: 5 (1d6 + 2) slashing
+/// damage plus 3 (1d6) fire damage.`
+fn parse_attack(name: &str, text: &str) -> Option<Attack> {
+    let to_hit = text.find("to hit")?;
+    let bonus = text[..to_hit].split_whitespace().last().and_then(|b| b.trim_start_matches('+').parse().ok())?;
+
+    let kind = text[..to_hit].to_lowercase();
+    let range = if kind.contains("melee") {
+        let reach = text.find("reach ").and_then(|at| {
+            text[at + 6..].split_whitespace().next()?.parse::<i64>().ok()
+        });
+        if reach.is_some_and(|ft| ft > 5) {
+            "reach"
+        } else {
+            "melee"
+        }
+    } else if kind.contains("ranged") {
+        "ranged"
+    } else {
+        "melee"
+    };
+
+    let hit = text.find("Hit:")?;
+    let effect = &text[hit + 4..];
+    let effect = effect.split(". ").next().unwrap_or(effect);
+    let mut damage = DiceSum::default();
+    let mut rest = effect;
+    let mut first = true;
+    while let Some(open) = rest.find('(') {
+        let Some(close) = rest[open..].find(')') else { break };
+        let before = rest[..open].to_lowercase();
+        if first || before.trim_end().rsplit(' ').nth(1) == Some("plus") {
+            damage.add(&rest[open + 1..open + close]);
+        }
+        first = false;
+        rest = &rest[open + close + 1..];
+    }
+    if damage.is_empty() {
+        // `Hit: 1 piercing damage.`
+        let flat: i64 = effect.split_whitespace().next()?.parse().ok()?;
+        damage.constant = flat;
+    }
+    Some(Attack { name: name.to_string(), bonus, range, damage })
+}
+
+/// Older Open5e actions also carry `attack_bonus`, `damage_dice`, and `damage_bonus` fields
+fn structured_attack(name: &str, action: &Value) -> Option<Attack> {
+    let bonus = action.get("attack_bonus")?.as_i64()?;
+    let mut damage = DiceSum::default();
+    damage.add(action.get("damage_dice")?.as_str()?);
+    damage.constant = damage.constant.saturating_add(action.get("damage_bonus").and_then(Value::as_i64).unwrap_or(0));
+    (!damage.is_empty()).then(|| Attack { name: name.to_string(), bonus, range: "melee", damage })
+}
+
+/// How many attacks a Multiattack action makes: the number after "makes"
+fn multiattack(text: &str) -> Option<u32> {
+    let lower = text.to_lowercase();
+    let count = lower[lower.find("makes ")? + 6..].split_whitespace().next()?;
+    let count = match count {
+        "two" => 2,
+        "three" => 3,
+        "four" => 4,
+        "five" => 5,
+        "six" => 6,
+        n => n.parse().ok()?,
+    };
+    (2..=10).contains(&count).then_some(count)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OseKey {
+    ArmorClass,
+    HitDice,
+    Attacks,
+    Damage,
+    Thac0,
+    AttackBonus,
+    Move,
+    /// Saves, morale, alignment, and the like, which don't carry over
+    Other,
+}
+
+/// Convert an OSE or B/X statblock line such as `Goblin: AC 6 [13], HD 1-1, Att 1 × weapon
+/// (1d6), THAC0 19 [0], MV 60' (20'), ML 7`. The name is whatever comes before the first key,
+/// unless `name` is given. All of its attacks are folded into one roll.
+pub fn import_ose(statblock: &str, name: Option<&str>) -> Result<Imported, String> {
+    let (prefix, fields) = ose_fields(statblock);
+    let name = name
+        .map(str::to_string)
+        .or_else(|| Some(prefix.trim_matches(|c: char| c.is_whitespace() || ":,;-\u{2013}\u{2014}".contains(c)).to_string()))
+        .filter(|name| !name.is_empty())
+        .ok_or("statblock has no name")?;
+    let field = |key: OseKey| fields.iter().find(|(k, _)| *k == key).map(|(_, value)| value.as_str());
+    let mut notes = Vec::new();
+
+    // `AC 6 [13]` gives ascending AC in brackets; otherwise AAC = 19 - AC
+    let ac = field(OseKey::ArmorClass).ok_or("statblock has no AC")?;
+    let ac = bracketed(ac).or_else(|| leading_number(ac).map(|ac| 19 - ac)).ok_or_else(|| format!("can't read AC '{}'", ac))?;
+
+    let hd = field(OseKey::HitDice).ok_or("statblock has no HD")?;
+    let (hp, level) = hit_dice(hd).ok_or_else(|| format!("can't read HD '{}'", hd))?;
+
+    // `THAC0 17 [+2]` gives the bonus in brackets; otherwise it's 19 - THAC0, and without
+    // either the B/X monster attack table by HD
+    let attack_bonus = match (field(OseKey::AttackBonus), field(OseKey::Thac0)) {
+        (Some(ab), _) => leading_number(ab).ok_or_else(|| format!("can't read AB '{}'", ab))?,
+        (None, Some(thac0)) => bracketed(thac0)
+            .or_else(|| leading_number(thac0).map(|thac0| 19 - thac0))
+            .ok_or_else(|| format!("can't read THAC0 '{}'", thac0))?,
+        (None, None) => {
+            let thac0 = thac0_for(level);
+            notes.push(format!("no THAC0 given; {} from the monster attack table", thac0));
+            19 - thac0
+        }
+    };
+
+    let (damage, attacks) = ose_damage(field(OseKey::Attacks), field(OseKey::Damage), &mut notes)
+        .ok_or("statblock has no attack damage")?;
+    if attacks > 1 {
+        notes.push(format!("{} attacks folded into one roll dealing {} damage", attacks, damage));
+    }
+
+    let zones = match field(OseKey::Move).and_then(leading_number) {
+        Some(mv) => {
+            let zones = (mv / MV_PER_ZONE).max(1);
+            notes.push(format!("MV {}' is {} zone{} per move", mv, zones, if zones == 1 { "" } else { "s" }));
+            zones
+        }
+        None => 1,
+    };
+    if fields.iter().any(|(key, _)| *key == OseKey::Other) {
+        notes.push("saves, morale, and the other OSR fields have no equivalent and were left out".to_string());
+    }
+
+    let template = json!({
+        "name": name,
+        "hp": hp,
+        "ac": ac,
+        "attack_bonus": attack_bonus,
+        "damage": damage.to_string(),
+        "speed": zones,
+        "start_zone": "melee",
+    });
+    let template = serde_json::from_value(template).map_err(|e| format!("{}: {}", name, e))?;
+    Ok(Imported { template, notes })
+}
+
+/// Split a statblock into the text before its first key and each key's value
+fn ose_fields(statblock: &str) -> (&str, Vec<(OseKey, String)>) {
+    let lower = statblock.to_ascii_lowercase();
+    let mut starts = Vec::new();
+    let mut at = 0;
+    while at < lower.len() {
+        let boundary = at == 0 || lower[..at].ends_with([' ', ',', ';', '(', '\n', '\t']);
+        let key = OSE_KEYS.iter().find(|(key, _)| {
+            lower[at..].starts_with(key) && lower[at + key.len()..].starts_with([' ', ':']) 
+        });
+        match key {
+            Some(&(key, kind)) if boundary => {
+                starts.push((at, at + key.len(), kind));
+                at += key.len();
+            }
+            _ => at += lower[at..].chars().next().map_or(1, char::len_utf8),
+        }
+    }
+    let prefix = &statblock[..starts.first().map_or(statblock.len(), |&(at, _, _)| at)];
+    let fields = starts
+        .iter()
+        .enumerate()
+        .map(|(i, &(_, value, kind))| {
+            let end = starts.get(i + 1).map_or(statblock.len(), |&(next, _, _)| next);
+            let value = statblock[value..end].trim_matches(|c: char| c.is_whitespace() || ":,;.".contains(c));
+            (kind, value.to_string())
+        })
+        .collect();
+    (prefix, fields)
+}
+
+/// The number a field starts with: `6` in `6 [13]`, `120` in `120' (40')`
+fn leading_number(value: &str) -> Option<i64> {
+    let value = value.trim_start();
+    let end = value
+        .char_indices()
+        .find(|&(i, c)| !(c.is_ascii_digit() || (i == 0 && (c == '-' || c == '+'))))
+        .map_or(value.len(), |(i, _)| i);
+    value[..end].trim_start_matches('+').parse().ok()
+}
+
+/// The number in square brackets: `13` in `6 [13]`, `2` in `17 [+2]`
+fn bracketed(value: &str) -> Option<i64> {
+    let open = value.find('[')?;
+    leading_number(&value[open + 1..])
+}
+
+/// HP dice for a hit dice figure, and the level it counts as on the attack table (half a
+/// level up for a bonus): `2+1` is `2d8+1` at 2.5, `1/2` is `1d4` at 0.5, and `3 (13hp)` is a
+/// fixed 13 at 3. Asterisks (special abilities) are ignored.
+fn hit_dice(hd: &str) -> Option<(Value, f64)> {
+    let hd = hd.replace('*', "");
+    let hd = hd.trim();
+    let fixed = hd
+        .find('(')
+        .and_then(|open| leading_number(&hd[open + 1..]))
+        .filter(|&hp| hp > 0 && hd.to_ascii_lowercase().contains("hp"));
+    if hd.starts_with("1/2") || hd.starts_with('\u{bd}') {
+        return Some((fixed.map_or_else(|| json!("1d4"), Value::from), 0.5));
+    }
+
+    let dice = leading_number(hd).filter(|&n| n > 0)?;
+    let rest = hd[hd.find(|c: char| !c.is_ascii_digit()).unwrap_or(hd.len())..].trim_start();
+    let modifier = if rest.starts_with(['+', '-']) { leading_number(rest).unwrap_or(0) } else { 0 };
+    let level = dice as f64 + if modifier > 0 { 0.5 } else { 0.0 };
+    let hp = match (fixed, modifier) {
+        (Some(hp), _) => json!(hp),
+        (None, 0) => json!(format!("{}d8", dice)),
+        (None, modifier) => json!(format!("{}d8{:+}", dice, modifier)),
+    };
+    Some((hp, level))
+}
+
+/// THAC0 for a monster of this level on the B/X attack table
+fn thac0_for(level: f64) -> i64 {
+    const TABLE: [(f64, i64); 14] = [
+        (1.0, 19),
+        (2.0, 18),
+        (3.0, 17),
+        (4.0, 16),
+        (5.0, 15),
+        (6.0, 14),
+        (7.0, 13),
+        (9.0, 12),
+        (11.0, 11),
+        (13.0, 10),
+        (15.0, 9),
+        (17.0, 8),
+        (19.0, 7),
+        (21.0, 6),
+    ];
+    TABLE.iter().find(|&&(up_to, _)| level <= up_to).map_or(5, |&(_, thac0)| thac0)
+}
+
+/// Total damage of the attack routine and how many attacks it has. Reads `2 × claw (1d4),
+/// 1 × bite (1d8)` from `Att`, or the B/X split of `Attacks: 2 claws/1 bite` with
+/// `Damage: 1d3/1d3/1d6`.
+fn ose_damage(attacks: Option<&str>, damage: Option<&str>, notes: &mut Vec<String>) -> Option<(DiceSum, u32)> {
+    let mut total = DiceSum::default();
+    let mut count = 0;
+    let mut add = |times: u32, text: &str, total: &mut DiceSum| {
+        // The longest leading dice expression: `1d3` of `1d3 + disease`
+        let mut dice: String = text.chars().take_while(|c| c.is_ascii_digit() || "dD+- ".contains(*c)).collect();
+        while !dice.is_empty() && parse_damage_dice(dice.trim().trim_end_matches(['+', '-'])).is_err() {
+            dice.pop();
+        }
+        let mut one = DiceSum::default();
+        one.add(dice.trim().trim_end_matches(['+', '-']));
+        let note = if one.is_empty() {
+            one.add("1d6");
+            Some(format!("'{}' has no damage dice; assumed 1d6", text.trim()))
+        } else if !text[dice.len()..].trim().is_empty() {
+            Some(format!("'{}': only the dice were used", text.trim()))
+        } else {
+            None
+        };
+        if let Some(note) = note.filter(|note| !notes.contains(note)) {
+            notes.push(note);
+        }
+        total.add_sum(&one.times(times));
+        count += times;
+    };
+
+    if let Some(attacks) = attacks.filter(|a| a.contains('(')) {
+        let mut rest = attacks;
+        while let Some(open) = rest.find('(') {
+            let close = rest[open..].find(')').map_or(rest.len(), |close| open + close);
+            let times = leading_number(rest[..open].trim_start_matches(|c: char| c == ',' || c.is_whitespace() || c == '&'))
+                .filter(|&n| n > 0)
+                .unwrap_or(1);
+            add(times.min(10) as u32, &rest[open + 1..close], &mut total);
+            rest = &rest[(close + 1).min(rest.len())..];
+        }
+    } else if let Some(damage) = damage {
+        for part in damage.split(['/', ',']) {
+            add(1, part.trim(), &mut total);
+        }
+    } else {
+        return None;
+    }
+    Some((total, count))
+}
+
+/// A sum of plain dice and a constant, e.g. `2d6+1d4+3`
+#[derive(Debug, Clone, Default)]
+struct DiceSum {
+    /// Dice count by number of sides
+    dice: BTreeMap<u32, u32>,
+    constant: i64,
+}
+
+impl DiceSum {
+    /// Add an expression like `1d6 + 2`; anything fancier than plain dice is skipped
+    fn add(&mut self, expression: &str) {
+        let expression: String = expression.chars().filter(|c| !c.is_whitespace()).collect();
+        if parse_damage_dice(&expression).is_err() {
+            return;
+        }
+        let mut sign = 1;
+        let mut term = String::new();
+        for c in expression.chars().chain(std::iter::once('+')) {
+            if c != '+' && c != '-' {
+                term.push(c);
+                continue;
+            }
+            match term.split_once('d') {
+                Some((count, sides)) if sign > 0 => {
+                    let count = if count.is_empty() { 1 } else { count.parse().unwrap_or(0) };
+                    if let Ok(sides) = sides.parse() {
+                        let total = self.dice.entry(sides).or_default();
+                        *total = total.saturating_add(count);
+                    }
+                }
+                Some(_) => {}
+                None => self.constant = self.constant.saturating_add(sign * term.parse::<i64>().unwrap_or(0)),
+            }
+            sign = if c == '-' { -1 } else { 1 };
+            term.clear();
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        self.dice.is_empty() && self.constant == 0
+    }
+
+    fn mean(&self) -> f64 {
+        self.dice.iter().map(|(&sides, &count)| count as f64 * (sides as f64 + 1.0) / 2.0).sum::<f64>() + self.constant as f64
+    }
+
+    fn add_sum(&mut self, other: &DiceSum) {
+        for (&sides, &count) in &other.dice {
+            let total = self.dice.entry(sides).or_default();
+            *total = total.saturating_add(count);
+        }
+        self.constant = self.constant.saturating_add(other.constant);
+    }
+
+    fn times(&self, n: u32) -> DiceSum {
+        DiceSum {
+            dice: self.dice.iter().map(|(&sides, &count)| (sides, count.saturating_mul(n))).collect(),
+            constant: self.constant.saturating_mul(n as i64),
+        }
+    }
+}
+
+impl std::fmt::Display for DiceSum {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let mut terms: Vec<String> = self.dice.iter().rev().map(|(sides, count)| format!("{}d{}", count, sides)).collect();
+        if self.constant != 0 || terms.is_empty() {
+            terms.push(self.constant.to_string());
+        }
+        write!(f, "{}", terms.join("+").replace("+-", "-"))
+    }
+}
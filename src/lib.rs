@@ -0,0 +1,64 @@
+//! Tunnel Fight: a zone-based OSR combat simulator.
+//!
+//! The HTTP server and CLI in `main.rs` are thin wrappers around this crate; other programs
+//! can embed the simulator directly:
+//!
+//! ```
+//! use tunnel_fight::{run_simulation, Encounter, SimulationOptions};
+//!
+//! let encounter: Encounter = serde_yaml::from_str(
+//!     r#"
+//! side1:
+//!   - { name: Fighter, hp: 12, ac: 16, attack_bonus: 3, damage: 1d8+1, start_zone: melee }
+//! side2:
+//!   - { name: Goblin, hp: 1d6, ac: 13, attack_bonus: 1, damage: 1d6, start_zone: melee, count: 3 }
+//! "#,
+//! )
+//! .unwrap();
+//!
+//! let options = SimulationOptions {
+//!     iterations: 1000,
+//!     seed: Some(42),
+//!     ..SimulationOptions::for_encounter(&encounter)
+//! };
+//! let result = run_simulation(&encounter, &options);
+//! assert_eq!(result.stats.iterations, 1000);
+//! ```
+//!
+//! For finer control, drive [`CombatSimulator`] yourself and feed each
+//! [`CombatResult`] into a [`StatsCollector`].
+
+pub mod analysis;
+pub mod apl;
+pub mod builtins;
+pub mod campaign;
+pub mod combat;
+pub mod decide;
+pub mod dice;
+pub mod effects;
+pub mod export;
+pub mod import;
+pub mod library;
+pub mod lint;
+pub mod loader;
+pub mod modifiers;
+pub mod optimize;
+pub mod rating;
+pub mod report;
+pub mod sensitivity;
+pub mod script;
+pub mod sequence;
+pub mod simulation;
+pub mod stats;
+#[cfg(feature = "server")]
+pub mod storage;
+pub mod traits;
+pub mod types;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+
+pub use apl::{execute_apl, validate, TurnActions, ValidationError};
+pub use combat::{CombatResult, CombatSimulator};
+pub use simulation::{run_simulation, run_simulation_with_progress, SimulationOptions};
+pub use stats::{SimulationResult, SimulationStats, StatsCollector};
+pub use types::{ActorTemplate, Encounter};
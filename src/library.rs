@@ -0,0 +1,429 @@
+use serde_yaml::{Mapping, Value};
+
+use crate::apl::{ErrorCode, ValidationError};
+use crate::builtins;
+use crate::dice::parse_damage_dice;
+use crate::import::{import_5e, import_ose, Imported};
+#[cfg(feature = "server")]
+use crate::storage::Storage;
+use crate::types::ActorTemplate;
+
+const SIDES: [&str; 2] = ["side1", "side2"];
+
+/// Where `ref:` entries find their statblocks: the server's SQLite library, or nothing at all
+/// where there's no database (built-ins still resolve either way)
+pub trait MonsterSource {
+    /// The statblock stored under `name`, or an error message if the lookup itself failed
+    fn get_monster(&self, name: &str) -> Result<Option<ActorTemplate>, String>;
+}
+
+#[cfg(feature = "server")]
+impl MonsterSource for Storage {
+    fn get_monster(&self, name: &str) -> Result<Option<ActorTemplate>, String> {
+        Storage::get_monster(self, name).map_err(|e| e.to_string())
+    }
+}
+
+/// No monster library: every `ref:` other than a built-in is unknown
+pub struct NoLibrary;
+
+impl MonsterSource for NoLibrary {
+    fn get_monster(&self, _name: &str) -> Result<Option<ActorTemplate>, String> {
+        Ok(None)
+    }
+}
+
+/// True if any side entry in the encounter document is a `ref:` to the monster library
+pub fn has_refs(doc: &Value) -> bool {
+    SIDES.iter().any(|side| {
+        doc.get(side)
+            .and_then(Value::as_sequence)
+            .is_some_and(|entries| entries.iter().any(|e| e.get("ref").is_some()))
+    })
+}
+
+/// Replace `{ ref: goblin, count: 6 }` side entries with the stored statblock, or with a
+/// built-in character for `ref: builtin/fighter_3`. Any other keys on the entry override the
+/// corresponding fields of the library template.
+pub fn resolve_refs(doc: &mut Value, storage: &impl MonsterSource) -> Result<(), Vec<ValidationError>> {
+    let mut errors = Vec::new();
+
+    for side in SIDES {
+        let Some(entries) = doc.get_mut(side).and_then(Value::as_sequence_mut) else {
+            continue;
+        };
+        for (i, entry) in entries.iter_mut().enumerate() {
+            let Some(overrides) = entry.as_mapping() else {
+                continue;
+            };
+            let Some(reference) = overrides.get("ref") else {
+                continue;
+            };
+
+            let mut error = |message: String| {
+                errors.push(ValidationError::new(format!("{}[{}].ref", side, i), ErrorCode::UnknownRef, message));
+            };
+            let Some(name) = reference.as_str() else {
+                error("ref must be a monster name".to_string());
+                continue;
+            };
+            let found = match name.strip_prefix(builtins::BUILTIN_PREFIX) {
+                Some(key) => builtins::get(key).ok_or_else(|| format!("no built-in named '{}' (see GET /builtins)", name)),
+                None => match storage.get_monster(name) {
+                    Ok(Some(template)) => Ok(template),
+                    Ok(None) => Err(format!("no monster named '{}' in the library", name)),
+                    Err(e) => Err(e),
+                },
+            };
+            let template = match found {
+                Ok(template) => template,
+                Err(message) => {
+                    error(message);
+                    continue;
+                }
+            };
+
+            let mut resolved = match serde_yaml::to_value(&template) {
+                Ok(Value::Mapping(m)) => m,
+                _ => Mapping::new(),
+            };
+            for (key, value) in overrides {
+                if !matches!(key.as_str(), Some("ref" | "adjust")) {
+                    resolved.insert(key.clone(), value.clone());
+                }
+            }
+            if let Some(adjustments) = overrides.get("adjust") {
+                if let Err((field, code, message)) = adjust(&mut resolved, adjustments) {
+                    errors.push(ValidationError::new(format!("{}[{}].{}", side, i, field), code, message));
+                    continue;
+                }
+            }
+            *entry = Value::Mapping(resolved);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// True if the encounter document has a `templates:` or `import:` section or any side entry
+/// that `extends:`, `adjust:`s, or is an inline `ose:` statblock
+pub fn has_templates(doc: &Value) -> bool {
+    doc.get("templates").is_some()
+        || doc.get("import").is_some()
+        || SIDES.iter().any(|side| {
+            doc.get(side)
+                .and_then(Value::as_sequence)
+                .is_some_and(|entries| entries.iter().any(|e| e.get("ose").is_some() || extends(e)))
+        })
+}
+
+/// True if the entry, or a statblock it summons or leaves behind, `extends:` or `adjust:`s
+fn extends(entry: &Value) -> bool {
+    let summons = entry.get("summons").and_then(Value::as_sequence).into_iter().flatten();
+    let on_death = entry.get("on_death").and_then(Value::as_mapping).into_iter().flat_map(|m| m.values());
+    entry.get("extends").is_some() || entry.get("adjust").is_some() || summons.chain(on_death).any(extends)
+}
+
+/// Convert the statblocks in the encounter's `import:` section (`import: { 5e: [...], ose:
+/// [...] }`) and add them to its `templates:` under their names, then remove the section. Side
+/// entries with an inline `ose:` statblock are converted in place, their other keys overriding.
+pub fn resolve_imports(doc: &mut Value) -> Result<(), Vec<ValidationError>> {
+    let mut errors = Vec::new();
+    convert_inline_statblocks(doc, &mut errors);
+
+    let Some(root) = doc.as_mapping_mut() else {
+        return Ok(());
+    };
+    let Some(imports) = root.remove("import") else {
+        return if errors.is_empty() { Ok(()) } else { Err(errors) };
+    };
+    let mut imported = Mapping::new();
+    match imports {
+        Value::Mapping(imports) => {
+            for (format, statblocks) in &imports {
+                let format = format.as_str().unwrap_or_default();
+                let Some(statblocks) = statblocks.as_sequence() else {
+                    errors.push(import_error(format!("import.{}", format), "expected a list of statblocks".to_string()));
+                    continue;
+                };
+                for (i, statblock) in statblocks.iter().enumerate() {
+                    let path = format!("import.{}[{}]", format, i);
+                    let converted = match format {
+                        "5e" => serde_json::to_value(statblock).map_err(|e| e.to_string()).and_then(|json| import_5e(&json)),
+                        "ose" => match statblock.as_str() {
+                            Some(statblock) => import_ose(statblock, None),
+                            None => Err("expected a statblock line".to_string()),
+                        },
+                        _ => {
+                            errors.push(import_error(
+                                format!("import.{}", format),
+                                format!("unknown statblock format '{}' (expected 5e or ose)", format),
+                            ));
+                            break;
+                        }
+                    };
+                    match converted.and_then(|converted| template_value(&converted)) {
+                        Ok(template) => {
+                            let name = template.get("name").cloned().unwrap_or_default();
+                            if imported.insert(name, template).is_some() {
+                                errors.push(ValidationError::new(path, ErrorCode::DuplicateName, "imported twice".to_string()));
+                            }
+                        }
+                        Err(message) => errors.push(import_error(path, message)),
+                    }
+                }
+            }
+        }
+        _ => errors.push(import_error("import".to_string(), "import must map formats to lists of statblocks".to_string())),
+    }
+
+    match root.get_mut("templates") {
+        None | Some(Value::Null) => {
+            root.insert(Value::from("templates"), Value::Mapping(imported));
+        }
+        Some(Value::Mapping(templates)) => {
+            for (name, template) in imported {
+                if templates.contains_key(&name) {
+                    let name = name.as_str().unwrap_or_default();
+                    errors.push(ValidationError::new(
+                        format!("templates.{}", name),
+                        ErrorCode::DuplicateName,
+                        format!("'{}' is both imported and a template", name),
+                    ));
+                } else {
+                    templates.insert(name, template);
+                }
+            }
+        }
+        // Not a mapping: `resolve_templates` reports it
+        Some(_) => {}
+    }
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Replace side entries holding an inline `ose:` statblock with the converted template
+fn convert_inline_statblocks(doc: &mut Value, errors: &mut Vec<ValidationError>) {
+    for side in SIDES {
+        let Some(entries) = doc.get_mut(side).and_then(Value::as_sequence_mut) else {
+            continue;
+        };
+        for (i, entry) in entries.iter_mut().enumerate() {
+            let Some(overrides) = entry.as_mapping() else {
+                continue;
+            };
+            let Some(statblock) = overrides.get("ose") else {
+                continue;
+            };
+            let name = overrides.get("name").and_then(Value::as_str);
+            let converted = statblock
+                .as_str()
+                .ok_or_else(|| "ose must be a statblock line".to_string())
+                .and_then(|statblock| import_ose(statblock, name))
+                .and_then(|imported| template_value(&imported));
+            match converted {
+                Ok(Value::Mapping(mut template)) => {
+                    for (key, value) in overrides {
+                        if key.as_str() != Some("ose") {
+                            template.insert(key.clone(), value.clone());
+                        }
+                    }
+                    *entry = Value::Mapping(template);
+                }
+                Ok(_) => {}
+                Err(message) => errors.push(import_error(format!("{}[{}].ose", side, i), message)),
+            }
+        }
+    }
+}
+
+fn template_value(imported: &Imported) -> Result<Value, String> {
+    serde_yaml::to_value(&imported.template).map_err(|e| e.to_string())
+}
+
+fn import_error(path: String, message: String) -> ValidationError {
+    ValidationError::new(path, ErrorCode::ParseError, message)
+}
+
+/// Expand side entries (and the statblocks they summon or leave behind) that `extends:` a statblock from the encounter's
+/// `templates:` section, then remove the section. Keys on the entry override the template's;
+/// `adjust:` adds to numeric fields and dice (`adjust: { hp: 10, attack_bonus: 2 }`). Templates
+/// can extend each other. An entry that ends up with a library `ref:` keeps its `adjust` for
+/// `resolve_refs`.
+pub fn resolve_templates(doc: &mut Value) -> Result<(), Vec<ValidationError>> {
+    let templates = match doc.as_mapping_mut().and_then(|m| m.remove("templates")) {
+        None | Some(Value::Null) => Mapping::new(),
+        Some(Value::Mapping(templates)) => templates,
+        Some(_) => {
+            return Err(vec![ValidationError::new(
+                "templates",
+                ErrorCode::ParseError,
+                "templates must map names to statblocks".to_string(),
+            )])
+        }
+    };
+    let mut errors = Vec::new();
+
+    for side in SIDES {
+        let Some(entries) = doc.get_mut(side).and_then(Value::as_sequence_mut) else {
+            continue;
+        };
+        for (i, entry) in entries.iter_mut().enumerate() {
+            expand_entry(entry, &templates, &format!("{}[{}]", side, i), &[], &mut errors);
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+/// Expand a side entry in place, then the statblocks inside it (`summons` and `on_death`) the
+/// same way. `nesting` names the templates whose statblocks this entry sits inside; an entry
+/// that gets its own `summons` or `on_death` from one of them would nest forever.
+fn expand_entry(entry: &mut Value, templates: &Mapping, path: &str, nesting: &[String], errors: &mut Vec<ValidationError>) {
+    let Some(mapping) = entry.as_mapping() else {
+        return;
+    };
+    let original = mapping.clone();
+    if mapping.get("extends").is_some() || mapping.get("adjust").is_some() {
+        match expand(mapping, templates, &mut Vec::new()) {
+            Ok(expanded) => *entry = Value::Mapping(expanded),
+            Err((field, code, message)) => {
+                errors.push(ValidationError::new(format!("{}.{}", path, field), code, message));
+                return;
+            }
+        }
+    }
+    for key in ["summons", "on_death"] {
+        if let Some(name) = supplier(&original, key, templates).filter(|name| nesting.contains(name)) {
+            errors.push(ValidationError::new(
+                format!("{}.extends", path),
+                ErrorCode::UnknownRef,
+                format!("templates summon or leave behind each other in a loop: {} -> {}", nesting.join(" -> "), name),
+            ));
+            return;
+        }
+    }
+    let inside = |key: &str| {
+        let mut nesting = nesting.to_vec();
+        nesting.extend(supplier(&original, key, templates));
+        nesting
+    };
+    if let Some(summons) = entry.get_mut("summons").and_then(Value::as_sequence_mut) {
+        let nesting = inside("summons");
+        for (i, summon) in summons.iter_mut().enumerate() {
+            expand_entry(summon, templates, &format!("{}.summons[{}]", path, i), &nesting, errors);
+        }
+    }
+    if let Some(on_death) = entry.get_mut("on_death").and_then(Value::as_mapping_mut) {
+        let nesting = inside("on_death");
+        for (kind, statblock) in on_death.iter_mut() {
+            let kind = kind.as_str().unwrap_or_default();
+            expand_entry(statblock, templates, &format!("{}.on_death.{}", path, kind), &nesting, errors);
+        }
+    }
+}
+
+/// The template an entry inherits `key` from through its `extends`, or None if the entry sets
+/// it itself or nothing it extends has it
+fn supplier(entry: &Mapping, key: &str, templates: &Mapping) -> Option<String> {
+    if entry.get(key).is_some() {
+        return None;
+    }
+    let mut name = entry.get("extends")?.as_str()?;
+    // Bounded in case of an `extends` loop, which `expand` reports
+    for _ in 0..templates.len() {
+        let template = templates.get(name)?.as_mapping()?;
+        if template.get(key).is_some() {
+            return Some(name.to_string());
+        }
+        name = template.get("extends")?.as_str()?;
+    }
+    None
+}
+
+/// A field of the entry being expanded, and what's wrong with it
+type ExpandError = (String, ErrorCode, String);
+
+/// An entry (or template) with what it `extends` filled in and its `adjust` applied. `chain`
+/// holds the templates being expanded, to catch loops.
+fn expand(entry: &Mapping, templates: &Mapping, chain: &mut Vec<String>) -> Result<Mapping, ExpandError> {
+    let mut expanded = match entry.get("extends") {
+        None => Mapping::new(),
+        Some(Value::String(name)) => template(name, templates, chain)?,
+        Some(_) => return Err(extends_error("extends must be a template name".to_string())),
+    };
+    for (key, value) in entry {
+        if !matches!(key.as_str(), Some("extends" | "adjust")) {
+            expanded.insert(key.clone(), value.clone());
+        }
+    }
+    if let Some(adjustments) = entry.get("adjust") {
+        if expanded.get("ref").is_some() {
+            expanded.insert(Value::from("adjust"), adjustments.clone());
+        } else {
+            adjust(&mut expanded, adjustments)?;
+        }
+    }
+    Ok(expanded)
+}
+
+/// A template fully expanded, named after its key unless it sets a `name`
+fn template(name: &str, templates: &Mapping, chain: &mut Vec<String>) -> Result<Mapping, ExpandError> {
+    if chain.iter().any(|n| n == name) {
+        return Err(extends_error(format!(
+            "templates extend each other in a loop: {} -> {}",
+            chain.join(" -> "),
+            name
+        )));
+    }
+    let Some(Value::Mapping(template)) = templates.get(name) else {
+        return Err(extends_error(format!("no template named '{}'", name)));
+    };
+    chain.push(name.to_string());
+    let mut expanded = expand(template, templates, chain)?;
+    chain.pop();
+    if expanded.get("name").is_none() {
+        expanded.insert(Value::from("name"), Value::from(name));
+    }
+    Ok(expanded)
+}
+
+fn extends_error(message: String) -> ExpandError {
+    ("extends".to_string(), ErrorCode::UnknownRef, message)
+}
+
+/// Add each `adjust` amount to the field of the same name: a number gains it (a missing field
+/// counts as 0), a dice expression gets it as an extra term
+fn adjust(entry: &mut Mapping, adjustments: &Value) -> Result<(), ExpandError> {
+    let Some(adjustments) = adjustments.as_mapping() else {
+        return Err(("adjust".to_string(), ErrorCode::ParseError, "adjust must map fields to amounts".to_string()));
+    };
+    for (key, amount) in adjustments {
+        let field = key.as_str().unwrap_or_default();
+        let path = format!("adjust.{}", field);
+        let Some(amount) = amount.as_i64() else {
+            return Err((path, ErrorCode::ParseError, "adjustments must be whole numbers".to_string()));
+        };
+        let adjusted = match entry.get(key) {
+            Some(Value::Number(n)) if n.is_i64() => Value::from(n.as_i64().unwrap_or_default().saturating_add(amount)),
+            Some(Value::String(dice)) if parse_damage_dice(dice).is_ok() => Value::from(format!("{}{:+}", dice, amount)),
+            Some(_) => {
+                return Err((path, ErrorCode::UnexpectedField, format!("{} isn't a number or dice, so it can't be adjusted", field)))
+            }
+            None => Value::from(amount),
+        };
+        entry.insert(key.clone(), adjusted);
+    }
+    Ok(())
+}
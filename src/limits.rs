@@ -0,0 +1,82 @@
+//! Server-side caps that keep one oversized request from pinning the whole server. Each one
+//! can be set in the server config (see `config.rs`).
+
+use std::time::Duration;
+
+use tunnel_fight::apl::{self, ErrorCode, ValidationError};
+use tunnel_fight::simulation;
+use tunnel_fight::types::Encounter;
+
+#[derive(Debug, Clone)]
+pub struct Limits {
+    /// Most iterations one run may ask for (`MAX_ITERATIONS`)
+    pub max_iterations: u32,
+    /// Longest round limit one combat may run to (`MAX_ROUNDS`)
+    pub max_rounds: u32,
+    /// Most actors an encounter may field across all sides, summons included (`MAX_ACTORS`)
+    pub max_actors: usize,
+    /// Largest request body accepted, in bytes (`MAX_BODY_BYTES`)
+    pub max_body_bytes: usize,
+    /// Wall-clock time one request may spend simulating (`SIMULATION_TIME_LIMIT_SECS`)
+    pub time_budget: Duration,
+    /// Time after which any request is abandoned with a 408 (`REQUEST_TIMEOUT_SECS`)
+    pub request_timeout: Duration,
+    /// Simulations run at once; requests beyond that get a 429 (`MAX_CONCURRENT_SIMULATIONS`)
+    pub max_concurrent_simulations: usize,
+    /// `/interactive` sessions open at once; sessions beyond that get a 429
+    /// (`MAX_INTERACTIVE_SESSIONS`)
+    pub max_interactive_sessions: usize,
+    /// Worker threads each simulation spreads its combats over (`SIMULATION_THREADS`)
+    pub threads: usize,
+    /// Most sample combat logs one run returns; asking for more gets this many (`MAX_SAMPLE_COUNT`)
+    pub max_sample_count: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            max_iterations: apl::MAX_ITERATIONS,
+            max_rounds: apl::MAX_ROUNDS,
+            max_actors: 500,
+            max_body_bytes: 2 << 20,
+            time_budget: Duration::from_secs(30),
+            request_timeout: Duration::from_secs(60),
+            max_concurrent_simulations: 4,
+            max_interactive_sessions: 16,
+            threads: simulation::default_threads(),
+            max_sample_count: 100,
+        }
+    }
+}
+
+impl Limits {
+    /// Check a run's size against the caps before starting it. `max_rounds` is the request's
+    /// override of the encounter's round limit, if any; both are held to the cap.
+    pub fn check(&self, encounter: &Encounter, iterations: u32, max_rounds: Option<u32>) -> Result<(), ValidationError> {
+        if iterations > self.max_iterations {
+            return Err(ValidationError::new(
+                "iterations",
+                ErrorCode::TooManyIterations,
+                format!("{} iterations exceeds this server's limit of {}", iterations, self.max_iterations),
+            ));
+        }
+        if let Some(rounds) = [Some(encounter.max_rounds), max_rounds].into_iter().flatten().find(|&r| r > self.max_rounds) {
+            return Err(ValidationError::new(
+                "max_rounds",
+                ErrorCode::TooManyRounds,
+                format!("max_rounds of {} exceeds this server's limit of {}", rounds, self.max_rounds),
+            ));
+        }
+        let actors = encounter
+            .sides()
+            .fold(0usize, |total, side| total.saturating_add(encounter.max_actor_count(side)));
+        if actors > self.max_actors {
+            return Err(ValidationError::new(
+                "",
+                ErrorCode::TooManyActors,
+                format!("{} actors exceeds this server's limit of {}", actors, self.max_actors),
+            ));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,184 @@
+//! Tactical lint: encounters that validate but probably don't play the way their author meant,
+//! caught before a long run is spent on them.
+
+use std::collections::BTreeMap;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::types::{ActorTemplate, AplEntry, Encounter, Side, Slot, WeaponRange, Zone, ZoneLayout};
+
+/// Machine-readable kind of a lint warning
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LintCode {
+    /// A ranged actor starts in a melee zone with no way to back off
+    StrandedRanged,
+    /// An actor's APL never reaches an attack
+    NeverAttacks,
+    /// More frontage starts in a zone than the zone holds
+    OverfilledZone,
+    /// An all-melee side faces an all-ranged side that backs away at least as fast
+    Kiting,
+}
+
+/// Something that will likely make the encounter play differently than intended
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct LintWarning {
+    /// Location in the encounter, e.g. `side1[0].apl`
+    pub path: String,
+    pub code: LintCode,
+    pub message: String,
+    /// 1-based line in the source YAML, when it can be located
+    pub line: Option<usize>,
+}
+
+impl LintWarning {
+    fn new(path: impl Into<String>, code: LintCode, message: String) -> Self {
+        LintWarning {
+            path: path.into(),
+            code,
+            message,
+            line: None,
+        }
+    }
+}
+
+/// Actions that claim an actor's attack for the turn whenever their entry applies
+const ATTACK_BLOCKERS: &[&str] = &["guard", "defend", "dodge", "hold", "flee", "dash"];
+
+fn is_action(entry: &AplEntry, action: &str) -> bool {
+    entry.ready.is_none() && entry.action.eq_ignore_ascii_case(action)
+}
+
+/// Whether the entry moves the actor away from the enemy
+fn backs_off(entry: &AplEntry) -> bool {
+    let backward = entry.target.as_deref().is_some_and(|t| t.eq_ignore_ascii_case("backward"));
+    is_action(entry, "flee") || is_action(entry, "withdraw") || ((is_action(entry, "move") || is_action(entry, "dash")) && backward)
+}
+
+/// Check an encounter that has already passed validation
+pub fn lint(encounter: &Encounter) -> Vec<LintWarning> {
+    let zones = encounter.zone_layout();
+    let mut warnings = Vec::new();
+    for side in encounter.sides() {
+        for (t, template) in encounter.templates(side).iter().enumerate() {
+            let path = format!("{}[{}]", side.path(), t);
+            if let Some(warning) = stranded_ranged(template, side, &zones, &path) {
+                warnings.push(warning);
+            }
+            if let Some(warning) = never_attacks(template, &path) {
+                warnings.push(warning);
+            }
+        }
+        overfilled_zones(encounter, side, &zones, &mut warnings);
+    }
+    for side in encounter.sides() {
+        if let Some(warning) = kiting(encounter, side) {
+            warnings.push(warning);
+        }
+    }
+    warnings
+}
+
+/// Ranged weapons can't hit adjacent enemies, so a ranged actor at the front needs an APL that
+/// backs away. (Speed 0 is rejected by validation; an APL with no way back plays the same.)
+fn stranded_ranged(template: &ActorTemplate, side: Side, zones: &ZoneLayout, path: &str) -> Option<LintWarning> {
+    let start = zones.start_zone(side, &template.start_zone)?;
+    if template.range != WeaponRange::Ranged || !zones.is_melee(start) || template.apl.iter().any(backs_off) {
+        return None;
+    }
+    Some(LintWarning::new(
+        format!("{}.start_zone", path),
+        LintCode::StrandedRanged,
+        format!(
+            "{} has a ranged weapon but starts in the melee zone and never moves backward or flees, so it can't shoot enemies that reach it",
+            template.name
+        ),
+    ))
+}
+
+/// An APL without an attack, or whose attacks sit behind an entry that always claims the attack
+fn never_attacks(template: &ActorTemplate, path: &str) -> Option<LintWarning> {
+    // The default APL attacks
+    if template.apl.is_empty() || template.apl.iter().any(|e| e.ready.as_deref().is_some_and(|r| r.eq_ignore_ascii_case("attack"))) {
+        return None;
+    }
+    let first_attack = template.apl.iter().position(|e| is_action(e, "attack"));
+    let blocker = template.apl.iter().enumerate().find(|(_, e)| {
+        let spends_action = !e.after_action && e.uses.is_none_or(|slot| slot == Slot::Action);
+        e.condition.is_none() && e.phase.is_none() && spends_action && ATTACK_BLOCKERS.iter().any(|action| is_action(e, action))
+    });
+    let message = match (first_attack, blocker) {
+        (None, _) => format!("{}'s APL has no attack entry, so it never deals damage", template.name),
+        (Some(attack), Some((i, entry))) if i < attack => format!(
+            "{}'s `{}` at apl[{}] has no condition, so the attack entries after it never run",
+            template.name, entry.action, i
+        ),
+        _ => return None,
+    };
+    Some(LintWarning::new(format!("{}.apl", path), LintCode::NeverAttacks, message))
+}
+
+/// The simulator places actors in their start zones whatever the zone's capacity
+fn overfilled_zones(encounter: &Encounter, side: Side, zones: &ZoneLayout, warnings: &mut Vec<LintWarning>) {
+    // Zone -> (first template starting there, frontage starting there)
+    let mut starts: BTreeMap<usize, (usize, u32)> = BTreeMap::new();
+    for (t, template) in encounter.templates(side).iter().enumerate() {
+        let Some(zone) = zones.start_zone(side, &template.start_zone) else {
+            continue;
+        };
+        let space = template.frontage.saturating_mul(template.size.bodies()).saturating_mul(template.count);
+        let entry = starts.entry(zone.index()).or_insert((t, 0));
+        entry.1 = entry.1.saturating_add(space);
+    }
+    for (zone, (t, space)) in starts {
+        let zone = Zone(zone);
+        let Some(capacity) = zones.capacity(zone) else {
+            continue;
+        };
+        if space > capacity {
+            warnings.push(LintWarning::new(
+                format!("{}[{}].start_zone", side.path(), t),
+                LintCode::OverfilledZone,
+                format!(
+                    "{} frontage of {} starts in {}, which holds {}; the extra actors start there anyway and block each other",
+                    space,
+                    side,
+                    zones.name(zone),
+                    capacity
+                ),
+            ));
+        }
+    }
+}
+
+/// `side` has only melee and reach weapons, and the sides it fights only ranged ones and an APL
+/// that backs away, at least as fast as `side` can follow
+fn kiting(encounter: &Encounter, side: Side) -> Option<LintWarning> {
+    let chasers = encounter.templates(side);
+    let kiters: Vec<&ActorTemplate> = encounter.enemy_templates(side).collect();
+    if chasers.iter().any(|t| t.range == WeaponRange::Ranged)
+        || !kiters.iter().all(|t| t.range == WeaponRange::Ranged && t.apl.iter().any(backs_off))
+    {
+        return None;
+    }
+    let fastest_chaser = chasers.iter().map(|t| t.speed).max()?;
+    let slowest_kiter = kiters.iter().map(|t| t.speed).min()?;
+    if fastest_chaser > slowest_kiter {
+        return None;
+    }
+    let hostile = encounter.hostile_to(side);
+    let enemies: Vec<String> = encounter.sides().filter(|&other| hostile.contains(other)).map(|other| other.to_string()).collect();
+    Some(LintWarning::new(
+        side.path(),
+        LintCode::Kiting,
+        format!(
+            "{} fights only in melee against an all-ranged {} that backs away at speed {} or more, while its own fastest actor has speed {}; expect long fights and round-limit draws",
+            side,
+            enemies.join(" and "),
+            slowest_kiter,
+            fastest_chaser
+        ),
+    ))
+}
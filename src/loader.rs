@@ -0,0 +1,358 @@
+use std::path::Path;
+
+use serde::{Deserialize, Deserializer};
+use utoipa::{PartialSchema, ToSchema};
+
+use crate::apl::{self, ErrorCode, ValidationError};
+use crate::campaign::{Campaign, CampaignStep, Rest};
+use crate::dice::parse_damage_dice;
+use crate::library::{self, MonsterSource};
+use crate::types::{Encounter, HpValue};
+
+/// Text formats an encounter or campaign can be written in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum SourceFormat {
+    Yaml,
+    Json,
+    Toml,
+}
+
+impl SourceFormat {
+    /// The format a file's extension declares, if it declares one
+    pub fn from_path(path: &Path) -> Option<SourceFormat> {
+        match path.extension()?.to_str()?.to_ascii_lowercase().as_str() {
+            "yaml" | "yml" => Some(SourceFormat::Yaml),
+            "json" => Some(SourceFormat::Json),
+            "toml" => Some(SourceFormat::Toml),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the text: a JSON object opens with `{"` (or is `{}`), and TOML's
+    /// first line is a `[table]` header or a `key = value` pair. Anything else is YAML.
+    pub fn detect(text: &str) -> SourceFormat {
+        let trimmed = text.trim_start();
+        if let Some(rest) = trimmed.strip_prefix('{') {
+            if rest.trim_start().starts_with(['"', '}']) {
+                return SourceFormat::Json;
+            }
+        }
+        let first_line = text.lines().map(str::trim).find(|l| !l.is_empty() && !l.starts_with('#'));
+        let is_toml = first_line.is_some_and(|line| {
+            line.starts_with('[')
+                || line.split_once('=').is_some_and(|(key, _)| {
+                    let key = key.trim();
+                    !key.is_empty() && key.chars().all(|c| c.is_ascii_alphanumeric() || "_-.\"' ".contains(c))
+                })
+        });
+        if is_toml {
+            SourceFormat::Toml
+        } else {
+            SourceFormat::Yaml
+        }
+    }
+
+    fn label(self) -> &'static str {
+        match self {
+            SourceFormat::Yaml => "YAML",
+            SourceFormat::Json => "JSON",
+            SourceFormat::Toml => "TOML",
+        }
+    }
+}
+
+/// Parse a document in `format` (detected from the text when `None`) into a YAML value,
+/// whatever it was written in. Syntax errors carry the line they were found on.
+pub fn parse_document(text: &str, format: Option<SourceFormat>) -> Result<(serde_yaml::Value, SourceFormat), Vec<ValidationError>> {
+    let format = format.unwrap_or_else(|| SourceFormat::detect(text));
+    let syntax_error = |line: Option<usize>, message: String| {
+        vec![ValidationError {
+            line,
+            ..ValidationError::new("", ErrorCode::ParseError, format!("Invalid {}: {}", format.label(), message))
+        }]
+    };
+    let doc = match format {
+        SourceFormat::Yaml => serde_yaml::from_str(text).map_err(yaml_syntax_error)?,
+        SourceFormat::Json => {
+            let json: serde_json::Value =
+                serde_json::from_str(text).map_err(|e| syntax_error(Some(e.line()).filter(|&l| l > 0), e.to_string()))?;
+            serde_yaml::to_value(json).map_err(|e| syntax_error(None, e.to_string()))?
+        }
+        SourceFormat::Toml => {
+            let toml: toml::Value = toml::from_str(text).map_err(|e| {
+                let line = e.span().map(|span| text[..span.start].matches('\n').count() + 1);
+                syntax_error(line, e.message().trim().replace('\n', ": "))
+            })?;
+            serde_yaml::to_value(toml).map_err(|e| syntax_error(None, e.to_string()))?
+        }
+    };
+    Ok((doc, format))
+}
+
+/// JSON Schema (2020-12) for an encounter document, for editors to check and autocomplete
+/// encounter files against. Side entries may instead pull their statblock from a `ref`,
+/// `extends`, or `ose` line, with the rest of their fields optional.
+pub fn encounter_schema() -> serde_json::Value {
+    let mut schemas = vec![("Encounter".to_string(), Encounter::schema())];
+    Encounter::schemas(&mut schemas);
+    let mut defs: serde_json::Map<String, serde_json::Value> = schemas
+        .into_iter()
+        .map(|(name, schema)| (name, serde_json::to_value(schema).unwrap_or_default()))
+        .collect();
+    for def in defs.values_mut() {
+        point_refs_at_defs(def);
+    }
+    if let Some(template) = defs.remove("ActorTemplate") {
+        let filled_in = serde_json::json!({
+            "type": "object",
+            "anyOf": [{ "required": ["ref"] }, { "required": ["extends"] }, { "required": ["ose"] }]
+        });
+        defs.insert("ActorTemplate".to_string(), serde_json::json!({ "anyOf": [template, filled_in] }));
+    }
+    if let Some(properties) = defs.get_mut("Encounter").and_then(|e| e.get_mut("properties")).and_then(|p| p.as_object_mut()) {
+        properties.insert(
+            "templates".to_string(),
+            serde_json::json!({ "type": "object", "description": "Named statblocks for side entries to `extends`" }),
+        );
+        properties.insert(
+            "import".to_string(),
+            serde_json::json!({ "type": "object", "description": "Statblocks to convert by format, e.g. `ose`" }),
+        );
+    }
+    serde_json::json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "title": "Tunnel Fight encounter",
+        "$ref": "#/$defs/Encounter",
+        "$defs": defs,
+    })
+}
+
+/// Rewrite OpenAPI component refs (`#/components/schemas/X`) as JSON Schema ones (`#/$defs/X`)
+fn point_refs_at_defs(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::Object(map) => {
+            for (key, value) in map.iter_mut() {
+                match value {
+                    serde_json::Value::String(target) if key == "$ref" => {
+                        if let Some(name) = target.strip_prefix("#/components/schemas/") {
+                            *target = format!("#/$defs/{}", name);
+                        }
+                    }
+                    value => point_refs_at_defs(value),
+                }
+            }
+        }
+        serde_json::Value::Array(items) => items.iter_mut().for_each(point_refs_at_defs),
+        _ => {}
+    }
+}
+
+/// Parse an encounter (YAML, JSON, or TOML, detected from the text), resolve monster library
+/// refs, and validate its APLs, attaching source line numbers to any errors
+pub fn parse_encounter(text: &str, storage: &impl MonsterSource) -> Result<Encounter, Vec<ValidationError>> {
+    parse_encounter_as(text, None, storage)
+}
+
+/// `parse_encounter` for text in a declared format. Only YAML errors past the syntax check
+/// carry line numbers.
+pub fn parse_encounter_as(
+    text: &str,
+    format: Option<SourceFormat>,
+    storage: &impl MonsterSource,
+) -> Result<Encounter, Vec<ValidationError>> {
+    let (doc, format) = parse_document(text, format)?;
+    build_encounter(doc, (format == SourceFormat::Yaml).then_some(text), storage)
+}
+
+fn yaml_syntax_error(e: serde_yaml::Error) -> Vec<ValidationError> {
+    vec![ValidationError {
+        line: e.location().map(|l| l.line()),
+        ..ValidationError::new("", ErrorCode::ParseError, format!("Invalid YAML: {}", e))
+    }]
+}
+
+/// Deserialize a document, reporting a value that doesn't fit at the field it's in, e.g.
+/// `side2[3].damage`. `source` is the YAML text it came from, if any, for the error's line.
+fn deserialize<'de, T: Deserialize<'de>>(
+    de: impl Deserializer<'de, Error = serde_yaml::Error>,
+    source: Option<&str>,
+    what: &str,
+) -> Result<T, Vec<ValidationError>> {
+    serde_path_to_error::deserialize(de).map_err(|e| {
+        let path = match e.path().to_string() {
+            root if root == "." => String::new(),
+            path => path,
+        };
+        // serde_yaml marks errors a Deserialize impl raises at the enclosing mapping, so find
+        // the field itself when there's text to look in
+        let line = source
+            .filter(|_| !path.is_empty())
+            .and_then(|yaml| apl::locate_line(yaml, &path))
+            .or_else(|| e.inner().location().map(|l| l.line()));
+        let message = bare_message(e.inner(), &path);
+        let message = match path.is_empty() {
+            true => format!("Invalid {}: {}", what, message),
+            false => message,
+        };
+        vec![ValidationError {
+            line,
+            ..ValidationError::new(path, ErrorCode::ParseError, message)
+        }]
+    })
+}
+
+/// serde_yaml's message without the location it appends, or the path it puts in front (at
+/// best a shorter version of `path`)
+fn bare_message(e: &serde_yaml::Error, path: &str) -> String {
+    let mut message = e.to_string();
+    if e.location().is_some() {
+        if let Some(at) = message.rfind(" at line ") {
+            message.truncate(at);
+        }
+    }
+    match message.split_once(": ") {
+        Some((prefix, rest)) if !prefix.is_empty() && path.starts_with(prefix) => rest.to_string(),
+        _ => message,
+    }
+}
+
+/// Turn a parsed encounter document into an `Encounter`. `source` is the YAML text it came
+/// from, if any, used to attach line numbers to errors.
+pub fn build_encounter(
+    mut doc: serde_yaml::Value,
+    source: Option<&str>,
+    storage: &impl MonsterSource,
+) -> Result<Encounter, Vec<ValidationError>> {
+    let locate = |mut errors: Vec<ValidationError>| {
+        if let Some(yaml) = source {
+            for error in &mut errors {
+                error.line = apl::locate_line(yaml, &error.path);
+            }
+        }
+        errors
+    };
+
+    let encounter: Encounter = match source {
+        // Parse from the source text so scalars read as YAML reads them (`damage: 1` as text)
+        Some(yaml) if !library::has_refs(&doc) && !library::has_templates(&doc) => {
+            deserialize(serde_yaml::Deserializer::from_str(yaml), source, "encounter")?
+        }
+        _ => {
+            library::resolve_imports(&mut doc).map_err(locate)?;
+            library::resolve_templates(&mut doc).map_err(locate)?;
+            library::resolve_refs(&mut doc, storage).map_err(locate)?;
+            deserialize(doc, source, "encounter")?
+        }
+    };
+
+    let errors = apl::validate(&encounter);
+    if errors.is_empty() {
+        Ok(encounter)
+    } else {
+        Err(locate(errors))
+    }
+}
+
+/// Parse a campaign (YAML, JSON, or TOML, detected from the text): its encounters are built
+/// and validated as `parse_encounter` does, with error paths under `steps[i].encounter`
+pub fn parse_campaign(text: &str, storage: &impl MonsterSource) -> Result<Campaign, Vec<ValidationError>> {
+    parse_campaign_as(text, None, storage)
+}
+
+/// `parse_campaign` for text in a declared format
+pub fn parse_campaign_as(
+    text: &str,
+    format: Option<SourceFormat>,
+    storage: &impl MonsterSource,
+) -> Result<Campaign, Vec<ValidationError>> {
+    let (doc, format) = parse_document(text, format)?;
+    build_campaign(doc, (format == SourceFormat::Yaml).then_some(text), storage)
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CampaignDoc {
+    name: Option<String>,
+    iterations: Option<u32>,
+    steps: Vec<StepDoc>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StepDoc {
+    encounter: Option<serde_yaml::Value>,
+    rest: Option<Rest>,
+}
+
+/// Turn a parsed campaign document into a `Campaign`. `source` is the YAML text it came from,
+/// if any, used to attach line numbers to errors.
+pub fn build_campaign(
+    doc: serde_yaml::Value,
+    source: Option<&str>,
+    storage: &impl MonsterSource,
+) -> Result<Campaign, Vec<ValidationError>> {
+    let doc: CampaignDoc = match source {
+        Some(yaml) => deserialize(serde_yaml::Deserializer::from_str(yaml), source, "campaign")?,
+        None => deserialize(doc, None, "campaign")?,
+    };
+
+    let mut errors = Vec::new();
+    let mut steps = Vec::with_capacity(doc.steps.len());
+    for (i, step) in doc.steps.into_iter().enumerate() {
+        let path = format!("steps[{}]", i);
+        match (step.encounter, step.rest) {
+            (Some(encounter), None) => match build_encounter(encounter, None, storage) {
+                Ok(encounter) => steps.push(CampaignStep::Encounter(Box::new(encounter))),
+                Err(e) => errors.extend(e.into_iter().map(|e| {
+                    let path = format!("{}.encounter{}{}", path, if e.path.is_empty() { "" } else { "." }, e.path);
+                    ValidationError {
+                        line: source.and_then(|yaml| apl::locate_line(yaml, &path)),
+                        path,
+                        ..e
+                    }
+                })),
+            },
+            (None, Some(rest)) => {
+                if let Rest::Heal { heal: HpValue::Dice(dice) } = &rest {
+                    if let Err(e) = parse_damage_dice(dice) {
+                        errors.push(ValidationError::new(
+                            format!("{}.rest.heal", path),
+                            ErrorCode::InvalidDice,
+                            format!("invalid dice '{}': {}", dice, e),
+                        ));
+                    }
+                }
+                steps.push(CampaignStep::Rest(rest));
+            }
+            (Some(_), Some(_)) => errors.push(ValidationError::new(
+                path,
+                ErrorCode::UnexpectedField,
+                "a step is either an encounter or a rest, not both".to_string(),
+            )),
+            (None, None) => errors.push(ValidationError::new(
+                path,
+                ErrorCode::MissingField,
+                "a step needs an encounter or a rest".to_string(),
+            )),
+        }
+    }
+    if errors.is_empty() && !steps.iter().any(|step| matches!(step, CampaignStep::Encounter(_))) {
+        errors.push(ValidationError::new("steps", ErrorCode::MissingField, "a campaign needs at least one encounter".to_string()));
+    }
+
+    if let Some(yaml) = source {
+        for error in errors.iter_mut().filter(|e| e.line.is_none()) {
+            error.line = apl::locate_line(yaml, &error.path);
+        }
+    }
+    if errors.is_empty() {
+        Ok(Campaign {
+            name: doc.name,
+            iterations: doc.iterations,
+            steps,
+        })
+    } else {
+        Err(errors)
+    }
+}
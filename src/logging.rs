@@ -0,0 +1,35 @@
+//! Server logs: `tracing_subscriber`'s formatter writing one line per event to stderr, prefixed
+//! with the fields of every span it happened in, plus tower-http layers giving each request an
+//! id and a span.
+//!
+//! ```text
+//! 2026-10-17T12:00:00.123456Z  INFO request{id=6f1c2b9e-4d0a-4a5b-9a3e-2f8c1d7b5e40 method=POST path=/simulate}:simulation{encounter="Orc ambush" iterations=30000 seed=42}: simulation finished duration_ms=812
+//! ```
+
+use axum::{extract::Request, Router};
+use tower_http::request_id::{MakeRequestUuid, PropagateRequestIdLayer, RequestId, SetRequestIdLayer};
+use tower_http::trace::{DefaultOnResponse, TraceLayer};
+use tracing::{Level, Span};
+use tracing_subscriber::filter::{EnvFilter, LevelFilter};
+
+/// Install the subscriber for the whole process, writing events up to `max_level` (the
+/// `LOG_LEVEL` setting). `RUST_LOG`, when set, replaces that with its own directives.
+pub fn init(max_level: Level) {
+    let filter = EnvFilter::builder().with_default_directive(LevelFilter::from_level(max_level).into()).from_env_lossy();
+    tracing_subscriber::fmt().with_env_filter(filter).with_target(false).with_writer(std::io::stderr).init();
+}
+
+/// Give each request an id (the client's `x-request-id`, or a fresh UUID), run it inside a
+/// `request` span, log its status and latency, and echo the id on the response
+pub fn trace_requests<S: Clone + Send + Sync + 'static>(router: Router<S>) -> Router<S> {
+    // The last layer added runs first
+    router
+        .layer(PropagateRequestIdLayer::x_request_id())
+        .layer(TraceLayer::new_for_http().make_span_with(request_span).on_response(DefaultOnResponse::new().level(Level::INFO)))
+        .layer(SetRequestIdLayer::x_request_id(MakeRequestUuid))
+}
+
+fn request_span(request: &Request) -> Span {
+    let id = request.extensions().get::<RequestId>().and_then(|id| id.header_value().to_str().ok()).unwrap_or_default();
+    tracing::info_span!("request", id = %id, method = %request.method(), path = %request.uri().path())
+}
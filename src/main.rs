@@ -1,24 +1,73 @@
 mod api;
-mod apl;
-mod combat;
-mod stats;
-mod types;
+mod auth;
+mod cache;
+mod cli;
+mod config;
+mod limits;
+mod logging;
 
 use std::env;
 use std::net::SocketAddr;
+use std::path::Path;
+use std::process::ExitCode;
+use std::sync::Arc;
+
+use clap::Parser;
+
+use tunnel_fight::storage::Storage;
+
+use api::AppState;
+use cache::ResultCache;
+use cli::{Cli, Command};
+use config::Config;
 
 #[tokio::main]
-async fn main() {
-    let app = api::create_router();
+async fn main() -> ExitCode {
+    // The CLI doesn't read the server config, only this one variable
+    let db_path = env::var("DATABASE_PATH").unwrap_or_else(|_| config::DEFAULT_DATABASE_PATH.to_string());
+
+    match Cli::parse().command {
+        None | Some(Command::Serve) => serve().await,
+        Some(Command::Simulate(args)) => match cli::simulate(&args, Path::new(&db_path)) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(message) => {
+                eprintln!("{}", message);
+                ExitCode::FAILURE
+            }
+        },
+        Some(Command::Campaign(args)) => match cli::campaign(&args, Path::new(&db_path)) {
+            Ok(()) => ExitCode::SUCCESS,
+            Err(message) => {
+                eprintln!("{}", message);
+                ExitCode::FAILURE
+            }
+        },
+    }
+}
 
-    let port: u16 = env::var("PORT")
-        .unwrap_or_else(|_| "3000".to_string())
-        .parse()
-        .expect("PORT must be a number");
+async fn serve() -> ExitCode {
+    let config = match Config::load() {
+        Ok(config) => config,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+    logging::init(config.log_level);
+    let storage = match Storage::open(&config.database_path) {
+        Ok(storage) => storage,
+        Err(e) => {
+            eprintln!("Can't open {}: {}", config.database_path, e);
+            return ExitCode::FAILURE;
+        }
+    };
+    let state = AppState::new(storage, config.limits, ResultCache::new(config.result_cache_size));
+    let app = api::create_router(Arc::new(state), config.cors_origins, config.access);
 
-    let addr = SocketAddr::from(([0, 0, 0, 0], port));
-    println!("Tunnel Fight server running on http://{}", addr);
+    tracing::info!("Tunnel Fight server running on http://{}", config.addr);
 
-    let listener = tokio::net::TcpListener::bind(addr).await.unwrap();
-    axum::serve(listener, app).await.unwrap();
+    let listener = tokio::net::TcpListener::bind(config.addr).await.unwrap();
+    // Peer addresses tell clients apart for rate limiting when there are no API keys
+    axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>()).await.unwrap();
+    ExitCode::SUCCESS
 }
@@ -0,0 +1,142 @@
+//! The attack modifier stack: every bonus and penalty on an attack roll or the AC it has to
+//! beat, and whatever gave it advantage or disadvantage, each with its source. The simulator
+//! builds one per attack (`CombatSimulator::attack_modifiers`), rolls against its totals, and
+//! logs it on the attack event, so new mechanics only have to add their entry here.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+/// Where a situational bonus or penalty came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ModifierSource {
+    /// The target is guarding or defending
+    Stance,
+    /// Allied and enemy auras in range
+    Aura,
+    /// Aiming at a hit location
+    CalledShot,
+    /// The target's zone gives cover against ranged attacks
+    Cover,
+    /// The shooter's allies stand between it and the target (`rules.line_of_fire: penalty`)
+    AlliesInTheWay,
+}
+
+/// One entry in a stack
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+pub struct Modifier {
+    pub source: ModifierSource,
+    pub value: i32,
+}
+
+/// What gave an attack advantage or disadvantage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum EdgeSource {
+    /// The target is dodging
+    Dodge,
+    /// A ranged or reach attack into dim light
+    DimLight,
+    /// The attacker is held in a grapple
+    Grappled,
+    Poisoned,
+    /// A melee attack on a prone target
+    Prone,
+    /// The target is stunned
+    Stunned,
+    PackTactics,
+    SunlightSensitivity,
+}
+
+/// Everything that went into one attack roll: the attacker's bonus and the target's AC, plus
+/// whatever the situation added to either
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+pub struct AttackModifiers {
+    /// The attacker's own `attack_bonus`
+    pub attack_bonus: i32,
+    /// The target's own AC
+    pub armor: i32,
+    /// Added to the d20 on top of `attack_bonus`
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub roll: Vec<Modifier>,
+    /// Added to `armor` for the AC to beat
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub ac: Vec<Modifier>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub advantage: Vec<EdgeSource>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub disadvantage: Vec<EdgeSource>,
+}
+
+impl AttackModifiers {
+    /// An attack with nothing situational about it yet
+    pub fn new(attack_bonus: i32, armor: i32) -> Self {
+        AttackModifiers { attack_bonus, armor, ..Default::default() }
+    }
+
+    /// Add to the roll (zeros are left out)
+    pub fn add_roll(&mut self, source: ModifierSource, value: i32) {
+        if value != 0 {
+            self.roll.push(Modifier { source, value });
+        }
+    }
+
+    /// Add to the AC to beat (zeros are left out)
+    pub fn add_ac(&mut self, source: ModifierSource, value: i32) {
+        if value != 0 {
+            self.ac.push(Modifier { source, value });
+        }
+    }
+
+    /// Grant advantage (or, with `advantage` false, disadvantage)
+    pub fn add_edge(&mut self, source: EdgeSource, advantage: bool) {
+        if advantage {
+            self.advantage.push(source);
+        } else {
+            self.disadvantage.push(source);
+        }
+    }
+
+    /// Total added to the d20
+    pub fn roll_bonus(&self) -> i32 {
+        self.attack_bonus + self.roll.iter().map(|m| m.value).sum::<i32>()
+    }
+
+    /// The AC the roll has to meet
+    pub fn target_ac(&self) -> i32 {
+        self.armor + self.ac.iter().map(|m| m.value).sum::<i32>()
+    }
+
+    /// Whether the attack rolls with advantage and with disadvantage, after the two cancel out
+    pub fn edge(&self) -> (bool, bool) {
+        let (advantage, disadvantage) = (!self.advantage.is_empty(), !self.disadvantage.is_empty());
+        (advantage && !disadvantage, disadvantage && !advantage)
+    }
+}
+
+impl std::fmt::Display for ModifierSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            ModifierSource::Stance => "stance",
+            ModifierSource::Aura => "aura",
+            ModifierSource::CalledShot => "called shot",
+            ModifierSource::Cover => "cover",
+            ModifierSource::AlliesInTheWay => "allies in the way",
+        })
+    }
+}
+
+impl std::fmt::Display for EdgeSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str(match self {
+            EdgeSource::Dodge => "dodging",
+            EdgeSource::DimLight => "dim light",
+            EdgeSource::Grappled => "grappled",
+            EdgeSource::Poisoned => "poisoned",
+            EdgeSource::Prone => "prone",
+            EdgeSource::Stunned => "stunned",
+            EdgeSource::PackTactics => "pack tactics",
+            EdgeSource::SunlightSensitivity => "sunlight",
+        })
+    }
+}
@@ -0,0 +1,209 @@
+//! Policy search: run the same encounter once per candidate APL for one actor and rank the
+//! candidates by that actor's side's win rate.
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::apl::{validate_apl, ErrorCode, ValidationError};
+use crate::simulation::{run_variants, SimulationOptions};
+use crate::stats::SimulationStats;
+use crate::types::{AplEntry, Encounter, Side};
+
+/// Most candidates one search will simulate (explicit ones plus the expanded grid)
+pub const MAX_CANDIDATES: usize = 256;
+
+/// An APL to try for the optimized actor
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Candidate {
+    /// Label in the results; defaults to `candidate N`, or the parameter values for grid entries
+    pub name: Option<String>,
+    pub apl: Vec<AplEntry>,
+}
+
+/// An APL template expanded once per combination of parameter values. `{param}` anywhere in a
+/// string is replaced by the value; a string that is only `{param}` becomes the value itself,
+/// so numeric fields like `amount` can be searched too.
+#[derive(Debug, Clone, Deserialize, ToSchema)]
+pub struct ParamGrid {
+    #[schema(value_type = Vec<Object>)]
+    pub apl: Vec<serde_json::Value>,
+    #[schema(value_type = BTreeMap<String, Vec<Object>>)]
+    pub params: BTreeMap<String, Vec<serde_json::Value>>,
+}
+
+/// How one candidate fared
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CandidateResult {
+    pub name: String,
+    pub apl: Vec<AplEntry>,
+    /// Win rate of the optimized actor's side
+    pub win_rate: f64,
+    pub stats: SimulationStats,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct OptimizeResult {
+    /// Master seed shared by every candidate, so they all face the same dice
+    pub seed: u64,
+    pub actor: String,
+    pub side: Side,
+    /// Best first
+    pub candidates: Vec<CandidateResult>,
+}
+
+impl ParamGrid {
+    /// One candidate per combination of parameter values, labelled like `threshold=20`
+    pub fn expand(&self) -> Result<Vec<Candidate>, Vec<ValidationError>> {
+        // Counted with checked arithmetic: a product that wrapped could slip under the limit
+        let combinations = self
+            .params
+            .values()
+            .try_fold(1usize, |total, values| total.checked_mul(values.len()));
+        let too_many = match combinations {
+            Some(n) if n <= MAX_CANDIDATES => None,
+            Some(n) => Some(format!("{} combinations exceeds the limit of {}", n, MAX_CANDIDATES)),
+            None => Some(format!("the grid's combinations exceed the limit of {}", MAX_CANDIDATES)),
+        };
+        if let Some(message) = too_many {
+            return Err(vec![error("grid.params", message)]);
+        }
+
+        let mut bindings: Vec<Vec<(&str, &serde_json::Value)>> = vec![Vec::new()];
+        for (name, values) in &self.params {
+            bindings = bindings
+                .into_iter()
+                .flat_map(|binding| {
+                    values.iter().map(move |value| {
+                        let mut binding = binding.clone();
+                        binding.push((name.as_str(), value));
+                        binding
+                    })
+                })
+                .collect();
+        }
+
+        bindings
+            .into_iter()
+            .map(|binding| {
+                let apl: Vec<serde_json::Value> = self.apl.iter().map(|entry| substitute(entry, &binding)).collect();
+                let apl = serde_json::from_value(serde_json::Value::Array(apl))
+                    .map_err(|e| vec![ValidationError::new("grid.apl", ErrorCode::ParseError, e.to_string())])?;
+                let name = binding
+                    .iter()
+                    .map(|(param, value)| format!("{}={}", param, plain(value)))
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                Ok(Candidate { name: Some(name), apl })
+            })
+            .collect()
+    }
+}
+
+/// Replace `{param}` placeholders throughout a JSON value
+fn substitute(value: &serde_json::Value, binding: &[(&str, &serde_json::Value)]) -> serde_json::Value {
+    match value {
+        serde_json::Value::String(s) => {
+            if let Some((_, bound)) = binding.iter().find(|(param, _)| *s == format!("{{{}}}", param)) {
+                return (*bound).clone();
+            }
+            let mut s = s.clone();
+            for (param, bound) in binding {
+                s = s.replace(&format!("{{{}}}", param), &plain(bound));
+            }
+            serde_json::Value::String(s)
+        }
+        serde_json::Value::Array(items) => {
+            serde_json::Value::Array(items.iter().map(|item| substitute(item, binding)).collect())
+        }
+        serde_json::Value::Object(map) => serde_json::Value::Object(
+            map.iter().map(|(k, v)| (k.clone(), substitute(v, binding))).collect(),
+        ),
+        other => other.clone(),
+    }
+}
+
+/// A parameter value as it reads inside a string (no quotes around strings)
+fn plain(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn error(path: &str, message: String) -> ValidationError {
+    ValidationError::new(path, ErrorCode::InvalidRequest, message)
+}
+
+/// Simulate the encounter once per candidate, with the candidate's APL given to every actor
+/// built from the `actor` template, and rank candidates by that side's win rate. Every
+/// candidate uses the same master seed; candidates run in parallel (see [`run_variants`]).
+pub fn optimize(
+    encounter: &Encounter,
+    actor: &str,
+    candidates: Vec<Candidate>,
+    options: &SimulationOptions,
+) -> Result<OptimizeResult, Vec<ValidationError>> {
+    let side = encounter
+        .sides()
+        .find(|&side| encounter.templates(side).iter().any(|t| t.name == actor))
+        .ok_or_else(|| vec![error("actor", format!("no actor named '{}' in the encounter", actor))])?;
+    if candidates.is_empty() {
+        return Err(vec![error("candidates", "no candidates to try".to_string())]);
+    }
+    if candidates.len() > MAX_CANDIDATES {
+        return Err(vec![error(
+            "candidates",
+            format!("{} candidates exceeds the limit of {}", candidates.len(), MAX_CANDIDATES),
+        )]);
+    }
+    let errors: Vec<ValidationError> = candidates
+        .iter()
+        .enumerate()
+        .flat_map(|(i, candidate)| {
+            validate_apl(&candidate.apl).into_iter().map(move |e| ValidationError {
+                path: format!("candidates[{}].apl{}", i, e.path),
+                ..e
+            })
+        })
+        .collect();
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+
+    let variants: Vec<Encounter> = candidates
+        .iter()
+        .map(|candidate| with_apl(encounter, side, actor, &candidate.apl))
+        .collect();
+    let (seed, stats) = run_variants(&variants, options);
+
+    let mut results: Vec<CandidateResult> = candidates
+        .into_iter()
+        .zip(stats)
+        .enumerate()
+        .map(|(i, (candidate, stats))| CandidateResult {
+            name: candidate.name.unwrap_or_else(|| format!("candidate {}", i + 1)),
+            apl: candidate.apl,
+            win_rate: stats.win_rate(side),
+            stats,
+        })
+        .collect();
+    results.sort_by(|a, b| b.win_rate.total_cmp(&a.win_rate));
+
+    Ok(OptimizeResult {
+        seed,
+        actor: actor.to_string(),
+        side,
+        candidates: results,
+    })
+}
+
+/// The encounter with `apl` given to every template named `actor` on `side`
+fn with_apl(encounter: &Encounter, side: Side, actor: &str, apl: &[AplEntry]) -> Encounter {
+    let mut encounter = encounter.clone();
+    for template in encounter.templates_mut(side).iter_mut().filter(|t| t.name == actor) {
+        template.apl = apl.to_vec();
+    }
+    encounter
+}
@@ -0,0 +1,151 @@
+//! Difficulty rating: a quick, reduced-iteration run mapped onto trivial/easy/medium/hard/deadly
+//! from the party's (side 1's) point of view, with a one-line justification.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::simulation::{run_simulation, SimulationOptions};
+use crate::stats::SimulationStats;
+use crate::types::{Encounter, Side};
+
+/// Iterations for a rating when the request doesn't say; enough to tell the bands apart
+pub const RATING_ITERATIONS: u32 = 2000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Difficulty {
+    Trivial,
+    Easy,
+    Medium,
+    Hard,
+    Deadly,
+}
+
+impl Difficulty {
+    fn label(self) -> &'static str {
+        match self {
+            Difficulty::Trivial => "Trivial",
+            Difficulty::Easy => "Easy",
+            Difficulty::Medium => "Medium",
+            Difficulty::Hard => "Hard",
+            Difficulty::Deadly => "Deadly",
+        }
+    }
+}
+
+/// The lowest band a fight must reach on each measure to rate at least that band. A fight takes
+/// the highest band any measure reaches.
+struct Band {
+    difficulty: Difficulty,
+    /// Percent of fights ending in a party TPK
+    tpk_rate: f64,
+    /// Percent of fights the party doesn't win
+    loss_rate: f64,
+    /// Share of the party killed, in percent
+    casualty_percent: f64,
+    /// Share of the party's HP lost, in percent
+    hp_lost_percent: f64,
+}
+
+const BANDS: [Band; 4] = [
+    Band {
+        difficulty: Difficulty::Deadly,
+        tpk_rate: 25.0,
+        loss_rate: 50.0,
+        casualty_percent: 50.0,
+        hp_lost_percent: 90.0,
+    },
+    Band {
+        difficulty: Difficulty::Hard,
+        tpk_rate: 5.0,
+        loss_rate: 20.0,
+        casualty_percent: 25.0,
+        hp_lost_percent: 60.0,
+    },
+    Band {
+        difficulty: Difficulty::Medium,
+        tpk_rate: 1.0,
+        loss_rate: 5.0,
+        casualty_percent: 5.0,
+        hp_lost_percent: 35.0,
+    },
+    Band {
+        difficulty: Difficulty::Easy,
+        tpk_rate: 0.1,
+        loss_rate: 1.0,
+        casualty_percent: 1.0,
+        hp_lost_percent: 10.0,
+    },
+];
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Rating {
+    pub difficulty: Difficulty,
+    /// Why the encounter got its rating, in a sentence or two
+    pub justification: String,
+    /// Master seed of the run behind the rating
+    pub seed: u64,
+    pub iterations: u32,
+    /// Side 1 (the party)'s numbers the rating is based on
+    pub win_rate: f64,
+    pub tpk_rate: f64,
+    pub avg_casualties: f64,
+    pub avg_hp_lost_percent: f64,
+}
+
+/// Simulate the encounter and rate it for side 1
+pub fn rate(encounter: &Encounter, options: &SimulationOptions) -> Rating {
+    let result = run_simulation(encounter, options);
+    let party_size = encounter.actor_count(Side::SIDE1);
+    let (difficulty, justification) = classify(&result.stats, party_size);
+    Rating {
+        difficulty,
+        justification,
+        seed: result.seed,
+        iterations: result.stats.iterations,
+        win_rate: result.stats.side1_win_rate,
+        tpk_rate: result.stats.side1_tpk_rate,
+        avg_casualties: result.stats.avg_side1_casualties,
+        avg_hp_lost_percent: result.stats.avg_side1_hp_lost_percent,
+    }
+}
+
+/// Rate side 1's results and explain the measure that set the rating
+pub fn classify(stats: &SimulationStats, party_size: usize) -> (Difficulty, String) {
+    let loss_rate = 100.0 - stats.side1_win_rate;
+    let casualty_percent = if party_size == 0 {
+        0.0
+    } else {
+        stats.avg_side1_casualties / party_size as f64 * 100.0
+    };
+    let hp_lost = stats.avg_side1_hp_lost_percent;
+
+    let reached = BANDS.iter().find_map(|band| {
+        let reason = if stats.side1_tpk_rate >= band.tpk_rate {
+            format!("the party is wiped out in {:.1}% of fights", stats.side1_tpk_rate)
+        } else if loss_rate >= band.loss_rate {
+            format!("the party fails to win {:.1}% of fights", loss_rate)
+        } else if casualty_percent >= band.casualty_percent {
+            format!("{:.0}% of the party falls on average", casualty_percent)
+        } else if hp_lost >= band.hp_lost_percent {
+            format!("the party loses {:.0}% of its HP on average", hp_lost)
+        } else {
+            return None;
+        };
+        Some((band.difficulty, reason))
+    });
+    let (difficulty, reason) = reached.unwrap_or_else(|| {
+        (Difficulty::Trivial, "the party wins nearly every fight and loses under 10% of its HP".to_string())
+    });
+
+    let justification = format!(
+        "{}: {}. It wins {:.1}% of fights, losing {:.1} of {} members and {:.0}% of its HP on average.",
+        difficulty.label(),
+        reason,
+        stats.side1_win_rate,
+        stats.avg_side1_casualties,
+        party_size,
+        hp_lost
+    );
+    (difficulty, justification)
+}
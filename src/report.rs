@@ -0,0 +1,476 @@
+use std::fmt::Write as _;
+
+use serde::Deserialize;
+use utoipa::ToSchema;
+
+use crate::combat::EventType;
+use crate::stats::{CombatLog, ConfidenceInterval, KillAttribution, SimulationResult};
+
+/// Shareable document formats for a simulation result
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+/// How many killer/victim pairs to list
+const TOP_MATCHUPS: usize = 5;
+
+pub fn render(format: ReportFormat, name: &str, result: &SimulationResult) -> String {
+    match format {
+        ReportFormat::Markdown => render_markdown(name, result),
+        ReportFormat::Html => render_html(name, result),
+    }
+}
+
+/// `Side1` as `Side 1`
+fn side_label(side: &str) -> String {
+    match side.strip_prefix("Side") {
+        Some(n) if !n.is_empty() && n.bytes().all(|b| b.is_ascii_digit()) => format!("Side {}", n),
+        _ => side.to_string(),
+    }
+}
+
+fn format_ci(ci: &ConfidenceInterval) -> String {
+    format!("{:.1}–{:.1}%", ci.low, ci.high)
+}
+
+/// One-sentence summary of who wins and how long it takes
+fn summary(result: &SimulationResult) -> String {
+    let stats = &result.stats;
+    let rounds = &stats.distributions.rounds_percentiles;
+    let mut favored = ("Side 1".to_string(), stats.side1_win_rate);
+    let others = std::iter::once((2, stats.side2_win_rate))
+        .chain(stats.factions.iter().map(|faction| (faction.side.index() + 1, faction.win_rate)));
+    for (number, rate) in others {
+        if rate > favored.1 {
+            favored = (format!("Side {}", number), rate);
+        }
+    }
+    let (favored, rate) = favored;
+    let mut text = format!(
+        "{} wins {:.1}% of fights, which last {} rounds at the median ({}–{} for the middle 80%).",
+        favored, rate, rounds.p50, rounds.p10, rounds.p90
+    );
+    if stats.mutual_destruction_rate > 0.0 {
+        let _ = write!(
+            text,
+            " {:.1}% end in a draw, {:.1}% with both sides wiped out.",
+            stats.draw_rate, stats.mutual_destruction_rate
+        );
+    } else if stats.draw_rate > 0.0 {
+        let _ = write!(text, " {:.1}% end in a draw.", stats.draw_rate);
+    }
+    if let Some(stalemates) = &stats.stalemates {
+        let reasons = [
+            ("attrition, too slow to finish", stalemates.attrition),
+            ("fighters in reach that never attack", stalemates.passive),
+            ("attacks that can't hit", stalemates.no_hits_possible),
+            ("full zones in the way", stalemates.zone_deadlock),
+            ("sides out of each other's reach", stalemates.out_of_reach),
+        ];
+        let (reason, rate) = reasons.into_iter().fold(reasons[0], |most, next| if next.1 > most.1 { next } else { most });
+        let _ = write!(
+            text,
+            " {:.1}% run out of rounds, most often from {} ({:.1}% of fights).",
+            stats.timeout_rate, reason, rate
+        );
+    }
+    if let Some(rate) = stats.decided_by_round2_rate.filter(|&rate| rate > 0.0) {
+        let _ = write!(
+            text,
+            " A side is broken (under a quarter of its HP) by the end of round 2 in {:.1}% of fights.",
+            rate
+        );
+    }
+    if stats.side1_surprised_rate > 0.0 || stats.side2_surprised_rate > 0.0 {
+        let _ = write!(
+            text,
+            " The side with the surprise round wins {:.1}% of the time.",
+            stats.ambusher_win_rate
+        );
+    }
+    if let Some(civilians) = &stats.civilians {
+        let _ = write!(
+            text,
+            " {:.1}% of the noncombatants die, and all of them make it in {:.1}% of fights.",
+            civilians.casualty_rate, civilians.all_saved_rate
+        );
+    }
+    text
+}
+
+/// Rows of (label, rate, confidence interval) for the outcome table
+fn outcome_rows(result: &SimulationResult) -> Vec<(String, f64, String)> {
+    let stats = &result.stats;
+    let mut rows = vec![
+        ("Side 1 wins".to_string(), stats.side1_win_rate, format_ci(&stats.side1_win_rate_ci)),
+        ("Side 2 wins".to_string(), stats.side2_win_rate, format_ci(&stats.side2_win_rate_ci)),
+    ];
+    for faction in &stats.factions {
+        rows.push((
+            format!("Side {} wins", faction.side.index() + 1),
+            faction.win_rate,
+            format_ci(&faction.win_rate_ci),
+        ));
+    }
+    rows.push(("Draws".to_string(), stats.draw_rate, format_ci(&stats.draw_rate_ci)));
+    rows
+}
+
+/// Rows of (label, side 1, side 2) comparing the two sides
+fn side_rows(result: &SimulationResult) -> Vec<(&'static str, String, String)> {
+    let stats = &result.stats;
+    let mut rows = vec![
+        (
+            "Average casualties",
+            format!("{:.2}", stats.avg_side1_casualties),
+            format!("{:.2}", stats.avg_side2_casualties),
+        ),
+        (
+            "Average HP lost",
+            format!("{:.1} ({:.1}%)", stats.avg_side1_hp_lost, stats.avg_side1_hp_lost_percent),
+            format!("{:.1} ({:.1}%)", stats.avg_side2_hp_lost, stats.avg_side2_hp_lost_percent),
+        ),
+        (
+            "Flawless victories",
+            format!("{:.1}%", stats.side1_flawless_rate),
+            format!("{:.1}%", stats.side2_flawless_rate),
+        ),
+        (
+            "Wiped out",
+            format!("{:.1}%", stats.side1_tpk_rate),
+            format!("{:.1}%", stats.side2_tpk_rate),
+        ),
+    ];
+    // Absent when the run didn't record the events they're counted from
+    if let Some((side1, side2)) = stats.avg_side1_round1_damage.zip(stats.avg_side2_round1_damage) {
+        rows.push(("Round 1 damage dealt", format!("{:.1}", side1), format!("{:.1}", side2)));
+    }
+    if let Some((side1, side2)) = stats.avg_side1_overkill.zip(stats.avg_side2_overkill) {
+        rows.push(("Overkill per kill", format!("{:.1}", side1), format!("{:.1}", side2)));
+    }
+    let nonzero = |&(side1, side2): &(f64, f64)| side1 > 0.0 || side2 > 0.0;
+    if let Some((side1, side2)) = stats.avg_side1_wasted_hits.zip(stats.avg_side2_wasted_hits).filter(nonzero) {
+        rows.push(("Hits on the already fallen", format!("{:.2}", side1), format!("{:.2}", side2)));
+    }
+    if let Some((side1, side2)) = stats.avg_side1_wasted_actions.zip(stats.avg_side2_wasted_actions).filter(nonzero) {
+        rows.push(("Attacks that lost their target", format!("{:.2}", side1), format!("{:.2}", side2)));
+    }
+    if let Some((side1, side2)) = stats.avg_side1_friendly_fire_damage.zip(stats.avg_side2_friendly_fire_damage).filter(nonzero) {
+        rows.push(("Damage dealt to own side", format!("{:.1}", side1), format!("{:.1}", side2)));
+        rows.push((
+            "Own side killed by own attacks",
+            format!("{:.2}", stats.avg_side1_self_inflicted_casualties.unwrap_or_default()),
+            format!("{:.2}", stats.avg_side2_self_inflicted_casualties.unwrap_or_default()),
+        ));
+    }
+    if stats.avg_side1_summoned > 0.0 || stats.avg_side2_summoned > 0.0 {
+        rows.push((
+            "Creatures summoned",
+            format!("{:.2}", stats.avg_side1_summoned),
+            format!("{:.2}", stats.avg_side2_summoned),
+        ));
+    }
+    if stats.side1_vip_survival_rate.is_some() || stats.side2_vip_survival_rate.is_some() {
+        let rate = |rate: Option<f64>| rate.map_or("-".to_string(), |r| format!("{:.1}%", r));
+        rows.push((
+            "VIP survived",
+            rate(stats.side1_vip_survival_rate),
+            rate(stats.side2_vip_survival_rate),
+        ));
+    }
+    if stats.side1_surprised_rate > 0.0 || stats.side2_surprised_rate > 0.0 {
+        rows.push((
+            "Surprised",
+            format!("{:.1}%", stats.side1_surprised_rate),
+            format!("{:.1}%", stats.side2_surprised_rate),
+        ));
+    }
+    rows
+}
+
+/// Highlights of a sample combat: first blood, the biggest hit, and who was left standing
+fn notable_moments(combat: &CombatLog) -> Vec<String> {
+    let mut moments = Vec::new();
+
+    let first_death = combat.events.iter().find_map(|e| match e.event {
+        EventType::Death { killer_id } => Some((e, killer_id)),
+        _ => None,
+    });
+    if let Some((death, killer_id)) = first_death {
+        let killer = killer_id.and_then(|id| combat.final_state.iter().find(|a| a.id == id));
+        moments.push(match killer {
+            Some(killer) => format!("First blood: {} kills {} in round {}", killer.name, death.actor, death.round),
+            None => format!("First blood: {} falls in round {}", death.actor, death.round),
+        });
+    }
+
+    let biggest_hit = combat
+        .events
+        .iter()
+        .filter_map(|e| match &e.event {
+            EventType::Attack { target_name, hit: true, damage, .. } => Some((e, target_name, *damage)),
+            _ => None,
+        })
+        .max_by_key(|(_, _, damage)| *damage);
+    if let Some((event, target, damage)) = biggest_hit {
+        moments.push(format!(
+            "Biggest hit: {} deals {} damage to {} in round {}",
+            event.actor, damage, target, event.round
+        ));
+    }
+
+    let survivors: Vec<String> = combat
+        .final_state
+        .iter()
+        .filter(|a| a.alive)
+        .map(|a| format!("{} ({} HP)", a.name, a.hp))
+        .collect();
+    if survivors.is_empty() {
+        moments.push("Nobody survived".to_string());
+    } else {
+        moments.push(format!("Left standing: {}", survivors.join(", ")));
+    }
+
+    moments
+}
+
+fn combat_heading(number: usize, combat: &CombatLog) -> String {
+    let outcome = match &combat.winner {
+        Some(side) => format!("{} wins", side_label(side)),
+        None => "Draw".to_string(),
+    };
+    let surprise = match &combat.surprised {
+        Some(side) => format!(" ({} surprised)", side_label(side)),
+        None => String::new(),
+    };
+    format!(
+        "Combat {} (iteration {}): {} after {} rounds{}",
+        number, combat.iteration, outcome, combat.rounds, surprise
+    )
+}
+
+pub fn render_markdown(name: &str, result: &SimulationResult) -> String {
+    let stats = &result.stats;
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# Battle Report: {}\n", name);
+    let _ = writeln!(
+        out,
+        "{} simulated combats (seed {}). {}\n",
+        stats.iterations,
+        result.seed,
+        summary(result)
+    );
+
+    let _ = writeln!(out, "## Outcomes\n");
+    let _ = writeln!(out, "| Outcome | Rate | 95% CI |");
+    let _ = writeln!(out, "|---|---:|---:|");
+    for (label, rate, ci) in outcome_rows(result) {
+        let _ = writeln!(out, "| {} | {:.1}% | {} |", label, rate, ci);
+    }
+
+    let _ = writeln!(out, "\n## Sides\n");
+    let _ = writeln!(out, "| | Side 1 | Side 2 |");
+    let _ = writeln!(out, "|---|---:|---:|");
+    for (label, side1, side2) in side_rows(result) {
+        let _ = writeln!(out, "| {} | {} | {} |", label, side1, side2);
+    }
+
+    let no_kills = KillAttribution::default();
+    let kills = stats.kills.as_ref().unwrap_or(&no_kills);
+    if !kills.actors.is_empty() {
+        let _ = writeln!(out, "\n## Actors\n");
+        let _ = writeln!(out, "| Actor | Side | Avg kills | Scores a kill | Dies |");
+        let _ = writeln!(out, "|---|---|---:|---:|---:|");
+        for actor in &kills.actors {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {:.2} | {:.1}% | {:.1}% |",
+                actor.name.replace('|', "\\|"),
+                side_label(&actor.side),
+                actor.avg_kills,
+                actor.kill_rate,
+                actor.death_rate
+            );
+        }
+    }
+
+    if !kills.matchups.is_empty() {
+        let _ = writeln!(out, "\n## Deadliest Matchups\n");
+        for matchup in kills.matchups.iter().take(TOP_MATCHUPS) {
+            let _ = writeln!(
+                out,
+                "- {} kills {} in {:.1}% of fights",
+                matchup.killer, matchup.victim, matchup.rate
+            );
+        }
+    }
+
+    let time_to_kill = stats.time_to_kill.as_deref().unwrap_or_default();
+    if !time_to_kill.is_empty() {
+        let _ = writeln!(out, "\n## Time to Kill\n");
+        let _ = writeln!(out, "| Attacker | Defender | Kills one | Attacks per kill | First kill (round) |");
+        let _ = writeln!(out, "|---|---|---:|---:|---:|");
+        for matchup in time_to_kill {
+            let _ = writeln!(
+                out,
+                "| {} | {} | {:.1}% | {:.1} | {:.1} |",
+                matchup.attacker.replace('|', "\\|"),
+                matchup.defender.replace('|', "\\|"),
+                matchup.kill_rate,
+                matchup.avg_attacks_to_kill,
+                matchup.avg_rounds_to_first_kill
+            );
+        }
+    }
+
+    if !result.sample_combats.is_empty() {
+        let _ = writeln!(out, "\n## Sample Combats");
+    }
+    for (i, combat) in result.sample_combats.iter().enumerate() {
+        let _ = writeln!(out, "\n### {}\n", combat_heading(i + 1, combat));
+        for moment in notable_moments(combat) {
+            let _ = writeln!(out, "- {}", moment);
+        }
+        let _ = writeln!(out, "\n<details><summary>Full log</summary>\n\n```");
+        for event in &combat.events {
+            let _ = writeln!(out, "[R{}] {} {}", event.round, event.actor, event.description);
+        }
+        let _ = writeln!(out, "```\n\n</details>");
+    }
+
+    out
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+const HTML_STYLE: &str = "body{font-family:system-ui,sans-serif;max-width:56rem;margin:2rem auto;padding:0 1rem;color:#222}\
+table{border-collapse:collapse;margin:1rem 0}th,td{border:1px solid #ccc;padding:.3rem .6rem}\
+td.num{text-align:right}pre{background:#f5f5f5;padding:.75rem;overflow-x:auto}";
+
+pub fn render_html(name: &str, result: &SimulationResult) -> String {
+    let stats = &result.stats;
+    let name = escape_html(name);
+    let mut out = String::new();
+
+    let _ = write!(
+        out,
+        "<!DOCTYPE html>\n<html><head><meta charset=\"utf-8\"><title>Battle Report: {}</title>\
+         <style>{}</style></head><body>\n",
+        name, HTML_STYLE
+    );
+    let _ = writeln!(out, "<h1>Battle Report: {}</h1>", name);
+    let _ = writeln!(
+        out,
+        "<p>{} simulated combats (seed {}). {}</p>",
+        stats.iterations,
+        result.seed,
+        escape_html(&summary(result))
+    );
+
+    let _ = writeln!(out, "<h2>Outcomes</h2>\n<table><tr><th>Outcome</th><th>Rate</th><th>95% CI</th></tr>");
+    for (label, rate, ci) in outcome_rows(result) {
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td class=\"num\">{:.1}%</td><td class=\"num\">{}</td></tr>",
+            label, rate, ci
+        );
+    }
+    let _ = writeln!(out, "</table>");
+
+    let _ = writeln!(out, "<h2>Sides</h2>\n<table><tr><th></th><th>Side 1</th><th>Side 2</th></tr>");
+    for (label, side1, side2) in side_rows(result) {
+        let _ = writeln!(
+            out,
+            "<tr><td>{}</td><td class=\"num\">{}</td><td class=\"num\">{}</td></tr>",
+            label, side1, side2
+        );
+    }
+    let _ = writeln!(out, "</table>");
+
+    let no_kills = KillAttribution::default();
+    let kills = stats.kills.as_ref().unwrap_or(&no_kills);
+    if !kills.actors.is_empty() {
+        let _ = writeln!(
+            out,
+            "<h2>Actors</h2>\n<table><tr><th>Actor</th><th>Side</th><th>Avg kills</th>\
+             <th>Scores a kill</th><th>Dies</th></tr>"
+        );
+        for actor in &kills.actors {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td class=\"num\">{:.2}</td><td class=\"num\">{:.1}%</td>\
+                 <td class=\"num\">{:.1}%</td></tr>",
+                escape_html(&actor.name),
+                side_label(&actor.side),
+                actor.avg_kills,
+                actor.kill_rate,
+                actor.death_rate
+            );
+        }
+        let _ = writeln!(out, "</table>");
+    }
+
+    if !kills.matchups.is_empty() {
+        let _ = writeln!(out, "<h2>Deadliest Matchups</h2>\n<ul>");
+        for matchup in kills.matchups.iter().take(TOP_MATCHUPS) {
+            let _ = writeln!(
+                out,
+                "<li>{} kills {} in {:.1}% of fights</li>",
+                escape_html(&matchup.killer),
+                escape_html(&matchup.victim),
+                matchup.rate
+            );
+        }
+        let _ = writeln!(out, "</ul>");
+    }
+
+    let time_to_kill = stats.time_to_kill.as_deref().unwrap_or_default();
+    if !time_to_kill.is_empty() {
+        let _ = writeln!(
+            out,
+            "<h2>Time to Kill</h2>\n<table>\n<tr><th>Attacker</th><th>Defender</th><th>Kills one</th>\
+             <th>Attacks per kill</th><th>First kill (round)</th></tr>"
+        );
+        for matchup in time_to_kill {
+            let _ = writeln!(
+                out,
+                "<tr><td>{}</td><td>{}</td><td class=\"num\">{:.1}%</td><td class=\"num\">{:.1}</td>\
+                 <td class=\"num\">{:.1}</td></tr>",
+                escape_html(&matchup.attacker),
+                escape_html(&matchup.defender),
+                matchup.kill_rate,
+                matchup.avg_attacks_to_kill,
+                matchup.avg_rounds_to_first_kill
+            );
+        }
+        let _ = writeln!(out, "</table>");
+    }
+
+    if !result.sample_combats.is_empty() {
+        let _ = writeln!(out, "<h2>Sample Combats</h2>");
+    }
+    for (i, combat) in result.sample_combats.iter().enumerate() {
+        let _ = writeln!(out, "<h3>{}</h3>\n<ul>", escape_html(&combat_heading(i + 1, combat)));
+        for moment in notable_moments(combat) {
+            let _ = writeln!(out, "<li>{}</li>", escape_html(&moment));
+        }
+        let _ = writeln!(out, "</ul>\n<details><summary>Full log</summary><pre>");
+        for event in &combat.events {
+            let line = format!("[R{}] {} {}", event.round, event.actor, event.description);
+            let _ = writeln!(out, "{}", escape_html(&line));
+        }
+        let _ = writeln!(out, "</pre></details>");
+    }
+
+    let _ = writeln!(out, "</body></html>");
+    out
+}
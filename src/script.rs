@@ -0,0 +1,196 @@
+//! Scripted APLs. An actor's `script` is Rhai code run at the start of each of its turns,
+//! with `self`, `allies`, `enemies`, `zones`, `round`, and `phase` in scope. It answers with
+//! APL entries, which are picked from exactly like a YAML APL (so conditions still apply
+//! after the actor moves); an empty answer falls back to the actor's own `apl`.
+//!
+//! Scripts are sandboxed: no modules, no printing, no randomness (so seeded runs replay), and
+//! at most [`MAX_OPERATIONS`] operations per turn. Needs the `scripting` feature.
+
+use std::sync::Arc;
+
+#[cfg(feature = "scripting")]
+use crate::apl::validate_apl;
+use crate::types::{Actor, AplEntry, Phase, ZoneLayout};
+#[cfg(feature = "scripting")]
+use crate::types::Zone;
+
+/// Operations a script gets per turn before it's cut off
+pub const MAX_OPERATIONS: u64 = 100_000;
+
+/// An actor as a script sees it
+#[cfg(feature = "scripting")]
+#[derive(serde::Serialize)]
+struct ScriptActor {
+    id: usize,
+    name: String,
+    template: String,
+    side: String,
+    hp: i32,
+    max_hp: i32,
+    temp_hp: i32,
+    ac: i32,
+    attack_bonus: i32,
+    zone: String,
+    /// Zones away from the scripted actor
+    distance: u32,
+    /// The scripted actor can attack it from where it stands
+    in_range: bool,
+    vip: bool,
+    noncombatant: bool,
+    conditions: Vec<String>,
+}
+
+/// A zone as a script sees it
+#[cfg(feature = "scripting")]
+#[derive(serde::Serialize)]
+struct ScriptZone {
+    name: String,
+    distance: u32,
+    allies: usize,
+    enemies: usize,
+    cover: i32,
+}
+
+/// Compiled scripts, kept by the simulator across combats
+#[derive(Default)]
+pub struct Scripts {
+    #[cfg(feature = "scripting")]
+    engine: Option<rhai::Engine>,
+    #[cfg(feature = "scripting")]
+    compiled: std::collections::HashMap<Arc<str>, Result<rhai::AST, String>>,
+}
+
+impl Scripts {
+    /// Run `actor`'s script for its turn and return the APL entries it answered with
+    #[cfg(feature = "scripting")]
+    pub fn run(
+        &mut self,
+        source: &Arc<str>,
+        actor: &Actor,
+        actors: &[Actor],
+        zones: &ZoneLayout,
+        round: u32,
+        phase: Option<Phase>,
+    ) -> Result<Vec<AplEntry>, String> {
+        let engine = self.engine.get_or_insert_with(engine);
+        let ast = self
+            .compiled
+            .entry(source.clone())
+            .or_insert_with(|| engine.compile(&**source).map_err(|e| e.to_string()))
+            .as_ref()
+            .map_err(String::clone)?;
+
+        let mut scope = rhai::Scope::new();
+        let view = |other: &Actor| script_actor(actor, other, actors, zones);
+        let living = || actors.iter().filter(|a| a.is_alive() && a.id != actor.id);
+        let allies: Vec<ScriptActor> = living().filter(|a| a.is_allied_with(actor)).map(view).collect();
+        let enemies: Vec<ScriptActor> = living().filter(|a| actor.is_hostile_to(a)).map(view).collect();
+        let zone_views: Vec<ScriptZone> = (0..zones.len())
+            .map(|index| {
+                let zone = Zone(index);
+                let here = || living().filter(move |a| a.zone == zone);
+                ScriptZone {
+                    name: zones.name(zone).to_string(),
+                    distance: zones.distance(actor.zone, zone),
+                    allies: here().filter(|a| a.is_allied_with(actor)).count(),
+                    enemies: here().filter(|a| actor.is_hostile_to(a)).count(),
+                    cover: zones.terrain(zone).cover,
+                }
+            })
+            .collect();
+        scope.push_constant("self", dynamic(&view(actor))?);
+        scope.push_constant("allies", dynamic(&allies)?);
+        scope.push_constant("enemies", dynamic(&enemies)?);
+        scope.push_constant("zones", dynamic(&zone_views)?);
+        scope.push_constant("round", round as i64);
+        scope.push_constant("phase", dynamic(&phase)?);
+
+        let answer: rhai::Dynamic = engine.eval_ast_with_scope(&mut scope, ast).map_err(|e| e.to_string())?;
+        let entries: Vec<AplEntry> = if answer.is_unit() {
+            Vec::new()
+        } else if answer.is_map() {
+            vec![rhai::serde::from_dynamic(&answer).map_err(|e| e.to_string())?]
+        } else {
+            rhai::serde::from_dynamic(&answer).map_err(|e| e.to_string())?
+        };
+        match validate_apl(&entries).first() {
+            Some(error) => Err(format!("{}: {}", error.path, error.message)),
+            None => Ok(entries),
+        }
+    }
+
+    #[cfg(not(feature = "scripting"))]
+    pub fn run(
+        &mut self,
+        _source: &Arc<str>,
+        _actor: &Actor,
+        _actors: &[Actor],
+        _zones: &ZoneLayout,
+        _round: u32,
+        _phase: Option<Phase>,
+    ) -> Result<Vec<AplEntry>, String> {
+        Err(UNSUPPORTED.to_string())
+    }
+}
+
+/// Check that `source` compiles
+#[cfg(feature = "scripting")]
+pub fn check(source: &str) -> Result<(), String> {
+    engine().compile(source).map(|_| ()).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "scripting"))]
+pub fn check(_source: &str) -> Result<(), String> {
+    Err(UNSUPPORTED.to_string())
+}
+
+#[cfg(not(feature = "scripting"))]
+const UNSUPPORTED: &str = "this build doesn't run scripts (it needs the `scripting` feature)";
+
+/// A Rhai engine with nothing to reach outside the simulation and a budget per turn
+#[cfg(feature = "scripting")]
+fn engine() -> rhai::Engine {
+    let mut engine = rhai::Engine::new();
+    engine.set_module_resolver(rhai::module_resolvers::DummyModuleResolver::new());
+    engine.disable_symbol("eval");
+    engine.on_print(|_| {});
+    engine.on_debug(|_, _, _| {});
+    engine.set_max_operations(MAX_OPERATIONS);
+    engine.set_max_call_levels(32);
+    engine.set_max_expr_depths(64, 32);
+    engine.set_max_string_size(10_000);
+    engine.set_max_array_size(10_000);
+    engine.set_max_map_size(1_000);
+    engine
+}
+
+#[cfg(feature = "scripting")]
+fn dynamic(value: &impl serde::Serialize) -> Result<rhai::Dynamic, String> {
+    rhai::serde::to_dynamic(value).map_err(|e| e.to_string())
+}
+
+#[cfg(feature = "scripting")]
+fn script_actor(actor: &Actor, other: &Actor, actors: &[Actor], zones: &ZoneLayout) -> ScriptActor {
+    use crate::types::ConditionKind::*;
+    ScriptActor {
+        id: other.id,
+        name: other.name.to_string(),
+        template: other.template_name.to_string(),
+        side: other.side.to_string(),
+        hp: other.current_hp,
+        max_hp: other.max_hp,
+        temp_hp: other.temp_hp(),
+        ac: other.effective_ac(),
+        attack_bonus: other.attack_bonus,
+        zone: zones.name(other.zone).to_string(),
+        distance: zones.distance(actor.zone, other.zone),
+        in_range: other.id != actor.id && actor.can_attack(other, zones, actors),
+        vip: other.vip,
+        noncombatant: other.noncombatant,
+        conditions: [Poisoned, Prone, Bleeding, Stunned, Disarmed, Slowed]
+            .into_iter()
+            .filter(|&kind| other.has_condition(kind))
+            .map(|kind| kind.to_string())
+            .collect(),
+    }
+}
@@ -0,0 +1,118 @@
+//! One-at-a-time sensitivity analysis: nudge each actor entry's AC, attack bonus, and HP up
+//! and down, re-simulate, and report how far its side's win rate moves.
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::simulation::{run_variants, SimulationOptions};
+use crate::types::{ActorTemplate, Encounter, Side};
+
+/// AC and attack bonus are moved this far each way
+const BONUS_STEP: i32 = 2;
+/// HP is scaled by this fraction each way
+const HP_STEP: f64 = 0.25;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Stat {
+    Ac,
+    AttackBonus,
+    Hp,
+}
+
+impl Stat {
+    const ALL: [Stat; 3] = [Stat::Ac, Stat::AttackBonus, Stat::Hp];
+
+    /// Apply a one-step change down (`up` false) or up to the template
+    fn perturb(self, template: &mut ActorTemplate, up: bool) {
+        let sign = if up { 1 } else { -1 };
+        match self {
+            Stat::Ac => template.ac += sign * BONUS_STEP,
+            Stat::AttackBonus => template.attack_bonus += sign * BONUS_STEP,
+            Stat::Hp => template.hp_scale = Some(1.0 + sign as f64 * HP_STEP),
+        }
+    }
+
+    /// The size of one step, e.g. `±2` or `±25%`
+    fn step(self) -> String {
+        match self {
+            Stat::Ac | Stat::AttackBonus => format!("±{}", BONUS_STEP),
+            Stat::Hp => format!("±{}%", HP_STEP * 100.0),
+        }
+    }
+}
+
+/// How one stat of one actor entry moves its side's win rate
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StatSensitivity {
+    pub actor: String,
+    pub side: Side,
+    pub stat: Stat,
+    pub step: String,
+    /// Change in the side's win rate (percentage points) with the stat lowered
+    pub down: f64,
+    /// Change in the side's win rate (percentage points) with the stat raised
+    pub up: f64,
+    /// `up - down`: how much the win rate moves across the whole range
+    pub swing: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct SensitivityResult {
+    /// Master seed shared by the baseline and every perturbed run
+    pub seed: u64,
+    pub iterations: u32,
+    pub side1_win_rate: f64,
+    pub side2_win_rate: f64,
+    /// Largest absolute swing first
+    pub stats: Vec<StatSensitivity>,
+}
+
+/// Re-simulate the encounter with each actor entry's AC, attack bonus, and HP moved one step
+/// down and up, all under one master seed, and rank the knobs by how far they swing that
+/// entry's side's win rate.
+pub fn analyze(encounter: &Encounter, options: &SimulationOptions) -> SensitivityResult {
+    let mut knobs = Vec::new();
+    let mut variants = vec![encounter.clone()];
+    for side in encounter.sides() {
+        for (index, template) in encounter.templates(side).iter().enumerate() {
+            for stat in Stat::ALL {
+                for up in [false, true] {
+                    let mut variant = encounter.clone();
+                    stat.perturb(&mut variant.templates_mut(side)[index], up);
+                    variants.push(variant);
+                }
+                knobs.push((template.name.clone(), side, stat));
+            }
+        }
+    }
+
+    let (seed, results) = run_variants(&variants, options);
+    let baseline = &results[0];
+    let mut stats: Vec<StatSensitivity> = knobs
+        .into_iter()
+        .zip(results[1..].chunks(2))
+        .map(|((actor, side, stat), pair)| {
+            let down = pair[0].win_rate(side) - baseline.win_rate(side);
+            let up = pair[1].win_rate(side) - baseline.win_rate(side);
+            StatSensitivity {
+                actor,
+                side,
+                stat,
+                step: stat.step(),
+                down,
+                up,
+                swing: up - down,
+            }
+        })
+        .collect();
+    stats.sort_by(|a, b| b.swing.abs().total_cmp(&a.swing.abs()));
+
+    SensitivityResult {
+        seed,
+        iterations: baseline.iterations,
+        side1_win_rate: baseline.side1_win_rate,
+        side2_win_rate: baseline.side2_win_rate,
+        stats,
+    }
+}
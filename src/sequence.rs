@@ -0,0 +1,232 @@
+//! Encounters fought back to back, with or without rests between them. Side 1 is the party:
+//! each of its actors carries its HP (or its death) from one fight into the next, matched by
+//! name, while each fight's side 2 starts fresh. A party that fails to win a fight is done for
+//! the day.
+
+use std::collections::BTreeMap;
+use std::time::Instant;
+
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::campaign::Rest;
+use crate::combat::{CombatResult, CombatSimulator};
+use crate::simulation::{in_parallel, iteration_rng, past, run_simulation, SimulationOptions, BATCH_SIZE};
+use crate::stats::{Histogram, Percentiles, SimulationResult};
+use crate::types::{Encounter, Side};
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct BatchResult {
+    /// Master seed shared by every run. Iteration `i` of the sequence fights its first
+    /// encounter exactly as iteration `i` of that encounter's own run does.
+    pub seed: u64,
+    /// Each encounter on its own, against a fresh party
+    pub encounters: Vec<SimulationResult>,
+    /// The party's state after each fight when they're fought in order without resting
+    pub attrition: Vec<AttritionStep>,
+    /// Sequences run for `attrition`
+    pub iterations: u32,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct AttritionStep {
+    pub encounter: String,
+    /// Percent of sequences in which the party got as far as this fight
+    pub reached_rate: f64,
+    /// Percent of sequences in which the party won this fight and every one before it
+    pub survival_rate: f64,
+    /// Percent of sequences that ended here, with the party failing to win this fight
+    pub break_rate: f64,
+    /// The party's remaining HP after this fight, as a percent of its full HP. A party that
+    /// broke earlier is counted as it was when it broke.
+    pub avg_party_hp_percent: f64,
+    pub party_hp_percentiles: Percentiles,
+    /// Party members still alive after this fight
+    pub avg_survivors: f64,
+}
+
+/// Simulate each encounter on its own, then fight them in order with the party's damage
+/// carried between fights
+pub fn run_batch(encounters: &[Encounter], options: &SimulationOptions) -> BatchResult {
+    let options = SimulationOptions {
+        seed: Some(options.seed.unwrap_or_else(rand::random)),
+        ..options.clone()
+    };
+    let seed = options.seed.unwrap_or_default();
+    let results = encounters
+        .iter()
+        .map(|encounter| run_simulation(encounter, &SimulationOptions { max_rounds: encounter.max_rounds, ..options.clone() }))
+        .collect();
+
+    let stages: Vec<Stage> = encounters.iter().map(Stage::Fight).collect();
+    let (iterations, attrition) = run_stages(&stages, &options);
+    BatchResult {
+        seed,
+        encounters: results,
+        attrition,
+        iterations,
+    }
+}
+
+/// One leg of a sequence
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Stage<'a> {
+    Fight(&'a Encounter),
+    Rest(&'a Rest),
+}
+
+/// Fight the stages in order `options.iterations` times (or until the deadline), under
+/// `options.seed`, and tally the party's state after each fight. Returns the number of
+/// sequences run with one step per fight.
+pub(crate) fn run_stages(stages: &[Stage], options: &SimulationOptions) -> (u32, Vec<AttritionStep>) {
+    let seed = options.seed.unwrap_or_default();
+    let encounters: Vec<&Encounter> = stages
+        .iter()
+        .filter_map(|stage| match stage {
+            Stage::Fight(encounter) => Some(*encounter),
+            Stage::Rest(_) => None,
+        })
+        .collect();
+    let mut tally = AttritionTally::new(encounters.len());
+    let mut start = 0;
+    while start < options.iterations && !past(options.deadline) {
+        let end = (start + BATCH_SIZE).min(options.iterations);
+        let days = in_parallel(options.threads, start..end, |i| fight_in_sequence(stages, seed, i, options.deadline));
+        // A batch the deadline caught partway is dropped whole, so every day tallied was fought out
+        if days.iter().flatten().any(|fight| fight.cut_off) {
+            break;
+        }
+        for day in days {
+            tally.add(&day);
+        }
+        start = end;
+    }
+    (start, tally.steps(&encounters))
+}
+
+/// One sequence: the results of the fights it got through, in order
+fn fight_in_sequence(stages: &[Stage], seed: u64, iteration: u32, deadline: Option<Instant>) -> Vec<CombatResult> {
+    let mut rng = iteration_rng(seed, iteration);
+    let mut party: BTreeMap<String, i32> = BTreeMap::new();
+    let mut fights = Vec::new();
+    for stage in stages {
+        let encounter = match stage {
+            Stage::Fight(encounter) => encounter,
+            Stage::Rest(rest) => {
+                rest.apply(&mut party, &mut rng);
+                continue;
+            }
+        };
+        let result = CombatSimulator::new(encounter, encounter.max_rounds, &mut rng)
+            .with_starting_hp(Side::SIDE1, &party)
+            .with_deadline(deadline)
+            .run(&mut rng);
+        // Summoned creatures don't follow the party into the next fight
+        for actor in result.final_state.iter().filter(|a| a.side == Side::SIDE1 && a.summoner.is_none()) {
+            party.insert(actor.name.to_string(), if actor.alive { actor.final_hp } else { 0 });
+        }
+        let won = result.winner == Some(Side::SIDE1);
+        fights.push(result);
+        if !won {
+            break;
+        }
+    }
+    fights
+}
+
+/// The party as it stands after a fight
+#[derive(Debug, Clone, Copy, Default)]
+struct PartyState {
+    hp: i64,
+    survivors: u32,
+}
+
+impl PartyState {
+    fn after(result: &CombatResult) -> Self {
+        result
+            .final_state
+            .iter()
+            .filter(|a| a.side == Side::SIDE1 && a.summoner.is_none() && a.alive)
+            .fold(PartyState::default(), |state, a| PartyState {
+                hp: state.hp + a.final_hp.max(0) as i64,
+                survivors: state.survivors + 1,
+            })
+    }
+}
+
+#[derive(Debug, Default)]
+struct StepTally {
+    reached: u32,
+    survived: u32,
+    broke: u32,
+    hp_percent: f64,
+    hp_percent_histogram: Histogram,
+    survivors: u64,
+}
+
+struct AttritionTally {
+    days: u32,
+    steps: Vec<StepTally>,
+}
+
+impl AttritionTally {
+    fn new(fights: usize) -> Self {
+        AttritionTally {
+            days: 0,
+            steps: (0..fights).map(|_| StepTally::default()).collect(),
+        }
+    }
+
+    fn add(&mut self, fights: &[CombatResult]) {
+        self.days += 1;
+        // The party's full HP: what its members start the first fight on
+        let full_hp: i64 = fights
+            .first()
+            .map(|first| {
+                first
+                    .final_state
+                    .iter()
+                    .filter(|a| a.side == Side::SIDE1 && a.summoner.is_none())
+                    .map(|a| a.max_hp as i64)
+                    .sum()
+            })
+            .unwrap_or(0);
+
+        let mut state = PartyState::default();
+        let mut won_so_far = true;
+        for (i, step) in self.steps.iter_mut().enumerate() {
+            if let Some(result) = fights.get(i) {
+                step.reached += 1;
+                state = PartyState::after(result);
+                won_so_far &= result.winner == Some(Side::SIDE1);
+                if won_so_far {
+                    step.survived += 1;
+                } else {
+                    step.broke += 1;
+                }
+            }
+            let percent = if full_hp > 0 { state.hp as f64 / full_hp as f64 * 100.0 } else { 0.0 };
+            step.hp_percent += percent;
+            step.hp_percent_histogram.add(percent.round() as i64);
+            step.survivors += state.survivors as u64;
+        }
+    }
+
+    fn steps(&self, encounters: &[&Encounter]) -> Vec<AttritionStep> {
+        let n = self.days.max(1) as f64;
+        self.steps
+            .iter()
+            .zip(encounters)
+            .enumerate()
+            .map(|(i, (step, encounter))| AttritionStep {
+                encounter: encounter.name.clone().unwrap_or_else(|| format!("Encounter {}", i + 1)),
+                reached_rate: step.reached as f64 / n * 100.0,
+                survival_rate: step.survived as f64 / n * 100.0,
+                break_rate: step.broke as f64 / n * 100.0,
+                avg_party_hp_percent: step.hp_percent / n,
+                party_hp_percentiles: step.hp_percent_histogram.percentiles(),
+                avg_survivors: step.survivors as f64 / n,
+            })
+            .collect()
+    }
+}
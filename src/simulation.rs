@@ -0,0 +1,288 @@
+use std::ops::Range;
+use std::thread;
+use std::time::Instant;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::analysis;
+use crate::combat::{ChoiceTrace, CombatResult, CombatSimulator, Controller, EventDetail};
+use crate::stats::{SimulationResult, SimulationStats, StatsCollector};
+use crate::types::{Encounter, Side};
+
+/// Settings for a batch of simulated combats
+#[derive(Debug, Clone)]
+pub struct SimulationOptions {
+    pub iterations: u32,
+    pub max_rounds: u32,
+    pub sample_count: usize,
+    /// Master seed; a random one is chosen (and reported in the result) when unset
+    pub seed: Option<u64>,
+    /// Stop once every outcome rate's 95% confidence interval is within ± this many
+    /// percentage points (e.g. 0.5). `iterations` is still the upper bound.
+    pub target_precision: Option<f64>,
+    /// Worker threads for simulating combats. Every iteration has its own RNG stream and
+    /// results are folded in iteration order, so the thread count never changes the outcome.
+    pub threads: usize,
+    /// Stop starting new combats once this instant passes; the result then covers only the
+    /// iterations completed so far
+    pub deadline: Option<Instant>,
+    /// Events recorded for combats that aren't kept as samples; the first `sample_count`
+    /// combats always record everything
+    pub event_detail: EventDetail,
+}
+
+/// Iterations between precision checks, and the minimum run before stopping early
+const PRECISION_CHECK_INTERVAL: u32 = 100;
+/// Combats simulated in parallel before their results are folded into the stats
+pub(crate) const BATCH_SIZE: u32 = 1024;
+
+impl SimulationOptions {
+    /// Options using the encounter's own iteration count and round limit
+    pub fn for_encounter(encounter: &Encounter) -> Self {
+        SimulationOptions {
+            iterations: encounter.iterations,
+            max_rounds: encounter.max_rounds,
+            sample_count: 5,
+            seed: None,
+            target_precision: None,
+            threads: default_threads(),
+            deadline: None,
+            event_detail: EventDetail::Full,
+        }
+    }
+}
+
+/// One worker per available core
+pub fn default_threads() -> usize {
+    thread::available_parallelism().map_or(1, |n| n.get())
+}
+
+/// Snapshot of a run in progress
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct Progress {
+    pub completed: u32,
+    pub total: u32,
+    pub side1_win_rate: f64,
+    pub side2_win_rate: f64,
+    pub draw_rate: f64,
+}
+
+/// Stats collector sized for an encounter (dice-based HP uses expected values)
+pub fn new_collector(encounter: &Encounter, sample_count: usize) -> StatsCollector {
+    StatsCollector::new(
+        encounter.actor_count(Side::SIDE1),
+        encounter.actor_count(Side::SIDE2),
+        encounter.expected_hp(Side::SIDE1),
+        encounter.expected_hp(Side::SIDE2),
+        sample_count,
+    )
+    .with_objectives(&encounter.objectives)
+    .with_draw_policy(encounter.draw_policy)
+    .with_factions(encounter.sides().skip(2).map(|side| (encounter.actor_count(side), encounter.expected_hp(side))))
+    .with_zones(&encounter.zone_layout())
+}
+
+/// RNG for one iteration of a run. Every iteration gets its own ChaCha stream under the
+/// master seed, so any single combat can be re-run without replaying the ones before it.
+pub fn iteration_rng(seed: u64, iteration: u32) -> ChaCha8Rng {
+    let mut rng = ChaCha8Rng::seed_from_u64(seed);
+    rng.set_stream(iteration as u64);
+    rng
+}
+
+/// Run the `iteration`th (0-based) combat of a run seeded with `seed`
+pub fn replay_combat(encounter: &Encounter, max_rounds: u32, seed: u64, iteration: u32) -> CombatResult {
+    let mut rng = iteration_rng(seed, iteration);
+    let mut sim = CombatSimulator::new(encounter, max_rounds, &mut rng);
+    sim.run(&mut rng)
+}
+
+/// [`replay_combat`], tracing every APL pass the actors make along the way
+pub fn debug_combat(encounter: &Encounter, max_rounds: u32, seed: u64, iteration: u32) -> (CombatResult, Vec<ChoiceTrace>) {
+    let mut rng = iteration_rng(seed, iteration);
+    let mut sim = CombatSimulator::new(encounter, max_rounds, &mut rng).with_trace();
+    let result = sim.run(&mut rng);
+    (result, sim.take_trace())
+}
+
+/// Run the "average fight": one deterministic combat where every roll takes its expected value
+/// (see [`CombatSimulator::average`])
+pub fn average_combat(encounter: &Encounter, max_rounds: u32) -> CombatResult {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    CombatSimulator::average(encounter, max_rounds).run(&mut rng)
+}
+
+/// Run one combat with `side`'s turns chosen by `controller`
+pub fn play_combat(encounter: &Encounter, max_rounds: u32, seed: u64, side: Side, controller: Controller) -> CombatResult {
+    let mut rng = iteration_rng(seed, 0);
+    let mut sim = CombatSimulator::new(encounter, max_rounds, &mut rng).with_controller(side, controller);
+    sim.run(&mut rng)
+}
+
+/// Simulate every variant of an encounter under the same master seed, so they all face the
+/// same dice, and return the seed with each variant's stats. Variants are shared out among
+/// the worker threads, or run in turn on this one when there's a single worker; no sample
+/// combats are kept.
+pub fn run_variants(variants: &[Encounter], options: &SimulationOptions) -> (u64, Vec<SimulationStats>) {
+    let workers = options.threads.max(1);
+    let options = SimulationOptions {
+        seed: Some(options.seed.unwrap_or_else(rand::random)),
+        sample_count: 0,
+        // Each variant runs single-threaded; the variants share out the workers
+        threads: 1,
+        ..options.clone()
+    };
+    let stats = in_parallel(workers, 0..variants.len() as u32, |i| {
+        run_simulation(&variants[i as usize], &options).stats
+    });
+    (options.seed.unwrap_or_default(), stats)
+}
+
+/// Run every iteration without aggregating, handing each combat to `on_combat` along with
+/// its iteration index. Returning false (or passing the deadline) stops the run. Every combat
+/// records events at `options.event_detail`; `target_precision` and `sample_count` are
+/// ignored. Returns the master seed used.
+pub fn for_each_combat(
+    encounter: &Encounter,
+    options: &SimulationOptions,
+    mut on_combat: impl FnMut(u32, &CombatResult) -> bool,
+) -> u64 {
+    let seed = options.seed.unwrap_or_else(rand::random);
+    let mut sim = CombatSimulator::prepare(encounter, options.max_rounds).with_deadline(options.deadline);
+    for i in 0..options.iterations {
+        if i % BATCH_SIZE == 0 && past(options.deadline) {
+            break;
+        }
+        let result = rerun(&mut sim, seed, i, options.event_detail);
+        if result.cut_off || !on_combat(i, &result) {
+            break;
+        }
+    }
+    seed
+}
+
+pub fn run_simulation(encounter: &Encounter, options: &SimulationOptions) -> SimulationResult {
+    run_simulation_with_progress(encounter, options, 0, |_| true)
+}
+
+/// Run the simulation, calling `on_progress` every `progress_interval` iterations
+/// (0 = never). Returning false from the callback, or passing `options.deadline`, stops the
+/// run early; the result then covers only the iterations completed so far.
+pub fn run_simulation_with_progress(
+    encounter: &Encounter,
+    options: &SimulationOptions,
+    progress_interval: u32,
+    mut on_progress: impl FnMut(Progress) -> bool,
+) -> SimulationResult {
+    let seed = options.seed.unwrap_or_else(rand::random);
+    let mut collector = new_collector(encounter, options.sample_count).with_event_detail(options.event_detail);
+
+    let mut batch = Vec::new().into_iter();
+    for i in 1..=options.iterations {
+        if batch.len() == 0 {
+            if past(options.deadline) {
+                break;
+            }
+            let end = (i - 1 + BATCH_SIZE).min(options.iterations);
+            batch = simulate_batch(encounter, options, seed, i - 1..end).into_iter();
+        }
+        let Some(result) = batch.next().filter(|result| !result.cut_off) else { break };
+        collector.add_result(result);
+
+        if progress_interval > 0 && i % progress_interval == 0 && i < options.iterations {
+            let stats = collector.compute_stats();
+            let progress = Progress {
+                completed: i,
+                total: options.iterations,
+                side1_win_rate: stats.side1_win_rate,
+                side2_win_rate: stats.side2_win_rate,
+                draw_rate: stats.draw_rate,
+            };
+            if !on_progress(progress) {
+                break;
+            }
+        }
+
+        if let Some(precision) = options.target_precision {
+            if i % PRECISION_CHECK_INTERVAL == 0 && collector.win_rate_margin() <= precision {
+                break;
+            }
+        }
+    }
+
+    SimulationResult {
+        seed,
+        stats: collector.compute_stats(),
+        matchups: analysis::matchups(encounter),
+        sample_combats: collector.get_sample_combats(options.sample_count),
+    }
+}
+
+pub(crate) fn past(deadline: Option<Instant>) -> bool {
+    deadline.is_some_and(|deadline| Instant::now() >= deadline)
+}
+
+/// Reset a prepared simulator for the `iteration`th combat of a run and play it, recording
+/// events at `detail`; otherwise the same combat `replay_combat` gives
+fn rerun(sim: &mut CombatSimulator, seed: u64, iteration: u32, detail: EventDetail) -> CombatResult {
+    sim.set_event_detail(detail);
+    let mut rng = iteration_rng(seed, iteration);
+    sim.reset(&mut rng);
+    sim.run(&mut rng)
+}
+
+/// Simulate a range of iterations, split across `options.threads` workers, in iteration order.
+/// Each worker builds its simulator once and resets it between combats.
+fn simulate_batch(encounter: &Encounter, options: &SimulationOptions, seed: u64, iterations: Range<u32>) -> Vec<CombatResult> {
+    in_parallel_with(
+        options.threads,
+        iterations,
+        || CombatSimulator::prepare(encounter, options.max_rounds).with_deadline(options.deadline),
+        |sim, i| {
+            // Sample combats keep their whole log
+            let sampled = (i as usize) < options.sample_count;
+            rerun(sim, seed, i, if sampled { EventDetail::Full } else { options.event_detail })
+        },
+    )
+}
+
+/// Call `simulate` for each iteration in the range, split across `threads` workers, and return
+/// the results in iteration order
+pub(crate) fn in_parallel<T: Send>(threads: usize, iterations: Range<u32>, simulate: impl Fn(u32) -> T + Sync) -> Vec<T> {
+    in_parallel_with(threads, iterations, || (), |_, i| simulate(i))
+}
+
+/// [`in_parallel`] with per-worker state: each worker calls `init` once and hands the result
+/// to every `simulate` call it makes
+pub(crate) fn in_parallel_with<S, T: Send>(
+    threads: usize,
+    iterations: Range<u32>,
+    init: impl Fn() -> S + Sync,
+    simulate: impl Fn(&mut S, u32) -> T + Sync,
+) -> Vec<T> {
+    let indices: Vec<u32> = iterations.collect();
+    if threads <= 1 {
+        let mut state = init();
+        return indices.into_iter().map(|i| simulate(&mut state, i)).collect();
+    }
+    let chunk_size = indices.len().div_ceil(threads).max(1);
+    let (init, simulate) = (&init, &simulate);
+    thread::scope(|scope| {
+        let handles: Vec<_> = indices
+            .chunks(chunk_size)
+            .map(|chunk| {
+                scope.spawn(move || {
+                    let mut state = init();
+                    chunk.iter().map(|&i| simulate(&mut state, i)).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|h| h.join().expect("combat simulation panicked"))
+            .collect()
+    })
+}
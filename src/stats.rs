@@ -1,15 +1,28 @@
+use std::collections::BTreeMap;
+
 use serde::Serialize;
+use utoipa::ToSchema;
 
-use crate::combat::{CombatResult, EventType};
-use crate::types::Side;
+use crate::analysis::Matchup;
+use crate::combat::{CombatEvent, CombatResult, EventDetail, EventType, Stalemate};
+use crate::modifiers::Modifier;
+use crate::types::{DrawPolicy, Objective, Side, Trigger, ZoneLayout};
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct SimulationStats {
     pub iterations: u32,
     pub side1_win_rate: f64,
     pub side2_win_rate: f64,
     pub draw_rate: f64,
+    /// Percent of combats that hit max_rounds (whether or not the draw policy picked a winner)
+    pub timeout_rate: f64,
+    /// Percent of combats in which both sides went down together, with nobody left standing.
+    /// These are draws too; the rest of `draw_rate` is timeouts nobody won.
+    pub mutual_destruction_rate: f64,
     pub avg_rounds: f64,
+    /// Why the timed-out combats stalled (absent without any)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stalemates: Option<StalemateRates>,
     pub avg_side1_casualties: f64,
     pub avg_side2_casualties: f64,
     pub side1_flawless_rate: f64,
@@ -20,70 +33,1143 @@ pub struct SimulationStats {
     pub avg_side2_hp_lost_percent: f64,
     pub side1_tpk_rate: f64,
     pub side2_tpk_rate: f64,
+    /// Damage each side's temp HP soaked per combat. This and the other stats built from
+    /// combat events are absent when `event_detail` dropped the events they need: below
+    /// `full` for most, at `none` for `first_round_death_rate` and `kills`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side1_temp_hp_absorbed: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side2_temp_hp_absorbed: Option<f64>,
+    /// Max HP permanently lost to drain, averaged over each side's survivors
+    pub avg_side1_survivor_hp_drained: f64,
+    pub avg_side2_survivor_hp_drained: f64,
+    /// Actors per combat that fled through an exit (alive, but out of the fight)
+    pub avg_side1_fled: f64,
+    pub avg_side2_fled: f64,
+    /// Creatures each side summoned, or left behind on death, per combat. Casualties, HP lost,
+    /// and TPKs count only the encounter's own actors.
+    pub avg_side1_summoned: f64,
+    pub avg_side2_summoned: f64,
+    /// Of the combats in which a side had VIPs, percent in which none of them died (absent
+    /// for a side without any)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub side1_vip_survival_rate: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub side2_vip_survival_rate: Option<f64>,
+    /// How the encounter's noncombatants fared, on whichever side (absent without any)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub civilians: Option<CivilianStats>,
+    /// Percent of combats in which each side was surprised
+    pub side1_surprised_rate: f64,
+    pub side2_surprised_rate: f64,
+    /// Of the combats with a surprise round, percent won by the side that got it
+    pub ambusher_win_rate: f64,
+    /// Percent of combats in which anyone died in round 1
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub first_round_death_rate: Option<f64>,
+    /// Attack damage each side dealt in round 1 (including damage soaked by temp HP)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side1_round1_damage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side2_round1_damage: Option<f64>,
+    /// Percent of combats in which a side was down to under 25% of its starting HP (the
+    /// dead and fled counting as zero) by the end of round 2 - high means swingy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub decided_by_round2_rate: Option<f64>,
+    /// Damage each side dealt beyond its targets' remaining HP, per kill it scored
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side1_overkill: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side2_overkill: Option<f64>,
+    /// Damaging hits per combat on targets that were already down (in simultaneous rounds,
+    /// where the fallen are only removed at the end)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side1_wasted_hits: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side2_wasted_hits: Option<f64>,
+    /// Attacks per combat that never happened because the target was out of reach (or down,
+    /// with `lost_target: retarget` and nobody else to attack) by the time they resolved
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side1_wasted_actions: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side2_wasted_actions: Option<f64>,
+    /// Damage each side's own attacks dealt to its own actors per combat (stray shots, and area
+    /// attacks with `friendly_fire`), including damage soaked by temp HP
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side1_friendly_fire_damage: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side2_friendly_fire_damage: Option<f64>,
+    /// The side's own actors killed by its own attacks, per combat
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side1_self_inflicted_casualties: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub avg_side2_self_inflicted_casualties: Option<f64>,
+    /// 95% confidence intervals (Wilson score) for the outcome rates, in percent
+    pub side1_win_rate_ci: ConfidenceInterval,
+    pub side2_win_rate_ci: ConfidenceInterval,
+    pub draw_rate_ci: ConfidenceInterval,
+    /// Standard errors of the outcome rates, in percentage points
+    pub side1_win_rate_std_error: f64,
+    pub side2_win_rate_std_error: f64,
+    /// Sample standard deviations of the per-combat values behind the averages
+    pub std_rounds: f64,
+    pub std_side1_casualties: f64,
+    pub std_side2_casualties: f64,
+    pub std_side1_hp_lost: f64,
+    pub std_side2_hp_lost: f64,
+    pub distributions: Distributions,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub kills: Option<KillAttribution>,
+    /// How long each encounter entry takes to kill each opposing one
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_to_kill: Option<Vec<TimeToKill>>,
+    /// How often each of the encounter's objectives decided the combat
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub objectives: Vec<ObjectiveRate>,
+    /// Scores of timed-out combats under a scored draw policy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scores: Option<ScoreDistribution>,
+    /// The same headline numbers for each side beyond the first two
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub factions: Vec<FactionStats>,
+    /// Average front line position at the end of each round
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub front_line: Vec<FrontLine>,
+    /// Who held each zone when the fighting stopped
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub zone_control: Vec<ZoneControl>,
+}
+
+impl SimulationStats {
+    pub fn win_rate(&self, side: Side) -> f64 {
+        match side.index() {
+            0 => self.side1_win_rate,
+            1 => self.side2_win_rate,
+            n => self.factions.get(n - 2).map_or(0.0, |faction| faction.win_rate),
+        }
+    }
+}
+
+/// Percent of all combats that timed out for each reason (see [`Stalemate`]); they add up to
+/// `timeout_rate`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StalemateRates {
+    pub attrition: f64,
+    pub passive: f64,
+    pub no_hits_possible: f64,
+    pub zone_deadlock: f64,
+    pub out_of_reach: f64,
+}
+
+/// Outcomes for one of the encounter's `factions`
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FactionStats {
+    pub side: Side,
+    pub win_rate: f64,
+    pub win_rate_ci: ConfidenceInterval,
+    pub avg_casualties: f64,
+    pub avg_hp_lost: f64,
+    pub avg_hp_lost_percent: f64,
+    pub flawless_rate: f64,
+    pub tpk_rate: f64,
+}
+
+/// Where the front line stood at the end of one round, averaged over the combats that had one
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct FrontLine {
+    pub round: u32,
+    /// Zones from side 1's rear to the midpoint between the two sides' most advanced fighters
+    /// on the main line: higher means side 1 has pushed further
+    pub avg_position: f64,
+    /// Combats still going at the end of the round with both sides on the main line
+    pub combats: u32,
 }
 
-#[derive(Debug, Clone, Serialize)]
+/// Percent of combats that ended with a zone in each side's hands
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ZoneControl {
+    pub zone: String,
+    pub side1_rate: f64,
+    pub side2_rate: f64,
+    /// More than one side standing in it
+    pub contested_rate: f64,
+}
+
+/// Noncombatant losses. They're kept out of their sides' casualties, HP lost, and TPKs.
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct CivilianStats {
+    /// Noncombatants killed per combat
+    pub avg_casualties: f64,
+    /// Percent of all noncombatants killed
+    pub casualty_rate: f64,
+    /// Percent of combats in which every noncombatant lived (fleeing counts)
+    pub all_saved_rate: f64,
+}
+
+/// How the sides scored in combats that hit the round limit
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ScoreDistribution {
+    /// The draw policy doing the scoring
+    pub metric: DrawPolicy,
+    /// Number of combats that were scored
+    pub combats: u32,
+    pub avg_side1: f64,
+    pub avg_side2: f64,
+    /// Side 1's score minus side 2's, per scored combat
+    pub margin_percentiles: Percentiles,
+    pub margin_histogram: Vec<HistogramBucket>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ObjectiveRate {
+    pub side: String,
+    pub objective: String,
+    /// Percent of combats won through this objective
+    pub rate: f64,
+}
+
+/// Who killed whom, aggregated over all combats
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct KillAttribution {
+    pub actors: Vec<ActorKillSummary>,
+    /// Killer/victim pairs, most frequent first
+    pub matchups: Vec<KillMatchup>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct ActorKillSummary {
+    pub name: String,
+    pub side: String,
+    pub avg_kills: f64,
+    /// Percent of combats in which this actor killed at least one enemy
+    pub kill_rate: f64,
+    /// Percent of combats in which this actor died
+    pub death_rate: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct KillMatchup {
+    pub killer: String,
+    pub victim: String,
+    /// Percent of combats in which `killer` landed the killing blow on `victim`
+    pub rate: f64,
+}
+
+/// Kills of one encounter entry's actors by another's, e.g. skeletons against the mage
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct TimeToKill {
+    pub attacker: String,
+    pub defender: String,
+    /// Percent of combats in which an `attacker` killed at least one `defender`
+    pub kill_rate: f64,
+    /// Attacks (hits and misses) `attacker`s aimed at a `defender` before it fell, per kill
+    pub avg_attacks_to_kill: f64,
+    /// Round of the first such kill, over the combats that had one
+    pub avg_rounds_to_first_kill: f64,
+}
+
+/// Full outcome distributions - averages hide bimodal fights
+#[derive(Debug, Clone, Default, Serialize, ToSchema)]
+pub struct Distributions {
+    /// How many combats lasted each number of rounds
+    pub rounds_histogram: Vec<HistogramBucket>,
+    pub rounds_percentiles: Percentiles,
+    pub side1_hp_lost_percentiles: Percentiles,
+    pub side2_hp_lost_percentiles: Percentiles,
+    /// Percent of combats ending with exactly N casualties (index = N)
+    pub side1_casualty_distribution: Vec<f64>,
+    pub side2_casualty_distribution: Vec<f64>,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, ToSchema)]
+pub struct HistogramBucket {
+    pub value: i64,
+    pub count: u32,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub struct Percentiles {
+    pub p10: f64,
+    pub p50: f64,
+    pub p90: f64,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, ToSchema)]
+pub struct ConfidenceInterval {
+    pub low: f64,
+    pub high: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct SimulationResult {
+    /// Master seed the run used; pass it to `/replay` with a sample's `iteration`
+    pub seed: u64,
     pub stats: SimulationStats,
+    /// Exact hit chances and expected damage for every attacker/target pair, computed from the
+    /// statblocks rather than simulated
+    pub matchups: Vec<Matchup>,
     pub sample_combats: Vec<CombatLog>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct CombatLog {
+    /// 0-based index of this combat within its run
+    pub iteration: u32,
     pub winner: Option<String>,
     pub rounds: u32,
+    /// Side that lost the first round to surprise
+    pub surprised: Option<String>,
+    /// Why the combat stalled, if it ran out of rounds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stalemate: Option<Stalemate>,
     pub events: Vec<CombatLogEntry>,
     pub final_state: Vec<ActorFinalState>,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct CombatLogEntry {
     pub round: u32,
+    pub actor_id: usize,
     pub actor: String,
     pub description: String,
+    /// The same event in machine-readable form
+    pub event: EventType,
 }
 
-#[derive(Debug, Clone, Serialize)]
+#[derive(Debug, Clone, Serialize, ToSchema)]
 pub struct ActorFinalState {
+    /// Matches `actor_id` and `target_id` in log events
+    pub id: usize,
     pub name: String,
     pub side: String,
     pub hp: String,
     pub alive: bool,
+    /// Left the fight through an exit
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub fled: bool,
+    /// Id of the actor that summoned this one, or whose death spawned it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub summoner: Option<usize>,
+    /// Its death loses the fight for its side
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub vip: bool,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub noncombatant: bool,
     pub zone: String,
 }
 
+/// Counts per distinct value. Memory is bounded by the number of distinct values
+/// (rounds, HP totals, casualty counts), not by the number of combats.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Histogram {
+    counts: BTreeMap<i64, u32>,
+    total: u32,
+}
+
+impl Histogram {
+    pub(crate) fn add(&mut self, value: i64) {
+        *self.counts.entry(value).or_insert(0) += 1;
+        self.total += 1;
+    }
+
+    /// Nearest-rank percentile (`p` in 0..=100)
+    fn percentile(&self, p: f64) -> f64 {
+        if self.total == 0 {
+            return 0.0;
+        }
+        let rank = ((p / 100.0 * self.total as f64).ceil() as u32).max(1);
+        let mut seen = 0;
+        for (&value, &count) in &self.counts {
+            seen += count;
+            if seen >= rank {
+                return value as f64;
+            }
+        }
+        0.0
+    }
+
+    pub(crate) fn percentiles(&self) -> Percentiles {
+        Percentiles {
+            p10: self.percentile(10.0),
+            p50: self.percentile(50.0),
+            p90: self.percentile(90.0),
+        }
+    }
+
+    fn buckets(&self) -> Vec<HistogramBucket> {
+        self.counts
+            .iter()
+            .map(|(&value, &count)| HistogramBucket { value, count })
+            .collect()
+    }
+
+    /// Percent of entries at each value from 0 to `max`
+    fn dense_rates(&self, max: usize) -> Vec<f64> {
+        (0..=max as i64)
+            .map(|v| {
+                let count = self.counts.get(&v).copied().unwrap_or(0);
+                if self.total == 0 {
+                    0.0
+                } else {
+                    count as f64 / self.total as f64 * 100.0
+                }
+            })
+            .collect()
+    }
+}
+
+/// Running per-actor kill/death counts, keyed by actor id (stable across iterations)
+#[derive(Debug, Clone, Default)]
+struct KillTally {
+    actors: BTreeMap<usize, (String, Side)>,
+    kills: BTreeMap<usize, u32>,
+    combats_with_kill: BTreeMap<usize, u32>,
+    deaths: BTreeMap<usize, u32>,
+    matchups: BTreeMap<(usize, usize), u32>,
+}
+
+impl KillTally {
+    fn add(&mut self, result: &CombatResult) {
+        for actor in &result.final_state {
+            self.actors
+                .entry(actor.id)
+                .or_insert_with(|| (actor.name.to_string(), actor.side));
+        }
+
+        let side_of = |id: usize| result.final_state.iter().find(|a| a.id == id).map(|a| a.side);
+        let mut killers = Vec::new();
+        for event in &result.events {
+            if let EventType::Death { killer_id } = event.event_type {
+                *self.deaths.entry(event.actor_id).or_insert(0) += 1;
+                if let Some(killer) = killer_id {
+                    *self.matchups.entry((killer, event.actor_id)).or_insert(0) += 1;
+                    // Friendly fire shows up in the matchups but isn't a kill
+                    if side_of(killer) == side_of(event.actor_id) {
+                        continue;
+                    }
+                    *self.kills.entry(killer).or_insert(0) += 1;
+                    if !killers.contains(&killer) {
+                        killers.push(killer);
+                    }
+                }
+            }
+        }
+        for killer in killers {
+            *self.combats_with_kill.entry(killer).or_insert(0) += 1;
+        }
+    }
+
+    fn attribution(&self, iterations: u32) -> KillAttribution {
+        let n = iterations as f64;
+        let name = |id: &usize| self.actors.get(id).map(|a| a.0.clone()).unwrap_or_default();
+        let count = |map: &BTreeMap<usize, u32>, id: &usize| map.get(id).copied().unwrap_or(0) as f64;
+
+        let actors = self
+            .actors
+            .iter()
+            .map(|(id, (name, side))| ActorKillSummary {
+                name: name.clone(),
+                side: format!("{:?}", side),
+                avg_kills: count(&self.kills, id) / n,
+                kill_rate: count(&self.combats_with_kill, id) / n * 100.0,
+                death_rate: count(&self.deaths, id) / n * 100.0,
+            })
+            .collect();
+
+        let mut matchups: Vec<KillMatchup> = self
+            .matchups
+            .iter()
+            .map(|((killer, victim), &kills)| KillMatchup {
+                killer: name(killer),
+                victim: name(victim),
+                rate: kills as f64 / n * 100.0,
+            })
+            .collect();
+        matchups.sort_by(|a, b| b.rate.total_cmp(&a.rate));
+
+        KillAttribution { actors, matchups }
+    }
+}
+
+/// Running totals behind `TimeToKill`, keyed by (attacker, defender) entry name
+#[derive(Debug, Default)]
+struct TimeToKillTally {
+    matchups: BTreeMap<(String, String), MatchupTally>,
+}
+
+#[derive(Debug, Default)]
+struct MatchupTally {
+    kills: u32,
+    attacks: u64,
+    combats_with_kill: u32,
+    first_kill_rounds: u64,
+}
+
+impl TimeToKillTally {
+    fn add(&mut self, result: &CombatResult) {
+        let template = |id: usize| result.final_state.iter().find(|a| a.id == id).map(|a| &*a.template);
+        let side_of = |id: usize| result.final_state.iter().find(|a| a.id == id).map(|a| a.side);
+        // Attacks so far on each actor, by the attacker's entry
+        let mut attacks: BTreeMap<(&str, usize), u32> = BTreeMap::new();
+        let mut first_kills: Vec<(&str, &str)> = Vec::new();
+        for event in &result.events {
+            match event.event_type {
+                EventType::Attack { target_id, damage, absorbed, overkill, .. } => {
+                    if let Some(attacker) = template(event.actor_id).filter(|_| !on_fallen(damage, absorbed, overkill)) {
+                        *attacks.entry((attacker, target_id)).or_insert(0) += 1;
+                    }
+                }
+                EventType::Death { killer_id: Some(killer_id) } if side_of(killer_id) != side_of(event.actor_id) => {
+                    let (Some(attacker), Some(defender)) = (template(killer_id), template(event.actor_id)) else {
+                        continue;
+                    };
+                    let tally = self.matchups.entry((attacker.to_string(), defender.to_string())).or_default();
+                    tally.kills += 1;
+                    tally.attacks += attacks.get(&(attacker, event.actor_id)).copied().unwrap_or(0) as u64;
+                    if !first_kills.contains(&(attacker, defender)) {
+                        first_kills.push((attacker, defender));
+                        tally.combats_with_kill += 1;
+                        tally.first_kill_rounds += event.round as u64;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    fn matrix(&self, iterations: u32) -> Vec<TimeToKill> {
+        self.matchups
+            .iter()
+            .map(|((attacker, defender), tally)| TimeToKill {
+                attacker: attacker.clone(),
+                defender: defender.clone(),
+                kill_rate: tally.combats_with_kill as f64 / iterations as f64 * 100.0,
+                avg_attacks_to_kill: tally.attacks as f64 / tally.kills as f64,
+                avg_rounds_to_first_kill: tally.first_kill_rounds as f64 / tally.combats_with_kill as f64,
+            })
+            .collect()
+    }
+}
+
+/// A damaging hit that went entirely to overkill: its target was already down
+fn on_fallen(damage: i32, absorbed: i32, overkill: i32) -> bool {
+    overkill > 0 && overkill == damage - absorbed
+}
+
+/// Headline numbers for a single combat (side 1 and side 2 only)
+#[derive(Debug, Clone, Copy)]
+pub struct CombatSummary {
+    pub winner: Option<Side>,
+    pub rounds: u32,
+    pub timed_out: bool,
+    pub side1_casualties: usize,
+    pub side2_casualties: usize,
+    pub side1_hp_lost: i32,
+    pub side2_hp_lost: i32,
+    pub stalemate: Option<Stalemate>,
+}
+
+impl CombatSummary {
+    pub fn from_result(result: &CombatResult) -> Self {
+        let mut summary = CombatSummary {
+            winner: result.winner,
+            rounds: result.rounds,
+            timed_out: result.timed_out,
+            side1_casualties: 0,
+            side2_casualties: 0,
+            side1_hp_lost: 0,
+            side2_hp_lost: 0,
+            stalemate: result.stalemate,
+        };
+        for actor in result.final_state.iter().filter(|a| a.summoner.is_none() && !a.noncombatant) {
+            let hp_lost = actor.max_hp - actor.final_hp.max(0);
+            let (casualties, hp) = match actor.side {
+                Side::SIDE1 => (&mut summary.side1_casualties, &mut summary.side1_hp_lost),
+                Side::SIDE2 => (&mut summary.side2_casualties, &mut summary.side2_hp_lost),
+                _ => continue,
+            };
+            *hp += hp_lost;
+            if !actor.alive {
+                *casualties += 1;
+            }
+        }
+        summary
+    }
+
+    /// One row of the raw per-iteration CSV export (see `CSV_HEADER`)
+    pub fn csv_row(&self, iteration: u32) -> String {
+        let winner = self.winner.map_or_else(|| "draw".to_string(), |side| side.to_string());
+        let stalemate = self.stalemate.map_or_else(String::new, |stalemate| stalemate.to_string());
+        format!(
+            "{},{},{},{},{},{},{},{},{}\n",
+            iteration,
+            winner,
+            self.rounds,
+            self.timed_out,
+            self.side1_casualties,
+            self.side2_casualties,
+            self.side1_hp_lost,
+            self.side2_hp_lost,
+            stalemate
+        )
+    }
+}
+
+/// Header for `CombatSummary::csv_row`
+pub const CSV_HEADER: &str =
+    "iteration,winner,rounds,timed_out,side1_casualties,side2_casualties,side1_hp_lost,side2_hp_lost,stalemate\n";
+
+/// Running per-side totals across all combats
+#[derive(Debug, Clone, Default)]
+struct SideTally {
+    wins: u32,
+    casualties: u64,
+    casualties_sq: f64,
+    flawless: u32,
+    hp_lost: i64,
+    hp_lost_sq: f64,
+    tpks: u32,
+    temp_hp_absorbed: i64,
+    /// Drained max HP and head count of actors alive at the end of a combat
+    survivor_hp_drained: i64,
+    survivors: u64,
+    fled: u64,
+    summoned: u64,
+    /// Combats in which the side had a VIP, and those in which every VIP lived
+    vip_combats: u32,
+    vip_survived: u32,
+    surprised: u32,
+    /// Attack damage dealt in round 1
+    round1_damage: i64,
+    /// Killing blows landed, the damage they (and hits on the already fallen) wasted, and
+    /// those hits on the fallen
+    kills: u64,
+    overkill: i64,
+    wasted_hits: u64,
+    wasted_actions: u64,
+    /// Damage dealt to the side's own actors by its own attacks, and those it killed
+    friendly_fire_damage: i64,
+    self_inflicted: u64,
+    casualty_histogram: Histogram,
+    hp_lost_histogram: Histogram,
+}
+
+/// A faction's size alongside its running totals
+#[derive(Debug, Clone, Default)]
+struct FactionTally {
+    actors: usize,
+    hp: i32,
+    tally: SideTally,
+}
+
+/// Combats that ended with a zone held by side 1, side 2, or contested
+#[derive(Debug, Clone, Default)]
+struct ZoneTally {
+    name: String,
+    side1: u32,
+    side2: u32,
+    contested: u32,
+}
+
+/// Running noncombatant totals
+#[derive(Debug, Clone, Default)]
+struct CivilianTally {
+    /// Noncombatants fielded, summed over combats
+    fielded: u64,
+    dead: u64,
+    /// Combats in which none of them died
+    all_saved: u32,
+}
+
+/// Running score totals for timed-out combats
+#[derive(Debug, Clone, Default)]
+struct ScoreTally {
+    metric: DrawPolicy,
+    combats: u32,
+    side1: i64,
+    side2: i64,
+    margins: Histogram,
+}
+
+impl ScoreTally {
+    fn distribution(&self) -> ScoreDistribution {
+        let average = |total: i64| {
+            if self.combats == 0 {
+                0.0
+            } else {
+                total as f64 / self.combats as f64
+            }
+        };
+        ScoreDistribution {
+            metric: self.metric,
+            combats: self.combats,
+            avg_side1: average(self.side1),
+            avg_side2: average(self.side2),
+            margin_percentiles: self.margins.percentiles(),
+            margin_histogram: self.margins.buckets(),
+        }
+    }
+}
+
+impl SideTally {
+    fn overkill_per_kill(&self) -> f64 {
+        if self.kills == 0 {
+            0.0
+        } else {
+            self.overkill as f64 / self.kills as f64
+        }
+    }
+
+    fn vip_survival_rate(&self) -> Option<f64> {
+        (self.vip_combats > 0).then(|| self.vip_survived as f64 / self.vip_combats as f64 * 100.0)
+    }
+
+    fn survivor_drain(&self) -> f64 {
+        if self.survivors == 0 {
+            0.0
+        } else {
+            self.survivor_hp_drained as f64 / self.survivors as f64
+        }
+    }
+}
+
+/// Streaming aggregator: folds each combat into running totals as it arrives and only
+/// keeps the first `sample_count` combats (formatted as logs) for debugging output.
 pub struct StatsCollector {
-    results: Vec<CombatResult>,
     side1_total_actors: usize,
     side2_total_actors: usize,
     side1_total_hp: i32,
     side2_total_hp: i32,
+    sample_count: usize,
+    samples: Vec<CombatLog>,
+    iterations: u32,
+    draws: u32,
+    timeouts: u32,
+    /// Timed-out combats by why they stalled, indexed by `Stalemate as usize`
+    stalemates: [u32; Stalemate::ALL.len()],
+    mutual_destructions: u32,
+    /// Combats won by the side that got a surprise round
+    ambusher_wins: u32,
+    first_round_deaths: u32,
+    decided_early: u32,
+    total_rounds: u64,
+    total_rounds_sq: f64,
+    rounds_histogram: Histogram,
+    kills: KillTally,
+    time_to_kill: TimeToKillTally,
+    /// Encounter objectives and how many combats each one decided
+    objectives: Vec<(Objective, u32)>,
+    scores: Option<ScoreTally>,
+    /// What non-sample combats record (see `with_event_detail`)
+    event_detail: EventDetail,
+    side1: SideTally,
+    side2: SideTally,
+    /// Sides beyond the first two, in order
+    factions: Vec<FactionTally>,
+    civilians: CivilianTally,
+    /// Sum and count of front line positions, by round
+    front_line: Vec<(f64, u32)>,
+    zones: Vec<ZoneTally>,
 }
 
 impl StatsCollector {
-    pub fn new(side1_count: usize, side2_count: usize, side1_hp: i32, side2_hp: i32) -> Self {
+    pub fn new(
+        side1_count: usize,
+        side2_count: usize,
+        side1_hp: i32,
+        side2_hp: i32,
+        sample_count: usize,
+    ) -> Self {
         StatsCollector {
-            results: Vec::new(),
             side1_total_actors: side1_count,
             side2_total_actors: side2_count,
             side1_total_hp: side1_hp,
             side2_total_hp: side2_hp,
+            sample_count,
+            samples: Vec::with_capacity(sample_count),
+            iterations: 0,
+            draws: 0,
+            timeouts: 0,
+            stalemates: [0; Stalemate::ALL.len()],
+            mutual_destructions: 0,
+            ambusher_wins: 0,
+            first_round_deaths: 0,
+            decided_early: 0,
+            total_rounds: 0,
+            total_rounds_sq: 0.0,
+            rounds_histogram: Histogram::default(),
+            kills: KillTally::default(),
+            time_to_kill: TimeToKillTally::default(),
+            objectives: Vec::new(),
+            scores: None,
+            event_detail: EventDetail::Full,
+            side1: SideTally::default(),
+            side2: SideTally::default(),
+            factions: Vec::new(),
+            civilians: CivilianTally::default(),
+            front_line: Vec::new(),
+            zones: Vec::new(),
+        }
+    }
+
+    /// Report who holds each of the layout's zones at the end of a combat
+    pub fn with_zones(mut self, layout: &ZoneLayout) -> Self {
+        self.zones = layout
+            .zones()
+            .iter()
+            .map(|zone| ZoneTally { name: zone.name.clone(), ..Default::default() })
+            .collect();
+        self
+    }
+
+    /// Tally the encounter's `factions` too, given each one's actor count and expected HP
+    pub fn with_factions(mut self, sizes: impl IntoIterator<Item = (usize, i32)>) -> Self {
+        self.factions = sizes
+            .into_iter()
+            .map(|(actors, hp)| FactionTally {
+                actors,
+                hp,
+                tally: SideTally::default(),
+            })
+            .collect();
+        self
+    }
+
+    fn tally(&mut self, side: Side) -> &mut SideTally {
+        match side.index() {
+            0 => &mut self.side1,
+            1 => &mut self.side2,
+            n => {
+                if self.factions.len() < n - 1 {
+                    self.factions.resize_with(n - 1, FactionTally::default);
+                }
+                &mut self.factions[n - 2].tally
+            }
         }
     }
 
+    /// A side's actor count at the start of a combat
+    fn total_actors(&self, side: Side) -> usize {
+        match side.index() {
+            0 => self.side1_total_actors,
+            1 => self.side2_total_actors,
+            n => self.factions.get(n - 2).map_or(0, |faction| faction.actors),
+        }
+    }
+
+    /// Expect combats recorded at `detail` (sample combats excepted). Stats that need events the
+    /// level drops are left out (None) rather than counted from the few combats that have them:
+    /// below `full`, temp HP absorbed, opening-round damage, `decided_by_round2_rate`, overkill,
+    /// wasted hits and actions, friendly fire, and `time_to_kill`; at `none`, also kill
+    /// attribution and `first_round_death_rate`.
+    pub fn with_event_detail(mut self, detail: EventDetail) -> Self {
+        self.event_detail = detail;
+        self
+    }
+
+    /// Track how often each of these objectives decides a combat
+    pub fn with_objectives(mut self, objectives: &[Objective]) -> Self {
+        self.objectives = objectives.iter().map(|o| (o.clone(), 0)).collect();
+        self
+    }
+
+    /// Collect score distributions for timeouts decided by a scored draw policy
+    pub fn with_draw_policy(mut self, policy: DrawPolicy) -> Self {
+        self.scores = policy.is_scored().then(|| ScoreTally {
+            metric: policy,
+            ..Default::default()
+        });
+        self
+    }
+
     pub fn add_result(&mut self, result: CombatResult) {
-        self.results.push(result);
+        self.iterations += 1;
+        if let (Some(tally), Some((side1, side2))) = (&mut self.scores, result.score) {
+            tally.combats += 1;
+            tally.side1 += side1;
+            tally.side2 += side2;
+            tally.margins.add(side1 - side2);
+        }
+        if let Some((_, count)) = result.objective.and_then(|i| self.objectives.get_mut(i)) {
+            *count += 1;
+        }
+        self.total_rounds += result.rounds as u64;
+        self.total_rounds_sq += (result.rounds as f64).powi(2);
+        self.rounds_histogram.add(result.rounds as i64);
+
+        match result.winner {
+            Some(side) => self.tally(side).wins += 1,
+            None => self.draws += 1,
+        }
+        if result.timed_out {
+            self.timeouts += 1;
+        }
+        if let Some(stalemate) = result.stalemate {
+            self.stalemates[stalemate as usize] += 1;
+        }
+        if result.mutual_destruction {
+            self.mutual_destructions += 1;
+        }
+        if let Some(side) = result.surprised {
+            self.tally(side).surprised += 1;
+            if result.winner.is_some_and(|winner| winner != side) {
+                self.ambusher_wins += 1;
+            }
+        }
+
+        // Casualties and HP lost by each side's own actors, indexed by side
+        let summary = CombatSummary::from_result(&result);
+        let mut losses = vec![
+            (summary.side1_casualties, summary.side1_hp_lost),
+            (summary.side2_casualties, summary.side2_hp_lost),
+        ];
+        for actor in result.final_state.iter().filter(|a| a.summoner.is_none() && !a.noncombatant && a.side.index() >= 2) {
+            if losses.len() <= actor.side.index() {
+                losses.resize(actor.side.index() + 1, (0, 0));
+            }
+            let (dead, hp_lost) = &mut losses[actor.side.index()];
+            *hp_lost += actor.max_hp - actor.final_hp.max(0);
+            if !actor.alive {
+                *dead += 1;
+            }
+        }
+        for (i, (dead, hp_loss)) in losses.into_iter().enumerate() {
+            let side = Side::new(i);
+            let tpk = dead == self.total_actors(side);
+            let tally = self.tally(side);
+            tally.casualties += dead as u64;
+            tally.hp_lost += hp_loss as i64;
+            tally.casualties_sq += (dead as f64).powi(2);
+            tally.hp_lost_sq += (hp_loss as f64).powi(2);
+            tally.casualty_histogram.add(dead as i64);
+            tally.hp_lost_histogram.add(hp_loss as i64);
+            if dead == 0 && result.winner == Some(side) {
+                tally.flawless += 1;
+            }
+            if tpk {
+                tally.tpks += 1;
+            }
+
+            let mut vips = result.final_state.iter().filter(|a| a.side == side && a.vip).peekable();
+            if vips.peek().is_some() {
+                tally.vip_combats += 1;
+                if vips.all(|a| a.alive) {
+                    tally.vip_survived += 1;
+                }
+            }
+        }
+
+        let civilians = result.final_state.iter().filter(|a| a.noncombatant && a.summoner.is_none());
+        let (fielded, dead) = civilians.fold((0, 0), |(fielded, dead), a| (fielded + 1, dead + !a.alive as u64));
+        self.civilians.fielded += fielded;
+        self.civilians.dead += dead;
+        if dead == 0 {
+            self.civilians.all_saved += 1;
+        }
+
+        for actor in &result.final_state {
+            let tally = self.tally(actor.side);
+            if actor.summoner.is_some() {
+                tally.summoned += 1;
+                continue;
+            }
+            if !actor.alive || actor.noncombatant {
+                continue;
+            }
+            tally.survivors += 1;
+            tally.survivor_hp_drained += actor.drained as i64;
+            if actor.fled {
+                tally.fled += 1;
+            }
+        }
+
+        for (round, position) in result.front_line.iter().enumerate() {
+            if self.front_line.len() <= round {
+                self.front_line.resize(round + 1, (0.0, 0));
+            }
+            if let Some(position) = position {
+                self.front_line[round].0 += position;
+                self.front_line[round].1 += 1;
+            }
+        }
+        self.add_zone_control(&result);
+
+        if self.event_detail != EventDetail::None {
+            self.kills.add(&result);
+            self.add_first_round_death(&result);
+        }
+        if self.event_detail == EventDetail::Full {
+            self.add_temp_hp(&result);
+            self.time_to_kill.add(&result);
+            self.add_opening(&result);
+            self.add_overkill(&result);
+            self.add_friendly_fire(&result);
+        }
+
+        // Only sampled combats keep their event log; everything else is dropped here
+        if self.samples.len() < self.sample_count {
+            self.samples.push(format_combat_log(&result, self.iterations - 1));
+        }
+    }
+
+    /// Which side, if any, each zone ended up with: the only side with fighters standing in it
+    fn add_zone_control(&mut self, result: &CombatResult) {
+        // None while empty, Some(None) once more than one side is in it
+        let mut holders: Vec<Option<Option<Side>>> = vec![None; self.zones.len()];
+        for actor in result.final_state.iter().filter(|a| a.alive && !a.fled && !a.noncombatant) {
+            let Some(i) = self.zones.iter().position(|zone| zone.name == actor.zone) else {
+                continue;
+            };
+            holders[i] = match holders[i] {
+                None => Some(Some(actor.side)),
+                Some(Some(side)) if side == actor.side => Some(Some(side)),
+                _ => Some(None),
+            };
+        }
+        for (zone, holder) in self.zones.iter_mut().zip(holders) {
+            match holder {
+                Some(Some(Side::SIDE1)) => zone.side1 += 1,
+                Some(Some(Side::SIDE2)) => zone.side2 += 1,
+                Some(None) => zone.contested += 1,
+                _ => {}
+            }
+        }
+    }
+
+    /// Temp HP absorption, credited to the side of the actor that was hit
+    fn add_temp_hp(&mut self, result: &CombatResult) {
+        for event in &result.events {
+            let (target_id, absorbed) = match event.event_type {
+                EventType::Attack { target_id, absorbed, .. } => (target_id, absorbed),
+                EventType::StrayShot { target_id, absorbed, .. } => (target_id, absorbed),
+                EventType::Hazard { absorbed, .. } => (event.actor_id, absorbed),
+                EventType::ConditionDamage { absorbed, .. } => (event.actor_id, absorbed),
+                _ => continue,
+            };
+            if absorbed == 0 {
+                continue;
+            }
+            if let Some(side) = result.final_state.iter().find(|a| a.id == target_id).map(|a| a.side) {
+                self.tally(side).temp_hp_absorbed += absorbed as i64;
+            }
+        }
+    }
+
+    /// Whether anyone died in round 1 (events come in round order)
+    fn add_first_round_death(&mut self, result: &CombatResult) {
+        let mut round1 = result.events.iter().take_while(|e| e.round == 1);
+        if round1.any(|e| matches!(e.event_type, EventType::Death { .. })) {
+            self.first_round_deaths += 1;
+        }
+    }
+
+    /// Round-1 damage, and whether either side was broken by the end of round 2. Strength is
+    /// the encounter's own actors' HP; summoned creatures add to neither side of that.
+    fn add_opening(&mut self, result: &CombatResult) {
+        let roster = || result.final_state.iter().filter(|a| a.summoner.is_none() && !a.noncombatant);
+        let mut remaining: BTreeMap<usize, (Side, i32)> = roster().map(|a| (a.id, (a.side, a.max_hp))).collect();
+        for event in result.events.iter().take_while(|e| e.round <= DECISIVE_ROUNDS) {
+            let (target_id, loss) = match event.event_type {
+                EventType::Attack { target_id, damage, absorbed, .. } => {
+                    if event.round == 1 {
+                        if let Some(side) = result.final_state.iter().find(|a| a.id == event.actor_id).map(|a| a.side) {
+                            self.tally(side).round1_damage += damage as i64;
+                        }
+                    }
+                    (target_id, damage - absorbed)
+                }
+                EventType::Hazard { damage, absorbed, .. } | EventType::ConditionDamage { damage, absorbed, .. } => {
+                    (event.actor_id, damage - absorbed)
+                }
+                EventType::Drain { target_id, max_hp, .. } => {
+                    if let Some((_, hp)) = remaining.get_mut(&target_id) {
+                        *hp = (*hp).min(max_hp);
+                    }
+                    continue;
+                }
+                EventType::Death { .. } => (event.actor_id, i32::MAX),
+                EventType::Flee { .. } => (event.actor_id, i32::MAX),
+                _ => continue,
+            };
+            if let Some((_, hp)) = remaining.get_mut(&target_id) {
+                *hp = hp.saturating_sub(loss).max(0);
+            }
+        }
+        let broken = |side: Side| {
+            let total: i64 = roster().filter(|a| a.side == side).map(|a| a.max_hp as i64).sum();
+            let left: i64 = remaining.values().filter(|(s, _)| *s == side).map(|&(_, hp)| hp as i64).sum();
+            total > 0 && (left as f64) < total as f64 * DECISIVE_STRENGTH
+        };
+        let sides = roster().map(|a| a.side.index() + 1).max().unwrap_or(0);
+        if (0..sides).map(Side::new).any(broken) {
+            self.decided_early += 1;
+        }
+    }
+
+    /// Damage past each kill, hits on targets already down, and attacks that lost their target,
+    /// credited to the attacker's side
+    fn add_overkill(&mut self, result: &CombatResult) {
+        let side_of = |id: usize| result.final_state.iter().find(|a| a.id == id).map(|a| a.side);
+        for event in &result.events {
+            let Some(side) = side_of(event.actor_id) else {
+                continue;
+            };
+            let tally = self.tally(side);
+            match event.event_type {
+                EventType::Attack { damage, absorbed, overkill, .. } => {
+                    tally.overkill += overkill as i64;
+                    if on_fallen(damage, absorbed, overkill) {
+                        tally.wasted_hits += 1;
+                    }
+                }
+                EventType::LostTarget { .. } => tally.wasted_actions += 1,
+                EventType::Death { killer_id: Some(killer_id) } => {
+                    if let Some(side) = side_of(killer_id).filter(|&s| s != side) {
+                        self.tally(side).kills += 1;
+                    }
+                }
+                _ => {}
+            }
+        }
+    }
+
+    /// Damage and deaths a side's attacks dealt its own actors, credited to that side
+    fn add_friendly_fire(&mut self, result: &CombatResult) {
+        let side_of = |id: usize| result.final_state.iter().find(|a| a.id == id).map(|a| a.side);
+        for event in &result.events {
+            let (attacker_id, target_id, damage) = match event.event_type {
+                EventType::Attack { target_id, damage, .. } | EventType::StrayShot { target_id, damage, .. } => {
+                    (event.actor_id, target_id, damage)
+                }
+                EventType::Death { killer_id: Some(killer_id) } => (killer_id, event.actor_id, 0),
+                _ => continue,
+            };
+            let Some(side) = side_of(attacker_id).filter(|&side| side_of(target_id) == Some(side)) else {
+                continue;
+            };
+            let tally = self.tally(side);
+            match event.event_type {
+                EventType::Death { .. } => tally.self_inflicted += 1,
+                _ => tally.friendly_fire_damage += damage as i64,
+            }
+        }
     }
 
     pub fn compute_stats(&self) -> SimulationStats {
-        let n = self.results.len() as f64;
+        let n = self.iterations as f64;
+        // Which of the stats built from events the combats recorded enough to count
+        let full = self.event_detail == EventDetail::Full;
+        let deaths = self.event_detail != EventDetail::None;
         if n == 0.0 {
             return SimulationStats {
                 iterations: 0,
                 side1_win_rate: 0.0,
                 side2_win_rate: 0.0,
                 draw_rate: 0.0,
+                timeout_rate: 0.0,
+                mutual_destruction_rate: 0.0,
                 avg_rounds: 0.0,
+                stalemates: None,
                 avg_side1_casualties: 0.0,
                 avg_side2_casualties: 0.0,
                 side1_flawless_rate: 0.0,
@@ -94,113 +1180,308 @@ impl StatsCollector {
                 avg_side2_hp_lost_percent: 0.0,
                 side1_tpk_rate: 0.0,
                 side2_tpk_rate: 0.0,
+                avg_side1_temp_hp_absorbed: full.then_some(0.0),
+                avg_side2_temp_hp_absorbed: full.then_some(0.0),
+                avg_side1_survivor_hp_drained: 0.0,
+                avg_side2_survivor_hp_drained: 0.0,
+                avg_side1_fled: 0.0,
+                avg_side2_fled: 0.0,
+                avg_side1_summoned: 0.0,
+                avg_side2_summoned: 0.0,
+                side1_vip_survival_rate: None,
+                side2_vip_survival_rate: None,
+                civilians: None,
+                side1_surprised_rate: 0.0,
+                side2_surprised_rate: 0.0,
+                ambusher_win_rate: 0.0,
+                first_round_death_rate: deaths.then_some(0.0),
+                avg_side1_round1_damage: full.then_some(0.0),
+                avg_side2_round1_damage: full.then_some(0.0),
+                decided_by_round2_rate: full.then_some(0.0),
+                avg_side1_overkill: full.then_some(0.0),
+                avg_side2_overkill: full.then_some(0.0),
+                avg_side1_wasted_hits: full.then_some(0.0),
+                avg_side2_wasted_hits: full.then_some(0.0),
+                avg_side1_wasted_actions: full.then_some(0.0),
+                avg_side2_wasted_actions: full.then_some(0.0),
+                avg_side1_friendly_fire_damage: full.then_some(0.0),
+                avg_side2_friendly_fire_damage: full.then_some(0.0),
+                avg_side1_self_inflicted_casualties: full.then_some(0.0),
+                avg_side2_self_inflicted_casualties: full.then_some(0.0),
+                side1_win_rate_ci: ConfidenceInterval::default(),
+                side2_win_rate_ci: ConfidenceInterval::default(),
+                draw_rate_ci: ConfidenceInterval::default(),
+                side1_win_rate_std_error: 0.0,
+                side2_win_rate_std_error: 0.0,
+                std_rounds: 0.0,
+                std_side1_casualties: 0.0,
+                std_side2_casualties: 0.0,
+                std_side1_hp_lost: 0.0,
+                std_side2_hp_lost: 0.0,
+                distributions: Distributions::default(),
+                kills: deaths.then(KillAttribution::default),
+                time_to_kill: full.then(Vec::new),
+                objectives: Vec::new(),
+                scores: None,
+                factions: Vec::new(),
+                front_line: Vec::new(),
+                zone_control: Vec::new(),
             };
         }
 
-        let mut side1_wins = 0;
-        let mut side2_wins = 0;
-        let mut draws = 0;
-        let mut total_rounds = 0;
-        let mut side1_casualties = 0;
-        let mut side2_casualties = 0;
-        let mut side1_flawless = 0;
-        let mut side2_flawless = 0;
-        let mut side1_hp_lost = 0;
-        let mut side2_hp_lost = 0;
-        let mut side1_tpk = 0;
-        let mut side2_tpk = 0;
-
-        for result in &self.results {
-            total_rounds += result.rounds;
-
-            match result.winner {
-                Some(Side::Side1) => side1_wins += 1,
-                Some(Side::Side2) => side2_wins += 1,
-                None => draws += 1,
-            }
-
-            let mut s1_dead = 0;
-            let mut s2_dead = 0;
-            let mut s1_hp_loss = 0;
-            let mut s2_hp_loss = 0;
-
-            for actor in &result.final_state {
-                let hp_lost = actor.max_hp - actor.final_hp.max(0);
-                match actor.side {
-                    Side::Side1 => {
-                        s1_hp_loss += hp_lost;
-                        if !actor.alive {
-                            s1_dead += 1;
-                        }
-                    }
-                    Side::Side2 => {
-                        s2_hp_loss += hp_lost;
-                        if !actor.alive {
-                            s2_dead += 1;
-                        }
-                    }
-                }
-            }
-
-            side1_casualties += s1_dead;
-            side2_casualties += s2_dead;
-            side1_hp_lost += s1_hp_loss;
-            side2_hp_lost += s2_hp_loss;
-
-            if s1_dead == 0 && result.winner == Some(Side::Side1) {
-                side1_flawless += 1;
-            }
-            if s2_dead == 0 && result.winner == Some(Side::Side2) {
-                side2_flawless += 1;
-            }
-
-            if s1_dead == self.side1_total_actors {
-                side1_tpk += 1;
-            }
-            if s2_dead == self.side2_total_actors {
-                side2_tpk += 1;
+        let rate = |count: u32| count as f64 / n * 100.0;
+        let hp_lost_percent = |hp_lost: i64, total_hp: i32| {
+            if total_hp > 0 {
+                (hp_lost as f64 / n) / total_hp as f64 * 100.0
+            } else {
+                0.0
             }
-        }
+        };
 
         SimulationStats {
-            iterations: self.results.len() as u32,
-            side1_win_rate: side1_wins as f64 / n * 100.0,
-            side2_win_rate: side2_wins as f64 / n * 100.0,
-            draw_rate: draws as f64 / n * 100.0,
-            avg_rounds: total_rounds as f64 / n,
-            avg_side1_casualties: side1_casualties as f64 / n,
-            avg_side2_casualties: side2_casualties as f64 / n,
-            side1_flawless_rate: side1_flawless as f64 / n * 100.0,
-            side2_flawless_rate: side2_flawless as f64 / n * 100.0,
-            avg_side1_hp_lost: side1_hp_lost as f64 / n,
-            avg_side2_hp_lost: side2_hp_lost as f64 / n,
-            avg_side1_hp_lost_percent: if self.side1_total_hp > 0 {
-                (side1_hp_lost as f64 / n) / self.side1_total_hp as f64 * 100.0
-            } else {
-                0.0
+            iterations: self.iterations,
+            side1_win_rate: rate(self.side1.wins),
+            side2_win_rate: rate(self.side2.wins),
+            draw_rate: rate(self.draws),
+            timeout_rate: rate(self.timeouts),
+            mutual_destruction_rate: rate(self.mutual_destructions),
+            avg_rounds: self.total_rounds as f64 / n,
+            stalemates: (self.timeouts > 0).then(|| {
+                let rate = |stalemate: Stalemate| rate(self.stalemates[stalemate as usize]);
+                StalemateRates {
+                    attrition: rate(Stalemate::Attrition),
+                    passive: rate(Stalemate::Passive),
+                    no_hits_possible: rate(Stalemate::NoHitsPossible),
+                    zone_deadlock: rate(Stalemate::ZoneDeadlock),
+                    out_of_reach: rate(Stalemate::OutOfReach),
+                }
+            }),
+            avg_side1_casualties: self.side1.casualties as f64 / n,
+            avg_side2_casualties: self.side2.casualties as f64 / n,
+            side1_flawless_rate: rate(self.side1.flawless),
+            side2_flawless_rate: rate(self.side2.flawless),
+            avg_side1_hp_lost: self.side1.hp_lost as f64 / n,
+            avg_side2_hp_lost: self.side2.hp_lost as f64 / n,
+            avg_side1_hp_lost_percent: hp_lost_percent(self.side1.hp_lost, self.side1_total_hp),
+            avg_side2_hp_lost_percent: hp_lost_percent(self.side2.hp_lost, self.side2_total_hp),
+            side1_tpk_rate: rate(self.side1.tpks),
+            side2_tpk_rate: rate(self.side2.tpks),
+            avg_side1_temp_hp_absorbed: full.then(|| self.side1.temp_hp_absorbed as f64 / n),
+            avg_side2_temp_hp_absorbed: full.then(|| self.side2.temp_hp_absorbed as f64 / n),
+            avg_side1_survivor_hp_drained: self.side1.survivor_drain(),
+            avg_side2_survivor_hp_drained: self.side2.survivor_drain(),
+            avg_side1_fled: self.side1.fled as f64 / n,
+            avg_side2_fled: self.side2.fled as f64 / n,
+            avg_side1_summoned: self.side1.summoned as f64 / n,
+            avg_side2_summoned: self.side2.summoned as f64 / n,
+            side1_vip_survival_rate: self.side1.vip_survival_rate(),
+            side2_vip_survival_rate: self.side2.vip_survival_rate(),
+            civilians: (self.civilians.fielded > 0).then(|| CivilianStats {
+                avg_casualties: self.civilians.dead as f64 / n,
+                casualty_rate: self.civilians.dead as f64 / self.civilians.fielded as f64 * 100.0,
+                all_saved_rate: rate(self.civilians.all_saved),
+            }),
+            side1_surprised_rate: rate(self.side1.surprised),
+            side2_surprised_rate: rate(self.side2.surprised),
+            ambusher_win_rate: match self.side1.surprised + self.side2.surprised {
+                0 => 0.0,
+                surprises => self.ambusher_wins as f64 / surprises as f64 * 100.0,
             },
-            avg_side2_hp_lost_percent: if self.side2_total_hp > 0 {
-                (side2_hp_lost as f64 / n) / self.side2_total_hp as f64 * 100.0
-            } else {
-                0.0
+            first_round_death_rate: deaths.then(|| rate(self.first_round_deaths)),
+            avg_side1_round1_damage: full.then(|| self.side1.round1_damage as f64 / n),
+            avg_side2_round1_damage: full.then(|| self.side2.round1_damage as f64 / n),
+            decided_by_round2_rate: full.then(|| rate(self.decided_early)),
+            avg_side1_overkill: full.then(|| self.side1.overkill_per_kill()),
+            avg_side2_overkill: full.then(|| self.side2.overkill_per_kill()),
+            avg_side1_wasted_hits: full.then(|| self.side1.wasted_hits as f64 / n),
+            avg_side2_wasted_hits: full.then(|| self.side2.wasted_hits as f64 / n),
+            avg_side1_wasted_actions: full.then(|| self.side1.wasted_actions as f64 / n),
+            avg_side2_wasted_actions: full.then(|| self.side2.wasted_actions as f64 / n),
+            avg_side1_friendly_fire_damage: full.then(|| self.side1.friendly_fire_damage as f64 / n),
+            avg_side2_friendly_fire_damage: full.then(|| self.side2.friendly_fire_damage as f64 / n),
+            avg_side1_self_inflicted_casualties: full.then(|| self.side1.self_inflicted as f64 / n),
+            avg_side2_self_inflicted_casualties: full.then(|| self.side2.self_inflicted as f64 / n),
+            side1_win_rate_ci: wilson_interval(self.side1.wins, self.iterations),
+            side2_win_rate_ci: wilson_interval(self.side2.wins, self.iterations),
+            draw_rate_ci: wilson_interval(self.draws, self.iterations),
+            side1_win_rate_std_error: rate_std_error(self.side1.wins, self.iterations),
+            side2_win_rate_std_error: rate_std_error(self.side2.wins, self.iterations),
+            std_rounds: std_dev(self.total_rounds as f64, self.total_rounds_sq, self.iterations),
+            std_side1_casualties: std_dev(self.side1.casualties as f64, self.side1.casualties_sq, self.iterations),
+            std_side2_casualties: std_dev(self.side2.casualties as f64, self.side2.casualties_sq, self.iterations),
+            std_side1_hp_lost: std_dev(self.side1.hp_lost as f64, self.side1.hp_lost_sq, self.iterations),
+            std_side2_hp_lost: std_dev(self.side2.hp_lost as f64, self.side2.hp_lost_sq, self.iterations),
+            distributions: Distributions {
+                rounds_histogram: self.rounds_histogram.buckets(),
+                rounds_percentiles: self.rounds_histogram.percentiles(),
+                side1_hp_lost_percentiles: self.side1.hp_lost_histogram.percentiles(),
+                side2_hp_lost_percentiles: self.side2.hp_lost_histogram.percentiles(),
+                side1_casualty_distribution: self.side1.casualty_histogram.dense_rates(self.side1_total_actors),
+                side2_casualty_distribution: self.side2.casualty_histogram.dense_rates(self.side2_total_actors),
             },
-            side1_tpk_rate: side1_tpk as f64 / n * 100.0,
-            side2_tpk_rate: side2_tpk as f64 / n * 100.0,
+            kills: deaths.then(|| self.kills.attribution(self.iterations)),
+            time_to_kill: full.then(|| self.time_to_kill.matrix(self.iterations)),
+            objectives: self
+                .objectives
+                .iter()
+                .map(|(objective, count)| ObjectiveRate {
+                    side: format!("{:?}", objective.side()),
+                    objective: objective.to_string(),
+                    rate: rate(*count),
+                })
+                .collect(),
+            scores: self.scores.as_ref().map(ScoreTally::distribution),
+            factions: self
+                .factions
+                .iter()
+                .enumerate()
+                .map(|(i, faction)| FactionStats {
+                    side: Side::new(i + 2),
+                    win_rate: rate(faction.tally.wins),
+                    win_rate_ci: wilson_interval(faction.tally.wins, self.iterations),
+                    avg_casualties: faction.tally.casualties as f64 / n,
+                    avg_hp_lost: faction.tally.hp_lost as f64 / n,
+                    avg_hp_lost_percent: hp_lost_percent(faction.tally.hp_lost, faction.hp),
+                    flawless_rate: rate(faction.tally.flawless),
+                    tpk_rate: rate(faction.tally.tpks),
+                })
+                .collect(),
+            front_line: self
+                .front_line
+                .iter()
+                .enumerate()
+                .filter(|(_, (_, combats))| *combats > 0)
+                .map(|(round, (total, combats))| FrontLine {
+                    round: round as u32 + 1,
+                    avg_position: total / *combats as f64,
+                    combats: *combats,
+                })
+                .collect(),
+            zone_control: self
+                .zones
+                .iter()
+                .map(|zone| ZoneControl {
+                    zone: zone.name.clone(),
+                    side1_rate: rate(zone.side1),
+                    side2_rate: rate(zone.side2),
+                    contested_rate: rate(zone.contested),
+                })
+                .collect(),
         }
     }
 
-    pub fn get_sample_combats(&self, count: usize) -> Vec<CombatLog> {
-        self.results
+    /// Half-width (in percentage points) of the widest 95% Wilson score interval among
+    /// the win rates (factions included) and the draw rate. Unlike the normal approximation
+    /// this isn't zero when a rate is still 0% or 100% after a handful of combats.
+    pub fn win_rate_margin(&self) -> f64 {
+        [self.side1.wins, self.side2.wins, self.draws]
             .iter()
-            .take(count)
-            .map(|r| format_combat_log(r))
-            .collect()
+            .chain(self.factions.iter().map(|faction| &faction.tally.wins))
+            .map(|&count| wilson_half_width(count, self.iterations) * 100.0)
+            .fold(0.0, f64::max)
+    }
+
+    /// The first `count` combats (up to the collector's `sample_count`) as readable logs
+    pub fn get_sample_combats(&self, count: usize) -> Vec<CombatLog> {
+        self.samples.iter().take(count).cloned().collect()
     }
 }
 
-fn format_combat_log(result: &CombatResult) -> CombatLog {
-    let events: Vec<CombatLogEntry> = result
-        .events
+/// A side is broken once its remaining HP falls below this share of its starting HP...
+const DECISIVE_STRENGTH: f64 = 0.25;
+/// ...and a fight is decided early when that happens by the end of this round
+const DECISIVE_ROUNDS: u32 = 2;
+
+/// z-score for a two-sided 95% confidence interval
+const Z_95: f64 = 1.96;
+
+/// Half-width of the 95% Wilson score interval for `successes` out of `n`, as a proportion
+fn wilson_half_width(successes: u32, n: u32) -> f64 {
+    if n == 0 {
+        return 1.0;
+    }
+    let n = n as f64;
+    let p = successes as f64 / n;
+    let z2 = Z_95 * Z_95;
+    Z_95 * (p * (1.0 - p) / n + z2 / (4.0 * n * n)).sqrt() / (1.0 + z2 / n)
+}
+
+/// 95% Wilson score interval for `successes` out of `n`, in percent
+fn wilson_interval(successes: u32, n: u32) -> ConfidenceInterval {
+    if n == 0 {
+        return ConfidenceInterval { low: 0.0, high: 100.0 };
+    }
+    let nf = n as f64;
+    let p = successes as f64 / nf;
+    let z2 = Z_95 * Z_95;
+    let center = (p + z2 / (2.0 * nf)) / (1.0 + z2 / nf);
+    let half = wilson_half_width(successes, n);
+    // Pin the bounds exactly at 0/100 when no/every combat succeeded (avoids float dust)
+    ConfidenceInterval {
+        low: if successes == 0 { 0.0 } else { ((center - half) * 100.0).max(0.0) },
+        high: if successes == n { 100.0 } else { ((center + half) * 100.0).min(100.0) },
+    }
+}
+
+/// Standard error of a rate (`successes` out of `n`), in percentage points
+fn rate_std_error(successes: u32, n: u32) -> f64 {
+    if n == 0 {
+        return 0.0;
+    }
+    let p = successes as f64 / n as f64;
+    (p * (1.0 - p) / n as f64).sqrt() * 100.0
+}
+
+/// Sample standard deviation from a running sum and sum of squares
+fn std_dev(sum: f64, sum_sq: f64, n: u32) -> f64 {
+    if n < 2 {
+        return 0.0;
+    }
+    let n = n as f64;
+    let mean = sum / n;
+    ((sum_sq - n * mean * mean) / (n - 1.0)).max(0.0).sqrt()
+}
+
+/// Render a combat's events as readable log lines
+pub fn format_combat_log(result: &CombatResult, iteration: u32) -> CombatLog {
+    let events = format_events(&result.events, |id| {
+        result.final_state.iter().find(|a| a.id == id).map(|a| &*a.name)
+    });
+
+    let final_state: Vec<ActorFinalState> = result
+        .final_state
+        .iter()
+        .map(|a| ActorFinalState {
+            id: a.id,
+            name: a.name.to_string(),
+            side: format!("{:?}", a.side),
+            hp: match a.drained {
+                0 => format!("{}/{}", a.final_hp.max(0), a.max_hp),
+                drained => format!("{}/{} ({} drained)", a.final_hp.max(0), a.max_hp - drained, drained),
+            },
+            alive: a.alive,
+            fled: a.fled,
+            summoner: a.summoner,
+            vip: a.vip,
+            noncombatant: a.noncombatant,
+            zone: a.zone.clone(),
+        })
+        .collect();
+
+    CombatLog {
+        iteration,
+        winner: result.winner.map(|s| format!("{:?}", s)),
+        rounds: result.rounds,
+        surprised: result.surprised.map(|s| format!("{:?}", s)),
+        stalemate: result.stalemate,
+        events,
+        final_state,
+    }
+}
+
+/// Describe combat events for a log. `actor_name` looks up an actor's name by id.
+pub fn format_events<'a>(events: &[CombatEvent], actor_name: impl Fn(usize) -> Option<&'a str>) -> Vec<CombatLogEntry> {
+    events
         .iter()
         .map(|e| {
             let description = match &e.event_type {
@@ -210,53 +1491,182 @@ fn format_combat_log(result: &CombatResult) -> CombatLog {
                     target_ac,
                     hit,
                     damage,
+                    absorbed,
+                    called_shot,
+                    damage_before_dr,
+                    modifiers,
                     ..
                 } => {
-                    if *hit {
-                        format!(
-                            "attacks {} (rolled {} vs AC {}) - HIT for {} damage",
-                            target_name, roll, target_ac, damage
-                        )
+                    // Everything beyond the attack bonus and the target's armor gets itemized
+                    let situational = |stack: &[Modifier]| -> Vec<String> {
+                        stack.iter().map(|m| format!("{:+} {}", m.value, m.source)).collect()
+                    };
+                    let mut extras = situational(&modifiers.roll);
+                    for (edge, sources) in [("advantage", &modifiers.advantage), ("disadvantage", &modifiers.disadvantage)] {
+                        if !sources.is_empty() {
+                            let sources: Vec<String> = sources.iter().map(ToString::to_string).collect();
+                            extras.push(format!("{} ({})", edge, sources.join(", ")));
+                        }
+                    }
+                    let roll = if extras.is_empty() {
+                        format!("rolled {}", roll)
+                    } else {
+                        format!("rolled {} with {}", roll, extras.join(" and "))
+                    };
+                    let ac_extras = situational(&modifiers.ac);
+                    let target_ac = if ac_extras.is_empty() {
+                        target_ac.to_string()
                     } else {
+                        format!("{} with {}", target_ac, ac_extras.join(" and "))
+                    };
+                    let target_name = match called_shot {
+                        Some(location) => format!("{}'s {}", target_name, location),
+                        None => target_name.to_string(),
+                    };
+                    let damage = match damage_before_dr {
+                        Some(rolled) if *hit && rolled != damage => {
+                            format!("{} damage ({} before armor)", damage, rolled)
+                        }
+                        _ => format!("{} damage", damage),
+                    };
+                    if *hit && *absorbed > 0 {
                         format!(
-                            "attacks {} (rolled {} vs AC {}) - MISS",
-                            target_name, roll, target_ac
+                            "attacks {} ({} vs AC {}) - HIT for {} ({} absorbed by temp HP)",
+                            target_name, roll, target_ac, damage, absorbed
                         )
+                    } else if *hit {
+                        format!("attacks {} ({} vs AC {}) - HIT for {}", target_name, roll, target_ac, damage)
+                    } else {
+                        format!("attacks {} ({} vs AC {}) - MISS", target_name, roll, target_ac)
                     }
                 }
                 EventType::Guard { ac_bonus } => {
                     format!("guards (AC +{})", ac_bonus)
                 }
+                EventType::Defend { ac_bonus } => {
+                    format!("defends (AC +{}, holds position)", ac_bonus)
+                }
+                EventType::Dodge => "dodges (attacks against have disadvantage)".to_string(),
+                EventType::Hold => "holds".to_string(),
+                EventType::Dash => "dashes".to_string(),
+                EventType::StrayShot { target_name, roll, target_ac, damage, .. } => {
+                    format!("misses, and the shot hits {} instead ({} vs AC {}) for {} damage", target_name, roll, target_ac, damage)
+                }
+                EventType::Withdraw => "withdraws (no opportunity attacks)".to_string(),
+                EventType::Buff {
+                    target_id,
+                    target_name,
+                    temp_hp,
+                    duration,
+                } => {
+                    let recipient = if *target_id == e.actor_id {
+                        "self".to_string()
+                    } else {
+                        target_name.to_string()
+                    };
+                    match duration {
+                        Some(d) => format!("grants {} {} temp HP for {} rounds", recipient, temp_hp, d),
+                        None => format!("grants {} {} temp HP", recipient, temp_hp),
+                    }
+                }
+                EventType::TempHpExpired { temp_hp } => {
+                    format!("loses {} expired temp HP", temp_hp)
+                }
+                EventType::Drain { target_name, amount, max_hp, .. } => {
+                    format!("drains {} max HP from {} (down to {})", amount, target_name, max_hp)
+                }
+                EventType::Summon { summoned_name, zone, .. } => {
+                    format!("summons {} in {}", summoned_name, zone)
+                }
+                EventType::Spawn { spawned_name, zone, .. } => {
+                    format!("leaves {} behind in {}", spawned_name, zone)
+                }
+                EventType::Transform { into_name, .. } => format!("rises again as {}", into_name),
+                EventType::Hazard { zone, damage, absorbed } => {
+                    if *absorbed > 0 {
+                        format!("takes {} damage from {} ({} absorbed by temp HP)", damage, zone, absorbed)
+                    } else {
+                        format!("takes {} damage from {}", damage, zone)
+                    }
+                }
+                EventType::Condition { target_name, condition, save, dc, applied, duration, .. } => {
+                    let lasting = match duration {
+                        Some(d) => format!(" for {} rounds", d),
+                        None => String::new(),
+                    };
+                    match (save, applied) {
+                        (Some(total), true) => {
+                            format!("leaves {} {}{} (save {} vs DC {})", target_name, condition, lasting, total, dc)
+                        }
+                        (Some(total), false) => {
+                            format!("fails to leave {} {} (save {} vs DC {})", target_name, condition, total, dc)
+                        }
+                        (None, _) => format!("leaves {} {}{}", target_name, condition, lasting),
+                    }
+                }
+                EventType::ConditionDamage { condition, damage, absorbed } => {
+                    if *absorbed > 0 {
+                        format!("takes {} damage from {} ({} absorbed by temp HP)", damage, condition, absorbed)
+                    } else {
+                        format!("takes {} damage from {}", damage, condition)
+                    }
+                }
+                EventType::ConditionEnded { condition } => {
+                    format!("is no longer {}", condition)
+                }
+                EventType::Flee { zone } => {
+                    format!("flees the fight from {}", zone)
+                }
+                EventType::Objective { objective } => {
+                    format!("completes objective: {}", objective)
+                }
                 EventType::Move { from, to } => {
-                    format!("moves from {:?} to {:?}", from, to)
+                    format!("moves from {} to {}", from, to)
+                }
+                EventType::ForcedMove { target_name, from, to, .. } => {
+                    format!("forces {} from {} to {}", target_name, from, to)
+                }
+                EventType::Grapple { target_name, roll, opposed, success, .. } => {
+                    let outcome = if *success { "holds them" } else { "fails" };
+                    format!("grapples {} ({} vs {}) - {}", target_name, roll, opposed, outcome)
+                }
+                EventType::Escape { grappler_name, roll, opposed, success, .. } => {
+                    let outcome = if *success { "breaks free" } else { "stays held" };
+                    format!("struggles against {} ({} vs {}) - {}", grappler_name, roll, opposed, outcome)
+                }
+                EventType::LostTarget { target_name, .. } => {
+                    format!("loses its target ({} is gone) - action wasted", target_name)
+                }
+                EventType::ScriptError { message } => {
+                    format!("falls back to its APL: script failed ({})", message)
+                }
+                EventType::Legendary { remaining } => {
+                    format!("takes a legendary action ({} left)", remaining)
+                }
+                EventType::Reaction { trigger, source_name, .. } => {
+                    let cause = match trigger {
+                        Trigger::EnemyEntersZone => format!("{} coming into reach", source_name),
+                        Trigger::EnemyLeavesZone => format!("{} leaving its reach", source_name),
+                        Trigger::Attacked => format!("{} attacking it", source_name),
+                        Trigger::AllyAttacked => format!("{} attacking an ally", source_name),
+                    };
+                    format!("reacts to {}", cause)
+                }
+                EventType::Death { killer_id } => {
+                    match killer_id.and_then(&actor_name) {
+                        Some(killer) => format!("dies! (slain by {})", killer),
+                        None => "dies!".to_string(),
+                    }
                 }
-                EventType::Death { killer_id: _ } => "dies!".to_string(),
             };
 
             CombatLogEntry {
                 round: e.round,
-                actor: e.actor_name.clone(),
+                actor_id: e.actor_id,
+                actor: e.actor_name.to_string(),
                 description,
+                event: e.event_type.clone(),
             }
         })
-        .collect();
-
-    let final_state: Vec<ActorFinalState> = result
-        .final_state
-        .iter()
-        .map(|a| ActorFinalState {
-            name: a.name.clone(),
-            side: format!("{:?}", a.side),
-            hp: format!("{}/{}", a.final_hp.max(0), a.max_hp),
-            alive: a.alive,
-            zone: format!("{:?}", a.zone),
-        })
-        .collect();
-
-    CombatLog {
-        winner: result.winner.map(|s| format!("{:?}", s)),
-        rounds: result.rounds,
-        events,
-        final_state,
-    }
+        .collect()
 }
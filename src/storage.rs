@@ -0,0 +1,255 @@
+use std::path::Path;
+use std::sync::{Mutex, MutexGuard};
+
+use rusqlite::{params, Connection, OptionalExtension, Row};
+use serde::Serialize;
+use thiserror::Error;
+use utoipa::ToSchema;
+
+use crate::types::ActorTemplate;
+
+#[derive(Debug, Error)]
+pub enum StorageError {
+    #[error("database error: {0}")]
+    Database(#[from] rusqlite::Error),
+    #[error("corrupt record: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error("{0} already exists")]
+    AlreadyExists(String),
+    #[error("{0} not found")]
+    NotFound(String),
+}
+
+/// A saved encounter
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StoredEncounter {
+    pub id: i64,
+    pub name: String,
+    pub encounter_yaml: String,
+    pub created_at: String,
+    pub updated_at: String,
+}
+
+impl StoredEncounter {
+    fn from_row(row: &Row) -> rusqlite::Result<Self> {
+        Ok(StoredEncounter {
+            id: row.get(0)?,
+            name: row.get(1)?,
+            encounter_yaml: row.get(2)?,
+            created_at: row.get(3)?,
+            updated_at: row.get(4)?,
+        })
+    }
+}
+
+/// The most recent simulation run for a saved encounter
+#[derive(Debug, Clone, Serialize, ToSchema)]
+pub struct StoredResult {
+    pub encounter_id: i64,
+    pub created_at: String,
+    #[schema(value_type = crate::stats::SimulationResult)]
+    pub result: serde_json::Value,
+}
+
+/// SQLite-backed persistence for the monster library and saved encounters
+pub struct Storage {
+    conn: Mutex<Connection>,
+}
+
+impl Storage {
+    pub fn open(path: impl AsRef<Path>) -> Result<Self, StorageError> {
+        Self::init(Connection::open(path)?)
+    }
+
+    pub fn open_in_memory() -> Result<Self, StorageError> {
+        Self::init(Connection::open_in_memory()?)
+    }
+
+    fn init(conn: Connection) -> Result<Self, StorageError> {
+        conn.execute_batch(
+            "PRAGMA foreign_keys = ON;
+            CREATE TABLE IF NOT EXISTS monsters (
+                name TEXT PRIMARY KEY COLLATE NOCASE,
+                template TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS encounters (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                name TEXT NOT NULL,
+                encounter_yaml TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT (datetime('now')),
+                updated_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );
+            CREATE TABLE IF NOT EXISTS encounter_results (
+                encounter_id INTEGER PRIMARY KEY REFERENCES encounters(id) ON DELETE CASCADE,
+                result TEXT NOT NULL,
+                created_at TEXT NOT NULL DEFAULT (datetime('now'))
+            );",
+        )?;
+        Ok(Storage {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    fn conn(&self) -> MutexGuard<'_, Connection> {
+        // A panic mid-query can't leave the connection itself in a bad state
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn list_monsters(&self) -> Result<Vec<ActorTemplate>, StorageError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("SELECT template FROM monsters ORDER BY name")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut monsters = Vec::new();
+        for json in rows {
+            monsters.push(serde_json::from_str(&json?)?);
+        }
+        Ok(monsters)
+    }
+
+    /// Look up a monster by name (case-insensitive)
+    pub fn get_monster(&self, name: &str) -> Result<Option<ActorTemplate>, StorageError> {
+        let json: Option<String> = self
+            .conn()
+            .query_row(
+                "SELECT template FROM monsters WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(json.map(|j| serde_json::from_str(&j)).transpose()?)
+    }
+
+    pub fn create_monster(&self, template: &ActorTemplate) -> Result<(), StorageError> {
+        let json = serde_json::to_string(template)?;
+        let inserted = self.conn().execute(
+            "INSERT OR IGNORE INTO monsters (name, template) VALUES (?1, ?2)",
+            params![template.name, json],
+        )?;
+        if inserted == 0 {
+            return Err(StorageError::AlreadyExists(template.name.clone()));
+        }
+        Ok(())
+    }
+
+    /// Insert or replace a monster. Returns true if one with the same name already existed.
+    pub fn put_monster(&self, template: &ActorTemplate) -> Result<bool, StorageError> {
+        let json = serde_json::to_string(template)?;
+        let conn = self.conn();
+        let existed = conn
+            .query_row(
+                "SELECT 1 FROM monsters WHERE name = ?1",
+                params![template.name],
+                |_| Ok(()),
+            )
+            .optional()?
+            .is_some();
+        conn.execute(
+            "INSERT OR REPLACE INTO monsters (name, template) VALUES (?1, ?2)",
+            params![template.name, json],
+        )?;
+        Ok(existed)
+    }
+
+    pub fn delete_monster(&self, name: &str) -> Result<(), StorageError> {
+        let deleted = self
+            .conn()
+            .execute("DELETE FROM monsters WHERE name = ?1", params![name])?;
+        if deleted == 0 {
+            return Err(StorageError::NotFound(name.to_string()));
+        }
+        Ok(())
+    }
+
+    pub fn create_encounter(&self, name: &str, encounter_yaml: &str) -> Result<StoredEncounter, StorageError> {
+        let id = {
+            let conn = self.conn();
+            conn.execute(
+                "INSERT INTO encounters (name, encounter_yaml) VALUES (?1, ?2)",
+                params![name, encounter_yaml],
+            )?;
+            conn.last_insert_rowid()
+        };
+        self.get_encounter(id)?
+            .ok_or_else(|| StorageError::NotFound(format!("encounter {}", id)))
+    }
+
+    pub fn update_encounter(
+        &self,
+        id: i64,
+        name: &str,
+        encounter_yaml: &str,
+    ) -> Result<StoredEncounter, StorageError> {
+        let updated = self.conn().execute(
+            "UPDATE encounters SET name = ?2, encounter_yaml = ?3, updated_at = datetime('now') WHERE id = ?1",
+            params![id, name, encounter_yaml],
+        )?;
+        if updated == 0 {
+            return Err(StorageError::NotFound(format!("encounter {}", id)));
+        }
+        self.get_encounter(id)?
+            .ok_or_else(|| StorageError::NotFound(format!("encounter {}", id)))
+    }
+
+    pub fn list_encounters(&self) -> Result<Vec<StoredEncounter>, StorageError> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, name, encounter_yaml, created_at, updated_at FROM encounters ORDER BY id",
+        )?;
+        let encounters = stmt
+            .query_map([], StoredEncounter::from_row)?
+            .collect::<Result<_, _>>()?;
+        Ok(encounters)
+    }
+
+    pub fn get_encounter(&self, id: i64) -> Result<Option<StoredEncounter>, StorageError> {
+        Ok(self
+            .conn()
+            .query_row(
+                "SELECT id, name, encounter_yaml, created_at, updated_at FROM encounters WHERE id = ?1",
+                params![id],
+                StoredEncounter::from_row,
+            )
+            .optional()?)
+    }
+
+    pub fn delete_encounter(&self, id: i64) -> Result<(), StorageError> {
+        let deleted = self
+            .conn()
+            .execute("DELETE FROM encounters WHERE id = ?1", params![id])?;
+        if deleted == 0 {
+            return Err(StorageError::NotFound(format!("encounter {}", id)));
+        }
+        Ok(())
+    }
+
+    /// Record a simulation result for an encounter, replacing any earlier one
+    pub fn save_result(&self, encounter_id: i64, result: &impl Serialize) -> Result<StoredResult, StorageError> {
+        let json = serde_json::to_string(result)?;
+        self.conn().execute(
+            "INSERT OR REPLACE INTO encounter_results (encounter_id, result, created_at)
+             VALUES (?1, ?2, datetime('now'))",
+            params![encounter_id, json],
+        )?;
+        self.latest_result(encounter_id)?
+            .ok_or_else(|| StorageError::NotFound(format!("results for encounter {}", encounter_id)))
+    }
+
+    pub fn latest_result(&self, encounter_id: i64) -> Result<Option<StoredResult>, StorageError> {
+        let row: Option<(String, String)> = self
+            .conn()
+            .query_row(
+                "SELECT result, created_at FROM encounter_results WHERE encounter_id = ?1",
+                params![encounter_id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        row.map(|(json, created_at)| {
+            Ok(StoredResult {
+                encounter_id,
+                created_at,
+                result: serde_json::from_str(&json)?,
+            })
+        })
+        .transpose()
+    }
+}
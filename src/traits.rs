@@ -0,0 +1,66 @@
+//! Passive traits: common monster abilities switched on by name in an actor's `traits` list
+//! instead of each getting its own template field. Every trait's effect lives here, behind a
+//! hook the simulator calls at a fixed point: attack rolls (`attack_edges`), retreat
+//! (`will_flee`), and movement (`provokes`).
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::modifiers::{AttackModifiers, EdgeSource};
+use crate::types::{Actor, Light, WeaponRange, ZoneLayout};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Trait {
+    /// Advantage on attacks against a target that one of the attacker's living allies could
+    /// hit in melee
+    PackTactics,
+    /// Disadvantage on attacks from a zone lit bright by its own light (torchlight isn't sunlight)
+    SunlightSensitivity,
+    /// Never breaks: `flee` entries in its APL are skipped
+    UndeadFearless,
+    /// Slips away freely: its moves never set off `enemy_leaves_zone` reactions
+    NimbleEscape,
+}
+
+/// Add the advantage and disadvantage the attacker's traits give an attack on `target`
+pub fn attack_edges(
+    attacker: &Actor,
+    target: &Actor,
+    actors: &[Actor],
+    zones: &ZoneLayout,
+    modifiers: &mut AttackModifiers,
+) {
+    for &t in attacker.traits.iter() {
+        match t {
+            Trait::PackTactics => {
+                let flanked = actors.iter().any(|ally| {
+                    ally.is_allied_with(attacker)
+                        && ally.id != attacker.id
+                        && ally.is_alive()
+                        && ally.range == WeaponRange::Melee
+                        && ally.can_attack(target, zones, actors)
+                });
+                if flanked {
+                    modifiers.add_edge(EdgeSource::PackTactics, true);
+                }
+            }
+            Trait::SunlightSensitivity => {
+                if zones.ambient_light(attacker.zone) == Light::Bright {
+                    modifiers.add_edge(EdgeSource::SunlightSensitivity, false);
+                }
+            }
+            Trait::UndeadFearless | Trait::NimbleEscape => {}
+        }
+    }
+}
+
+/// Whether the actor will take a `flee` entry in its APL
+pub fn will_flee(actor: &Actor) -> bool {
+    !actor.has_trait(Trait::UndeadFearless)
+}
+
+/// Whether moving out of enemies' reach gives them their `enemy_leaves_zone` reactions
+pub fn provokes(actor: &Actor) -> bool {
+    !actor.has_trait(Trait::NimbleEscape)
+}
@@ -1,7 +1,14 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::fmt;
+use std::sync::Arc;
+use utoipa::ToSchema;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+pub use crate::dice::{parse_damage_dice, DamageDice, DiceRules};
+use crate::effects::{EffectKind, Effects, Stacking, Tick};
+use crate::traits::Trait;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum InitiativeType {
     /// One side acts completely, then the other (re-rolled each round)
@@ -13,9 +20,52 @@ pub enum InitiativeType {
     SidePhases,
     /// Individual initiative, but actions happen in phases (all movement, then ranged, then melee)
     IndividualPhases,
+    /// Everyone declares from the start-of-round state, then all actions resolve simultaneously;
+    /// actors dropped during the round still act and are removed at its end
+    Declared,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// When initiative is rolled
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum InitiativeRoll {
+    /// Re-roll side and individual initiative at the top of every round
+    #[default]
+    EachRound,
+    /// Roll at the start of combat and keep the order for the whole fight
+    Once,
+}
+
+/// What a declared attack does when its target is down or out of reach by the time it resolves
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LostTarget {
+    /// The attack goes ahead as declared: it lands on a downed target, and one out of reach
+    /// wastes the action
+    #[default]
+    Wasted,
+    /// The attacker re-runs its APL for a new target; the action is only wasted if there is none
+    Retarget,
+}
+
+/// How actors with equal individual initiative are ordered
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum TieBreak {
+    /// Tied actors go in a random order
+    #[default]
+    Random,
+    /// Tied actors roll the initiative dice again (without modifiers) until they're apart
+    Reroll,
+    /// The higher `initiative_modifier` goes first; ties beyond that are random
+    Modifier,
+    /// Side 1 goes first; ties within a side are random
+    Side1First,
+    /// Tied actors act at the same moment: anyone one of them drops still takes their turn
+    Simultaneous,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum Phase {
     Movement,
@@ -24,6 +74,23 @@ pub enum Phase {
     Melee,
 }
 
+/// A part of an actor's turn that an APL entry can spend, besides its movement
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Slot {
+    /// The turn's main action
+    Action,
+    /// A lesser action on top of it (an offhand attack, a quick guard)
+    BonusAction,
+    /// A trivial one on top of both (drawing a potion, a shove in passing)
+    FreeInteraction,
+}
+
+impl Slot {
+    /// In the order a turn spends them
+    pub const ALL: [Slot; 3] = [Slot::Action, Slot::BonusAction, Slot::FreeInteraction];
+}
+
 fn default_phases() -> Vec<Phase> {
     vec![Phase::Movement, Phase::Ranged, Phase::Reach, Phase::Melee]
 }
@@ -32,14 +99,25 @@ fn default_initiative_dice() -> String {
     "1d20".to_string()
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct InitiativeConfig {
     #[serde(rename = "type", default)]
     pub initiative_type: InitiativeType,
     #[serde(default = "default_initiative_dice")]
     pub dice: String,
+    #[serde(default)]
+    pub roll: InitiativeRoll,
+    /// Ordering of tied individual initiative rolls
+    #[serde(default)]
+    pub tie_break: TieBreak,
+    /// Declared attacks whose target is gone by the time they resolve
+    #[serde(default)]
+    pub lost_target: LostTarget,
     #[serde(default = "default_phases")]
     pub phases: Vec<Phase>,
+    /// Heavy weapons strike later: actors' `weapon_speed` delays their attacks
+    #[serde(default)]
+    pub weapon_speed: bool,
 }
 
 impl Default for InitiativeConfig {
@@ -47,170 +125,515 @@ impl Default for InitiativeConfig {
         InitiativeConfig {
             initiative_type: InitiativeType::default(),
             dice: default_initiative_dice(),
+            roll: InitiativeRoll::default(),
+            tie_break: TieBreak::default(),
+            lost_target: LostTarget::default(),
             phases: default_phases(),
+            weapon_speed: false,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum Side {
-    Side1,
-    Side2,
-}
+/// One faction in the fight, written `side1`, `side2`, `side3`, ...: the encounter's `side1`
+/// and `side2` lists, then each of its `factions` in order
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Side(u8);
 
 impl Side {
-    pub fn opposite(&self) -> Side {
-        match self {
-            Side::Side1 => Side::Side2,
-            Side::Side2 => Side::Side1,
+    pub const SIDE1: Side = Side(0);
+    pub const SIDE2: Side = Side(1);
+
+    /// The `index`th side, counting from 0 for side 1
+    pub fn new(index: usize) -> Side {
+        Side(u8::try_from(index).expect("at most MAX_FACTIONS sides"))
+    }
+
+    pub fn index(&self) -> usize {
+        usize::from(self.0)
+    }
+
+    /// Where the side's actors are listed in an encounter, e.g. `side2` or `factions[0].actors`
+    pub fn path(&self) -> String {
+        match self.index() {
+            0 | 1 => self.to_string(),
+            n => format!("factions[{}].actors", n - 2),
+        }
+    }
+
+    /// The other of the two main sides; `factions` are neither
+    pub fn opposite(&self) -> Option<Side> {
+        match *self {
+            Side::SIDE1 => Some(Side::SIDE2),
+            Side::SIDE2 => Some(Side::SIDE1),
+            _ => None,
         }
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum Zone {
-    Side1Ranged,
-    Side1Reach,
-    Side1Melee,
-    Side2Melee,
-    Side2Reach,
-    Side2Ranged,
+/// Most sides one encounter may have, `side1` and `side2` included
+pub const MAX_FACTIONS: usize = 32;
+
+/// Reads like the old two-variant enum, e.g. `Side1`, so logs and reports keep their labels
+impl fmt::Debug for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Side{}", self.0 + 1)
+    }
+}
+
+impl fmt::Display for Side {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "side{}", self.0 + 1)
+    }
+}
+
+impl std::str::FromStr for Side {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        s.strip_prefix("side")
+            .and_then(|n| n.parse::<usize>().ok())
+            .filter(|n| (1..=MAX_FACTIONS).contains(n))
+            .map(|n| Side::new(n - 1))
+            .ok_or_else(|| format!("unknown side '{}': expected side1 to side{}", s, MAX_FACTIONS))
+    }
 }
 
+impl Serialize for Side {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Side {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl utoipa::PartialSchema for Side {
+    fn schema() -> utoipa::openapi::RefOr<utoipa::openapi::schema::Schema> {
+        utoipa::openapi::ObjectBuilder::new()
+            .schema_type(utoipa::openapi::schema::Type::String)
+            .pattern(Some("^side[1-9][0-9]*$"))
+            .description(Some("A side: side1, side2, then one per entry of `factions` (side3, ...)"))
+            .into()
+    }
+}
+
+impl ToSchema for Side {}
+
+/// A set of sides, e.g. those an actor is hostile to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct SideSet(u32);
+
+impl SideSet {
+    /// Every side except `side`
+    pub fn all_but(side: Side) -> SideSet {
+        SideSet(!(1 << side.0))
+    }
+
+    pub fn contains(&self, side: Side) -> bool {
+        self.0 & (1 << side.0) != 0
+    }
+
+    pub fn insert(&mut self, side: Side) {
+        self.0 |= 1 << side.0;
+    }
+
+    /// Has a side in common with `other`
+    pub fn intersects(&self, other: SideSet) -> bool {
+        self.0 & other.0 != 0
+    }
+}
+
+/// Position on the battlefield: an index into the encounter's zone layout. The main line
+/// comes first, counted from side 1's rear (0) to side 2's rear, followed by any lanes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Zone(pub usize);
+
 impl Zone {
-    pub fn side(&self) -> Side {
-        match self {
-            Zone::Side1Ranged | Zone::Side1Reach | Zone::Side1Melee => Side::Side1,
-            Zone::Side2Melee | Zone::Side2Reach | Zone::Side2Ranged => Side::Side2,
-        }
-    }
-
-    pub fn distance_to(&self, other: &Zone) -> u32 {
-        let zones = [
-            Zone::Side1Ranged,
-            Zone::Side1Reach,
-            Zone::Side1Melee,
-            Zone::Side2Melee,
-            Zone::Side2Reach,
-            Zone::Side2Ranged,
-        ];
-        let self_idx = zones.iter().position(|z| z == self).unwrap() as i32;
-        let other_idx = zones.iter().position(|z| z == other).unwrap() as i32;
-        (self_idx - other_idx).unsigned_abs()
-    }
-
-    pub fn toward(&self, target: &Zone) -> Option<Zone> {
-        if self == target {
-            return None;
-        }
-        let zones = [
-            Zone::Side1Ranged,
-            Zone::Side1Reach,
-            Zone::Side1Melee,
-            Zone::Side2Melee,
-            Zone::Side2Reach,
-            Zone::Side2Ranged,
-        ];
-        let self_idx = zones.iter().position(|z| z == self).unwrap();
-        let target_idx = zones.iter().position(|z| z == target).unwrap();
-        if target_idx > self_idx {
-            Some(zones[self_idx + 1])
-        } else {
-            Some(zones[self_idx - 1])
-        }
+    pub fn index(&self) -> usize {
+        self.0
     }
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+/// One band of the battlefield
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ZoneSpec {
+    pub name: String,
+    /// Frontage units the zone holds (omit or null for unlimited)
+    #[serde(default)]
+    pub capacity: Option<u32>,
+    /// Movement points needed to enter the zone; long corridors take several turns to cross
+    #[serde(default = "default_zone_length")]
+    pub length: u32,
+    /// Extra connections to other zones by name, e.g. where a side passage rejoins the tunnel
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub links: Vec<String>,
+    #[serde(default)]
+    pub terrain: Terrain,
+    /// Light level (defaults to the encounter's `light`)
+    #[serde(default)]
+    pub light: Option<Light>,
+}
+
+fn default_zone_length() -> u32 {
+    1
+}
+
+/// Terrain modifiers for a zone
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct Terrain {
+    /// Entering the zone costs double movement
+    #[serde(default)]
+    pub difficult: bool,
+    /// Attack penalty for ranged attacks against actors in the zone
+    #[serde(default)]
+    pub cover: i32,
+    /// Damage (fixed or dice) dealt to each actor still in the zone at the end of a round
+    #[serde(default)]
+    pub hazard: Option<HpValue>,
+}
+
+/// How well lit a zone is. Ordered from darkest to brightest.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default, Serialize, Deserialize, ToSchema)]
 #[serde(rename_all = "snake_case")]
-pub enum WeaponRange {
-    Melee,
-    Reach,
-    Ranged,
+pub enum Light {
+    Dark,
+    Dim,
+    #[default]
+    Bright,
 }
 
-impl WeaponRange {
-    /// Check if this weapon can hit a target at the given distance
-    /// - Melee: adjacent only (distance == 1)
-    /// - Reach: exactly 2 zones away (distance == 2)
-    /// - Ranged: any zone except adjacent (distance >= 2)
-    pub fn can_hit_at_distance(&self, distance: u32) -> bool {
+impl Light {
+    fn brighter(self) -> Light {
         match self {
-            WeaponRange::Melee => distance == 1,
-            WeaponRange::Reach => distance == 2,
-            WeaponRange::Ranged => distance >= 2,
+            Light::Dark => Light::Dim,
+            Light::Dim | Light::Bright => Light::Bright,
         }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DamageDice {
-    pub count: u32,
-    pub sides: u32,
-    pub modifier: i32,
+/// A side corridor running alongside the main line. Consecutive zones are adjacent; `links`
+/// on its zones say where it joins the rest of the map.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Lane {
+    pub name: Option<String>,
+    pub zones: Vec<ZoneSpec>,
 }
 
-impl DamageDice {
-    pub fn roll(&self, rng: &mut impl rand::Rng) -> i32 {
-        let mut total = self.modifier;
-        for _ in 0..self.count {
-            total += rng.gen_range(1..=self.sides) as i32;
+/// The encounter's zone graph: the main line from side 1's rear to side 2's rear, plus lanes
+#[derive(Debug, Clone)]
+pub struct ZoneLayout {
+    zones: Vec<ZoneSpec>,
+    /// Number of zones on the main line (they come first in `zones`)
+    main_len: usize,
+    neighbors: Vec<Vec<usize>>,
+    /// All-pairs shortest path lengths in steps (u32::MAX when unreachable)
+    distances: Vec<Vec<u32>>,
+    /// Light level for zones that don't set their own
+    default_light: Light,
+    /// End of the main line each of the encounter's `factions` deploys at
+    faction_ends: Vec<Side>,
+}
+
+impl ZoneLayout {
+    pub fn new(main: Vec<ZoneSpec>, lanes: &[Lane], default_light: Light) -> Self {
+        let main_len = main.len();
+        let mut zones = main;
+        let mut edges: Vec<(usize, usize)> = (1..main_len).map(|i| (i - 1, i)).collect();
+        for lane in lanes {
+            let start = zones.len();
+            edges.extend((start + 1..start + lane.zones.len()).map(|i| (i - 1, i)));
+            zones.extend(lane.zones.iter().cloned());
+        }
+
+        let find = |name: &str| zones.iter().position(|z| z.name.eq_ignore_ascii_case(name));
+        for (i, zone) in zones.iter().enumerate() {
+            // Unknown names are reported by validation
+            edges.extend(zone.links.iter().filter_map(|link| find(link)).map(|j| (i, j)));
+        }
+
+        let mut neighbors = vec![Vec::new(); zones.len()];
+        for (a, b) in edges {
+            if a != b && !neighbors[a].contains(&b) {
+                neighbors[a].push(b);
+                neighbors[b].push(a);
+            }
+        }
+        for list in &mut neighbors {
+            list.sort_unstable();
+        }
+
+        let distances = (0..zones.len())
+            .map(|from| {
+                let mut dist = vec![u32::MAX; zones.len()];
+                dist[from] = 0;
+                let mut queue = std::collections::VecDeque::from([from]);
+                while let Some(zone) = queue.pop_front() {
+                    for &next in &neighbors[zone] {
+                        if dist[next] == u32::MAX {
+                            dist[next] = dist[zone] + 1;
+                            queue.push_back(next);
+                        }
+                    }
+                }
+                dist
+            })
+            .collect();
+
+        ZoneLayout {
+            zones,
+            main_len,
+            neighbors,
+            distances,
+            default_light,
+            faction_ends: Vec::new(),
         }
-        total.max(0)
     }
-}
 
-impl fmt::Display for DamageDice {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        if self.modifier == 0 {
-            write!(f, "{}d{}", self.count, self.sides)
-        } else if self.modifier > 0 {
-            write!(f, "{}d{}+{}", self.count, self.sides, self.modifier)
+    /// Deploy the `factions` beyond side 1 and side 2 at these ends of the main line, in order
+    pub fn with_faction_ends(mut self, ends: Vec<Side>) -> Self {
+        self.faction_ends = ends;
+        self
+    }
+
+    /// The end of the main line `side` deploys at: side 1's or side 2's
+    pub fn end(&self, side: Side) -> Side {
+        match side.index() {
+            0 | 1 => side,
+            n => self.faction_ends.get(n - 2).copied().unwrap_or(Side::SIDE2),
+        }
+    }
+
+    /// The classic six-zone tunnel: ranged, reach, and melee bands for each side
+    pub fn tunnel_zones(capacities: &ZoneCapacities) -> Vec<ZoneSpec> {
+        let zone = |name: &str, capacity: Option<u32>| ZoneSpec {
+            name: name.to_string(),
+            capacity,
+            length: 1,
+            links: Vec::new(),
+            terrain: Terrain::default(),
+            light: None,
+        };
+        vec![
+            zone("side1_ranged", capacities.ranged),
+            zone("side1_reach", Some(capacities.reach)),
+            zone("side1_melee", Some(capacities.melee)),
+            zone("side2_melee", Some(capacities.melee)),
+            zone("side2_reach", Some(capacities.reach)),
+            zone("side2_ranged", capacities.ranged),
+        ]
+    }
+
+    pub fn len(&self) -> usize {
+        self.zones.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.zones.is_empty()
+    }
+
+    /// Zones on the main line, numbered from side 1's rear (side lanes follow them)
+    pub fn main_len(&self) -> usize {
+        self.main_len
+    }
+
+    pub fn zones(&self) -> &[ZoneSpec] {
+        &self.zones
+    }
+
+    pub fn name(&self, zone: Zone) -> &str {
+        &self.zones[zone.0].name
+    }
+
+    pub fn capacity(&self, zone: Zone) -> Option<u32> {
+        self.zones[zone.0].capacity
+    }
+
+    pub fn terrain(&self, zone: Zone) -> &Terrain {
+        &self.zones[zone.0].terrain
+    }
+
+    /// A zone's own light level, before any torches
+    pub fn ambient_light(&self, zone: Zone) -> Light {
+        self.zones[zone.0].light.unwrap_or(self.default_light)
+    }
+
+    /// Light in a zone: its own level, raised to bright by a torch carried in it and to at
+    /// least dim by one in a neighbouring zone
+    pub fn light_at(&self, zone: Zone, actors: &[Actor]) -> Light {
+        let base = self.ambient_light(zone);
+        if base == Light::Bright {
+            return base;
+        }
+        let torchlight = actors
+            .iter()
+            .filter(|a| a.carries_torch && a.is_alive())
+            .map(|a| match self.distance(a.zone, zone) {
+                0 => Light::Bright,
+                1 => Light::Dim,
+                _ => Light::Dark,
+            })
+            .max()
+            .unwrap_or(Light::Dark);
+        base.max(torchlight)
+    }
+
+    /// Movement points needed to enter a zone: its length, doubled for difficult terrain
+    pub fn move_cost(&self, zone: Zone) -> u32 {
+        let spec = &self.zones[zone.0];
+        if spec.terrain.difficult {
+            spec.length * 2
         } else {
-            write!(f, "{}d{}{}", self.count, self.sides, self.modifier)
+            spec.length
+        }
+    }
+
+    pub fn find(&self, name: &str) -> Option<Zone> {
+        self.zones
+            .iter()
+            .position(|z| z.name.eq_ignore_ascii_case(name))
+            .map(Zone)
+    }
+
+    /// Zones one step away
+    pub fn neighbors(&self, zone: Zone) -> impl Iterator<Item = Zone> + '_ {
+        self.neighbors[zone.0].iter().map(|&i| Zone(i))
+    }
+
+    /// Steps along the shortest path between two zones (u32::MAX if they aren't connected)
+    pub fn distance(&self, from: Zone, to: Zone) -> u32 {
+        self.distances[from.0][to.0]
+    }
+
+    /// Neighbours that lie on a shortest path from `from` to `target`, lowest index first
+    pub fn steps_toward(&self, from: Zone, target: Zone) -> impl Iterator<Item = Zone> + '_ {
+        let remaining = self.distance(from, target);
+        self.neighbors(from)
+            .filter(move |&next| remaining != u32::MAX && self.distance(next, target) < remaining)
+    }
+
+    /// A side's rearmost zone, where `start_zone: ranged` actors begin
+    pub fn rear(&self, side: Side) -> Zone {
+        match self.end(side) {
+            Side::SIDE1 => Zone(0),
+            _ => Zone(self.main_len - 1),
         }
     }
+
+    /// The rear at the other end of the main line from where `side` deploys
+    pub fn far_rear(&self, side: Side) -> Zone {
+        match self.end(side) {
+            Side::SIDE1 => self.rear(Side::SIDE2),
+            _ => self.rear(Side::SIDE1),
+        }
+    }
+
+    /// A side's frontmost zone on the main line; with an odd number of main zones the middle
+    /// one is no-man's-land
+    pub fn front(&self, side: Side) -> Zone {
+        let len = self.main_len;
+        match self.end(side) {
+            Side::SIDE1 => Zone((len / 2).saturating_sub(1)),
+            _ => Zone((len - len / 2).min(len.saturating_sub(1))),
+        }
+    }
+
+    /// Where an actor with the given `start_zone` begins, or None for an unknown zone name
+    pub fn start_zone(&self, side: Side, start: &StartingZone) -> Option<Zone> {
+        let front = self.front(side);
+        match start {
+            StartingZone::Ranged => Some(self.rear(side)),
+            StartingZone::Reach if front == self.rear(side) => Some(front),
+            StartingZone::Reach => match self.end(side) {
+                Side::SIDE1 => Some(Zone(front.0 - 1)),
+                _ => Some(Zone(front.0 + 1)),
+            },
+            StartingZone::Melee => Some(front),
+            StartingZone::Named(name) => self.find(name),
+        }
+    }
+
+    /// Steps from `side`'s own rear (0) toward the enemy's rear
+    pub fn depth_for(&self, zone: Zone, side: Side) -> u32 {
+        self.distance(self.rear(side), zone)
+    }
+
+    /// True for either side's front zone
+    pub fn is_melee(&self, zone: Zone) -> bool {
+        zone == self.front(Side::SIDE1) || zone == self.front(Side::SIDE2)
+    }
 }
 
-pub fn parse_damage_dice(s: &str) -> Result<DamageDice, String> {
-    let s = s.trim().to_lowercase();
+/// Creature size. Large and huge creatures take up several bodies' worth of zone capacity and
+/// reach one zone further than their weapon alone would.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Size {
+    Small,
+    #[default]
+    Medium,
+    Large,
+    Huge,
+}
 
-    let (dice_part, modifier) = if let Some(idx) = s.find('+') {
-        let (dice, mod_str) = s.split_at(idx);
-        (dice, mod_str[1..].parse::<i32>().map_err(|e| e.to_string())?)
-    } else if let Some(idx) = s.rfind('-') {
-        if idx == 0 {
-            return Err("Invalid dice format".to_string());
+impl Size {
+    /// How many bodies the creature counts as against zone capacity
+    pub fn bodies(&self) -> u32 {
+        match self {
+            Size::Small | Size::Medium => 1,
+            Size::Large => 2,
+            Size::Huge => 3,
         }
-        let (dice, mod_str) = s.split_at(idx);
-        (dice, mod_str.parse::<i32>().map_err(|e| e.to_string())?)
-    } else {
-        (s.as_str(), 0)
-    };
+    }
 
-    let parts: Vec<&str> = dice_part.split('d').collect();
-    if parts.len() != 2 {
-        return Err("Invalid dice format: expected NdM".to_string());
+    /// Extra zones of reach
+    pub fn extra_reach(&self) -> u32 {
+        match self {
+            Size::Small | Size::Medium => 0,
+            Size::Large | Size::Huge => 1,
+        }
     }
+}
 
-    let count = parts[0].parse::<u32>().map_err(|e| e.to_string())?;
-    let sides = parts[1].parse::<u32>().map_err(|e| e.to_string())?;
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum WeaponRange {
+    #[default]
+    Melee,
+    Reach,
+    Ranged,
+}
 
-    Ok(DamageDice { count, sides, modifier })
+impl WeaponRange {
+    /// Check if this weapon can hit a target at the given distance
+    /// - Melee: adjacent only (distance == 1)
+    /// - Reach: exactly 2 zones away (distance == 2)
+    /// - Ranged: any zone except adjacent (distance >= 2)
+    pub fn can_hit_at_distance(&self, distance: u32) -> bool {
+        match self {
+            WeaponRange::Melee => distance == 1,
+            WeaponRange::Reach => distance == 2,
+            WeaponRange::Ranged => distance >= 2,
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, Default)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
 #[serde(rename_all = "snake_case")]
 pub enum StartingZone {
     #[default]
     Ranged,
     Reach,
     Melee,
+    /// Any zone of the layout, by name
+    #[serde(untagged)]
+    Named(String),
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 #[serde(untagged)]
 pub enum HpValue {
     Fixed(i32),
@@ -231,14 +654,21 @@ impl HpValue {
         }
     }
 
+    /// A roll multiplied by `scale` (see `ActorTemplate::hp_scale`), at least 1
+    pub fn roll_scaled(&self, scale: Option<f64>, rng: &mut impl rand::Rng) -> i32 {
+        let hp = self.roll(rng);
+        match scale {
+            Some(scale) => ((hp as f64 * scale).round() as i32).max(1),
+            None => hp,
+        }
+    }
+
     pub fn expected_value(&self) -> f64 {
         match self {
             HpValue::Fixed(v) => *v as f64,
             HpValue::Dice(s) => {
                 if let Ok(dice) = parse_damage_dice(s) {
-                    // Expected value of NdM is N * (M+1) / 2
-                    let dice_avg = dice.count as f64 * (dice.sides as f64 + 1.0) / 2.0;
-                    (dice_avg + dice.modifier as f64).max(1.0)
+                    dice.expected_value().max(1.0)
                 } else {
                     1.0
                 }
@@ -247,26 +677,161 @@ impl HpValue {
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ActorTemplate {
     pub name: String,
     pub hp: HpValue,
     pub ac: i32,
     pub attack_bonus: i32,
-    #[serde(deserialize_with = "deserialize_damage_dice")]
+    #[schema(value_type = String, example = "1d8+2")]
     pub damage: DamageDice,
     #[serde(default = "default_speed")]
     pub speed: u32,
     #[serde(default)]
     pub range: WeaponRange,
+    /// Attacks strike everyone in the target's zone, each rolled separately: the target's
+    /// allies, and with the encounter's `friendly_fire` the attacker's own too
+    #[serde(default)]
+    pub area: bool,
     #[serde(default)]
     pub start_zone: StartingZone,
+    /// Added to individual initiative rolls (e.g. a dexterity bonus)
     #[serde(default)]
     pub initiative_modifier: i32,
+    /// Weapon speed factor; with `initiative.weapon_speed` it is subtracted from initiative for
+    /// attacks, and slower weapons strike last within a side
+    #[serde(default)]
+    pub weapon_speed: i32,
     #[serde(default = "default_frontage")]
     pub frontage: u32,
+    /// Riders applied by every hit, e.g. `push 1` or a condition (APL attack entries can
+    /// override them)
+    #[serde(default, deserialize_with = "deserialize_riders")]
+    #[schema(value_type = Vec<Object>, example = "push 1")]
+    pub on_hit: Vec<OnHit>,
+    /// Saving throw bonuses against on-hit conditions
+    #[serde(default)]
+    pub saves: Saves,
+    /// Large and huge creatures fill multiples of their frontage and reach one zone further
+    #[serde(default)]
+    pub size: Size,
+    /// Added to the side's surprise roll (the least stealthy actor counts)
+    #[serde(default)]
+    pub stealth: i32,
+    /// Raises the DC to surprise this actor's side (the most perceptive actor counts)
+    #[serde(default)]
+    pub perception: i32,
+    /// Sees dark zones as dim and dim zones as bright
+    #[serde(default)]
+    pub darkvision: bool,
+    /// Lights the actor's zone (bright) and its neighbours (dim), but makes the bearer easy to spot
+    #[serde(default)]
+    pub carries_torch: bool,
+    /// Passive abilities such as `pack_tactics` (see [`Trait`])
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub traits: Vec<Trait>,
+    /// Effects on nearby allies or enemies (see [`Aura`])
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auras: Vec<Aura>,
+    /// Statblocks the actor can call up mid-combat with the `summon` action. Each entry's
+    /// `count` is how many of it the actor can summon per combat.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    #[schema(value_type = Vec<Object>)]
+    pub summons: Vec<ActorTemplate>,
+    /// What the actor becomes when it dies (see [`OnDeath`])
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    #[schema(value_type = Option<Object>)]
+    pub on_death: Option<OnDeath>,
+    /// The side loses the moment this actor dies, however many others it has left (fleeing
+    /// doesn't count). Only the encounter's own actors can be VIPs.
+    #[serde(default)]
+    pub vip: bool,
+    /// Doesn't fight: never attacks, holds no ground, and is left alone unless an enemy's APL
+    /// picks it out with `target: noncombatant`. It doesn't keep its side in the fight either.
+    #[serde(default)]
+    pub noncombatant: bool,
     #[serde(default)]
     pub apl: Vec<AplEntry>,
+    /// Rhai script run each turn in place of `apl`, answering with APL entries (see
+    /// `crate::script`); `apl` is the fallback when it answers with none
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub script: Option<String>,
+    /// Extra actions per round, each taken at the end of another creature's turn
+    #[serde(default)]
+    pub legendary_actions: u32,
+    /// APL for legendary actions (the default APL if empty); one move or action per use
+    #[serde(default)]
+    pub legendary_apl: Vec<AplEntry>,
+    /// Dies to any damage that gets past temp HP, and deals its average damage without rolling
+    #[serde(default)]
+    pub minion: bool,
+    /// Hits that deal less damage than this deal none
+    #[serde(default)]
+    pub damage_threshold: i32,
+    /// Damage armor soaks from every hit, with the encounter's `rules.armor_dr` on
+    #[serde(default, alias = "dr")]
+    pub damage_reduction: i32,
+    /// A swarm deals half damage once it's down to half HP or less
+    #[serde(default)]
+    pub swarm: bool,
+    /// Max HP each hit drains permanently (level drain), on top of its damage
+    #[serde(default)]
+    pub drain: Option<HpValue>,
+    /// Hits only drain, dealing no ordinary damage
+    #[serde(default)]
+    pub drain_only: bool,
+    /// Multiplier on rolled HP for what-if runs (sensitivity analysis); not read from encounters
+    #[serde(skip)]
+    pub hp_scale: Option<f64>,
+    /// Number of identical actors to create from this template
+    #[serde(default = "default_count")]
+    pub count: u32,
+}
+
+impl ActorTemplate {
+    /// Name for the Nth (0-based) actor created from this template.
+    /// Groups get numbered names ("Goblin 1", "Goblin 2", ...); singletons keep the plain name.
+    pub fn instance_name(&self, index: u32) -> String {
+        if self.count > 1 {
+            format!("{} {}", self.name, index + 1)
+        } else {
+            self.name.clone()
+        }
+    }
+
+    /// Statblocks this one can bring into a fight mid-combat, each with the most of it that can
+    /// appear: its `summons` and what it leaves behind `on_death`
+    pub fn spawns(&self) -> impl Iterator<Item = (&ActorTemplate, u32)> {
+        let on_death = self.on_death.as_ref();
+        let spawn = on_death.and_then(|d| d.spawn.as_deref()).map(|t| (t, t.count));
+        let transform = on_death.and_then(|d| d.transform.as_deref()).map(|t| (t, 1));
+        self.summons.iter().map(|summon| (summon, summon.count)).chain(spawn).chain(transform)
+    }
+
+    /// Most actors one of these can put on the field: itself and everything it can summon or
+    /// leave behind, down through whatever those can in turn
+    pub fn fielded(&self) -> usize {
+        self.spawns().fold(1usize, |total, (template, count)| {
+            total.saturating_add((count as usize).saturating_mul(template.fielded()))
+        })
+    }
+
+    /// Index into `summons` of the entry called `name` (case-insensitive)
+    pub fn summon_index(summons: &[ActorTemplate], name: &str) -> Option<usize> {
+        summons.iter().position(|s| s.name.eq_ignore_ascii_case(name.trim()))
+    }
+
+    pub fn roll_hp(&self, rng: &mut impl rand::Rng) -> i32 {
+        self.hp.roll_scaled(self.hp_scale, rng)
+    }
+
+    pub fn expected_hp(&self) -> f64 {
+        self.hp.expected_value() * self.hp_scale.unwrap_or(1.0)
+    }
+}
+
+fn default_count() -> u32 {
+    1
 }
 
 fn default_frontage() -> u32 {
@@ -277,93 +842,708 @@ fn default_speed() -> u32 {
     1
 }
 
-fn deserialize_damage_dice<'de, D>(deserializer: D) -> Result<DamageDice, D::Error>
+/// What an actor leaves behind when it dies, e.g. `on_death: { spawn: <statblock> }`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OnDeath {
+    /// `count` creatures of this statblock appear where the actor fell, e.g. an ooze splitting
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub spawn: Option<Box<ActorTemplate>>,
+    /// The actor rises again as this statblock, e.g. a slain villager as a zombie
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<Box<ActorTemplate>>,
+}
+
+/// Shove or drag the target of a hit, written like `push 1`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum ForcedMove {
+    /// Shove the target this many zones away from the attacker
+    Push(u32),
+    /// Drag the target this many zones toward the attacker
+    Pull(u32),
+}
+
+impl TryFrom<String> for ForcedMove {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        let lower = s.trim().to_lowercase();
+        let mut parts = lower.split_whitespace();
+        let kind = parts.next().unwrap_or("");
+        let zones = match parts.next() {
+            None => 1,
+            Some(n) => n
+                .parse::<u32>()
+                .ok()
+                .filter(|&n| n > 0)
+                .ok_or_else(|| format!("invalid distance '{}' in on_hit '{}'", n, s))?,
+        };
+        if parts.next().is_some() {
+            return Err(format!("invalid on_hit '{}' (expected e.g. push 1 or pull 1)", s));
+        }
+        match kind {
+            "push" => Ok(ForcedMove::Push(zones)),
+            "pull" => Ok(ForcedMove::Pull(zones)),
+            _ => Err(format!("unknown on_hit '{}' (expected push N, pull N, or a condition)", s)),
+        }
+    }
+}
+
+impl From<ForcedMove> for String {
+    fn from(forced: ForcedMove) -> String {
+        match forced {
+            ForcedMove::Push(n) => format!("push {}", n),
+            ForcedMove::Pull(n) => format!("pull {}", n),
+        }
+    }
+}
+
+/// Lingering effects an attack can inflict
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ConditionKind {
+    /// Attacks at disadvantage
+    Poisoned,
+    /// Can't move, and melee attacks against it have advantage
+    #[serde(alias = "knockdown", alias = "knocked_down")]
+    Prone,
+    /// Takes the rider's `damage` at the end of every round
+    Bleeding,
+    /// Loses its turns and reactions, and attacks against it have advantage
+    Stunned,
+    /// Has dropped its weapon: can't attack or grapple
+    Disarmed,
+    /// Every zone costs twice the movement to enter (half speed)
+    Slowed,
+}
+
+impl fmt::Display for ConditionKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ConditionKind::Poisoned => write!(f, "poisoned"),
+            ConditionKind::Prone => write!(f, "prone"),
+            ConditionKind::Bleeding => write!(f, "bleeding"),
+            ConditionKind::Stunned => write!(f, "stunned"),
+            ConditionKind::Disarmed => write!(f, "disarmed"),
+            ConditionKind::Slowed => write!(f, "slowed"),
+        }
+    }
+}
+
+/// Where a called shot aims (APL `called_shot`, with the encounter's `called_shots` on). Each
+/// costs a penalty on the attack roll and, if it hits, inflicts a condition.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum HitLocation {
+    /// -5 to hit; stuns for the rest of this round and the next
+    Head,
+    /// -3 to hit; disarms for the rest of this round and the next
+    Arm,
+    /// -2 to hit; slows for the rest of this round and the two after
+    Leg,
+}
+
+impl HitLocation {
+    /// Taken off the attack roll
+    pub fn penalty(&self) -> i32 {
+        match self {
+            HitLocation::Head => 5,
+            HitLocation::Arm => 3,
+            HitLocation::Leg => 2,
+        }
+    }
+
+    /// What a hit there does to the target (no save)
+    pub fn rider(&self) -> ConditionRider {
+        let (effect, duration) = match self {
+            HitLocation::Head => (ConditionKind::Stunned, 2),
+            HitLocation::Arm => (ConditionKind::Disarmed, 2),
+            HitLocation::Leg => (ConditionKind::Slowed, 3),
+        };
+        ConditionRider {
+            save: None,
+            dc: 0,
+            effect,
+            duration: Some(duration),
+            damage: None,
+            tick: None,
+            stacking: Stacking::Replace,
+        }
+    }
+}
+
+impl fmt::Display for HitLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HitLocation::Head => write!(f, "head"),
+            HitLocation::Arm => write!(f, "arm"),
+            HitLocation::Leg => write!(f, "leg"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Ability {
+    Str,
+    Dex,
+    Con,
+    Int,
+    Wis,
+    Cha,
+}
+
+/// Saving throw bonuses (default 0)
+#[derive(Debug, Clone, Default, Serialize, Deserialize, ToSchema)]
+#[serde(default)]
+pub struct Saves {
+    pub str: i32,
+    pub dex: i32,
+    pub con: i32,
+    pub int: i32,
+    pub wis: i32,
+    pub cha: i32,
+}
+
+impl Saves {
+    pub fn bonus(&self, ability: Ability) -> i32 {
+        match ability {
+            Ability::Str => self.str,
+            Ability::Dex => self.dex,
+            Ability::Con => self.con,
+            Ability::Int => self.int,
+            Ability::Wis => self.wis,
+            Ability::Cha => self.cha,
+        }
+    }
+}
+
+/// Who an aura works on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum AuraTarget {
+    /// The bearer's side, the bearer included
+    #[default]
+    Allies,
+    /// The other side
+    Enemies,
+}
+
+/// A standing effect on everyone it `affects` within `radius` zones of its living bearer (0 is
+/// the bearer's own zone), e.g. bless (`attack: 1`) or frightful presence (`morale: -2`,
+/// `affects: enemies`). Moves into and out of range count at once.
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Aura {
+    #[serde(default)]
+    pub radius: u32,
+    #[serde(default)]
+    pub affects: AuraTarget,
+    /// Added to attack rolls
+    #[serde(default)]
+    pub attack: i32,
+    /// Added to AC
+    #[serde(default)]
+    pub ac: i32,
+    /// Added to the APL variable `self.morale`
+    #[serde(default)]
+    pub morale: i32,
+}
+
+/// What the auras reaching an actor add up to. Like effects don't stack: each number is the
+/// biggest bonus plus the worst penalty among them.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct AuraEffect {
+    pub attack: i32,
+    pub ac: i32,
+    pub morale: i32,
+}
+
+impl AuraEffect {
+    fn of(aura: &Aura) -> Self {
+        AuraEffect { attack: aura.attack, ac: aura.ac, morale: aura.morale }
+    }
+
+    fn zip(self, other: Self, f: impl Fn(i32, i32) -> i32) -> Self {
+        AuraEffect {
+            attack: f(self.attack, other.attack),
+            ac: f(self.ac, other.ac),
+            morale: f(self.morale, other.morale),
+        }
+    }
+}
+
+/// A condition inflicted by a hit, e.g. `{ save: con, dc: 12, effect: poisoned, duration: 3 }`
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct ConditionRider {
+    /// Saving throw that shrugs the effect off (omit for no save)
+    #[serde(default)]
+    pub save: Option<Ability>,
+    /// The save succeeds on d20 + bonus >= dc
+    #[serde(default)]
+    pub dc: i32,
+    pub effect: ConditionKind,
+    /// Rounds the condition lasts (None = rest of combat)
+    #[serde(default)]
+    pub duration: Option<u32>,
+    /// Damage per round, for `bleeding`
+    #[serde(default)]
+    pub damage: Option<HpValue>,
+    /// When the damage lands (default end of round; only with `damage`)
+    #[serde(default)]
+    pub tick: Option<Tick>,
+    /// What a fresh dose does to one the target already has (default `replace`)
+    #[serde(default)]
+    pub stacking: Stacking,
+}
+
+/// Something that happens to the target of a successful attack: a forced move written like
+/// `push 1`, or a condition map
+#[derive(Debug, Clone, Serialize)]
+#[serde(untagged)]
+pub enum OnHit {
+    Forced(ForcedMove),
+    Condition(ConditionRider),
+}
+
+impl<'de> Deserialize<'de> for OnHit {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(RidersVisitor)?.pop().ok_or_else(|| {
+            serde::de::Error::custom("expected one on_hit rider")
+        })
+    }
+}
+
+/// Accepts a single rider (string or map) or a list of them
+struct RidersVisitor;
+
+impl<'de> serde::de::Visitor<'de> for RidersVisitor {
+    type Value = Vec<OnHit>;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a rider like `push 1`, a condition map, or a list of riders")
+    }
+
+    fn visit_str<E: serde::de::Error>(self, v: &str) -> Result<Self::Value, E> {
+        let forced = ForcedMove::try_from(v.to_string()).map_err(E::custom)?;
+        Ok(vec![OnHit::Forced(forced)])
+    }
+
+    fn visit_map<A: serde::de::MapAccess<'de>>(self, map: A) -> Result<Self::Value, A::Error> {
+        let rider = ConditionRider::deserialize(serde::de::value::MapAccessDeserializer::new(map))?;
+        Ok(vec![OnHit::Condition(rider)])
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let mut riders = Vec::new();
+        while let Some(rider) = seq.next_element::<OnHit>()? {
+            riders.push(rider);
+        }
+        Ok(riders)
+    }
+
+    fn visit_unit<E: serde::de::Error>(self) -> Result<Self::Value, E> {
+        Ok(Vec::new())
+    }
+}
+
+fn deserialize_riders<'de, D>(deserializer: D) -> Result<Vec<OnHit>, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let s = String::deserialize(deserializer)?;
-    parse_damage_dice(&s).map_err(serde::de::Error::custom)
+    deserializer.deserialize_any(RidersVisitor)
 }
 
-impl Default for WeaponRange {
-    fn default() -> Self {
-        WeaponRange::Melee
+/// What sets off a reaction (an APL entry with `ready`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Trigger {
+    /// An enemy finishes a move where the actor can attack it
+    EnemyEntersZone,
+    /// An enemy is about to move out of the actor's reach (an opportunity attack)
+    EnemyLeavesZone,
+    /// The actor is targeted by an attack, before the roll
+    Attacked,
+    /// An ally is targeted by an attack, before the roll
+    AllyAttacked,
+}
+
+impl fmt::Display for Trigger {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Trigger::EnemyEntersZone => write!(f, "enemy_enters_zone"),
+            Trigger::EnemyLeavesZone => write!(f, "enemy_leaves_zone"),
+            Trigger::Attacked => write!(f, "attacked"),
+            Trigger::AllyAttacked => write!(f, "ally_attacked"),
+        }
     }
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct AplEntry {
+    /// Empty for reactions, which use `ready` instead
+    #[serde(default)]
     pub action: String,
     #[serde(rename = "if")]
     pub condition: Option<String>,
     pub target: Option<String>,
+    /// Amount granted by the action (e.g. temp HP for `buff`), fixed or dice
+    #[serde(default)]
+    pub amount: Option<HpValue>,
+    /// How many rounds the effect lasts (None = rest of combat)
+    #[serde(default)]
+    pub duration: Option<u32>,
+    /// Only use this entry during the given phase (phase-based initiative only)
+    #[serde(default)]
+    pub phase: Option<Phase>,
+    /// Riders for attacks made through this entry, replacing the actor's own `on_hit`
+    #[serde(default, deserialize_with = "deserialize_riders")]
+    #[schema(value_type = Vec<Object>, example = "push 1")]
+    pub on_hit: Vec<OnHit>,
+    /// Aim attacks made through this entry at a hit location (needs the encounter's
+    /// `called_shots`)
+    #[serde(default)]
+    pub called_shot: Option<HitLocation>,
+    /// Makes the entry a reaction: the action (attack, guard, dodge) taken when `trigger` fires
+    #[serde(default)]
+    pub ready: Option<String>,
+    #[serde(default)]
+    pub trigger: Option<Trigger>,
+    /// Only armed while the actor is holding its action (it took `hold` on its last turn)
+    #[serde(default)]
+    pub held: bool,
+    /// The part of the turn the entry spends (None = the action)
+    #[serde(default)]
+    pub uses: Option<Slot>,
+    /// A `move` or `flee` taken once the actor has acted, with whatever movement it has left
+    #[serde(default)]
+    pub after_action: bool,
 }
 
 #[derive(Debug, Clone)]
 pub struct Actor {
     pub id: usize,
-    pub name: String,
+    pub name: Arc<str>,
+    pub template_name: Arc<str>,
     pub side: Side,
     pub max_hp: i32,
     pub current_hp: i32,
     pub ac: i32,
-    pub ac_bonus: i32,  // Temporary AC bonus (e.g., from guarding)
+    pub reacted: bool,  // Already used its one reaction this round
+    pub grappled_by: Option<usize>, // Held in place by this actor (while it lives)
+    pub grappling: Option<usize>,   // Holding this actor in place (while it lives)
     pub attack_bonus: i32,
     pub damage: DamageDice,
     pub speed: u32,
     pub range: WeaponRange,
+    pub area: bool,
     pub zone: Zone,
+    /// Movement already spent toward entering a zone longer than the actor's speed
+    pub move_progress: Option<(Zone, u32)>,
+    /// In a melee zone but waiting behind a full fighting front (see `Encounter::front_width`)
+    pub in_reserve: bool,
     pub initiative_modifier: i32,
+    pub weapon_speed: i32,
     pub frontage: u32,
-    pub apl: Vec<AplEntry>,
+    pub on_hit: Vec<OnHit>,
+    pub saves: Saves,
+    /// Conditions, temp HP, and postures currently on the actor
+    pub effects: Effects,
+    pub size: Size,
+    pub stealth: i32,
+    pub perception: i32,
+    pub darkvision: bool,
+    pub carries_torch: bool,
+    pub traits: Arc<[Trait]>,
+    pub auras: Arc<[Aura]>,
+    pub summons: Arc<[ActorTemplate]>,
+    /// How many of each `summons` entry the actor has called up this combat
+    pub summoned: Vec<u32>,
+    /// The actor that summoned this one, or whose death spawned it (None for the encounter's
+    /// own actors)
+    pub summoner: Option<usize>,
+    pub on_death: Option<Arc<OnDeath>>,
+    /// Its death loses the fight for its side
+    pub vip: bool,
+    /// Civilian: stays out of the fighting and doesn't count toward its side standing
+    pub noncombatant: bool,
+    /// Sides it fights (every side but its own unless the encounter's `hostility` says otherwise)
+    pub hostile_to: SideSet,
+    pub apl: Arc<[AplEntry]>,
+    pub script: Option<Arc<str>>,
+    pub legendary_actions: u32,
+    /// Legendary actions left this round
+    pub legendary_remaining: u32,
+    pub legendary_apl: Arc<[AplEntry]>,
+    pub minion: bool,
+    pub damage_threshold: i32,
+    pub damage_reduction: i32,
+    pub swarm: bool,
+    pub drain: Option<HpValue>,
+    pub drain_only: bool,
+    /// Max HP lost to drain so far
+    pub drained: i32,
+    /// Left the fight through an exit, alive
+    pub fled: bool,
 }
 
 impl Actor {
-    pub fn from_template(id: usize, template: &ActorTemplate, side: Side, rng: &mut impl rand::Rng) -> Self {
-        let zone = match (side, template.start_zone) {
-            (Side::Side1, StartingZone::Ranged) => Zone::Side1Ranged,
-            (Side::Side1, StartingZone::Reach) => Zone::Side1Reach,
-            (Side::Side1, StartingZone::Melee) => Zone::Side1Melee,
-            (Side::Side2, StartingZone::Ranged) => Zone::Side2Ranged,
-            (Side::Side2, StartingZone::Reach) => Zone::Side2Reach,
-            (Side::Side2, StartingZone::Melee) => Zone::Side2Melee,
-        };
-        let hp = template.hp.roll(rng);
+    /// An actor with no HP yet; [`Actor::reset`] gives it its rolled HP before each combat
+    pub fn from_template(id: usize, template: &ActorTemplate, side: Side, zone: Zone) -> Self {
+        let name: Arc<str> = template.name.as_str().into();
         Actor {
             id,
-            name: template.name.clone(),
+            name: name.clone(),
+            template_name: name,
             side,
-            max_hp: hp,
-            current_hp: hp,
+            max_hp: 0,
+            current_hp: 0,
             ac: template.ac,
-            ac_bonus: 0,
+            reacted: false,
+            grappled_by: None,
+            grappling: None,
             attack_bonus: template.attack_bonus,
             damage: template.damage.clone(),
             speed: template.speed,
             range: template.range,
+            area: template.area,
             zone,
+            move_progress: None,
+            in_reserve: false,
             initiative_modifier: template.initiative_modifier,
+            weapon_speed: template.weapon_speed,
             frontage: template.frontage,
-            apl: template.apl.clone(),
+            on_hit: template.on_hit.clone(),
+            saves: template.saves.clone(),
+            effects: Effects::default(),
+            size: template.size,
+            stealth: template.stealth,
+            perception: template.perception,
+            darkvision: template.darkvision,
+            carries_torch: template.carries_torch,
+            traits: template.traits.as_slice().into(),
+            auras: template.auras.as_slice().into(),
+            summons: template.summons.as_slice().into(),
+            summoned: vec![0; template.summons.len()],
+            summoner: None,
+            on_death: template.on_death.clone().map(Arc::new),
+            vip: template.vip,
+            noncombatant: template.noncombatant,
+            hostile_to: SideSet::all_but(side),
+            apl: template.apl.as_slice().into(),
+            script: template.script.as_deref().map(Arc::from),
+            legendary_actions: template.legendary_actions,
+            legendary_remaining: 0,
+            legendary_apl: template.legendary_apl.as_slice().into(),
+            minion: template.minion,
+            damage_threshold: template.damage_threshold,
+            damage_reduction: template.damage_reduction,
+            swarm: template.swarm,
+            drain: template.drain.clone(),
+            drain_only: template.drain_only,
+            drained: 0,
+            fled: false,
         }
     }
 
+    /// Put the actor back as it stood before its first turn, on `hp` HP in `zone`. Clears
+    /// every field a combat changes.
+    pub fn reset(&mut self, hp: i32, zone: Zone, in_reserve: bool) {
+        self.max_hp = hp;
+        self.current_hp = hp;
+        self.reacted = false;
+        self.grappled_by = None;
+        self.grappling = None;
+        self.zone = zone;
+        self.move_progress = None;
+        self.in_reserve = in_reserve;
+        self.effects.clear();
+        self.legendary_remaining = 0;
+        self.drained = 0;
+        self.fled = false;
+        self.summoned.iter_mut().for_each(|n| *n = 0);
+    }
+
+    /// Still in the fight: above 0 HP and hasn't fled
     pub fn is_alive(&self) -> bool {
-        self.current_hp > 0
+        self.current_hp > 0 && !self.fled
+    }
+
+    /// Apply damage, draining temp HP first. Returns the amount absorbed by temp HP.
+    /// Minions drop to 0 HP from any damage that gets through.
+    pub fn take_damage(&mut self, damage: i32) -> i32 {
+        let mut absorbed = 0;
+        if let Some(temp_hp) = self.effects.get_mut(EffectKind::TempHp) {
+            absorbed = damage.min(temp_hp.magnitude).max(0);
+            temp_hp.magnitude -= absorbed;
+            if temp_hp.magnitude == 0 {
+                self.effects.remove(EffectKind::TempHp);
+            }
+        }
+        self.current_hp -= damage - absorbed;
+        if self.minion && damage > absorbed {
+            self.current_hp = self.current_hp.min(0);
+        }
+        absorbed
+    }
+
+    /// Permanently lower max HP, pulling current HP down with it. Returns the amount drained.
+    pub fn drain_max_hp(&mut self, amount: i32) -> i32 {
+        let drained = amount.clamp(0, self.max_hp);
+        self.max_hp -= drained;
+        self.drained += drained;
+        self.current_hp = self.current_hp.min(self.max_hp);
+        drained
+    }
+
+    pub fn has_condition(&self, kind: ConditionKind) -> bool {
+        self.effects.has(EffectKind::Condition(kind))
+    }
+
+    /// Temporary HP left to absorb damage
+    pub fn temp_hp(&self) -> i32 {
+        self.effects.magnitude(EffectKind::TempHp)
+    }
+
+    /// How many more of its `index`th `summons` entry the actor can call up this combat
+    pub fn summons_left(&self, index: usize) -> u32 {
+        self.summons[index].count.saturating_sub(self.summoned[index])
+    }
+
+    pub fn has_trait(&self, t: Trait) -> bool {
+        self.traits.contains(&t)
+    }
+
+    /// The combined effect of every living actor's auras that reach this one where it stands
+    pub fn aura_effect(&self, actors: &[Actor], zones: &ZoneLayout) -> AuraEffect {
+        let (mut best, mut worst) = (AuraEffect::default(), AuraEffect::default());
+        for bearer in actors.iter().filter(|a| !a.auras.is_empty() && a.is_alive()) {
+            let distance = zones.distance(bearer.zone, self.zone);
+            for aura in bearer.auras.iter() {
+                let affected = match aura.affects {
+                    AuraTarget::Allies => bearer.is_allied_with(self),
+                    AuraTarget::Enemies => bearer.is_hostile_to(self),
+                };
+                if affected && distance <= aura.radius {
+                    best = best.zip(AuraEffect::of(aura), i32::max);
+                    worst = worst.zip(AuraEffect::of(aura), i32::min);
+                }
+            }
+        }
+        best.zip(worst, |bonus, penalty| bonus + penalty)
+    }
+
+    /// Held by a living grappler: can't move and attacks at disadvantage
+    pub fn is_grappled(&self, actors: &[Actor]) -> bool {
+        self.grappled_by.is_some_and(|id| actors[id].is_alive())
+    }
+
+    /// Holding a living target in a grapple, which keeps the grappler in place too
+    pub fn is_grappling(&self, actors: &[Actor]) -> bool {
+        self.grappling.is_some_and(|id| actors[id].is_alive())
+    }
+
+    /// Damage after the actor's damage threshold: smaller hits are ignored
+    pub fn after_threshold(&self, damage: i32) -> i32 {
+        if damage < self.damage_threshold {
+            0
+        } else {
+            damage
+        }
+    }
+
+    /// Roll the actor's weapon damage; minions deal their average without rolling, and a
+    /// swarm at half HP or less deals half
+    pub fn roll_damage(&self, rules: DiceRules, rng: &mut impl rand::Rng) -> i32 {
+        let damage = if self.minion {
+            (self.damage.expected_value_with(rules) as i32).max(1)
+        } else {
+            self.damage.roll_with(rules, rng)
+        };
+        if self.is_thinned() {
+            damage / 2
+        } else {
+            damage
+        }
+    }
+
+    /// Mean of `roll_damage`
+    pub fn average_damage(&self, rules: DiceRules) -> f64 {
+        let damage = if self.minion {
+            (self.damage.expected_value_with(rules) as i32).max(1) as f64
+        } else {
+            self.damage.expected_value_with(rules).max(if rules.min_one { 1.0 } else { 0.0 })
+        };
+        if self.is_thinned() {
+            damage / 2.0
+        } else {
+            damage
+        }
+    }
+
+    /// `other` is on a side this actor fights
+    pub fn is_hostile_to(&self, other: &Actor) -> bool {
+        self.hostile_to.contains(other.side)
+    }
+
+    /// On the same side, or on sides that leave each other alone
+    pub fn is_allied_with(&self, other: &Actor) -> bool {
+        !self.is_hostile_to(other) && !other.is_hostile_to(self)
+    }
+
+    /// A swarm that has lost at least half its HP
+    pub fn is_thinned(&self) -> bool {
+        self.swarm && self.current_hp * 2 <= self.max_hp
+    }
+
+    /// Zone capacity the actor takes up: its frontage once per body
+    pub fn space(&self) -> u32 {
+        self.frontage * self.size.bodies()
     }
 
     pub fn effective_ac(&self) -> i32 {
-        self.ac + self.ac_bonus
+        self.ac + self.effects.magnitude(EffectKind::Stance)
+    }
+
+    /// Expected damage per round against a given AC (d20 + attack bonus >= AC to hit)
+    pub fn expected_damage_against(&self, ac: i32) -> f64 {
+        let needed = ac - self.attack_bonus;
+        let hit_chance = ((21 - needed) as f64 / 20.0).clamp(0.0, 1.0);
+        hit_chance * self.damage.expected_value().max(0.0)
     }
 
-    pub fn can_attack(&self, target: &Actor) -> bool {
-        let distance = self.zone.distance_to(&target.zone);
-        self.range.can_hit_at_distance(distance)
+    /// How well this actor sees `target`
+    pub fn sight_of(&self, target: &Actor, zones: &ZoneLayout, actors: &[Actor]) -> Light {
+        let light = zones.light_at(target.zone, actors);
+        if self.darkvision {
+            light.brighter()
+        } else {
+            light
+        }
+    }
+
+    /// In range, not held in reserve, and - for ranged and reach weapons - able to see the target.
+    /// A target sharing the actor's zone (a faction deployed among its enemies) counts as adjacent.
+    pub fn can_attack(&self, target: &Actor, zones: &ZoneLayout, actors: &[Actor]) -> bool {
+        if self.in_reserve {
+            return false;
+        }
+        let distance = zones.distance(self.zone, target.zone);
+        if distance == u32::MAX {
+            return false;
+        }
+        let distance = distance.max(1);
+        let reach = self.size.extra_reach();
+        let in_range = (0..=reach)
+            .any(|extra| distance > extra && self.range.can_hit_at_distance(distance - extra));
+        if !in_range {
+            return false;
+        }
+        self.range == WeaponRange::Melee || self.sight_of(target, zones, actors) > Light::Dark
     }
 }
 
 /// Zone capacity in "frontage units" - actors occupy space based on their frontage
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct ZoneCapacities {
     #[serde(default = "default_ranged_capacity")]
     pub ranged: Option<u32>,  // None means infinite
@@ -395,17 +1575,7 @@ fn default_melee_capacity() -> u32 {
     10
 }
 
-impl ZoneCapacities {
-    pub fn capacity_for(&self, zone: Zone) -> Option<u32> {
-        match zone {
-            Zone::Side1Ranged | Zone::Side2Ranged => self.ranged,
-            Zone::Side1Reach | Zone::Side2Reach => Some(self.reach),
-            Zone::Side1Melee | Zone::Side2Melee => Some(self.melee),
-        }
-    }
-}
-
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
 pub struct Encounter {
     pub name: Option<String>,
     pub side1: Vec<ActorTemplate>,
@@ -414,10 +1584,281 @@ pub struct Encounter {
     pub iterations: u32,
     #[serde(default)]
     pub zone_capacity: ZoneCapacities,
+    /// Light level for zones that don't set their own
+    #[serde(default)]
+    pub light: Light,
+    /// Custom zone layout from side 1's rear to side 2's rear (replaces the six-zone tunnel
+    /// and `zone_capacity`)
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub zones: Vec<ZoneSpec>,
+    /// Side corridors joined to the main line by zone `links`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub lanes: Vec<Lane>,
+    /// How many actors per side can fight from the melee zones at once (None = everyone)
+    #[serde(default)]
+    pub front_width: Option<u32>,
     #[serde(default)]
     pub initiative: InitiativeConfig,
+    #[serde(default = "default_max_rounds")]
+    pub max_rounds: u32,
+    #[serde(default)]
+    pub draw_policy: DrawPolicy,
+    /// Side holding its ground, used by `draw_policy: defender_wins`
+    #[serde(default = "default_defender")]
+    pub defender: Side,
+    #[serde(default)]
+    pub surprise: Surprise,
+    /// Ways to win besides wiping out the other side
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub objectives: Vec<Objective>,
+    /// Each side's rear zone is an exit: actors that `flee` from it leave the fight alive
+    #[serde(default)]
+    pub exits: bool,
+    /// Lets APL attacks aim at a hit location with `called_shot` (see [`HitLocation`])
+    #[serde(default)]
+    pub called_shots: bool,
+    /// Ranged attacks that miss a target engaged in melee may hit one of the shooter's allies
+    /// fighting it, and `area` attacks catch the attacker's allies in the area too
+    #[serde(default)]
+    pub friendly_fire: bool,
+    /// Optional rule variants
+    #[serde(default)]
+    pub rules: Rules,
+    /// Sides beyond the first two, `side3` onward, for fights with more than two parties
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub factions: Vec<Faction>,
+    /// The sides each side fights, e.g. `{ side3: [side1, side2] }`. A side left out fights
+    /// every other side; a side nobody fights and that fights nobody stays out of it.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub hostility: BTreeMap<Side, Vec<Side>>,
+}
+
+/// A third (fourth, ...) party to the fight, such as a wandering monster
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+pub struct Faction {
+    pub actors: Vec<ActorTemplate>,
+    /// The end of the main line the faction deploys at: `side1`'s or `side2`'s (default)
+    #[serde(default = "default_faction_end")]
+    pub deploys: Side,
+}
+
+fn default_faction_end() -> Side {
+    Side::SIDE2
+}
+
+impl Encounter {
+    /// Every side in the fight: side 1, side 2, then one per faction
+    pub fn sides(&self) -> impl Iterator<Item = Side> {
+        (0..self.factions.len() + 2).map(Side::new)
+    }
+
+    pub fn templates(&self, side: Side) -> &[ActorTemplate] {
+        match side.index() {
+            0 => &self.side1,
+            1 => &self.side2,
+            n => &self.factions[n - 2].actors,
+        }
+    }
+
+    pub fn templates_mut(&mut self, side: Side) -> &mut Vec<ActorTemplate> {
+        match side.index() {
+            0 => &mut self.side1,
+            1 => &mut self.side2,
+            n => &mut self.factions[n - 2].actors,
+        }
+    }
+
+    /// The sides `side` fights, per `hostility`
+    pub fn hostile_to(&self, side: Side) -> SideSet {
+        let mut hostile = SideSet::default();
+        match self.hostility.get(&side) {
+            Some(sides) => sides.iter().for_each(|&other| hostile.insert(other)),
+            None => self.sides().filter(|&other| other != side).for_each(|other| hostile.insert(other)),
+        }
+        hostile
+    }
+
+    /// Templates on every side `side` fights
+    pub fn enemy_templates(&self, side: Side) -> impl Iterator<Item = &ActorTemplate> {
+        let hostile = self.hostile_to(side);
+        self.sides()
+            .filter(move |&other| hostile.contains(other))
+            .flat_map(|other| self.templates(other))
+    }
+
+    pub fn zone_layout(&self) -> ZoneLayout {
+        let main = if self.zones.is_empty() {
+            ZoneLayout::tunnel_zones(&self.zone_capacity)
+        } else {
+            self.zones.clone()
+        };
+        ZoneLayout::new(main, &self.lanes, self.light).with_faction_ends(self.factions.iter().map(|f| f.deploys).collect())
+    }
+
+    /// Number of combatants on a side after expanding template counts
+    pub fn actor_count(&self, side: Side) -> usize {
+        self.combatants(side).map(|t| t.count as usize).sum()
+    }
+
+    /// Templates on `side` that fight (everything but noncombatants)
+    fn combatants(&self, side: Side) -> impl Iterator<Item = &ActorTemplate> {
+        self.templates(side).iter().filter(|t| !t.noncombatant)
+    }
+
+    /// Noncombatants on every side
+    pub fn noncombatant_count(&self) -> usize {
+        self.sides()
+            .flat_map(|side| self.templates(side))
+            .filter(|t| t.noncombatant)
+            .map(|t| t.count as usize)
+            .sum()
+    }
+
+    /// Most actors a side can field, counting everything its actors can summon
+    pub fn max_actor_count(&self, side: Side) -> usize {
+        self.templates(side)
+            .iter()
+            .fold(0usize, |total, t| total.saturating_add((t.count as usize).saturating_mul(t.fielded())))
+    }
+
+    /// Expected total HP of a side's combatants (dice-based HP uses expected values)
+    pub fn expected_hp(&self, side: Side) -> i32 {
+        self.combatants(side)
+            .map(|t| t.expected_hp() as i32 * t.count as i32)
+            .sum()
+    }
 }
 
 fn default_iterations() -> u32 {
     30000
 }
+
+fn default_max_rounds() -> u32 {
+    100
+}
+
+fn default_defender() -> Side {
+    Side::SIDE2
+}
+
+/// Whether one side is caught off guard and loses the first round
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum Surprise {
+    /// Nobody is surprised
+    #[default]
+    None,
+    /// Side 1 is surprised: side 2 gets a free round
+    Side1,
+    /// Side 2 is surprised: side 1 gets a free round
+    Side2,
+    /// Each side rolls d20 + its worst `stealth` against 10 + the other side's best
+    /// `perception`; if exactly one side succeeds, the other is surprised
+    Roll,
+}
+
+/// An alternative victory condition for one side
+#[derive(Debug, Clone, Serialize, Deserialize, ToSchema)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Objective {
+    /// `side` wins as soon as one of its actors reaches `zone` (default: the enemy's rear)
+    Breakthrough {
+        side: Side,
+        #[serde(default)]
+        zone: Option<String>,
+    },
+    /// `side` wins if it still has someone standing after `rounds` rounds
+    Hold { side: Side, rounds: u32 },
+}
+
+impl Objective {
+    pub fn side(&self) -> Side {
+        match self {
+            Objective::Breakthrough { side, .. } | Objective::Hold { side, .. } => *side,
+        }
+    }
+}
+
+impl fmt::Display for Objective {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Objective::Breakthrough { zone: Some(zone), .. } => write!(f, "breakthrough to {}", zone),
+            Objective::Breakthrough { zone: None, .. } => write!(f, "breakthrough"),
+            Objective::Hold { rounds, .. } => write!(f, "hold for {} rounds", rounds),
+        }
+    }
+}
+
+/// Rule variants for systems that play differently from the default d20 rules
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, ToSchema)]
+pub struct Rules {
+    /// Armor as soak: AC still decides whether an attack hits, and each hit then loses the
+    /// target's `damage_reduction`
+    #[serde(default)]
+    pub armor_dr: bool,
+    /// Weapon damage dice explode on their highest face
+    #[serde(default)]
+    pub exploding_dice: bool,
+    /// Weapon damage dice that roll a 1 are rerolled once
+    #[serde(default)]
+    pub reroll_ones: bool,
+    /// A hit always rolls at least 1 damage, before armor and damage threshold
+    #[serde(default)]
+    pub min_damage: bool,
+    /// What the shooter's allies standing between it and its target do to a ranged attack
+    #[serde(default)]
+    pub line_of_fire: LineOfFire,
+}
+
+/// How allies in zones between a ranged attacker and its target get in the way
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum LineOfFire {
+    /// They don't: shots pass over them
+    #[default]
+    Clear,
+    /// The shot takes a penalty to hit while any of them are in the way
+    Penalty,
+    /// A shot that misses may hit one of them instead
+    HitAllies,
+}
+
+impl Rules {
+    /// House rules for rolling weapon damage
+    pub fn dice(&self) -> DiceRules {
+        DiceRules {
+            explode: self.exploding_dice,
+            reroll_ones: self.reroll_ones,
+            min_one: self.min_damage,
+        }
+    }
+}
+
+/// How to decide a combat that hits `max_rounds` with both sides still standing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DrawPolicy {
+    /// No winner
+    #[default]
+    Draw,
+    /// Side with more surviving HP wins (equal HP is still a draw)
+    MostHp,
+    /// The encounter's `defender` side wins by holding out
+    DefenderWins,
+    /// Side with the higher percentage of its starting HP left wins
+    MostHpPercent,
+    /// Side that has killed more enemies wins
+    MostKills,
+    /// Side holding more zones (with living actors in them) wins
+    MostZones,
+}
+
+impl DrawPolicy {
+    /// Policies that score both sides and compare, rather than picking a winner outright
+    pub fn is_scored(&self) -> bool {
+        matches!(
+            self,
+            DrawPolicy::MostHp | DrawPolicy::MostHpPercent | DrawPolicy::MostKills | DrawPolicy::MostZones
+        )
+    }
+}
@@ -0,0 +1,71 @@
+//! JavaScript bindings for running simulations in the browser, with no server round trip.
+//! Built with `--no-default-features --features wasm` for `wasm32-unknown-unknown`. There's no
+//! monster library in the browser, so only `ref: builtin/...` refs resolve, and runs are
+//! single-threaded: keep them small.
+//!
+//! Results and errors are JSON text in the same shapes the HTTP API uses.
+
+use serde::Deserialize;
+use wasm_bindgen::prelude::*;
+
+use crate::combat::EventDetail;
+use crate::library::NoLibrary;
+use crate::lint;
+use crate::loader::parse_encounter;
+use crate::simulation::{run_simulation, SimulationOptions};
+use crate::ValidationError;
+
+/// Optional run settings, as in a `/simulate` request
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+struct Params {
+    iterations: Option<u32>,
+    seed: Option<u64>,
+    max_rounds: Option<u32>,
+    sample_count: Option<usize>,
+    target_precision: Option<f64>,
+    event_detail: EventDetail,
+}
+
+/// A JSON array of errors, for the rejected promise or thrown exception
+fn errors(errors: &[ValidationError]) -> JsValue {
+    JsValue::from_str(&serde_json::to_string(errors).unwrap_or_default())
+}
+
+/// Simulate an encounter (YAML, JSON, or TOML text). `params` is a JSON object with any of
+/// `iterations`, `seed`, `max_rounds`, `sample_count` (default 5), `target_precision`, and
+/// `event_detail`, or an empty string. Returns the `/simulate` result as JSON; throws a JSON
+/// array of validation errors.
+#[wasm_bindgen]
+pub fn simulate(encounter: &str, params: &str) -> Result<String, JsValue> {
+    let params: Params = match params.trim() {
+        "" => Params::default(),
+        params => serde_json::from_str(params).map_err(|e| JsValue::from_str(&format!("invalid params: {}", e)))?,
+    };
+    let encounter = parse_encounter(encounter, &NoLibrary).map_err(|e| errors(&e))?;
+    let defaults = SimulationOptions::for_encounter(&encounter);
+    let options = SimulationOptions {
+        iterations: params.iterations.unwrap_or(defaults.iterations),
+        max_rounds: params.max_rounds.unwrap_or(defaults.max_rounds),
+        sample_count: params.sample_count.unwrap_or(defaults.sample_count),
+        seed: params.seed,
+        target_precision: params.target_precision,
+        // The browser gives a module one thread
+        threads: 1,
+        event_detail: params.event_detail,
+        ..defaults
+    };
+    let result = run_simulation(&encounter, &options);
+    serde_json::to_string(&result).map_err(|e| JsValue::from_str(&e.to_string()))
+}
+
+/// Validate and lint an encounter without running it. Returns `{ "valid", "errors",
+/// "warnings" }` as JSON, the shape of `POST /lint`.
+#[wasm_bindgen]
+pub fn validate(encounter: &str) -> String {
+    let (errors, warnings) = match parse_encounter(encounter, &NoLibrary) {
+        Ok(encounter) => (Vec::new(), lint::lint(&encounter)),
+        Err(errors) => (errors, Vec::new()),
+    };
+    serde_json::json!({ "valid": errors.is_empty(), "errors": errors, "warnings": warnings }).to_string()
+}
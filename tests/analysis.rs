@@ -0,0 +1,53 @@
+//! Matchup analysis: the computed hit chance and damage per attack against what simulated
+//! attacks actually do.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use tunnel_fight::combat::{CombatSimulator, EventType};
+use tunnel_fight::simulation::{run_simulation, SimulationOptions};
+use tunnel_fight::types::Encounter;
+
+#[test]
+fn simulated_attacks_match_analytical_odds() {
+    // A hit never kills (the dummy's HP dwarfs the damage), so every round is one more swing.
+    // The second dummy's armor soaks damage before its threshold sees it.
+    let dummies = [
+        ("damage_threshold: 5", "false"),
+        ("damage_threshold: 3, damage_reduction: 2", "true"),
+    ];
+    for (defenses, armor_dr) in dummies {
+        let encounter: Encounter = serde_yaml::from_str(&format!(
+            "max_rounds: 50\n\
+             rules: {{ armor_dr: {} }}\n\
+             side1: [ {{ name: Fighter, hp: 10000, ac: 30, attack_bonus: 3, damage: 2d4+1, start_zone: melee }} ]\n\
+             side2: [ {{ name: Dummy, hp: 10000, ac: 14, attack_bonus: 0, damage: 1, start_zone: melee, {} }} ]\n",
+            armor_dr, defenses
+        ))
+        .unwrap();
+        let matchup = &run_simulation(&encounter, &SimulationOptions { iterations: 1, ..SimulationOptions::for_encounter(&encounter) }).matchups[0];
+        assert_eq!((matchup.attacker.as_str(), matchup.target.as_str()), ("Fighter", "Dummy"));
+
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        let (mut attacks, mut hits, mut damage) = (0u32, 0u32, 0i64);
+        for _ in 0..200 {
+            let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+            for event in result.events.iter().filter(|e| &*e.actor_name == "Fighter") {
+                if let EventType::Attack { hit, damage: dealt, .. } = event.event_type {
+                    attacks += 1;
+                    hits += u32::from(hit);
+                    damage += i64::from(dealt);
+                }
+            }
+        }
+        let hit_rate = f64::from(hits) / f64::from(attacks) * 100.0;
+        let per_attack = damage as f64 / f64::from(attacks);
+        assert!((hit_rate - matchup.hit_chance).abs() < 1.5, "hit {}%, expected {}%", hit_rate, matchup.hit_chance);
+        assert!(
+            (per_attack - matchup.expected_damage_per_attack).abs() < 0.1,
+            "{} damage per attack, expected {}",
+            per_attack,
+            matchup.expected_damage_per_attack
+        );
+    }
+}
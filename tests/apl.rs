@@ -0,0 +1,44 @@
+//! APL tracing: what `debug_combat` reports about each entry an actor's APL considered.
+
+use tunnel_fight::apl::EntryOutcome;
+use tunnel_fight::simulation::debug_combat;
+use tunnel_fight::types::Encounter;
+
+#[test]
+fn traces_explain_every_entry_of_the_apl() {
+    let encounter: Encounter = serde_yaml::from_str(
+        "
+side1:
+  - name: A
+    hp: 10
+    ac: 12
+    attack_bonus: 2
+    damage: 1d6
+    start_zone: melee
+    apl:
+      - { action: flee, if: self.hp_percent < 50 }
+      - { action: attack }
+      - { action: guard }
+      - { action: move, target: forward }
+      - { action: dodge }
+side2: [ { name: B, hp: 10, ac: 12, attack_bonus: 2, damage: 1d6, start_zone: melee } ]
+",
+    )
+    .unwrap();
+    let (_, choices) = debug_combat(&encounter, encounter.max_rounds, 1, 0);
+    let first = choices.iter().find(|c| c.actor_id == 0).unwrap();
+    let outcomes: Vec<_> = first.entries.iter().map(|e| e.outcome).collect();
+    assert_eq!(
+        outcomes,
+        [
+            EntryOutcome::ConditionFailed,
+            EntryOutcome::Chosen,
+            EntryOutcome::Skipped,
+            EntryOutcome::Chosen,
+            EntryOutcome::NotReached
+        ]
+    );
+    assert_eq!(first.entries[0].terms[0].values["self.hp_percent"], Some(100.0));
+    assert_eq!(first.entries[1].reason, "action: attack B (id 1)");
+    assert_eq!(first.entries[2].reason, "the action is already taken");
+}
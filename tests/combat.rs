@@ -0,0 +1,469 @@
+//! Combat rules: small hand-built fights checking that one rule (summons, deaths, VIPs,
+//! factions, conditions, movement, line of fire, called shots, timeouts) plays out as written.
+
+use std::time::Instant;
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use tunnel_fight::combat::{CombatEvent, CombatSimulator, EventType, Stalemate};
+use tunnel_fight::simulation::{run_simulation, SimulationOptions};
+use tunnel_fight::types::{Encounter, Side};
+
+#[test]
+fn every_combat_ends() {
+    // A stalemate (no one can reach anyone) must still stop at the round limit
+    let encounter: Encounter = serde_yaml::from_str(
+        r#"
+max_rounds: 25
+side1:
+  - { name: Archer, hp: 10, ac: 12, attack_bonus: 2, damage: 1d6, range: ranged, start_zone: ranged,
+      apl: [ { action: defend } ] }
+side2:
+  - { name: Turtle, hp: 10, ac: 12, attack_bonus: 2, damage: 1d6, start_zone: ranged,
+      apl: [ { action: defend } ] }
+"#,
+    )
+    .unwrap();
+    let mut rng = ChaCha8Rng::seed_from_u64(1);
+    let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+    assert!(result.timed_out);
+    assert_eq!(result.rounds, 25);
+    assert!(!result.cut_off);
+
+    // A deadline stops it between rounds, and a run drops the combat it caught
+    let deadline = Some(Instant::now());
+    let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).with_deadline(deadline).run(&mut rng);
+    assert!(result.cut_off);
+    assert_eq!(result.rounds, 0);
+    let options = SimulationOptions { iterations: 10, deadline, ..SimulationOptions::for_encounter(&encounter) };
+    assert_eq!(run_simulation(&encounter, &options).stats.iterations, 0);
+}
+
+#[test]
+fn timeouts_are_diagnosed() {
+    let stalemate = |yaml: &str| {
+        let encounter: Encounter = serde_yaml::from_str(&format!("max_rounds: 5\n{}", yaml)).unwrap();
+        assert!(tunnel_fight::validate(&encounter).is_empty());
+        let mut rng = ChaCha8Rng::seed_from_u64(1);
+        let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+        assert!(result.timed_out);
+        result.stalemate
+    };
+    let attrition = "
+side1: [ { name: A, hp: 500, ac: 10, attack_bonus: 0, damage: 1, start_zone: melee } ]
+side2: [ { name: B, hp: 500, ac: 10, attack_bonus: 0, damage: 1, start_zone: melee } ]
+";
+    assert_eq!(stalemate(attrition), Some(Stalemate::Attrition));
+    let passive = attrition.replace("start_zone: melee }", "start_zone: melee, apl: [ { action: defend } ] }");
+    assert_eq!(stalemate(&passive), Some(Stalemate::Passive));
+    let no_hits = "
+side1: [ { name: Rat, hp: 10, ac: 12, attack_bonus: -5, damage: 1, start_zone: melee } ]
+side2: [ { name: Golem, hp: 10, ac: 30, attack_bonus: -20, damage: 1, start_zone: melee } ]
+";
+    assert_eq!(stalemate(no_hits), Some(Stalemate::NoHitsPossible));
+    let deadlock = "
+zones: [ { name: a }, { name: b, capacity: 3 }, { name: c }, { name: d } ]
+side1:
+  - { name: Ogre, hp: 10, ac: 12, attack_bonus: 2, damage: 1d6, start_zone: a, frontage: 3 }
+  - { name: Wall, hp: 10, ac: 12, attack_bonus: 2, damage: 1d6, start_zone: b, frontage: 3, apl: [ { action: hold } ] }
+side2:
+  - { name: Orc, hp: 10, ac: 12, attack_bonus: 2, damage: 1d6, start_zone: d, apl: [ { action: hold } ] }
+";
+    assert_eq!(stalemate(deadlock), Some(Stalemate::ZoneDeadlock));
+    let kiting = "
+side1: [ { name: A, hp: 10, ac: 12, attack_bonus: 2, damage: 1d6, start_zone: melee, apl: [ { action: move, target: backward }, { action: attack } ] } ]
+side2: [ { name: B, hp: 10, ac: 12, attack_bonus: 2, damage: 1d6, start_zone: melee, apl: [ { action: move, target: backward }, { action: attack } ] } ]
+";
+    assert_eq!(stalemate(kiting), Some(Stalemate::OutOfReach));
+}
+
+#[test]
+fn simultaneous_ties_trade_blows() {
+    let yaml = "
+initiative: { type: individual, dice: 1d1, tie_break: simultaneous }
+side1:
+  - { name: Duelist, hp: 1, ac: 1, attack_bonus: 30, damage: 5, start_zone: melee }
+side2:
+  - { name: Rival, hp: 1, ac: 1, attack_bonus: 30, damage: 5, start_zone: melee }
+";
+    let encounter: Encounter = serde_yaml::from_str(yaml).unwrap();
+    for seed in 0..20 {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+        assert_eq!(result.winner, None, "seed {}: a tied actor was dropped before acting", seed);
+        assert!(result.mutual_destruction && !result.timed_out, "seed {}: expected a mutual kill", seed);
+        assert!(result.final_state.iter().all(|a| a.final_hp <= 0), "seed {}: both should fall", seed);
+    }
+}
+
+#[test]
+fn summons_stop_at_their_limit() {
+    let yaml = "
+side1:
+  - { name: Knight, hp: 200, ac: 30, attack_bonus: 0, damage: 1, start_zone: melee, apl: [ { action: defend } ] }
+side2:
+  - name: Necromancer
+    count: 2
+    hp: 200
+    ac: 30
+    attack_bonus: 0
+    damage: 1
+    summons: [ { name: Skeleton, hp: 5, ac: 30, attack_bonus: 0, damage: 1, count: 3 } ]
+    apl: [ { action: summon, target: skeleton, amount: 2 }, { action: hold } ]
+max_rounds: 10
+";
+    let encounter: Encounter = serde_yaml::from_str(yaml).unwrap();
+    assert!(tunnel_fight::validate(&encounter).is_empty());
+    let mut sim = CombatSimulator::prepare(&encounter, encounter.max_rounds);
+    for seed in 0..5 {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        sim.reset(&mut rng);
+        let result = sim.run(&mut rng);
+        let summoned: Vec<_> = result.final_state.iter().filter(|a| a.summoner.is_some()).collect();
+        assert_eq!(summoned.len(), 6, "seed {}: each necromancer summons exactly its 3", seed);
+        assert_eq!(result.final_state.len(), 9, "seed {}: last combat's summons weren't cleared", seed);
+        let mut names: Vec<_> = summoned.iter().map(|a| a.name.to_string()).collect();
+        names.sort();
+        names.dedup();
+        assert_eq!(names.len(), 6, "seed {}: summoned actors share names: {:?}", seed, names);
+    }
+}
+
+#[test]
+fn the_fallen_rise_before_the_fight_ends() {
+    let yaml = "
+side1:
+  - { name: Paladin, hp: 500, ac: 30, attack_bonus: 30, damage: 100, start_zone: melee }
+side2:
+  - name: Villager
+    hp: 1
+    ac: 1
+    attack_bonus: 0
+    damage: 1
+    start_zone: melee
+    on_death:
+      transform: { name: Zombie, hp: 1, ac: 1, attack_bonus: 0, damage: 1, on_death: { spawn: { name: Maggots, hp: 1, ac: 1, attack_bonus: 0, damage: 1, count: 2 } } }
+";
+    let encounter: Encounter = serde_yaml::from_str(yaml).unwrap();
+    assert!(tunnel_fight::validate(&encounter).is_empty());
+    for seed in 0..5 {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+        let names: Vec<_> = result.final_state.iter().map(|a| a.name.to_string()).collect();
+        assert_eq!(names, ["Paladin", "Villager", "Zombie 1", "Maggots 1", "Maggots 2"], "seed {}", seed);
+        assert_eq!(result.winner, Some(Side::SIDE1), "seed {}", seed);
+        let deaths = result.events.iter().filter(|e| matches!(e.event_type, EventType::Death { .. })).count();
+        assert_eq!(deaths, 4, "seed {}: everything that rose should have been cut down too", seed);
+    }
+}
+
+#[test]
+fn losing_the_vip_loses_the_fight() {
+    let yaml = "
+side1:
+  - { name: Noble, hp: 1, ac: 1, attack_bonus: 0, damage: 1, start_zone: melee, vip: true }
+  - { name: Knight, hp: 500, ac: 30, attack_bonus: 0, damage: 1, start_zone: melee, count: 3 }
+side2:
+  - name: Assassin
+    hp: 500
+    ac: 30
+    attack_bonus: 30
+    damage: 100
+    start_zone: melee
+    apl:
+      - { action: attack, target: vip }
+";
+    let encounter: Encounter = serde_yaml::from_str(yaml).unwrap();
+    assert!(tunnel_fight::validate(&encounter).is_empty());
+    for seed in 0..5 {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+        assert_eq!(result.winner, Some(Side::SIDE2), "seed {}", seed);
+        assert_eq!(result.rounds, 1, "seed {}: the fight should end with the noble", seed);
+        let dead: Vec<_> = result.final_state.iter().filter(|a| !a.alive).map(|a| a.name.to_string()).collect();
+        assert_eq!(dead, ["Noble"], "seed {}: the assassin should go straight for the VIP", seed);
+    }
+}
+
+#[test]
+fn three_way_fights_end_with_one_side_standing() {
+    let yaml = "
+side1:
+  - { name: Ranger, count: 2, hp: 20, ac: 12, attack_bonus: 4, damage: 1d8 }
+side2:
+  - { name: Orc, count: 2, hp: 20, ac: 12, attack_bonus: 4, damage: 1d8 }
+factions:
+  - deploys: side1
+    actors:
+      - { name: Troll, count: 2, hp: 20, ac: 12, attack_bonus: 4, damage: 1d8 }
+";
+    let encounter: Encounter = serde_yaml::from_str(yaml).unwrap();
+    assert!(tunnel_fight::validate(&encounter).is_empty());
+    let mut winners = [0; 3];
+    for seed in 0..60 {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+        let Some(winner) = result.winner else { continue };
+        winners[winner.index()] += 1;
+        let standing: Vec<_> = result.final_state.iter().filter(|a| a.alive).map(|a| a.side).collect();
+        assert!(!standing.is_empty() && standing.iter().all(|&side| side == winner), "seed {}: {:?} won but {:?} stand", seed, winner, standing);
+    }
+    // Rangers and trolls start out brawling in side1's zones, so the orcs usually clean up
+    assert!(winners[1] > 0 && winners[2] > 0, "both outsiders should win sometimes: {:?}", winners);
+}
+
+#[test]
+fn noncombatants_are_left_alone_and_dont_hold_the_line() {
+    let yaml = "
+side1:
+  - { name: Guard, hp: 1, ac: 1, attack_bonus: 0, damage: 1, start_zone: melee }
+  - { name: Villager, count: 3, hp: 50, ac: 1, attack_bonus: 0, damage: 1, start_zone: melee, noncombatant: true, apl: [ { action: hold } ] }
+side2:
+  - { name: Raider, hp: 500, ac: 30, attack_bonus: 30, damage: 100, start_zone: melee }
+";
+    let encounter: Encounter = serde_yaml::from_str(yaml).unwrap();
+    assert!(tunnel_fight::validate(&encounter).is_empty());
+    for seed in 0..5 {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+        assert_eq!(result.winner, Some(Side::SIDE2), "seed {}: villagers alone shouldn't keep side 1 in the fight", seed);
+        for event in &result.events {
+            if let EventType::Attack { target_id, .. } = event.event_type {
+                assert!(!result.final_state[target_id].noncombatant, "seed {}: a villager was attacked", seed);
+            }
+        }
+        let villagers_alive = result.final_state.iter().filter(|a| a.noncombatant && a.alive).count();
+        assert_eq!(villagers_alive, 3, "seed {}", seed);
+    }
+
+    let raid = yaml.replace("damage: 100, start_zone: melee }", "damage: 100, start_zone: melee, apl: [ { action: attack, target: noncombatant } ] }");
+    let encounter: Encounter = serde_yaml::from_str(&raid).unwrap();
+    let options = SimulationOptions { iterations: 20, seed: Some(1), ..SimulationOptions::for_encounter(&encounter) };
+    let stats = run_simulation(&encounter, &options).stats;
+    let civilians = stats.civilians.expect("civilian stats");
+    assert!(civilians.avg_casualties > 0.0 && civilians.all_saved_rate < 100.0, "{:?}", civilians);
+    assert_eq!(stats.avg_side1_casualties, 0.0, "villagers don't count as side 1 casualties");
+}
+
+#[test]
+fn stacked_bleeds_tick_separately() {
+    let yaml = "
+max_rounds: 5
+side1:
+  - name: Slasher
+    hp: 500
+    ac: 30
+    attack_bonus: 30
+    damage: 1
+    start_zone: melee
+    on_hit: { effect: bleeding, damage: 1, duration: 10, stacking: STACKING }
+side2:
+  - { name: Dummy, hp: 500, ac: 1, attack_bonus: 0, damage: 1, start_zone: melee, apl: [ { action: hold } ] }
+";
+    // Most bleed ticks the dummy takes in any one round
+    let worst_round = |stacking: &str| {
+        let encounter: Encounter = serde_yaml::from_str(&yaml.replace("STACKING", stacking)).unwrap();
+        assert!(tunnel_fight::validate(&encounter).is_empty());
+        let mut rng = ChaCha8Rng::seed_from_u64(3);
+        let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+        (1..=result.rounds)
+            .map(|round| {
+                let ticks = |e: &&CombatEvent| e.round == round && matches!(e.event_type, EventType::ConditionDamage { .. });
+                result.events.iter().filter(ticks).count()
+            })
+            .max()
+            .unwrap_or(0)
+    };
+    assert_eq!(worst_round("replace"), 1);
+    assert_eq!(worst_round("extend"), 1);
+    assert_eq!(worst_round("stack"), 5, "a fresh dose every round should pile up");
+}
+
+#[test]
+fn bonus_actions_and_leftover_movement_extend_the_turn() {
+    let yaml = "
+max_rounds: 3
+zones: [ { name: a }, { name: b }, { name: c } ]
+side1:
+  - name: Rogue
+    hp: 500
+    ac: 30
+    attack_bonus: 0
+    damage: 1
+    speed: 2
+    start_zone: a
+    apl:
+      - { action: attack, if: enemy.in_range }
+      - { action: attack, uses: bonus_action, if: enemy.in_range }
+      - { action: move, if: '!enemy.in_range' }
+      - { action: move, target: backward, after_action: true }
+side2:
+  - { name: Dummy, hp: 500, ac: 1, attack_bonus: 0, damage: 1, start_zone: c, apl: [ { action: hold } ] }
+";
+    let encounter: Encounter = serde_yaml::from_str(yaml).unwrap();
+    assert!(tunnel_fight::validate(&encounter).is_empty());
+    let mut rng = ChaCha8Rng::seed_from_u64(5);
+    let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+    let turn = |round: u32| -> Vec<&str> {
+        let rogue = |e: &&CombatEvent| e.round == round && e.actor_id == 0;
+        result
+            .events
+            .iter()
+            .filter(rogue)
+            .map(|e| match e.event_type {
+                EventType::Move { .. } => "move",
+                EventType::Attack { .. } => "attack",
+                _ => "other",
+            })
+            .collect()
+    };
+    // Closes in, swings twice, and backs off on the movement it has left
+    assert_eq!(turn(1), ["move", "attack", "attack", "move"]);
+    assert_eq!(turn(2), ["move", "attack", "attack", "move"]);
+}
+
+#[test]
+fn withdrawing_gives_no_opportunity_attacks() {
+    let yaml = "
+max_rounds: 4
+side1:
+  - { name: Fighter, hp: 500, ac: 10, attack_bonus: 0, damage: 1, start_zone: melee, apl: [ RETREAT ] }
+side2:
+  - name: Spear
+    hp: 500
+    ac: 10
+    attack_bonus: 0
+    damage: 1
+    start_zone: melee
+    apl: [ { ready: attack, trigger: enemy_leaves_zone }, { action: hold } ]
+";
+    // (opportunity attacks taken, zones the fighter ended up from where it started)
+    let retreat = |entry: &str| {
+        let encounter: Encounter = serde_yaml::from_str(&yaml.replace("RETREAT", entry)).unwrap();
+        assert!(tunnel_fight::validate(&encounter).is_empty());
+        let mut rng = ChaCha8Rng::seed_from_u64(9);
+        let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+        let reactions = result.events.iter().filter(|e| matches!(e.event_type, EventType::Reaction { .. })).count();
+        let moves = result.events.iter().filter(|e| e.actor_id == 0 && matches!(e.event_type, EventType::Move { .. })).count();
+        (reactions, moves)
+    };
+    let (provoked, moved) = retreat("{ action: move, target: backward }");
+    assert!(provoked > 0 && moved == 2, "a plain retreat provokes and covers a zone a turn");
+    // Half speed: a zone every other turn, and nobody gets a swing
+    assert_eq!(retreat("{ action: withdraw }"), (0, 2));
+}
+
+#[test]
+fn allies_in_the_line_of_fire_spoil_or_catch_shots() {
+    let yaml = "
+max_rounds: 20
+rules: { line_of_fire: MODE }
+side1:
+  - { name: Archer, hp: 500, ac: 10, attack_bonus: 0, damage: 1d6, range: ranged, start_zone: ranged, apl: [ { action: attack } ] }
+  - { name: Shield, hp: 500, ac: 5, attack_bonus: 0, damage: 1, start_zone: SHIELD, apl: [ { action: hold } ] }
+side2:
+  - { name: Orc, hp: 500, ac: 18, attack_bonus: 0, damage: 1, start_zone: melee, apl: [ { action: hold } ] }
+";
+    // (archer attacks taking the penalty, stray shots, stray shots at anyone but the shield)
+    let shoot = |mode: &str, shield: &str| {
+        let yaml = yaml.replace("MODE", mode).replace("SHIELD", shield);
+        let encounter: Encounter = serde_yaml::from_str(&yaml).unwrap();
+        assert!(tunnel_fight::validate(&encounter).is_empty());
+        let mut rng = ChaCha8Rng::seed_from_u64(4);
+        let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+        let (mut penalized, mut strays, mut elsewhere) = (0, 0, 0);
+        for event in result.events.iter().filter(|e| e.actor_id == 0) {
+            match &event.event_type {
+                EventType::Attack { modifiers, .. } => penalized += modifiers.roll.iter().filter(|m| m.value == -2).count(),
+                EventType::StrayShot { target_id, .. } => {
+                    strays += 1;
+                    elsewhere += (*target_id != 1) as usize;
+                }
+                _ => {}
+            }
+        }
+        (penalized, strays, elsewhere)
+    };
+    assert_eq!(shoot("clear", "melee"), (0, 0, 0));
+    let (penalized, strays, _) = shoot("penalty", "melee");
+    assert!(penalized == 20 && strays == 0, "every shot past the shield takes the penalty");
+    let (penalized, strays, elsewhere) = shoot("hit_allies", "melee");
+    assert!(penalized == 0 && strays > 0 && elsewhere == 0, "misses land on the shield in the way");
+    // Beside the archer, the shield isn't in the way
+    assert_eq!(shoot("penalty", "ranged"), (0, 0, 0));
+    assert_eq!(shoot("hit_allies", "ranged"), (0, 0, 0));
+}
+
+#[test]
+fn friendly_fire_catches_allies_and_is_reported_as_self_inflicted() {
+    let yaml = "
+friendly_fire: FF
+max_rounds: 8
+zones: [ { name: back }, { name: mid }, { name: brawl } ]
+side1:
+  - { name: Fighter, hp: 20, ac: 12, attack_bonus: 2, damage: 1d6, start_zone: brawl, count: 2, apl: [ { action: attack } ] }
+  - { name: Wizard, hp: 40, ac: 10, attack_bonus: 5, damage: 3d6, range: ranged, area: true, start_zone: back, apl: [ { action: attack } ] }
+side2:
+  - { name: Orc, hp: 15, ac: 13, attack_bonus: 3, damage: 1d8, start_zone: brawl, count: 3, apl: [ { action: attack } ] }
+";
+    for friendly_fire in [false, true] {
+        let encounter: Encounter = serde_yaml::from_str(&yaml.replace("FF", &friendly_fire.to_string())).unwrap();
+        assert!(tunnel_fight::validate(&encounter).is_empty());
+        let mut rng = ChaCha8Rng::seed_from_u64(2);
+        let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+        let wizard_hits: Vec<usize> = result
+            .events
+            .iter()
+            .filter(|e| e.actor_id == 2)
+            .filter_map(|e| match e.event_type {
+                EventType::Attack { target_id, .. } => Some(target_id),
+                _ => None,
+            })
+            .collect();
+        // Every fireball catches all three orcs while they stand, and both fighters with friendly fire
+        assert!(wizard_hits.iter().filter(|&&id| id > 2).count() > 3);
+        assert_eq!(wizard_hits.iter().any(|&id| id < 2), friendly_fire);
+
+        let options = SimulationOptions { iterations: 200, seed: Some(3), ..SimulationOptions::for_encounter(&encounter) };
+        let stats = run_simulation(&encounter, &options).stats;
+        assert_eq!(stats.avg_side1_friendly_fire_damage.unwrap() > 0.0, friendly_fire);
+        assert_eq!(stats.avg_side1_self_inflicted_casualties.unwrap() > 0.0, friendly_fire);
+        assert_eq!(stats.avg_side2_friendly_fire_damage, Some(0.0));
+        // Fighters the wizard kills aren't kills
+        let kills: f64 = stats.kills.unwrap().actors.iter().filter(|a| a.side == "Side1").map(|a| a.avg_kills).sum();
+        let enemy_deaths = stats.avg_side2_casualties;
+        assert!((kills - enemy_deaths).abs() < 1e-9, "{} kills for {} dead orcs", kills, enemy_deaths);
+    }
+}
+
+#[test]
+fn called_shots_to_the_head_cost_the_target_its_turns() {
+    let yaml = "
+called_shots: true
+side1:
+  - name: Duelist
+    hp: 500
+    ac: 30
+    attack_bonus: 30
+    damage: 1
+    start_zone: melee
+    apl:
+      - { action: attack, called_shot: head }
+side2:
+  - { name: Brute, hp: 50, ac: 10, attack_bonus: 0, damage: 1, start_zone: melee }
+";
+    let encounter: Encounter = serde_yaml::from_str(yaml).unwrap();
+    assert!(tunnel_fight::validate(&encounter).is_empty());
+    for seed in 0..5 {
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng).run(&mut rng);
+        let brute_attacks = result
+            .events
+            .iter()
+            .filter(|e| e.actor_id == 1 && matches!(e.event_type, EventType::Attack { .. }))
+            .count();
+        // Only a turn taken before the first hit can get through
+        assert!(brute_attacks <= 1, "seed {}: the brute attacked {} times while stunned", seed, brute_attacks);
+    }
+}
@@ -0,0 +1,46 @@
+//! Decision cases: posing a state and checking what an actor's APL picks from it.
+
+use tunnel_fight::decide::{run_cases, DecisionCase};
+use tunnel_fight::types::Encounter;
+
+#[test]
+fn decisions_follow_the_posed_state() {
+    let encounter: Encounter = serde_yaml::from_str(
+        "
+side1:
+  - name: Wizard
+    hp: 20
+    ac: 11
+    attack_bonus: 4
+    damage: 1d6
+    range: ranged
+    start_zone: ranged
+    apl:
+      - { action: move, target: backward, if: self.hp_percent < 25 }
+      - { action: attack, if: enemy.in_range }
+      - { action: move, target: nearest_enemy }
+side2: [ { name: Orc, hp: 15, ac: 13, attack_bonus: 3, damage: 1d8, start_zone: melee, count: 2 } ]
+",
+    )
+    .unwrap();
+    let cases: Vec<DecisionCase> = serde_yaml::from_str(
+        "
+- { actor: Wizard, state: { Wizard: { hp_percent: 20, zone: side1_reach } }, expect: { move: backward, action: attack Orc } }
+- { actor: Wizard, state: { Orc 1: { hp: 0 } }, expect: { action: attack Orc 2 (id 2) } }
+- { actor: Wizard, state: { Wizard: { conditions: [stunned] } }, expect: { move: none, action: none } }
+- { actor: Orc 1, apl: [ { action: dodge } ], expect: { move: none, action: dodge } }
+- { actor: Wizard, expect: { move: backward, action: attack Orc 1 (id 2) } }
+",
+    )
+    .unwrap();
+    let report = run_cases(&encounter, &cases, 0).unwrap();
+    assert_eq!((report.passed, report.failed), (4, 1));
+    assert_eq!(
+        report.results[4].failures,
+        ["move: expected backward, got toward Orc 1 (id 1)", "action: expected attack Orc 1 (id 2), got attack Orc 1 (id 1)"]
+    );
+
+    let typo: Vec<DecisionCase> = serde_yaml::from_str("[ { actor: Wizard, state: { Wizard: { zone: moat } } } ]").unwrap();
+    let errors = run_cases(&encounter, &typo, 0).unwrap_err();
+    assert_eq!(errors[0].path, "cases[0].state.Wizard.zone");
+}
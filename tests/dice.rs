@@ -0,0 +1,147 @@
+//! Dice expressions: what parses, how it prints, and that `expected_value` and `distribution`
+//! match what `roll` actually produces, with and without house rules.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use tunnel_fight::dice::{parse_damage_dice, DiceRules};
+
+const SAMPLES: u32 = 200_000;
+
+#[test]
+fn expressions_round_trip_through_display() {
+    for (input, printed) in [
+        ("1d8+2", "1d8+2"),
+        ("1d6-1", "1d6-1"),
+        ("2d6 + 1d4 + 3", "2d6+1d4+3"),
+        ("d6", "1d6"),
+        ("4D6KH3", "4d6kh3"),
+        ("4d6k3", "4d6kh3"),
+        ("4d6d1", "4d6dl1"),
+        ("2d20kl1", "2d20kl1"),
+        ("1d6!", "1d6!"),
+        ("1d20adv", "1d20adv"),
+        ("1d20dis+5", "1d20dis+5"),
+        ("7", "7"),
+    ] {
+        let dice = parse_damage_dice(input).unwrap_or_else(|e| panic!("{:?} should parse: {}", input, e));
+        assert_eq!(dice.to_string(), printed);
+        assert_eq!(parse_damage_dice(printed).unwrap(), dice);
+    }
+}
+
+#[test]
+fn bad_modifiers_are_rejected() {
+    for input in ["4d6kh5", "4d6kh0", "4d6dl4", "2d20adv", "1d1!", "1d6!!", "4d6!kh3", "4d6kh3kl1", "1d6x", "1d1001", "1001d6", "101d6kh1"] {
+        assert!(parse_damage_dice(input).is_err(), "{:?} should not parse", input);
+    }
+}
+
+#[test]
+fn expected_values_match_rolls() {
+    for (input, expected) in [
+        ("2d6+3", 10.0),
+        ("2d6+1d4+3", 12.5),
+        ("1d20adv", 13.825),
+        ("1d20dis", 7.175),
+        ("4d6kh3", 12.2446),
+        ("4d6dl1", 12.2446),
+        ("3d6kl1", 2.0417),
+        ("1d6!", 4.2),
+    ] {
+        let dice = parse_damage_dice(input).unwrap();
+        assert!(
+            (dice.expected_value() - expected).abs() < 0.001,
+            "{}: expected {}, computed {}",
+            input,
+            expected,
+            dice.expected_value()
+        );
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mean = (0..SAMPLES).map(|_| f64::from(dice.roll(&mut rng))).sum::<f64>() / f64::from(SAMPLES);
+        assert!((mean - expected).abs() < 0.05, "{}: expected {}, rolled a mean of {}", input, expected, mean);
+    }
+}
+
+#[test]
+fn distributions_match_rolls() {
+    for input in ["2d6+3", "1d4-2", "1d20adv", "1d20dis+1", "2d4-1d6", "5"] {
+        let dice = parse_damage_dice(input).unwrap();
+        let (lowest, distribution) = dice.distribution().unwrap_or_else(|| panic!("{} should have a distribution", input));
+        assert!((distribution.iter().sum::<f64>() - 1.0).abs() < 1e-9, "{}: chances add up to 1", input);
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut counts = vec![0u32; distribution.len()];
+        for _ in 0..SAMPLES {
+            counts[(i64::from(dice.roll(&mut rng)) - lowest) as usize] += 1;
+        }
+        for (i, (&chance, &count)) in distribution.iter().zip(&counts).enumerate() {
+            let rolled = f64::from(count) / f64::from(SAMPLES);
+            assert!((rolled - chance).abs() < 0.005, "{}: P({}) is {}, rolled {}", input, lowest + i as i64, chance, rolled);
+        }
+    }
+    for input in ["4d6kh3", "1d6!", "1000d1000"] {
+        assert!(parse_damage_dice(input).unwrap().distribution().is_none(), "{}", input);
+    }
+}
+
+#[test]
+fn house_rules_match_rolls() {
+    let explode = DiceRules { explode: true, ..DiceRules::default() };
+    let reroll = DiceRules { reroll_ones: true, ..DiceRules::default() };
+    let min_one = DiceRules { min_one: true, ..DiceRules::default() };
+    for (input, rules, expected) in [
+        ("1d6", explode, 4.2),
+        ("2d6+1", explode, 9.4),
+        ("4d6kh3", explode, 12.2446),
+        ("1d6", reroll, 3.9167),
+        ("1d6!", reroll, 4.7333),
+        ("1d20adv", reroll, 14.1421),
+        ("1d4-2", min_one, 1.25),
+    ] {
+        let dice = parse_damage_dice(input).unwrap();
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mean = (0..SAMPLES).map(|_| f64::from(dice.roll_with(rules, &mut rng))).sum::<f64>() / f64::from(SAMPLES);
+        assert!((mean - expected).abs() < 0.05, "{} {:?}: expected {}, rolled a mean of {}", input, rules, expected, mean);
+        if !rules.min_one {
+            let computed = dice.expected_value_with(rules);
+            assert!((computed - expected).abs() < 0.001, "{} {:?}: expected {}, computed {}", input, rules, expected, computed);
+        }
+    }
+
+    for (input, rules) in [("2d6+3", reroll), ("1d20dis", reroll), ("1d4-2", min_one), ("1d6-10", min_one), ("1d8+1d4-4", reroll)] {
+        let dice = parse_damage_dice(input).unwrap();
+        let (lowest, distribution) = dice.distribution_with(rules).unwrap_or_else(|| panic!("{} should have a distribution", input));
+        assert!((distribution.iter().sum::<f64>() - 1.0).abs() < 1e-9, "{}: chances add up to 1", input);
+        if rules.min_one {
+            assert_eq!(lowest, 1, "{}: nothing rolls 0 with min_one", input);
+        }
+
+        let mut rng = ChaCha8Rng::seed_from_u64(0);
+        let mut counts = vec![0u32; distribution.len()];
+        for _ in 0..SAMPLES {
+            counts[(i64::from(dice.roll_with(rules, &mut rng)) - lowest) as usize] += 1;
+        }
+        for (i, (&chance, &count)) in distribution.iter().zip(&counts).enumerate() {
+            let rolled = f64::from(count) / f64::from(SAMPLES);
+            assert!((rolled - chance).abs() < 0.005, "{}: P({}) is {}, rolled {}", input, lowest + i as i64, chance, rolled);
+        }
+    }
+    assert!(parse_damage_dice("2d6").unwrap().distribution_with(explode).is_none());
+}
+
+#[test]
+fn large_constants_only_move_the_distribution() {
+    for (input, rules, lowest) in [
+        ("1d4+2000000000", DiceRules::default(), 2_000_000_001),
+        ("1d4+1d6+1000000", DiceRules::default(), 1_000_002),
+        ("1d4-2000000000", DiceRules::default(), 0),
+        ("1d4-2000000000", DiceRules { min_one: true, ..DiceRules::default() }, 1),
+    ] {
+        let (from, chances) = parse_damage_dice(input).unwrap().distribution_with(rules).unwrap();
+        assert_eq!(from, lowest, "{}", input);
+        assert!(chances.len() <= 10, "{}: {} chances", input, chances.len());
+        assert!((chances.iter().sum::<f64>() - 1.0).abs() < 1e-9, "{}: chances add up to 1", input);
+    }
+}
@@ -0,0 +1,385 @@
+//! Property tests: simulate randomly generated encounters and check invariants that must hold
+//! in every combat. A failure is shrunk to the smallest encounter that still breaks, printed as
+//! YAML, and saved to `invariants.proptest-regressions` so the next run tries it first.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+
+use proptest::prelude::*;
+use proptest::sample::select;
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use tunnel_fight::campaign::{run_campaign, Campaign, CampaignStep, LongRest, Rest};
+use tunnel_fight::combat::{ActorView, CombatSimulator, Decision, EventDetail, EventType};
+use tunnel_fight::simulation::{average_combat, debug_combat, replay_combat, run_simulation, SimulationOptions};
+use tunnel_fight::types::{Encounter, Side};
+
+const CASES: u32 = 300;
+
+/// A few identical actors on one side of a random encounter
+#[derive(Debug, Clone)]
+struct Group {
+    start: &'static str,
+    range: &'static str,
+    count: u32,
+    hp: String,
+    ac: i32,
+    attack_bonus: i32,
+    damage: String,
+    speed: u32,
+    traits: &'static str,
+    /// How many thralls it can call up mid-fight, if any
+    thralls: Option<u32>,
+    /// Splits in two (false) or rises again (true) when it falls
+    rises: Option<bool>,
+    vip: bool,
+}
+
+fn group() -> impl Strategy<Value = Group> {
+    let zone = || select(vec!["melee", "reach", "ranged"]);
+    let hp = prop_oneof![
+        (1..30u32).prop_map(|hp| hp.to_string()),
+        (1..4u32, select(vec![4, 6, 8, 10])).prop_map(|(count, sides)| format!("{}d{}", count, sides)),
+    ];
+    let damage = (select(vec![4, 6, 8, 10, 12]), 0..4u32).prop_map(|(sides, bonus)| format!("1d{}+{}", sides, bonus));
+    let stats = (zone(), zone(), 1..5u32, hp, 5..22i32, -2..8i32, damage, 1..3u32);
+    let extras = (
+        select(vec!["", "pack_tactics", "sunlight_sensitivity", "nimble_escape"]),
+        prop::option::weighted(0.25, 1..4u32),
+        prop::option::weighted(0.25, any::<bool>()),
+        prop::bool::weighted(0.1),
+    );
+    (stats, extras).prop_map(|((start, range, count, hp, ac, attack_bonus, damage, speed), (traits, thralls, rises, vip))| Group {
+        start,
+        range,
+        count,
+        hp,
+        ac,
+        attack_bonus,
+        damage,
+        speed,
+        traits,
+        thralls,
+        rises,
+        vip,
+    })
+}
+
+/// A random encounter, with zone capacities that fit everyone's starting position
+#[derive(Clone)]
+struct Case {
+    yaml: String,
+    melee_capacity: u32,
+    reach_capacity: u32,
+}
+
+/// Failures print the encounter as it would be written
+impl fmt::Debug for Case {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "\n{}", self.yaml)
+    }
+}
+
+fn encounter() -> impl Strategy<Value = Case> {
+    let rules = (
+        1..40u32,
+        select(vec!["side", "individual", "side_phases", "individual_phases"]),
+        select(vec!["random", "reroll", "modifier", "side1_first", "simultaneous"]),
+        select(vec!["wasted", "retarget"]),
+    );
+    let sides = (prop::collection::vec(group(), 1..4), prop::collection::vec(group(), 1..4));
+    (rules, sides, 1..8u32, 1..8u32).prop_map(|((max_rounds, initiative, tie_break, lost_target), (side1, side2), melee, reach)| {
+        let mut yaml = format!(
+            "max_rounds: {}\ninitiative:\n  type: {}\n  dice: 1d4\n  tie_break: {}\n  lost_target: {}\n",
+            max_rounds, initiative, tie_break, lost_target
+        );
+        let mut starting: HashMap<(&str, &str), u32> = HashMap::new();
+        for (side, groups) in [("side1", &side1), ("side2", &side2)] {
+            yaml.push_str(&format!("{}:\n", side));
+            for (i, group) in groups.iter().enumerate() {
+                *starting.entry((side, group.start)).or_default() += group.count;
+                yaml.push_str(&group_yaml(side, i, group));
+            }
+        }
+
+        let most = |zone: &str| starting.iter().filter(|((_, z), _)| *z == zone).map(|(_, &n)| n).max().unwrap_or(0);
+        let melee_capacity = most("melee").max(melee);
+        let reach_capacity = most("reach").max(reach);
+        yaml.push_str(&format!("zone_capacity: {{ melee: {}, reach: {} }}\n", melee_capacity, reach_capacity));
+        Case {
+            yaml,
+            melee_capacity,
+            reach_capacity,
+        }
+    })
+}
+
+/// The side entry for the `i`th group on `side`
+fn group_yaml(side: &str, i: usize, group: &Group) -> String {
+    let summons = match group.thralls {
+        Some(count) => format!(
+            ", summons: [ {{ name: {}{}_thrall, hp: 1d6, ac: 10, attack_bonus: 1, damage: 1d4, \
+             frontage: 1, count: {} }} ], apl: [ {{ action: summon, amount: 1d2 }}, {{ action: attack }}, \
+             {{ action: move, target: nearest_enemy }} ]",
+            side, i, count
+        ),
+        None => String::new(),
+    };
+    let on_death = match group.rises {
+        Some(false) => format!(
+            ", on_death: {{ spawn: {{ name: {}{}_split, hp: 3, ac: 8, attack_bonus: 0, damage: 1d4, frontage: 1, count: 2 }} }}",
+            side, i
+        ),
+        Some(true) => format!(
+            ", on_death: {{ transform: {{ name: {}{}_risen, hp: 5, ac: 8, attack_bonus: 1, damage: 1d6, frontage: 1 }} }}",
+            side, i
+        ),
+        None => String::new(),
+    };
+    let vip = if group.vip { ", vip: true" } else { "" };
+    format!(
+        "  - {{ name: {}{}, hp: {}, ac: {}, attack_bonus: {}, damage: {}, range: {}, \
+         start_zone: {}, speed: {}, frontage: 1, count: {}, traits: [{}]{}{}{} }}\n",
+        side,
+        i,
+        group.hp,
+        group.ac,
+        group.attack_bonus,
+        group.damage,
+        group.range,
+        group.start,
+        group.speed,
+        group.count,
+        group.traits,
+        summons,
+        on_death,
+        vip
+    )
+}
+
+fn parse(case: &Case) -> Encounter {
+    serde_yaml::from_str(&case.yaml).unwrap_or_else(|e| panic!("generated encounter failed to parse: {}", e))
+}
+
+/// Checks that must hold at every decision point
+fn check_decision(decision: &Decision, case: &Case) -> Result<(), String> {
+    let actor = decision.actors.iter().find(|a| a.id == decision.actor_id).ok_or("unknown actor")?;
+    if !actor.alive {
+        return Err(format!("round {}: dead actor {} was asked to act", decision.round, actor.name));
+    }
+    check_hp(&decision.actors)?;
+
+    let mut occupancy: HashMap<&str, u32> = HashMap::new();
+    for a in decision.actors.iter().filter(|a| a.alive) {
+        *occupancy.entry(a.zone.as_str()).or_default() += 1;
+    }
+    for (zone, count) in occupancy {
+        let capacity = if zone.ends_with("_melee") {
+            case.melee_capacity
+        } else if zone.ends_with("_reach") {
+            case.reach_capacity
+        } else {
+            continue;
+        };
+        if count > capacity {
+            return Err(format!("round {}: {} holds {} (capacity {})", decision.round, zone, count, capacity));
+        }
+    }
+    Ok(())
+}
+
+fn check_hp(actors: &[ActorView]) -> Result<(), String> {
+    match actors.iter().find(|a| a.hp > a.max_hp) {
+        Some(a) => Err(format!("{} has {} HP of {} max", a.name, a.hp, a.max_hp)),
+        None => Ok(()),
+    }
+}
+
+proptest! {
+    #![proptest_config(ProptestConfig::with_cases(CASES))]
+
+    #[test]
+    fn random_encounters_keep_invariants(case in encounter(), seed in any::<u64>(), watch_side1 in any::<bool>()) {
+        let encounter = parse(&case);
+        let errors = tunnel_fight::validate(&encounter);
+        prop_assert!(errors.is_empty(), "generated encounter is invalid: {:?}", errors);
+
+        // Watch one side's decisions; an empty answer keeps it on the default APL
+        let side = if watch_side1 { Side::SIDE1 } else { Side::SIDE2 };
+        let case = Arc::new(case);
+        let violations = Arc::new(Mutex::new(Vec::new()));
+        let (watched_case, watched) = (Arc::clone(&case), Arc::clone(&violations));
+        let controller = Box::new(move |decision: &Decision| {
+            if let Err(violation) = check_decision(decision, &watched_case) {
+                watched.lock().unwrap().push(violation);
+            }
+            Vec::new()
+        });
+
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let result = CombatSimulator::new(&encounter, encounter.max_rounds, &mut rng)
+            .with_controller(side, controller)
+            .run(&mut rng);
+
+        let violations = violations.lock().unwrap();
+        prop_assert!(violations.is_empty(), "{:?}", violations);
+        prop_assert!(result.rounds <= encounter.max_rounds, "ran past max_rounds");
+        prop_assert_eq!(result.stalemate.is_some(), result.timed_out, "stalemate only on timeouts");
+        let last_zone = (encounter.zone_layout().main_len() - 1) as f64;
+        prop_assert_eq!(result.front_line.len(), result.rounds as usize, "one front line per round");
+        for position in result.front_line.iter().flatten() {
+            prop_assert!((0.0..=last_zone).contains(position), "front line at {}", position);
+        }
+
+        for actor in &result.final_state {
+            prop_assert!(
+                actor.final_hp <= actor.max_hp - actor.drained,
+                "{} ended on {} HP of {} max",
+                actor.name,
+                actor.final_hp,
+                actor.max_hp - actor.drained
+            );
+        }
+
+        let mut dead = Vec::new();
+        for event in &result.events {
+            match event.event_type {
+                EventType::Death { .. } => dead.push(event.actor_id),
+                EventType::Attack { damage, absorbed, overkill, roll, target_ac, ref modifiers, .. } => {
+                    prop_assert!(
+                        (0..=damage - absorbed).contains(&overkill),
+                        "{} overkilled by {} with {} damage ({} absorbed)",
+                        event.actor_name, overkill, damage, absorbed
+                    );
+                    prop_assert!(
+                        (1..=20).contains(&(roll - modifiers.roll_bonus())) && target_ac == modifiers.target_ac(),
+                        "{} rolled {} vs AC {}, which {:?} doesn't add up to",
+                        event.actor_name, roll, target_ac, modifiers
+                    );
+                    prop_assert!(!dead.contains(&event.actor_id), "{} acted in round {} after dying", event.actor_name, event.round);
+                }
+                EventType::Move { .. } => {
+                    prop_assert!(!dead.contains(&event.actor_id), "{} acted in round {} after dying", event.actor_name, event.round);
+                }
+                _ => {}
+            }
+        }
+    }
+
+    #[test]
+    fn tracing_explains_choices_without_changing_the_fight(case in encounter(), seed in any::<u64>()) {
+        let encounter = parse(&case);
+        let plain = replay_combat(&encounter, encounter.max_rounds, seed, 0);
+        let (traced, choices) = debug_combat(&encounter, encounter.max_rounds, seed, 0);
+        prop_assert_eq!(
+            format!("{:?}{:?}", plain.events, plain.final_state),
+            format!("{:?}{:?}", traced.events, traced.final_state),
+            "tracing changed the fight"
+        );
+        prop_assert!(!choices.is_empty(), "nothing traced");
+    }
+
+    #[test]
+    fn reset_simulator_fights_like_a_new_one(case in encounter()) {
+        let encounter = parse(&case);
+        let mut reused = CombatSimulator::prepare(&encounter, encounter.max_rounds);
+        for seed in 0..3 {
+            let mut fresh_rng = ChaCha8Rng::seed_from_u64(seed);
+            let fresh = CombatSimulator::new(&encounter, encounter.max_rounds, &mut fresh_rng).run(&mut fresh_rng);
+            let mut reused_rng = ChaCha8Rng::seed_from_u64(seed);
+            reused.reset(&mut reused_rng);
+            let again = reused.run(&mut reused_rng);
+            prop_assert_eq!(
+                format!("{:?}{:?}", fresh.events, fresh.final_state),
+                format!("{:?}{:?}", again.events, again.final_state),
+                "seed {}: a reset simulator fought differently",
+                seed
+            );
+        }
+    }
+
+    #[test]
+    fn average_fight_is_repeatable(case in encounter()) {
+        let encounter = parse(&case);
+        let first = average_combat(&encounter, encounter.max_rounds);
+        let second = average_combat(&encounter, encounter.max_rounds);
+        prop_assert!(first.rounds <= encounter.max_rounds, "ran past the round limit");
+        prop_assert_eq!(format!("{:?}", first.events), format!("{:?}", second.events), "average fight changed between runs");
+    }
+}
+
+proptest! {
+    // Each case runs whole simulations, so fewer of them
+    #![proptest_config(ProptestConfig::with_cases(CASES / 10))]
+
+    #[test]
+    fn event_detail_only_drops_event_stats(case in encounter(), seed in any::<u64>()) {
+        let encounter = parse(&case);
+        let run = |event_detail| {
+            let options = SimulationOptions {
+                iterations: 200,
+                sample_count: 2,
+                seed: Some(seed),
+                event_detail,
+                ..SimulationOptions::for_encounter(&encounter)
+            };
+            run_simulation(&encounter, &options)
+        };
+        let (full, deaths, none) = (run(EventDetail::Full), run(EventDetail::Deaths), run(EventDetail::None));
+        for other in [&deaths, &none] {
+            prop_assert_eq!(
+                format!("{:?}", (full.stats.side1_win_rate, full.stats.avg_rounds, &full.stats.distributions)),
+                format!("{:?}", (other.stats.side1_win_rate, other.stats.avg_rounds, &other.stats.distributions)),
+                "outcomes changed with the event detail"
+            );
+            prop_assert_eq!(format!("{:?}", full.sample_combats), format!("{:?}", other.sample_combats));
+            prop_assert!(other.stats.time_to_kill.is_none() && other.stats.avg_side1_overkill.is_none());
+        }
+        prop_assert!(full.stats.time_to_kill.is_some() && full.stats.avg_side1_overkill.is_some());
+        prop_assert_eq!(format!("{:?}", full.stats.kills), format!("{:?}", deaths.stats.kills));
+        prop_assert_eq!(full.stats.first_round_death_rate, deaths.stats.first_round_death_rate);
+        prop_assert!(none.stats.kills.is_none() && none.stats.first_round_death_rate.is_none());
+        // Left out of the JSON rather than reported as zero
+        let json = serde_json::to_value(&none.stats).unwrap();
+        for key in ["kills", "time_to_kill", "first_round_death_rate", "avg_side1_temp_hp_absorbed"] {
+            prop_assert!(json.get(key).is_none(), "{} was serialized", key);
+        }
+    }
+
+    #[test]
+    fn campaign_rates_add_up(case in encounter(), seed in any::<u64>()) {
+        let encounter = parse(&case);
+        let options = SimulationOptions {
+            iterations: 200,
+            seed: Some(seed),
+            ..SimulationOptions::for_encounter(&encounter)
+        };
+        let campaign = Campaign {
+            name: None,
+            iterations: None,
+            steps: vec![
+                CampaignStep::Encounter(Box::new(encounter.clone())),
+                CampaignStep::Rest(Rest::Long(LongRest::Long)),
+                CampaignStep::Encounter(Box::new(encounter.clone())),
+            ],
+        };
+        let result = run_campaign(&campaign, &options);
+        let (first, second) = (&result.steps[0], &result.steps[1]);
+
+        // The first fight of each day is that iteration of the encounter's own run
+        let alone = run_simulation(&encounter, &options);
+        prop_assert!(
+            (first.survival_rate - alone.stats.side1_win_rate).abs() < 1e-9,
+            "first fight won {}% in the campaign but {}% alone",
+            first.survival_rate,
+            alone.stats.side1_win_rate
+        );
+        prop_assert!((second.reached_rate - first.survival_rate).abs() < 1e-9);
+        for step in &result.steps {
+            prop_assert!((step.survival_rate + step.break_rate - step.reached_rate).abs() < 1e-9, "{} doesn't add up", step.encounter);
+        }
+        prop_assert!((result.survival_rate - second.survival_rate).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,376 @@
+//! Malformed input must come back as errors, never panics. These run seeded random mutations
+//! of real inputs on every `cargo test`; `fuzz/` has cargo-fuzz targets for longer runs.
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+
+use tunnel_fight::apl::ErrorCode;
+use tunnel_fight::combat::CombatSimulator;
+use tunnel_fight::import::{import_5e, import_ose};
+use tunnel_fight::lint::{lint, LintCode};
+use tunnel_fight::optimize::ParamGrid;
+use tunnel_fight::loader::{encounter_schema, parse_encounter, parse_encounter_as, SourceFormat};
+use tunnel_fight::storage::Storage;
+use tunnel_fight::types::{parse_damage_dice, Side};
+
+const EXAMPLES: [&str; 4] = [
+    include_str!("../examples/fighter_vs_orc.yaml"),
+    include_str!("../examples/goblin_raid.yaml"),
+    include_str!("../examples/sample_encounter.yaml"),
+    include_str!("../examples/spearwall_vs_zombies.yaml"),
+];
+
+/// A 5e.tools monster, for the importer
+const STATBLOCK_5E: &str = r#"{"name": "Bugbear", "size": ["M"], "ac": [{"ac": 16, "from": ["hide armor", "shield"]}],
+"hp": {"average": 27, "formula": "5d8 + 5"}, "speed": {"walk": 30}, "str": 15, "dex": 14, "con": 13,
+"skill": {"stealth": "+6", "survival": "+2"}, "senses": ["darkvision 60 ft."],
+"action": [
+  {"name": "Morningstar", "entries": ["{@atk mw} {@hit 4} to hit, reach 5 ft., one target. {@h}11 ({@damage 2d8 + 2}) piercing damage."]},
+  {"name": "Javelin", "entries": ["{@atk mw,rw} {@hit 4} to hit, reach 5 ft. or range 30/120 ft., one target. {@h}9 ({@damage 2d6 + 2}) piercing damage."]}
+]}"#;
+
+/// An OSE statblock line
+const STATBLOCK_OSE: &str =
+    "Gnoll: AC 5 [14], HD 2 (9hp), Att 1 × weapon (2d4 or by weapon), THAC0 18 [+1], MV 90' (30'), SV D12 W13 P14 B15 S16 (2), ML 8";
+
+/// Characters that tend to matter to the parsers
+const INTERESTING: &[u8] = b"0123456789dD+-xX* :{}[],'\"\n\t#&*!|>-~.ref";
+
+/// Apply a few random byte-level edits: deletions, insertions, and duplicated slices
+fn mutate(input: &[u8], rng: &mut impl Rng) -> Vec<u8> {
+    let mut bytes = input.to_vec();
+    for _ in 0..rng.gen_range(1..8) {
+        let at = rng.gen_range(0..=bytes.len());
+        match rng.gen_range(0..4) {
+            0 if at < bytes.len() => {
+                bytes.remove(at);
+            }
+            1 => bytes.insert(at, INTERESTING[rng.gen_range(0..INTERESTING.len())]),
+            2 => bytes.insert(at, rng.gen()),
+            _ => {
+                let end = (at + rng.gen_range(0..16)).min(bytes.len());
+                let slice = bytes[at..end].to_vec();
+                bytes.splice(at..at, slice);
+            }
+        }
+    }
+    bytes
+}
+
+#[test]
+fn dice_parser_rejects_garbage_without_panicking() {
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    for seed in ["1d6", "2d8+3", "1d4-1", "10d10", "d6", "3", "2d6+1d4+3", "4d6kh3", "1d6!", "1d20adv"] {
+        for _ in 0..2000 {
+            let input = String::from_utf8_lossy(&mutate(seed.as_bytes(), &mut rng)).into_owned();
+            let _ = parse_damage_dice(&input);
+        }
+    }
+    for input in ["", "d", "+", "-", "-1d6", "1d6+", "1d6-", "1dd6", "1d0", "99999999999d6", "1d6+99999999999"] {
+        assert!(parse_damage_dice(input).is_err(), "{:?} should not parse", input);
+    }
+}
+
+#[test]
+fn encounter_parser_rejects_garbage_without_panicking() {
+    let storage = Storage::open_in_memory().unwrap();
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    for example in EXAMPLES {
+        for _ in 0..300 {
+            let input = String::from_utf8_lossy(&mutate(example.as_bytes(), &mut rng)).into_owned();
+            let Ok(encounter) = parse_encounter(&input, &storage) else {
+                continue;
+            };
+            // Anything that validates must simulate. Skip mutations that made a huge number
+            // (thousands of actors or dice), which are slow rather than wrong.
+            let huge = input.as_bytes().windows(5).any(|w| w.iter().all(u8::is_ascii_digit));
+            if huge || encounter.actor_count(Side::SIDE1) + encounter.actor_count(Side::SIDE2) > 100 {
+                continue;
+            }
+            let mut sim_rng = ChaCha8Rng::seed_from_u64(0);
+            CombatSimulator::new(&encounter, encounter.max_rounds.min(20), &mut sim_rng).run(&mut sim_rng);
+        }
+    }
+}
+
+#[test]
+fn statblock_importer_rejects_garbage_without_panicking() {
+    let imported = import_5e(&serde_json::from_str(STATBLOCK_5E).unwrap()).unwrap();
+    assert_eq!((imported.template.ac, imported.template.attack_bonus), (16, 4));
+    assert_eq!(imported.template.damage.to_string(), "2d8+2");
+
+    let imported = import_ose(STATBLOCK_OSE, None).unwrap();
+    assert_eq!(imported.template.name, "Gnoll");
+    assert_eq!((imported.template.ac, imported.template.attack_bonus), (14, 1));
+    assert_eq!(imported.template.damage.to_string(), "2d4");
+
+    let mut rng = ChaCha8Rng::seed_from_u64(0);
+    for _ in 0..2000 {
+        let input = String::from_utf8_lossy(&mutate(STATBLOCK_5E.as_bytes(), &mut rng)).into_owned();
+        if let Ok(statblock) = serde_json::from_str(&input) {
+            let _ = import_5e(&statblock);
+        }
+        let input = String::from_utf8_lossy(&mutate(STATBLOCK_OSE.as_bytes(), &mut rng)).into_owned();
+        let _ = import_ose(&input, None);
+    }
+}
+
+#[test]
+fn validation_reports_error_codes() {
+    let storage = Storage::open_in_memory().unwrap();
+    let fighter = "{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6 }";
+    let encounter = |side1: &str, extra: &str| format!("side1: [ {} ]\nside2: [ {} ]\n{}", side1, fighter, extra);
+    let cases = [
+        ("side1: []\nside2: [ { name: B, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6 } ]".to_string(), "side1", ErrorCode::EmptySide),
+        (encounter(fighter, "iterations: 100000000"), "iterations", ErrorCode::TooManyIterations),
+        (encounter(fighter, "max_rounds: 4000000000"), "max_rounds", ErrorCode::OutOfRange),
+        (encounter("{ name: A, hp: 5, ac: -1, attack_bonus: 0, damage: 1d6 }", ""), "side1[0].ac", ErrorCode::OutOfRange),
+        (encounter("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, speed: 0 }", ""), "side1[0].speed", ErrorCode::OutOfRange),
+        (encounter("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d4+2000000000 }", ""), "side1[0].damage", ErrorCode::OutOfRange),
+        (encounter("{ name: A, hp: 1d0, ac: 10, attack_bonus: 0, damage: 1d6 }", ""), "side1[0].hp", ErrorCode::InvalidDice),
+        (
+            encounter("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, apl: [ { action: zap } ] }", ""),
+            "side1[0].apl[0].action",
+            ErrorCode::UnknownAction,
+        ),
+        (encounter("{ extends: orc }", ""), "side1[0].extends", ErrorCode::UnknownRef),
+        (encounter("{ ref: builtin/fighter_11 }", ""), "side1[0].ref", ErrorCode::UnknownRef),
+        (encounter("{ extends: a }", "templates: { a: { extends: b }, b: { extends: a } }"), "side1[0].extends", ErrorCode::UnknownRef),
+        (
+            encounter(
+                "{ extends: necro }",
+                "templates: { necro: { hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, summons: [ { extends: necro } ] } }",
+            ),
+            "side1[0].summons[0].extends",
+            ErrorCode::UnknownRef,
+        ),
+        (
+            encounter(
+                "{ extends: lich }",
+                "templates: { lich: { hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, on_death: { transform: { extends: lich } } } }",
+            ),
+            "side1[0].on_death.transform.extends",
+            ErrorCode::UnknownRef,
+        ),
+        (encounter("{ extends: a, adjust: { hp: x } }", &format!("templates: {{ a: {} }}", fighter)), "side1[0].adjust.hp", ErrorCode::ParseError),
+        (
+            encounter("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, apl: [ { action: summon } ] }", ""),
+            "side1[0].apl[0].action",
+            ErrorCode::MissingField,
+        ),
+        (
+            encounter(
+                "{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, summons: [ { extends: imp } ], \
+                 apl: [ { action: summon, target: wolf } ] }",
+                "templates: { imp: { hp: 0, ac: 10, attack_bonus: 0, damage: 1d4 } }",
+            ),
+            "side1[0].apl[0].target",
+            ErrorCode::UnknownTarget,
+        ),
+        (
+            encounter(
+                "{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, summons: [ { extends: imp } ] }",
+                "templates: { imp: { hp: 0, ac: 10, attack_bonus: 0, damage: 1d4 } }",
+            ),
+            "side1[0].summons[0].hp",
+            ErrorCode::OutOfRange,
+        ),
+        (
+            encounter("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, on_death: { transform: { extends: b } } }", ""),
+            "side1[0].on_death.transform.extends",
+            ErrorCode::UnknownRef,
+        ),
+        (
+            encounter(
+                "{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, on_death: { spawn: { name: B, hp: 1, ac: 1, attack_bonus: 0, damage: 1, vip: true } } }",
+                "",
+            ),
+            "side1[0].on_death.spawn.vip",
+            ErrorCode::UnexpectedField,
+        ),
+        (
+            encounter(
+                "{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, apl: [ { action: attack, called_shot: leg } ] }",
+                "",
+            ),
+            "side1[0].apl[0].called_shot",
+            ErrorCode::UnexpectedField,
+        ),
+        (
+            encounter("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, damage_reduction: -1 }", ""),
+            "side1[0].damage_reduction",
+            ErrorCode::OutOfRange,
+        ),
+        (
+            encounter("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, noncombatant: true }", ""),
+            "side1",
+            ErrorCode::EmptySide,
+        ),
+        (
+            encounter(
+                &format!("{}, {{ name: V, hp: 4, ac: 10, attack_bonus: 0, damage: 1, noncombatant: true, apl: [ {{ action: attack }} ] }}", fighter),
+                "",
+            ),
+            "side1[1].apl[0].action",
+            ErrorCode::UnexpectedField,
+        ),
+        (
+            encounter("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, on_hit: { effect: poisoned, tick: start_of_turn } }", ""),
+            "side1[0].on_hit",
+            ErrorCode::UnexpectedField,
+        ),
+        (
+            encounter("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, script: 'if self.hp < {' }", ""),
+            "side1[0].script",
+            ErrorCode::InvalidScript,
+        ),
+        (
+            encounter("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, apl: [ { action: defend, uses: bonus_action } ] }", ""),
+            "side1[0].apl[0].uses",
+            ErrorCode::UnexpectedField,
+        ),
+        (
+            encounter("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, apl: [ { action: dash, after_action: true } ] }", ""),
+            "side1[0].apl[0].after_action",
+            ErrorCode::UnexpectedField,
+        ),
+        (encounter(fighter, "hostility: { side3: [side1] }"), "hostility.side3", ErrorCode::UnknownSide),
+        (encounter(fighter, "hostility: { side1: [side1] }"), "hostility.side1[0]", ErrorCode::OutOfRange),
+        (encounter(fighter, &format!("factions: [ {{ deploys: side3, actors: [ {} ] }} ]", fighter)), "factions[0].deploys", ErrorCode::OutOfRange),
+    ];
+    for (yaml, path, code) in cases {
+        let errors = parse_encounter(&yaml, &storage).expect_err(&yaml);
+        assert!(
+            errors.iter().any(|e| e.path == path && e.code == code),
+            "{}\nexpected {:?} at {}, got {:?}",
+            yaml,
+            code,
+            path,
+            errors
+        );
+    }
+
+    let party = parse_encounter(&encounter("{ ref: builtin/magic_user_10, count: 2 }", ""), &storage).expect("built-in ref");
+    assert_eq!((party.side1[0].name.as_str(), party.side1[0].count), ("Level 10 Magic-User", 2));
+
+    // Summoning the template you extend is fine as long as it doesn't summon in turn
+    let templates = "templates: { goblin: { hp: 5, ac: 10, attack_bonus: 0, damage: 1d6 }, shaman: { extends: goblin, summons: [ { extends: goblin } ] } }";
+    let shaman = parse_encounter(&encounter("{ extends: shaman }", templates), &storage).expect("shaman summoning goblins");
+    assert_eq!(shaman.side1[0].summons[0].name, "goblin");
+
+    let errors = parse_encounter(&encounter("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d0 }", ""), &storage)
+        .expect_err("zero-sided damage dice");
+    assert_eq!(errors[0].code, ErrorCode::ParseError);
+}
+
+#[test]
+fn values_that_dont_fit_are_reported_at_their_field() {
+    let storage = Storage::open_in_memory().unwrap();
+    let yaml = "side1:\n  - { name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6 }\nside2:\n  - name: B\n    hp: 5\n    ac: 10\n    attack_bonus: 0\n    damage: 2d\n";
+    let errors = parse_encounter(yaml, &storage).expect_err("bad dice");
+    assert_eq!((errors[0].path.as_str(), errors[0].line), ("side2[0].damage", Some(8)));
+    assert!(errors[0].message.starts_with("\"2d\" is not a valid dice expression"), "{}", errors[0].message);
+
+    let json = r#"{"side1": [{"name": "A", "hp": 5, "ac": "ten", "attack_bonus": 0, "damage": "1d6"}], "side2": []}"#;
+    let errors = parse_encounter(json, &storage).expect_err("bad AC");
+    assert_eq!((errors[0].path.as_str(), errors[0].line), ("side1[0].ac", None));
+
+    // Statblocks filled in from templates are checked the same way once they are
+    let templated = "templates: { a: { name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6 } }\n\
+                     side1: [ { extends: a, range: far } ]\nside2: [ { extends: a } ]\n";
+    let errors = parse_encounter(templated, &storage).expect_err("bad range");
+    assert_eq!(errors[0].path, "side1[0].range");
+}
+
+#[test]
+fn encounter_schema_refs_resolve() {
+    let schema = encounter_schema();
+    let defs = schema["$defs"].as_object().unwrap();
+    fn refs<'a>(value: &'a serde_json::Value, found: &mut Vec<&'a str>) {
+        match value {
+            serde_json::Value::Object(map) => map.iter().for_each(|(key, value)| match value.as_str() {
+                Some(target) if key == "$ref" => found.push(target),
+                _ => refs(value, found),
+            }),
+            serde_json::Value::Array(items) => items.iter().for_each(|item| refs(item, found)),
+            _ => {}
+        }
+    }
+    let mut found = Vec::new();
+    refs(&schema, &mut found);
+    for target in found {
+        let name = target.strip_prefix("#/$defs/").unwrap_or_else(|| panic!("{} isn't a $defs ref", target));
+        assert!(defs.contains_key(name), "{} is missing", target);
+    }
+    assert!(defs["Encounter"]["properties"]["side1"].is_object());
+}
+
+#[test]
+fn json_and_toml_parse_like_yaml_and_report_syntax_lines() {
+    let storage = Storage::open_in_memory().unwrap();
+    let yaml = "side1:\n  - { name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6 }\nside2:\n  - { name: B, hp: 1d8, ac: 12, attack_bonus: 1, damage: 1d4 }\n";
+    let json = r#"{"side1": [{"name": "A", "hp": 5, "ac": 10, "attack_bonus": 0, "damage": "1d6"}],
+"side2": [{"name": "B", "hp": "1d8", "ac": 12, "attack_bonus": 1, "damage": "1d4"}]}"#;
+    let toml = "# A against B\n[[side1]]\nname = \"A\"\nhp = 5\nac = 10\nattack_bonus = 0\ndamage = \"1d6\"\n\n\
+                [[side2]]\nname = \"B\"\nhp = \"1d8\"\nac = 12\nattack_bonus = 1\ndamage = \"1d4\"\n";
+    let expected = format!("{:?}", parse_encounter(yaml, &storage).unwrap());
+    for (format, text) in [(SourceFormat::Json, json), (SourceFormat::Toml, toml)] {
+        assert_eq!(SourceFormat::detect(text), format);
+        assert_eq!(format!("{:?}", parse_encounter(text, &storage).unwrap()), expected, "{:?}", format);
+    }
+
+    for (format, text, line) in [
+        (SourceFormat::Json, json.replacen("\"hp\": 5", "\"hp\": 5,,", 1), 1),
+        (SourceFormat::Toml, toml.replacen("ac = 12", "ac = ", 1), 12),
+    ] {
+        let errors = parse_encounter_as(&text, Some(format), &storage).expect_err(&text);
+        assert_eq!((errors[0].code, errors[0].line), (ErrorCode::ParseError, Some(line)), "{:?}", errors);
+    }
+}
+
+#[test]
+fn lint_flags_encounters_that_validate_but_misplay() {
+    let storage = Storage::open_in_memory().unwrap();
+    let goblin = "{ name: G, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, start_zone: melee }";
+    let cases = [
+        ("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, range: ranged, start_zone: melee }", goblin, "side1[0].start_zone", LintCode::StrandedRanged),
+        ("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, apl: [ { action: move } ] }", goblin, "side1[0].apl", LintCode::NeverAttacks),
+        (
+            "{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, apl: [ { action: guard }, { action: attack } ] }",
+            goblin,
+            "side1[0].apl",
+            LintCode::NeverAttacks,
+        ),
+        ("{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, start_zone: melee, count: 4 }", goblin, "side1[0].start_zone", LintCode::OverfilledZone),
+        (
+            "{ name: A, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6 }",
+            "{ name: B, hp: 5, ac: 10, attack_bonus: 0, damage: 1d6, range: ranged, apl: [ { action: move, target: backward }, { action: attack } ] }",
+            "side1",
+            LintCode::Kiting,
+        ),
+    ];
+    for (side1, side2, path, code) in cases {
+        let yaml = format!("zone_capacity: {{ melee: 9 }}\nside1: [ {} ]\nside2: [ {} ]\n", side1, side2);
+        let warnings = lint(&parse_encounter(&yaml, &storage).expect(&yaml));
+        assert!(
+            warnings.iter().any(|w| w.path == path && w.code == code),
+            "{}\nexpected {:?} at {}, got {:?}",
+            yaml,
+            code,
+            path,
+            warnings
+        );
+    }
+
+    let clean = parse_encounter(EXAMPLES[0], &storage).unwrap();
+    assert!(lint(&clean).is_empty(), "{:?}", lint(&clean));
+}
+
+#[test]
+fn grids_too_big_to_count_are_rejected() {
+    // 2^64 combinations wraps a usize product to 0
+    let params = (0..64).map(|i| (format!("p{}", i), vec![serde_json::json!(1), serde_json::json!(2)])).collect();
+    let grid = ParamGrid { apl: vec![serde_json::json!({ "action": "attack" })], params };
+    let errors = grid.expand().unwrap_err();
+    assert_eq!(errors[0].path, "grid.params");
+}
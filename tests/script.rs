@@ -0,0 +1,44 @@
+//! Script APLs: actors driven by a Rhai `script`, and the fallback to their APL when it fails.
+
+use rand::SeedableRng;
+use rand_chacha::ChaCha8Rng;
+
+use tunnel_fight::combat::{CombatSimulator, EventType};
+use tunnel_fight::types::Encounter;
+
+#[test]
+fn scripts_pick_targets_and_fall_back_when_they_fail() {
+    let yaml = "
+side1:
+  - name: Sniper
+    hp: 500
+    ac: 30
+    attack_bonus: 30
+    damage: 1
+    start_zone: melee
+    script: SCRIPT
+side2:
+  - { name: Goblin, count: 3, hp: 50, ac: 1, attack_bonus: 0, damage: 1, start_zone: melee }
+";
+    let run = |script: &str| {
+        let encounter: Encounter = serde_yaml::from_str(&yaml.replace("SCRIPT", script)).unwrap();
+        assert!(tunnel_fight::validate(&encounter).is_empty(), "{}", script);
+        let mut rng = ChaCha8Rng::seed_from_u64(5);
+        CombatSimulator::new(&encounter, 5, &mut rng).run(&mut rng)
+    };
+
+    // Always the last goblin in the list, by id
+    let result = run("'let last = enemies[enemies.len() - 1]; #{ action: \"attack\", target: `id:${last.id}` }'");
+    for event in &result.events {
+        if let (0, EventType::Attack { target_id, .. }) = (event.actor_id, &event.event_type) {
+            assert_eq!(*target_id, 3, "the script picked goblin 3");
+        }
+        assert!(!matches!(event.event_type, EventType::ScriptError { .. }), "{:?}", event);
+    }
+
+    // A script that never finishes uses up its budget, and the default APL takes over
+    let result = run("'loop {}'");
+    let errors = result.events.iter().filter(|e| matches!(e.event_type, EventType::ScriptError { .. })).count();
+    assert_eq!(errors as u32, result.rounds);
+    assert!(result.events.iter().any(|e| e.actor_id == 0 && matches!(e.event_type, EventType::Attack { .. })));
+}